@@ -0,0 +1,34 @@
+/// The WebAssembly offset of a call in a compiled function.
+///
+/// `lucetc` only records call sites when asked to, so that backtraces can point at the
+/// WebAssembly instruction each guest frame is executing. Like [`TrapSite`](struct.TrapSite.html),
+/// this must be repr(C) to support zero-copy deserialization.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct CallSite {
+    /// The offset of the call's return address from the start of the function.
+    pub ret_offset: u32,
+    /// The offset in the WebAssembly module of the call instruction.
+    pub wasm_offset: u32,
+}
+
+/// The call sites of a single function (see `FunctionSpec::call_sites`), sorted by
+/// `ret_offset`.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct CallSiteManifest<'a> {
+    pub call_sites: &'a [CallSite],
+}
+
+impl<'a> CallSiteManifest<'a> {
+    pub fn new(call_sites: &'a [CallSite]) -> CallSiteManifest<'_> {
+        CallSiteManifest { call_sites }
+    }
+    /// Look up the offset in the WebAssembly module of the call that returns to `ret_offset`.
+    pub fn lookup_addr(&self, ret_offset: u32) -> Option<u32> {
+        self.call_sites
+            .binary_search_by(|site| site.ret_offset.cmp(&ret_offset))
+            .ok()
+            .map(|i| self.call_sites[i].wasm_offset)
+    }
+}
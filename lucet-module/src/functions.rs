@@ -1,3 +1,4 @@
+use crate::call_sites::{CallSite, CallSiteManifest};
 use crate::traps::{TrapManifest, TrapSite};
 use cranelift_entity::entity_impl;
 use serde::{Deserialize, Serialize};
@@ -138,7 +139,8 @@ pub struct FunctionHandle {
 
 // The layout of this struct is very tightly coupled to lucetc's `write_function_manifest`!
 //
-// Specifically, `write_function_manifest` sets up relocations on `code_addr` and `traps_addr`.
+// Specifically, `write_function_manifest` sets up relocations on `code_addr`, `traps_addr`, and
+// `call_sites_addr`.
 // It does not explicitly serialize a correctly formed `FunctionSpec`, because addresses
 // for these fields do not exist until the object is loaded in the future.
 //
@@ -151,6 +153,8 @@ pub struct FunctionSpec {
     code_len: u32,
     traps_addr: u64,
     traps_len: u64,
+    call_sites_addr: u64,
+    call_sites_len: u64,
 }
 
 impl FunctionSpec {
@@ -160,8 +164,15 @@ impl FunctionSpec {
             code_len,
             traps_addr,
             traps_len,
+            call_sites_addr: 0,
+            call_sites_len: 0,
         }
     }
+    pub fn with_call_sites(mut self, call_sites_addr: u64, call_sites_len: u64) -> Self {
+        self.call_sites_addr = call_sites_addr;
+        self.call_sites_len = call_sites_len;
+        self
+    }
     pub fn ptr(&self) -> FunctionPointer {
         FunctionPointer::from_usize(self.code_addr as usize)
     }
@@ -195,4 +206,18 @@ impl FunctionSpec {
             None
         }
     }
+    pub fn call_sites_len(&self) -> u64 {
+        self.call_sites_len
+    }
+    /// The call sites of the function, if `lucetc` recorded them.
+    pub fn call_sites(&self) -> Option<CallSiteManifest<'_>> {
+        let call_sites_ptr = self.call_sites_addr as *const CallSite;
+        if !call_sites_ptr.is_null() {
+            let call_sites_slice =
+                unsafe { from_raw_parts(call_sites_ptr, self.call_sites_len as usize) };
+            Some(CallSiteManifest::new(call_sites_slice))
+        } else {
+            None
+        }
+    }
 }
@@ -62,6 +62,7 @@ pub enum GlobalDef {
     I64(i64),
     F32(f32),
     F64(f64),
+    V128(u128),
 }
 
 impl GlobalDef {
@@ -71,16 +72,24 @@ impl GlobalDef {
             GlobalDef::I64(i) => GlobalValue { i_64: *i },
             GlobalDef::F32(f) => GlobalValue { f_32: *f },
             GlobalDef::F64(f) => GlobalValue { f_64: *f },
+            GlobalDef::V128(v) => GlobalValue { v_128: *v },
         }
     }
 }
 
+/// The value of a global, as stored in an instance's globals.
+///
+/// Every global takes up `size_of::<GlobalValue>()` bytes, so that `v128` globals fit alongside
+/// the scalar ones; the `n`th global of a module is at that multiple of `n` from the start of the
+/// globals.
+#[repr(C)]
 #[derive(Copy, Clone)]
 pub union GlobalValue {
     pub i_32: i32,
     pub i_64: i64,
     pub f_32: f32,
     pub f_64: f64,
+    pub v_128: u128,
 }
 
 impl std::fmt::Debug for GlobalValue {
@@ -99,6 +108,7 @@ impl std::fmt::Debug for GlobalValue {
             writeln!(f, "  i_64: {},", self.i_64)?;
             writeln!(f, "  f_32: {},", self.f_32)?;
             writeln!(f, "  f_64: {},", self.f_64)?;
+            writeln!(f, "  v_128: {:#034x},", self.v_128)?;
         }
         writeln!(f, "}}")
     }
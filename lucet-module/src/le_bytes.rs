@@ -0,0 +1,61 @@
+use byteorder::{ByteOrder, LittleEndian};
+use thiserror::Error;
+
+/// A read from [`LeBytes`](struct.LeBytes.html) that does not fit in its bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LeBytesError {
+    #[error("{len} bytes at offset {offset:#x} are out of bounds")]
+    OutOfBounds { offset: u64, len: u64 },
+    #[error("string at offset {0:#x} is out of bounds")]
+    StringOutOfBounds(u64),
+    #[error("unterminated string at offset {0:#x}")]
+    UnterminatedString(u64),
+    #[error("invalid UTF-8 in string at offset {0:#x}")]
+    InvalidString(u64),
+}
+
+/// Bounds-checked little-endian reads from a buffer, such as the ELF files `lucetc` links and the
+/// runtime loads.
+///
+/// Offsets are `u64`, as they are in ELF headers, so that they can be passed along unchecked.
+#[derive(Clone, Copy, Debug)]
+pub struct LeBytes<'a>(pub &'a [u8]);
+
+impl<'a> LeBytes<'a> {
+    pub fn slice(&self, offset: u64, len: u64) -> Result<&'a [u8], LeBytesError> {
+        let start = offset as usize;
+        start
+            .checked_add(len as usize)
+            .and_then(|end| self.0.get(start..end))
+            .ok_or(LeBytesError::OutOfBounds { offset, len })
+    }
+
+    pub fn u8(&self, offset: u64) -> Result<u8, LeBytesError> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    pub fn u16(&self, offset: u64) -> Result<u16, LeBytesError> {
+        Ok(LittleEndian::read_u16(self.slice(offset, 2)?))
+    }
+
+    pub fn u32(&self, offset: u64) -> Result<u32, LeBytesError> {
+        Ok(LittleEndian::read_u32(self.slice(offset, 4)?))
+    }
+
+    pub fn u64(&self, offset: u64) -> Result<u64, LeBytesError> {
+        Ok(LittleEndian::read_u64(self.slice(offset, 8)?))
+    }
+
+    /// Read a NUL-terminated string starting at `offset`.
+    pub fn str(&self, offset: u64) -> Result<&'a str, LeBytesError> {
+        let bytes = self
+            .0
+            .get(offset as usize..)
+            .ok_or(LeBytesError::StringOutOfBounds(offset))?;
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(LeBytesError::UnterminatedString(offset))?;
+        std::str::from_utf8(&bytes[..len]).map_err(|_| LeBytesError::InvalidString(offset))
+    }
+}
@@ -6,9 +6,11 @@
 #![deny(bare_trait_objects)]
 
 pub mod bindings;
+mod call_sites;
 pub mod error;
 mod functions;
 mod globals;
+mod le_bytes;
 mod linear_memory;
 mod module;
 mod module_data;
@@ -19,18 +21,20 @@ mod traps;
 mod types;
 mod version_info;
 
+pub use crate::call_sites::{CallSite, CallSiteManifest};
 pub use crate::error::Error;
 pub use crate::functions::{
     ExportFunction, FunctionHandle, FunctionIndex, FunctionMetadata, FunctionPointer, FunctionSpec,
     ImportFunction, UniqueSignatureIndex,
 };
 pub use crate::globals::{Global, GlobalDef, GlobalSpec, GlobalValue};
+pub use crate::le_bytes::{LeBytes, LeBytesError};
 pub use crate::linear_memory::{HeapSpec, LinearMemorySpec, SparseData};
 pub use crate::module::{Module, SerializedModule, LUCET_MODULE_SYM};
 pub use crate::module_data::{ModuleData, ModuleFeatures, MODULE_DATA_SYM};
 pub use crate::runtime::InstanceRuntimeData;
 pub use crate::signature::{ModuleSignature, PublicKey};
-pub use crate::tables::TableElement;
+pub use crate::tables::{TableElement, TableElementType, TableRef, TableSpec};
 pub use crate::traps::{TrapCode, TrapManifest, TrapSite};
 pub use crate::types::{Signature, ValueType};
 pub use crate::version_info::VersionInfo;
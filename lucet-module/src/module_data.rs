@@ -1,9 +1,11 @@
 use crate::{
     functions::{
         ExportFunction, FunctionIndex, FunctionMetadata, ImportFunction, OwnedFunctionMetadata,
+        UniqueSignatureIndex,
     },
     globals::GlobalSpec,
     linear_memory::{HeapSpec, LinearMemorySpec, SparseData},
+    tables::TableSpec,
     types::Signature,
     Error,
 };
@@ -34,6 +36,15 @@ pub struct ModuleData<'a> {
     linear_memory: Option<LinearMemorySpec<'a>>,
     #[serde(borrow)]
     globals_spec: Vec<GlobalSpec<'a>>,
+    tables_spec: Vec<TableSpec>,
+    /// The contents of the module's data segments, indexed by data segment index, for use by
+    /// `memory.init`. Active segments are applied when an instance is created, and are empty
+    /// here.
+    #[serde(borrow)]
+    passive_data: Vec<&'a [u8]>,
+    /// The functions of the module's element segments, indexed by element segment index, for use
+    /// by `table.init`. Active segments are empty here, like active data segments.
+    passive_elements: Vec<Vec<FunctionIndex>>,
     #[serde(borrow)]
     function_info: Vec<FunctionMetadata<'a>>,
     #[serde(borrow)]
@@ -60,6 +71,11 @@ pub struct ModuleFeatures {
     pub lzcnt: bool,
     pub popcnt: bool,
     pub instruction_count: bool,
+    /// Whether the module checks the instance's fuel limit when it updates the instruction count.
+    pub fuel_checks: bool,
+    pub epoch_interruption: bool,
+    /// Whether the module uses instructions or types from the SIMD proposal.
+    pub simd: bool,
     _hidden: (),
 }
 
@@ -76,6 +92,9 @@ impl ModuleFeatures {
             lzcnt: false,
             popcnt: false,
             instruction_count: false,
+            fuel_checks: false,
+            epoch_interruption: false,
+            simd: false,
             _hidden: (),
         }
     }
@@ -85,6 +104,9 @@ impl<'a> ModuleData<'a> {
     pub fn new(
         linear_memory: Option<LinearMemorySpec<'a>>,
         globals_spec: Vec<GlobalSpec<'a>>,
+        tables_spec: Vec<TableSpec>,
+        passive_data: Vec<&'a [u8]>,
+        passive_elements: Vec<Vec<FunctionIndex>>,
         function_info: Vec<FunctionMetadata<'a>>,
         import_functions: Vec<ImportFunction<'a>>,
         export_functions: Vec<ExportFunction<'a>>,
@@ -95,6 +117,9 @@ impl<'a> ModuleData<'a> {
         Self {
             linear_memory,
             globals_spec,
+            tables_spec,
+            passive_data,
+            passive_elements,
             function_info,
             import_functions,
             export_functions,
@@ -125,6 +150,26 @@ impl<'a> ModuleData<'a> {
         &self.globals_spec
     }
 
+    pub fn tables_spec(&self) -> &[TableSpec] {
+        &self.tables_spec
+    }
+
+    /// Get the contents of a passive data segment.
+    ///
+    /// Active segments are empty. Returns `None` if the module has no such segment.
+    pub fn passive_data(&self, idx: u32) -> Option<&'a [u8]> {
+        self.passive_data.get(idx as usize).copied()
+    }
+
+    /// Get the functions of a passive element segment.
+    ///
+    /// Active segments are empty. Returns `None` if the module has no such segment.
+    pub fn passive_elements(&self, idx: u32) -> Option<&[FunctionIndex]> {
+        self.passive_elements
+            .get(idx as usize)
+            .map(|elements| elements.as_slice())
+    }
+
     pub fn function_info(&self) -> &[FunctionMetadata<'a>] {
         &self.function_info
     }
@@ -142,8 +187,22 @@ impl<'a> ModuleData<'a> {
     //
     // This is an index of all functions in the module.
     pub fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
-        let sig_idx = self.function_info[fn_id.as_u32() as usize].signature;
-        &self.signatures[sig_idx.as_u32() as usize]
+        &self.signatures[self.get_signature_index(fn_id).as_u32() as usize]
+    }
+
+    /// Get the unique signature index of a function, as stored in table elements.
+    pub fn get_signature_index(&self, fn_id: FunctionIndex) -> UniqueSignatureIndex {
+        self.function_info[fn_id.as_u32() as usize].signature
+    }
+
+    /// Get the human-friendly name of a function, if it has one.
+    ///
+    /// Returns `None` for indices past the end of the function info, such as the stack probe
+    /// `lucetc` appends to every module.
+    pub fn get_function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.function_info
+            .get(fn_id.as_u32() as usize)
+            .and_then(|info| info.name)
     }
 
     pub fn get_export_func_id(&self, name: &str) -> Option<FunctionIndex> {
@@ -213,6 +272,9 @@ use crate::{
 pub struct OwnedModuleData {
     linear_memory: Option<OwnedLinearMemorySpec>,
     globals_spec: Vec<OwnedGlobalSpec>,
+    tables_spec: Vec<TableSpec>,
+    passive_data: Vec<Vec<u8>>,
+    passive_elements: Vec<Vec<FunctionIndex>>,
     function_info: Vec<OwnedFunctionMetadata>,
     imports: Vec<OwnedImportFunction>,
     exports: Vec<OwnedExportFunction>,
@@ -225,6 +287,9 @@ impl OwnedModuleData {
     pub fn new(
         linear_memory: Option<OwnedLinearMemorySpec>,
         globals_spec: Vec<OwnedGlobalSpec>,
+        tables_spec: Vec<TableSpec>,
+        passive_data: Vec<Vec<u8>>,
+        passive_elements: Vec<Vec<FunctionIndex>>,
         function_info: Vec<OwnedFunctionMetadata>,
         imports: Vec<OwnedImportFunction>,
         exports: Vec<OwnedExportFunction>,
@@ -235,6 +300,9 @@ impl OwnedModuleData {
         Self {
             linear_memory,
             globals_spec,
+            tables_spec,
+            passive_data,
+            passive_elements,
             function_info,
             imports,
             exports,
@@ -254,6 +322,12 @@ impl OwnedModuleData {
                 None
             },
             self.globals_spec.iter().map(|gs| gs.to_ref()).collect(),
+            self.tables_spec.clone(),
+            self.passive_data
+                .iter()
+                .map(|data| data.as_slice())
+                .collect(),
+            self.passive_elements.clone(),
             self.function_info
                 .iter()
                 .map(|info| info.to_ref())
@@ -274,6 +348,9 @@ impl OwnedModuleData {
             vec![],
             vec![],
             vec![],
+            vec![],
+            vec![],
+            vec![],
             ModuleFeatures::none(),
             None,
         )
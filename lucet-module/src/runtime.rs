@@ -1,8 +1,24 @@
+use crate::tables::TableRef;
+
 /// This struct describes the handful of fields that Lucet-compiled programs may directly interact with, but
 /// are provided through VMContext.
+///
+/// The struct ends at the start of the heap, and generated code addresses its fields relative to
+/// that end, so new fields must be added at the start to keep existing offsets stable.
 #[repr(C)]
 #[repr(align(8))]
 pub struct InstanceRuntimeData {
+    /// A pointer to references to the instance's tables, indexed by table index.
+    pub tables_ptr: *const TableRef,
+    /// A pointer to the epoch counter that code compiled with epoch interruption compares against
+    /// `epoch_deadline`.
+    pub epoch_ptr: *const u64,
+    /// The epoch at which code compiled with epoch interruption traps with
+    /// `TrapCode::Interrupt`; `u64::MAX` when the instance has no deadline.
+    pub epoch_deadline: u64,
+    /// The instruction count above which instrumented code calls into the runtime to handle
+    /// running out of fuel; `u64::MAX` when the instance has no fuel limit.
+    pub instruction_count_bound: u64,
     pub globals_ptr: *mut i64,
     pub instruction_count: u64,
 }
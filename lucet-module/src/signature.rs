@@ -18,13 +18,33 @@ impl ModuleSignature {
         so_path: P,
         pk: &PublicKey,
         module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        let raw_module_and_data = RawModuleAndData::from_file(&so_path).map_err(IOError)?;
+        Self::verify_raw(raw_module_and_data, pk, module_data)
+    }
+
+    /// Verify the signature of a shared object that has been read into memory rather than loaded
+    /// from a path.
+    pub fn verify_bytes(
+        obj_bin: &[u8],
+        pk: &PublicKey,
+        module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        let raw_module_and_data =
+            RawModuleAndData::from_bytes(obj_bin.to_vec()).map_err(IOError)?;
+        Self::verify_raw(raw_module_and_data, pk, module_data)
+    }
+
+    fn verify_raw(
+        mut raw_module_and_data: RawModuleAndData,
+        pk: &PublicKey,
+        module_data: &ModuleData<'_>,
     ) -> Result<(), Error> {
         let signature_box: SignatureBox =
             SignatureBones::from_bytes(&module_data.get_module_signature())
                 .map_err(ModuleSignatureError)?
                 .into();
 
-        let mut raw_module_and_data = RawModuleAndData::from_file(&so_path).map_err(IOError)?;
         let cleared_module_data_bin =
             ModuleData::clear_module_signature(raw_module_and_data.module_data_bin())?;
         raw_module_and_data.patch_module_data(&cleared_module_data_bin);
@@ -78,7 +98,10 @@ impl RawModuleAndData {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
         let mut obj_bin: Vec<u8> = Vec::new();
         File::open(&path)?.read_to_end(&mut obj_bin)?;
+        Self::from_bytes(obj_bin)
+    }
 
+    pub fn from_bytes(obj_bin: Vec<u8>) -> Result<Self, io::Error> {
         let native_data_symbol_data =
             Self::symbol_data(&obj_bin, LUCET_MODULE_SYM, true)?.ok_or(io::Error::new(
                 io::ErrorKind::InvalidInput,
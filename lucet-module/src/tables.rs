@@ -1,4 +1,25 @@
 use crate::functions::FunctionPointer;
+use serde::{Deserialize, Serialize};
+
+/// The type of the elements of a WebAssembly table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TableElementType {
+    /// References to functions, which may be called with `call_indirect`.
+    FuncRef,
+    /// References to host values, which are opaque to the guest.
+    ExternRef,
+}
+
+/// Specification of a WebAssembly table of a Lucet module.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSpec {
+    /// The type of the elements of the table.
+    pub element_type: TableElementType,
+    /// The number of elements in the table when an instance is created.
+    pub minimum: u32,
+    /// The maximum number of elements the table may grow to, if the module declares one.
+    pub maximum: Option<u32>,
+}
 
 #[repr(C)]
 #[derive(Clone, Debug)]
@@ -8,7 +29,49 @@ pub struct TableElement {
 }
 
 impl TableElement {
+    /// The type of elements that do not refer to a function.
+    ///
+    /// Signature indices are 32 bits in wasm, so this never matches the type expected by a
+    /// `call_indirect`.
+    pub const NO_TYPE: u64 = std::u64::MAX;
+
+    /// Create an element with the given signature index and function pointer.
+    pub fn new(ty: u64, func: u64) -> Self {
+        TableElement { ty, func }
+    }
+
+    /// Create a null element.
+    pub fn null() -> Self {
+        TableElement::new(Self::NO_TYPE, 0)
+    }
+
+    /// Create an element of an `externref` table holding the given host value.
+    pub fn externref(value: u64) -> Self {
+        TableElement::new(Self::NO_TYPE, value)
+    }
+
     pub fn function_pointer(&self) -> FunctionPointer {
         FunctionPointer::from_usize(self.func as usize)
     }
+
+    /// The reference stored in the element: the function pointer of a `funcref` element, or the
+    /// host value of an `externref` element. Null elements hold `0`.
+    pub fn reference(&self) -> u64 {
+        self.func
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.func == 0
+    }
+}
+
+/// A reference to a table of an instance, as read by generated code.
+///
+/// This has the same layout as a `&[TableElement]`: a pointer to the elements, followed by the
+/// number of elements.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TableRef {
+    pub elements: *mut TableElement,
+    pub len: u64,
 }
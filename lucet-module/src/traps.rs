@@ -26,6 +26,10 @@ pub enum TrapCode {
 pub struct TrapSite {
     pub offset: u32,
     pub code: TrapCode,
+    /// The offset in the WebAssembly module of the instruction that traps, or 0 if the trap does
+    /// not come from an instruction, as in the stack probe. No instruction lies at offset 0, which
+    /// is the module's magic number.
+    pub wasm_offset: u32,
 }
 
 /// A collection of trap sites, typically obtained from a
@@ -41,11 +45,20 @@ impl<'a> TrapManifest<'a> {
         TrapManifest { traps }
     }
     pub fn lookup_addr(&self, addr: u32) -> Option<TrapCode> {
+        self.lookup_site(addr).map(|site| site.code)
+    }
+    /// Look up the offset in the WebAssembly module of the instruction that traps at `addr`.
+    pub fn lookup_wasm_offset(&self, addr: u32) -> Option<u32> {
+        self.lookup_site(addr)
+            .map(|site| site.wasm_offset)
+            .filter(|offset| *offset != 0)
+    }
+    fn lookup_site(&self, addr: u32) -> Option<&'a TrapSite> {
         // predicate to find the trapsite for the addr via binary search
         let f = |ts: &TrapSite| ts.offset.cmp(&addr);
 
         if let Ok(i) = self.traps.binary_search_by(f) {
-            Some(self.traps[i].code)
+            Some(&self.traps[i])
         } else {
             None
         }
@@ -7,6 +7,12 @@ pub enum ValueType {
     I64,
     F32,
     F64,
+    /// A reference to a host value, opaque to the guest.
+    ExternRef,
+    /// A reference to a function.
+    FuncRef,
+    /// A 128-bit vector, from the SIMD proposal.
+    V128,
 }
 
 impl Display for ValueType {
@@ -16,6 +22,9 @@ impl Display for ValueType {
             ValueType::I64 => write!(f, "I64"),
             ValueType::F32 => write!(f, "F32"),
             ValueType::F64 => write!(f, "F64"),
+            ValueType::ExternRef => write!(f, "ExternRef"),
+            ValueType::FuncRef => write!(f, "FuncRef"),
+            ValueType::V128 => write!(f, "V128"),
         }
     }
 }
@@ -27,8 +36,9 @@ impl Display for ValueType {
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Signature {
     pub params: Vec<ValueType>,
-    // In the future, wasm may permit this to be a Vec of ValueType
-    pub ret_ty: Option<ValueType>,
+    /// The types of the values returned by the function, in order. With the multi-value proposal,
+    /// wasm functions may return any number of values.
+    pub results: Vec<ValueType>,
 }
 
 impl Display for Signature {
@@ -42,9 +52,20 @@ impl Display for Signature {
             }
         }
         write!(f, ") -> ")?;
-        match self.ret_ty {
-            Some(ty) => write!(f, "{}", ty),
-            None => write!(f, "()"),
+        match self.results.as_slice() {
+            [] => write!(f, "()"),
+            [ty] => write!(f, "{}", ty),
+            results => {
+                write!(f, "(")?;
+                for (i, r) in results.iter().enumerate() {
+                    if i == 0 {
+                        write!(f, "{}", r)?;
+                    } else {
+                        write!(f, ", {}", r)?;
+                    }
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -54,19 +75,25 @@ macro_rules! lucet_signature {
     ((() -> ())) => {
         $crate::Signature {
             params: vec![],
-            ret_ty: None
+            results: vec![],
         }
     };
     (($($arg_ty:ident),*) -> ()) => {
         $crate::Signature {
             params: vec![$($crate::ValueType::$arg_ty),*],
-            ret_ty: None,
+            results: vec![],
+        }
+    };
+    (($($arg_ty:ident),*) -> ($($ret_ty:ident),+)) => {
+        $crate::Signature {
+            params: vec![$($crate::ValueType::$arg_ty),*],
+            results: vec![$($crate::ValueType::$ret_ty),+],
         }
     };
     (($($arg_ty:ident),*) -> $ret_ty:ident) => {
         $crate::Signature {
             params: vec![$($crate::ValueType::$arg_ty),*],
-            ret_ty: Some($crate::ValueType::$ret_ty),
+            results: vec![$crate::ValueType::$ret_ty],
         }
     };
 }
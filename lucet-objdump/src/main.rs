@@ -1,8 +1,8 @@
 #![deny(bare_trait_objects)]
 
 use lucet_module::{
-    FunctionSpec, Module, ModuleData, SerializedModule, TableElement, TrapManifest, TrapSite,
-    VersionInfo,
+    CallSite, CallSiteManifest, FunctionSpec, Module, ModuleData, SerializedModule, TableElement,
+    TrapManifest, TrapSite, VersionInfo,
 };
 
 use byteorder::{LittleEndian, ReadBytesExt};
@@ -166,6 +166,38 @@ fn parse_trap_manifest<'a>(
     }
 }
 
+/// Parse the call sites for function `f`, if `lucetc` recorded them.
+///
+/// Like trap manifests, call sites must be rebuilt from the buffer, see `parse_trap_manifest`.
+fn parse_call_sites<'a>(
+    summary: &'a ArtifactSummary<'a>,
+    f: &FunctionSpec,
+) -> Option<CallSiteManifest<'a>> {
+    if let Some(faulty_call_sites) = f.call_sites() {
+        let call_sites_ptr = faulty_call_sites.call_sites.as_ptr();
+        let call_sites_count = faulty_call_sites.call_sites.len();
+        let call_sites_byte_count = call_sites_count * std::mem::size_of::<CallSite>();
+        if let Some(call_sites_byte_slice) =
+            summary.read_memory(call_sites_ptr as u64, call_sites_byte_count as u64)
+        {
+            let real_call_sites_ptr = call_sites_byte_slice.as_ptr() as *const CallSite;
+            Some(CallSiteManifest {
+                call_sites: unsafe {
+                    std::slice::from_raw_parts(real_call_sites_ptr, call_sites_count)
+                },
+            })
+        } else {
+            println!(
+                "Failed to read call site bytes for function {:?}, at {:p}",
+                f, call_sites_ptr
+            );
+            None
+        }
+    } else {
+        None
+    }
+}
+
 fn load_module<'b, 'a: 'b>(
     summary: &'a ArtifactSummary<'a>,
     serialized_module: &SerializedModule,
@@ -349,12 +381,37 @@ fn summarize_module<'a, 'b: 'a>(summary: &'a ArtifactSummary<'a>, module: &Modul
                     if trap_count == 1 { "trap" } else { "traps" },
                 );
                 for trap in trap_manifest.traps {
-                    println!("        $+{:#06x}: {:?}", trap.offset, trap.code);
+                    if trap.wasm_offset != 0 {
+                        println!(
+                            "        $+{:#06x}: {:?} (wasm offset {:#x})",
+                            trap.offset, trap.code, trap.wasm_offset
+                        );
+                    } else {
+                        println!("        $+{:#06x}: {:?}", trap.offset, trap.code);
+                    }
                 }
             } else {
                 println!("      No traps for this function");
             }
         }
+        if let Some(call_sites) = parse_call_sites(&summary, f) {
+            let call_site_count = call_sites.call_sites.len();
+            println!(
+                "    Call sites: {} {} ...",
+                call_site_count,
+                if call_site_count == 1 {
+                    "call"
+                } else {
+                    "calls"
+                },
+            );
+            for call_site in call_sites.call_sites {
+                println!(
+                    "        $+{:#06x}: returns from wasm offset {:#x}",
+                    call_site.ret_offset, call_site.wasm_offset
+                );
+            }
+        }
     }
 
     println!("");
@@ -50,6 +50,7 @@ enum lucet_terminated_reason {
     lucet_terminated_reason_borrow_error,
     lucet_terminated_reason_provided,
     lucet_terminated_reason_remote,
+    lucet_terminated_reason_out_of_fuel,
 };
 
 enum lucet_trapcode {
@@ -82,6 +83,9 @@ enum lucet_val_type {
     lucet_val_type_bool,
     lucet_val_type_f32,
     lucet_val_type_f64,
+    lucet_val_type_externref,
+    lucet_val_type_funcref,
+    lucet_val_type_v128,
 };
 
 union lucet_val_inner_val {
@@ -90,6 +94,7 @@ union lucet_val_inner_val {
     int64_t  as_i64;
     float    as_f32;
     double   as_f64;
+    uint8_t  as_v128[16];
 };
 
 struct lucet_val {
@@ -121,7 +126,7 @@ struct lucet_alloc_limits {
      */
     uint64_t stack_size;
     /**
-     * Size of the globals region in bytes; each global uses 8 bytes. (default 4K)
+     * Size of the globals region in bytes; each global uses 16 bytes. (default 4K)
      */
     uint64_t globals_size;
     /**
@@ -50,6 +50,9 @@
 #define LUCET_VAL_F32(X) LUCET_VAL_T(f32, as_f32, X)
 #define LUCET_VAL_F64(X) LUCET_VAL_T(f64, as_f64, X)
 
+#define LUCET_VAL_EXTERNREF(X) LUCET_VAL_T(externref, as_u64, X)
+#define LUCET_VAL_FUNCREF(X) LUCET_VAL_T(funcref, as_u64, X)
+
 // Converts a lucet_val value to the given type
 
 #define LUCET_VAL_TO_T(T, C, V) ((T)((V).inner_val.C))
@@ -75,6 +78,9 @@
 #define LUCET_VAL_TO_F32(X) LUCET_VAL_TO_T(float, as_f32, X)
 #define LUCET_VAL_TO_F64(X) LUCET_VAL_TO_T(double, as_f64, X)
 
+#define LUCET_VAL_TO_EXTERNREF(X) LUCET_VAL_TO_T(uint64_t, as_u64, X)
+#define LUCET_VAL_TO_FUNCREF(X) LUCET_VAL_TO_T(uint64_t, as_u64, X)
+
 // Converts an untyped return value to the given type
 
 #define LUCET_UNTYPED_RETVAL_TO_GP_T(T, C, X) ((T) lucet_retval_gp(&(X)).C)
@@ -39,7 +39,15 @@ int32_t lucet_vmctx_grow_memory(struct lucet_vmctx const *, uint32_t additional_
 void *lucet_vmctx_get_func_from_idx(struct lucet_vmctx const *ctx, uint32_t table_id,
                                     uint32_t func_id);
 
+union lucet_global_value {
+    int32_t  as_i32;
+    int64_t  as_i64;
+    float    as_f32;
+    double   as_f64;
+    uint8_t  as_v128[16];
+};
+
 // Mostly for tests - this conversion is builtin to lucetc
-int64_t *lucet_vmctx_get_globals(struct lucet_vmctx const *ctx);
+union lucet_global_value *lucet_vmctx_get_globals(struct lucet_vmctx const *ctx);
 
 #endif // LUCET_VMCTX_H
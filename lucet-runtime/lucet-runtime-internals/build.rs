@@ -3,8 +3,13 @@ use std::fs::File;
 use std::path::Path;
 
 fn main() {
+    let context_asm = match env::var("CARGO_CFG_TARGET_ARCH").unwrap().as_str() {
+        "x86_64" => "src/context/context_asm.S",
+        "aarch64" => "src/context/context_asm_aarch64.S",
+        arch => panic!("lucet-runtime does not support the {} architecture", arch),
+    };
     cc::Build::new()
-        .file("src/context/context_asm.S")
+        .file(context_asm)
         .compile("context_context_asm");
     cc::Build::new()
         .file("src/instance/siginfo_ext.c")
@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::memory_pool::MemoryPool;
 use crate::module::Module;
 use crate::region::RegionInternal;
 use crate::sysdeps::host_page_size;
@@ -57,6 +58,10 @@ pub struct Slot {
     /// Should not change through the lifetime of the `Alloc`.
     pub limits: Limits,
 
+    /// The module of the last instance created in this slot, for regions that support
+    /// `AllocStrategy::ModuleAffinity`.
+    pub last_module: Option<Weak<dyn Module>>,
+
     pub region: Weak<dyn RegionInternal>,
 }
 
@@ -82,6 +87,13 @@ pub enum AllocStrategy {
     ///
     /// This strategy is used to create reproducible behavior for testing.
     CustomRandom(Arc<Mutex<dyn RngCore + Send>>),
+    /// Allocate from an available slot last used by an instance of the same module, if there is
+    /// one, and from the next slot available otherwise.
+    ///
+    /// Along with `CleanupStrategy::Zero`, this lets a new instance reuse memory that is already
+    /// backed by pages from an earlier instance of its module. Regions that do not track the
+    /// module of each slot allocate from the next slot available.
+    ModuleAffinity,
 }
 
 impl fmt::Debug for AllocStrategy {
@@ -90,6 +102,7 @@ impl fmt::Debug for AllocStrategy {
             AllocStrategy::Linear => write!(f, "AllocStrategy::Linear"),
             AllocStrategy::Random => write!(f, "AllocStrategy::Random"),
             AllocStrategy::CustomRandom(_) => write!(f, "AllocStrategy::CustomRandom(...)"),
+            AllocStrategy::ModuleAffinity => write!(f, "AllocStrategy::ModuleAffinity"),
         }
     }
 }
@@ -103,7 +116,7 @@ impl AllocStrategy {
             return Err(Error::RegionFull(capacity));
         }
         match self {
-            AllocStrategy::Linear => Ok(free_slots - 1),
+            AllocStrategy::Linear | AllocStrategy::ModuleAffinity => Ok(free_slots - 1),
             AllocStrategy::Random => {
                 // Instantiate a random number generator and get a
                 // random slot index.
@@ -120,6 +133,36 @@ impl AllocStrategy {
     }
 }
 
+/// How a `Region` clears the memory of an instance when the instance is dropped or reset, so that
+/// the next instance to use it starts from zeroed memory.
+///
+/// The strategies trade the memory an idle slot holds onto against the cost of page faults when
+/// the slot is next used.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CleanupStrategy {
+    /// Discard the pages with `madvise(MADV_DONTNEED)`, returning them to the operating system.
+    ///
+    /// The next instance faults in fresh zeroed pages as it touches them. This is the default.
+    Decommit,
+    /// Write zeros over the parts of the memory the instance used, keeping the pages backing them.
+    ///
+    /// The next instance to use the slot does not fault on the memory the last one touched, at the
+    /// cost of an idle slot holding onto that memory. Only the part of the heap the instance could
+    /// access, as given by `Alloc::heap_accessible_size`, the parts of the stack and signal stack
+    /// below their high-water marks, and the module's globals, as given by `Alloc::globals_size`,
+    /// are written.
+    Zero,
+    /// Replace the memory with a fresh anonymous mapping, releasing its pages along with any state
+    /// the operating system keeps for the old mapping.
+    Unmap,
+}
+
+impl Default for CleanupStrategy {
+    fn default() -> Self {
+        CleanupStrategy::Decommit
+    }
+}
+
 /// The structure that manages the allocations backing an `Instance`.
 ///
 /// `Alloc`s are not to be created directly, but rather are created by `Region`s during instance
@@ -128,6 +171,17 @@ pub struct Alloc {
     pub heap_accessible_size: usize,
     pub heap_inaccessible_size: usize,
     pub heap_memory_size_limit: usize,
+    /// Whether a module's [`HeapImage`](../module/struct.HeapImage.html) is currently mapped over
+    /// the start of the heap.
+    pub heap_image_mapped: bool,
+    /// How the region clears the memory of the instance when it is dropped or reset.
+    pub cleanup_strategy: CleanupStrategy,
+    /// The number of bytes at the start of the globals used by the module's globals.
+    pub globals_size: usize,
+    /// The [`MemoryPool`](../memory_pool/struct.MemoryPool.html) the heap is charged to, if any.
+    pub memory_pool: Option<Arc<MemoryPool>>,
+    /// The number of bytes currently taken out of `memory_pool` for the heap.
+    pub memory_pool_reservation: usize,
     pub slot: Option<Slot>,
     pub region: Arc<dyn RegionInternal>,
 }
@@ -136,6 +190,9 @@ impl Drop for Alloc {
     fn drop(&mut self) {
         // eprintln!("Alloc::drop()");
         self.region.clone().drop_alloc(self);
+        if let Some(pool) = &self.memory_pool {
+            pool.release(self.memory_pool_reservation);
+        }
     }
 }
 
@@ -215,8 +272,6 @@ impl Alloc {
     }
 
     pub fn expand_heap(&mut self, expand_bytes: u32, module: &dyn Module) -> Result<u32, Error> {
-        let slot = self.slot();
-
         if expand_bytes == 0 {
             // no expansion takes place, which is not an error
             return Ok(self.heap_accessible_size as u32);
@@ -271,15 +326,23 @@ impl Alloc {
         if self.heap_accessible_size + expand_pagealigned as usize > self.heap_memory_size_limit {
             bail_limits_exceeded!(
                 "expansion would exceed runtime-specified heap limit: {:?}",
-                slot.limits
+                self.slot().limits
             );
         }
 
         let newly_accessible = self.heap_accessible_size;
 
-        self.region
-            .clone()
-            .expand_heap(slot, newly_accessible as u32, expand_pagealigned)?;
+        self.set_memory_pool_reservation(newly_accessible + expand_pagealigned as usize)?;
+
+        if let Err(e) = self.region.clone().expand_heap(
+            self.slot(),
+            newly_accessible as u32,
+            expand_pagealigned,
+        ) {
+            self.set_memory_pool_reservation(newly_accessible)
+                .expect("shrinking a memory pool reservation succeeds");
+            return Err(e);
+        }
 
         self.heap_accessible_size += expand_pagealigned as usize;
         self.heap_inaccessible_size -= expand_pagealigned as usize;
@@ -288,9 +351,28 @@ impl Alloc {
     }
 
     pub fn reset_heap(&mut self, module: &dyn Module) -> Result<(), Error> {
+        let initial_size = module
+            .heap_spec()
+            .map(|h| h.initial_size as usize)
+            .unwrap_or(0);
+        self.set_memory_pool_reservation(initial_size)?;
         self.region.clone().reset_heap(self, module)
     }
 
+    /// Take more out of, or return some to, the memory pool so that `heap_size` bytes are taken
+    /// out for the heap.
+    fn set_memory_pool_reservation(&mut self, heap_size: usize) -> Result<(), Error> {
+        if let Some(pool) = &self.memory_pool {
+            if heap_size > self.memory_pool_reservation {
+                pool.reserve(heap_size - self.memory_pool_reservation)?;
+            } else {
+                pool.release(self.memory_pool_reservation - heap_size);
+            }
+            self.memory_pool_reservation = heap_size;
+        }
+        Ok(())
+    }
+
     pub fn heap_len(&self) -> usize {
         self.heap_accessible_size
     }
@@ -432,7 +514,7 @@ pub struct Limits {
     pub heap_address_space_size: usize,
     /// Size of the guest stack. (default 128K)
     pub stack_size: usize,
-    /// Size of the globals region in bytes; each global uses 8 bytes. (default 4K)
+    /// Size of the globals region in bytes; each global uses 16 bytes. (default 4K)
     pub globals_size: usize,
     /// Size of the signal stack in bytes. (default SIGSTKSZ for release builds, at least 12K for
     /// debug builds; minimum MINSIGSTKSZ)
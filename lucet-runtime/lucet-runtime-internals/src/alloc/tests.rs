@@ -10,8 +10,10 @@ macro_rules! alloc_tests {
         use $crate::context::{Context, ContextHandle};
         use $crate::error::Error;
         use $crate::instance::InstanceInternal;
+        use $crate::memory_pool::MemoryPool;
         use $crate::module::{
             FunctionPointer, GlobalValue, HeapSpec, MockExportBuilder, MockModuleBuilder, Module,
+            ModuleInternal,
         };
         use $crate::region::{Region, RegionCreate};
         use $crate::sysdeps::host_page_size;
@@ -585,6 +587,163 @@ macro_rules! alloc_tests {
             assert_eq!(heap[reset_heap_len - 1], 0xFF);
         }
 
+        /// This test shows that modules are only instantiated when their globals, at the size of a
+        /// `GlobalValue` each, fit in the globals limit.
+        #[test]
+        fn globals_limit_counts_global_value_size() {
+            fn module_with_globals(count: u32) -> Arc<dyn Module> {
+                (0..count)
+                    .fold(MockModuleBuilder::new(), |builder, idx| {
+                        builder.with_global(idx, idx as i64)
+                    })
+                    .build()
+            }
+            let max_globals = LIMITS_GLOBALS_SIZE / std::mem::size_of::<GlobalValue>();
+            assert_eq!(max_globals, 256);
+
+            let region = <TestRegion as RegionCreate>::create(1, &LIMITS).expect("region created");
+            let inst = region
+                .new_instance(module_with_globals(256))
+                .expect("new_instance succeeds");
+            assert_eq!(inst.globals().len(), 256);
+            drop(inst);
+
+            for &count in [257, 512].iter() {
+                match region.new_instance(module_with_globals(count)) {
+                    Err(Error::LimitsExceeded(_)) => (),
+                    Err(e) => panic!("unexpected error for {} globals: {}", count, e),
+                    Ok(_) => panic!("instance created with {} globals", count),
+                }
+            }
+        }
+
+        /// This test shows that instances created from a snapshot begin with the heap and globals of
+        /// the instance the snapshot was taken from, and are reset back to that state.
+        #[test]
+        fn snapshot_restores_heap_and_globals() {
+            let region = <TestRegion as RegionCreate>::create(2, &LIMITS).expect("region created");
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .with_initial_heap(b"initial")
+                .with_global(0, 1)
+                .build();
+            let mut inst = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+
+            inst.alloc_mut()
+                .expand_heap(64 * 1024, module.as_ref())
+                .expect("expand_heap succeeds");
+            let heap = unsafe { inst.alloc_mut().heap_mut() };
+            heap[0] = 0xAA;
+            heap[5000] = 0xCC;
+            heap[64 * 1024 + 1] = 0xBB;
+            inst.globals_mut()[0] = GlobalValue { i_64: 42 };
+
+            let snapshot = inst.snapshot().expect("snapshot succeeds");
+
+            fn check_snapshot_state(inst: &$crate::instance::Instance) {
+                let heap = inst.heap();
+                assert_eq!(heap.len(), 2 * 64 * 1024);
+                assert_eq!(heap[0], 0xAA);
+                assert_eq!(&heap[1..7], b"nitial");
+                assert_eq!(heap[5000], 0xCC);
+                assert_eq!(heap[64 * 1024 + 1], 0xBB);
+                assert_eq!(unsafe { inst.globals()[0].i_64 }, 42);
+            }
+
+            let mut from_snapshot = region
+                .new_instance_from_snapshot(snapshot.clone())
+                .expect("new_instance_from_snapshot succeeds");
+            check_snapshot_state(&from_snapshot);
+
+            // changes to one instance are not visible through the snapshot
+            from_snapshot.heap_mut()[0] = 0x11;
+            from_snapshot.heap_mut()[64 * 1024 + 2] = 0x22;
+            from_snapshot.globals_mut()[0] = GlobalValue { i_64: 7 };
+            let other = region.new_instance_from_snapshot(snapshot.clone());
+            assert!(other.is_err(), "region is full");
+            drop(inst);
+            let other = region
+                .new_instance_from_snapshot(snapshot.clone())
+                .expect("new_instance_from_snapshot succeeds");
+            check_snapshot_state(&other);
+            drop(other);
+
+            from_snapshot.reset().expect("reset succeeds");
+            check_snapshot_state(&from_snapshot);
+
+            // a slot that held a snapshot starts from the module's initial state when reused
+            drop(from_snapshot);
+            let inst = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            let heap = inst.heap();
+            assert_eq!(heap.len(), 64 * 1024);
+            assert_eq!(&heap[0..7], b"initial");
+            assert_eq!(heap[5000], 0);
+            assert_eq!(unsafe { inst.globals()[0].i_64 }, 1);
+        }
+
+        /// This test shows that an instance created from a snapshot can grow its heap past the
+        /// captured heap, and that the new pages start out zeroed.
+        #[test]
+        fn snapshot_heap_grows_past_captured_heap() {
+            let region = <TestRegion as RegionCreate>::create(1, &LIMITS).expect("region created");
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .with_initial_heap(b"initial")
+                .build();
+            let inst = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            let snapshot = inst.snapshot().expect("snapshot succeeds");
+            assert_eq!(
+                snapshot.get_sparse_page_data(0).map(|p| &p[0..7]),
+                Some(&b"initial"[..])
+            );
+            drop(inst);
+
+            let mut inst = region
+                .new_instance_from_snapshot(snapshot.clone())
+                .expect("new_instance_from_snapshot succeeds");
+            inst.alloc_mut()
+                .expand_heap(2 * 64 * 1024, snapshot.as_ref())
+                .expect("expand_heap succeeds");
+            let heap = unsafe { inst.alloc_mut().heap_mut() };
+            assert_eq!(heap.len(), 3 * 64 * 1024);
+            assert_eq!(&heap[0..7], b"initial");
+            assert!(heap[64 * 1024..].iter().all(|b| *b == 0));
+            heap[3 * 64 * 1024 - 1] = 0xDD;
+
+            inst.reset().expect("reset succeeds");
+            let heap = inst.heap();
+            assert_eq!(heap.len(), 64 * 1024);
+            assert_eq!(&heap[0..7], b"initial");
+        }
+
+        /// This test shows that a snapshot cannot be used to build an instance of another module.
+        #[test]
+        fn snapshot_rejects_other_module() {
+            let region = <TestRegion as RegionCreate>::create(2, &LIMITS).expect("region created");
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(ONE_PAGE_HEAP)
+                .build();
+            let inst = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            let snapshot = inst.snapshot().expect("snapshot succeeds");
+
+            let other_module = MockModuleBuilder::new()
+                .with_heap_spec(ONE_PAGE_HEAP)
+                .build();
+            let res = region
+                .new_instance_builder(other_module)
+                .from_snapshot(snapshot)
+                .build();
+            assert!(res.is_err(), "new_instance fails");
+        }
+
         const GUARDLESS_HEAP: HeapSpec = HeapSpec {
             reserved_size: SPEC_HEAP_RESERVED_SIZE,
             guard_size: 0,
@@ -829,6 +988,220 @@ macro_rules! alloc_tests {
             assert_eq!(region.used_slots(), 0);
         }
 
+        /// This test shows that instance and region statistics follow instantiation, heap growth,
+        /// and resets.
+        #[test]
+        fn stats_track_instances() {
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .build();
+            let region = <TestRegion as RegionCreate>::create(1, &LIMITS).expect("region created");
+            let mut inst = region.new_instance(module).expect("new_instance succeeds");
+
+            let stats = inst.stats();
+            assert_eq!(stats.heap_size, THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(stats.peak_heap_size, THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(stats.grow_memory_calls, 0);
+            assert_eq!(stats.resets, 0);
+            assert!(stats.last_reset_time.is_none());
+            assert_eq!(stats.stack_high_water_mark, 0);
+
+            inst.grow_memory(1).expect("grow_memory succeeds");
+            inst.heap_mut()[0] = 0xFF;
+            let stats = inst.stats();
+            assert_eq!(stats.heap_size, THREEPAGE_INITIAL_SIZE as usize + 64 * 1024);
+            assert_eq!(stats.grow_memory_calls, 1);
+            assert!(stats.resident_heap_pages >= 1);
+            if let Some(page_faults) = stats.page_faults {
+                assert!(page_faults >= 1);
+            }
+
+            inst.reset().expect("instance resets");
+            let stats = inst.stats();
+            assert_eq!(stats.heap_size, THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(
+                stats.peak_heap_size,
+                THREEPAGE_INITIAL_SIZE as usize + 64 * 1024
+            );
+            assert_eq!(stats.grow_memory_calls, 1);
+            assert_eq!(stats.resets, 1);
+            assert!(stats.last_reset_time.is_some());
+
+            let region_stats = region.stats();
+            assert_eq!(region_stats.capacity, 1);
+            assert_eq!(region_stats.used_slots, 1);
+            assert_eq!(region_stats.instances_created, 1);
+            assert_eq!(region_stats.resets, 1);
+            assert_eq!(region_stats.grow_memory_calls, 1);
+            // the instance itself is resident
+            assert!(region_stats.resident_pages >= 1);
+            assert_eq!(
+                region_stats.page_faults.is_some(),
+                stats.page_faults.is_some()
+            );
+
+            drop(inst);
+            let region_stats = region.stats();
+            assert_eq!(region_stats.used_slots, 0);
+            assert_eq!(region_stats.instances_created, 1);
+        }
+
+        /// This test shows that the pages of a heap image that an instance has only read are
+        /// counted as shared, rather than as memory of each instance or region mapping them.
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn heap_image_pages_are_counted_as_shared() {
+            let region = <TestRegion as RegionCreate>::create(2, &LIMITS).expect("region created");
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(ONE_PAGE_HEAP)
+                .with_initial_heap(b"initial")
+                .build();
+            let inst = region.new_instance(module).expect("new_instance succeeds");
+            let snapshot = inst.snapshot().expect("snapshot succeeds");
+            drop(inst);
+
+            let mut insts = (0..2)
+                .map(|_| {
+                    region
+                        .new_instance_from_snapshot(snapshot.clone())
+                        .expect("new_instance_from_snapshot succeeds")
+                })
+                .collect::<Vec<_>>();
+            for inst in insts.iter() {
+                assert_eq!(&inst.heap()[0..7], b"initial");
+            }
+            let stats = insts[0].stats();
+            assert_eq!(stats.resident_heap_pages, 0);
+            assert!(stats.shared_heap_pages >= 1);
+            let resident_pages = region.stats().resident_pages;
+
+            // writing to the page gives the instance a copy of its own
+            insts[0].heap_mut()[0] = 0xAA;
+            let stats = insts[0].stats();
+            assert_eq!(stats.resident_heap_pages, 1);
+            assert_eq!(region.stats().resident_pages, resident_pages + 1);
+        }
+
+        /// This test shows that instances sharing a memory pool cannot grow their heaps beyond it
+        /// together, and that memory goes back to the pool as heaps are reset and dropped.
+        #[test]
+        fn memory_pool_limits_heap_growth() {
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .build();
+            let pool = Arc::new(MemoryPool::new(3 * 64 * 1024));
+            let region = <TestRegion as RegionCreate>::create(2, &LIMITS).expect("region created");
+            let mut inst_a = region
+                .new_instance_builder(module.clone())
+                .with_memory_pool(pool.clone())
+                .build()
+                .expect("new_instance succeeds");
+            let mut inst_b = region
+                .new_instance_builder(module.clone())
+                .with_memory_pool(pool.clone())
+                .build()
+                .expect("new_instance succeeds");
+            assert_eq!(pool.used(), 2 * 64 * 1024);
+
+            inst_a.grow_memory(1).expect("grow_memory succeeds");
+            assert_eq!(pool.used(), 3 * 64 * 1024);
+            assert_eq!(pool.available(), 0);
+
+            match inst_b.grow_memory(1) {
+                Err(Error::LimitsExceeded(_)) => (),
+                res => panic!("unexpected result: {:?}", res),
+            }
+            assert_eq!(inst_b.heap().len(), THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(pool.used(), 3 * 64 * 1024);
+
+            inst_a.reset().expect("instance resets");
+            assert_eq!(pool.used(), 2 * 64 * 1024);
+
+            inst_b.grow_memory(1).expect("grow_memory succeeds");
+            assert_eq!(pool.used(), 3 * 64 * 1024);
+
+            drop(inst_a);
+            drop(inst_b);
+            assert_eq!(pool.used(), 0);
+        }
+
+        /// This test shows that an instance cannot be created when its memory pool does not have
+        /// room for its initial heap.
+        #[test]
+        fn memory_pool_limits_instantiation() {
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .build();
+            let pool = Arc::new(MemoryPool::new(THREEPAGE_INITIAL_SIZE as usize));
+            let region = <TestRegion as RegionCreate>::create(2, &LIMITS).expect("region created");
+            let inst = region
+                .new_instance_builder(module.clone())
+                .with_memory_pool(pool.clone())
+                .build()
+                .expect("new_instance succeeds");
+
+            match region
+                .new_instance_builder(module.clone())
+                .with_memory_pool(pool.clone())
+                .build()
+            {
+                Err(Error::LimitsExceeded(_)) => (),
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("instance created beyond the memory pool"),
+            }
+            assert_eq!(pool.used(), THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(region.free_slots(), 1);
+
+            drop(inst);
+            assert_eq!(pool.used(), 0);
+            region
+                .new_instance_builder(module)
+                .with_memory_pool(pool.clone())
+                .build()
+                .expect("new_instance succeeds");
+        }
+
+        /// This test shows that instances are charged to the region's memory pool unless they are
+        /// given one of their own.
+        #[test]
+        fn region_memory_pool_is_default() {
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .build();
+            let region_pool = Arc::new(MemoryPool::new(THREEPAGE_INITIAL_SIZE as usize));
+            let own_pool = Arc::new(MemoryPool::new(THREEPAGE_INITIAL_SIZE as usize));
+            let region = <TestRegion as RegionCreate>::create(3, &LIMITS).expect("region created");
+            region.set_memory_pool(Some(region_pool.clone()));
+
+            let inst = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            assert_eq!(region_pool.used(), THREEPAGE_INITIAL_SIZE as usize);
+            match region.new_instance(module.clone()) {
+                Err(Error::LimitsExceeded(_)) => (),
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("instance created beyond the region's memory pool"),
+            }
+
+            let own = region
+                .new_instance_builder(module.clone())
+                .with_memory_pool(own_pool.clone())
+                .build()
+                .expect("new_instance succeeds");
+            assert_eq!(own_pool.used(), THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(region_pool.used(), THREEPAGE_INITIAL_SIZE as usize);
+
+            // instances keep their pool when the region's changes
+            region.set_memory_pool(None);
+            let unpooled = region.new_instance(module).expect("new_instance succeeds");
+            assert_eq!(region_pool.used(), THREEPAGE_INITIAL_SIZE as usize);
+            drop(inst);
+            drop(own);
+            drop(unpooled);
+            assert_eq!(region_pool.used(), 0);
+            assert_eq!(own_pool.used(), 0);
+        }
+
         /// This test exercises the AllocStrategy::Random. In this scenario,
         /// the Region has a single slot which is "randomly" allocated and then dropped.
         #[test]
@@ -1009,6 +1382,20 @@ macro_rules! alloc_tests {
                 .build()
         }
 
+        #[test]
+        fn stack_high_water_mark_follows_guest() {
+            let region = <TestRegion as RegionCreate>::create(1, &LIMITS).expect("region created");
+            let mut inst = region
+                .new_instance(do_nothing_module())
+                .expect("new_instance succeeds");
+            assert_eq!(inst.stats().stack_high_water_mark, 0);
+
+            inst.run("do_nothing", &[]).expect("instance runs");
+            let high_water_mark = inst.stats().stack_high_water_mark;
+            assert!(high_water_mark > 0);
+            assert!(high_water_mark <= LIMITS_STACK_SIZE);
+        }
+
         #[test]
         fn reject_sigstack_smaller_than_min() {
             if MINSIGSTKSZ == 0 {
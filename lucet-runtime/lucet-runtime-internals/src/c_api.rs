@@ -142,7 +142,7 @@ pub struct lucet_alloc_limits {
     pub heap_address_space_size: u64,
     /// Size of the guest stack. (default 128K)
     pub stack_size: u64,
-    /// Size of the globals region in bytes; each global uses 8 bytes. (default 4K)
+    /// Size of the globals region in bytes; each global uses 16 bytes. (default 4K)
     pub globals_size: u64,
     /// Size of the signal stack in bytes. (default SIGSTKSZ for release builds, at least 12K for
     /// debug builds; minimum MINSIGSTKSZ)
@@ -315,6 +315,10 @@ pub mod lucet_result {
                                 reason: lucet_terminated_reason::Remote,
                                 provided: std::ptr::null_mut(),
                             },
+                            TerminationDetails::OutOfFuel => lucet_terminated {
+                                reason: lucet_terminated_reason::OutOfFuel,
+                                provided: std::ptr::null_mut(),
+                            },
                         },
                     },
                 },
@@ -369,6 +373,7 @@ pub mod lucet_result {
         BorrowError,
         Provided,
         Remote,
+        OutOfFuel,
     }
 
     #[repr(C)]
@@ -500,26 +505,29 @@ pub mod lucet_val {
 
     // Note on the value associated with each type: the most significant bits represent the "class"
     // of the type (1: a C pointer, 2: something unsigned that fits in 64 bits, 3: something signed
-    // that fits in 64 bits, 4: f32, 5: f64). The remain bits can be anything as long as it is
-    // unique.
+    // that fits in 64 bits, 4: f32, 5: f64, 6: a 128-bit vector). The remain bits can be anything
+    // as long as it is unique.
     #[repr(C)]
     #[derive(Clone, Copy, Debug)]
     pub enum lucet_val_type {
-        C_Ptr,    // = (1 << 16) | 0x0100,
-        GuestPtr, // = (2 << 16) | 0x0101,
-        U8,       // = (2 << 16) | 0x0201,
-        U16,      // = (2 << 16) | 0x0202,
-        U32,      // = (2 << 16) | 0x0203,
-        U64,      // = (2 << 16) | 0x0204,
-        I8,       // = (3 << 16) | 0x0300,
-        I16,      // = (3 << 16) | 0x0301,
-        I32,      // = (3 << 16) | 0x0302,
-        I64,      // = (3 << 16) | 0x0303,
-        USize,    // = (2 << 16) | 0x0400,
-        ISize,    // = (3 << 16) | 0x0401,
-        Bool,     // = (2 << 16) | 0x0700,
-        F32,      // = (4 << 16) | 0x0800,
-        F64,      // = (5 << 16) | 0x0801,
+        C_Ptr,     // = (1 << 16) | 0x0100,
+        GuestPtr,  // = (2 << 16) | 0x0101,
+        U8,        // = (2 << 16) | 0x0201,
+        U16,       // = (2 << 16) | 0x0202,
+        U32,       // = (2 << 16) | 0x0203,
+        U64,       // = (2 << 16) | 0x0204,
+        I8,        // = (3 << 16) | 0x0300,
+        I16,       // = (3 << 16) | 0x0301,
+        I32,       // = (3 << 16) | 0x0302,
+        I64,       // = (3 << 16) | 0x0303,
+        USize,     // = (2 << 16) | 0x0400,
+        ISize,     // = (3 << 16) | 0x0401,
+        Bool,      // = (2 << 16) | 0x0700,
+        F32,       // = (4 << 16) | 0x0800,
+        F64,       // = (5 << 16) | 0x0801,
+        ExternRef, // = (2 << 16) | 0x0900,
+        FuncRef,   // = (2 << 16) | 0x0901,
+        V128,      // = (6 << 16) | 0x0a00,
     }
 
     #[repr(C)]
@@ -530,6 +538,7 @@ pub mod lucet_val {
         as_i64: i64,           // (3 << 16)
         as_f32: f32,           // (4 << 16)
         as_f64: f64,           // (5 << 16)
+        as_v128: [u8; 16],     // (6 << 16)
     }
 
     #[repr(C)]
@@ -563,6 +572,11 @@ pub mod lucet_val {
                 lucet_val_type::Bool => Val::Bool(unsafe { val.inner_val.as_u64 } != 0),
                 lucet_val_type::F32 => Val::F32(unsafe { val.inner_val.as_f32 } as _),
                 lucet_val_type::F64 => Val::F64(unsafe { val.inner_val.as_f64 } as _),
+                lucet_val_type::ExternRef => Val::ExternRef(unsafe { val.inner_val.as_u64 }),
+                lucet_val_type::FuncRef => Val::FuncRef(unsafe { val.inner_val.as_u64 }),
+                lucet_val_type::V128 => {
+                    Val::V128(u128::from_le_bytes(unsafe { val.inner_val.as_v128 }))
+                }
             }
         }
     }
@@ -636,6 +650,20 @@ pub mod lucet_val {
                     ty: lucet_val_type::F64,
                     inner_val: lucet_val_inner_val { as_f64: *a as _ },
                 },
+                Val::ExternRef(a) => lucet_val {
+                    ty: lucet_val_type::ExternRef,
+                    inner_val: lucet_val_inner_val { as_u64: *a },
+                },
+                Val::FuncRef(a) => lucet_val {
+                    ty: lucet_val_type::FuncRef,
+                    inner_val: lucet_val_inner_val { as_u64: *a },
+                },
+                Val::V128(a) => lucet_val {
+                    ty: lucet_val_type::V128,
+                    inner_val: lucet_val_inner_val {
+                        as_v128: a.to_le_bytes(),
+                    },
+                },
             }
         }
     }
@@ -663,10 +691,8 @@ pub mod lucet_val {
                 gp: [0; 8],
             };
             unsafe {
-                core::arch::x86_64::_mm_storeu_ps(
-                    v.fp.as_mut().as_mut_ptr() as *mut f32,
-                    retval.fp(),
-                );
+                *(v.fp.as_mut().as_mut_ptr() as *mut [u8; 16]) =
+                    crate::val::__m128_as_u128(retval.fp()).to_le_bytes();
                 *(v.gp.as_mut().as_mut_ptr() as *mut u64) = retval.gp();
             }
             v
@@ -4,9 +4,11 @@
 mod tests;
 
 use crate::instance::Instance;
-use crate::val::{val_to_reg, val_to_stack, RegVal, UntypedRetVal, Val};
+use crate::val::{
+    fp_reg_zero, val_to_reg, val_to_stack, RegVal, UntypedRetVal, Val, __m128, FP_ARG_REGS,
+    GP_ARG_REGS,
+};
 
-use std::arch::x86_64::{__m128, _mm_setzero_ps};
 use std::ptr::NonNull;
 use std::{mem, ptr};
 use thiserror::Error;
@@ -23,6 +25,7 @@ use thiserror::Error;
 /// - Unlike the C code, this doesn't use the `packed` repr due to warnings in the Nomicon:
 /// <https://doc.rust-lang.org/nomicon/other-reprs.html#reprpacked>. Since the members are all
 /// `u64`, this should be fine?
+#[cfg(target_arch = "x86_64")]
 #[repr(C)]
 pub(crate) struct GpRegs {
     pub(crate) rbx: u64,
@@ -36,6 +39,7 @@ pub(crate) struct GpRegs {
     pub(crate) rsi: u64,
 }
 
+#[cfg(target_arch = "x86_64")]
 impl GpRegs {
     fn new() -> Self {
         GpRegs {
@@ -52,6 +56,41 @@ impl GpRegs {
     }
 }
 
+/// Callee-saved general-purpose registers in the AArch64 procedure call standard, along with the
+/// stack pointer and the first two argument registers.
+///
+/// # Layout
+///
+/// `repr(C)` is required to preserve the ordering of members, which are read by the assembly at
+/// hard-coded offsets.
+#[cfg(target_arch = "aarch64")]
+#[repr(C)]
+pub(crate) struct GpRegs {
+    /// `x19` through `x28`.
+    pub(crate) callee_saved: [u64; 10],
+    /// `x29`, the frame pointer.
+    pub(crate) fp: u64,
+    /// `x30`, the link register, which a swap to this context returns to.
+    pub(crate) lr: u64,
+    pub(crate) sp: u64,
+    pub(crate) x0: u64,
+    pub(crate) x1: u64,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl GpRegs {
+    fn new() -> Self {
+        GpRegs {
+            callee_saved: [0; 10],
+            fp: 0,
+            lr: 0,
+            sp: 0,
+            x0: 0,
+            x1: 0,
+        }
+    }
+}
+
 /// Floating-point argument registers in the AMD64 ABI.
 ///
 /// # Layout
@@ -64,6 +103,7 @@ impl GpRegs {
 /// - Unlike the C code, this doesn't use the `packed` repr due to warnings in the Nomicon:
 /// <https://doc.rust-lang.org/nomicon/other-reprs.html#reprpacked>. Since the members are all
 /// `__m128`, this should be fine?
+#[cfg(target_arch = "x86_64")]
 #[repr(C)]
 struct FpRegs {
     xmm0: __m128,
@@ -76,9 +116,10 @@ struct FpRegs {
     xmm7: __m128,
 }
 
+#[cfg(target_arch = "x86_64")]
 impl FpRegs {
     fn new() -> Self {
-        let zero = unsafe { _mm_setzero_ps() };
+        let zero = fp_reg_zero();
         FpRegs {
             xmm0: zero,
             xmm1: zero,
@@ -92,6 +133,34 @@ impl FpRegs {
     }
 }
 
+/// Floating-point argument registers and callee-saved floating-point registers in the AArch64
+/// procedure call standard.
+///
+/// Only the low 64 bits of `v8` through `v15` are callee-saved, so only those are kept.
+///
+/// # Layout
+///
+/// `repr(C)` is required to preserve the ordering of members, which are read by the assembly at
+/// hard-coded offsets.
+#[cfg(target_arch = "aarch64")]
+#[repr(C)]
+struct FpRegs {
+    /// `v0` through `v7`.
+    args: [__m128; 8],
+    /// `d8` through `d15`.
+    callee_saved: [u64; 8],
+}
+
+#[cfg(target_arch = "aarch64")]
+impl FpRegs {
+    fn new() -> Self {
+        FpRegs {
+            args: [fp_reg_zero(); 8],
+            callee_saved: [0; 8],
+        }
+    }
+}
+
 /// Everything we need to make a context switch: a signal mask, and the registers and return values
 /// that are manipulated directly by assembly code.
 ///
@@ -122,6 +191,9 @@ pub struct Context {
     // TODO ACF 2019-10-23: make Instance into a generic parameter?
     backstop_callback: *const unsafe extern "C" fn(*mut Instance),
     callback_data: *mut Instance,
+    // The second floating-point return value, used by guest functions that return multiple values.
+    // It comes last so that the offsets of the fields above stay the same.
+    retval_fp1: __m128,
 }
 
 impl Context {
@@ -131,10 +203,11 @@ impl Context {
             gpr: GpRegs::new(),
             fpr: FpRegs::new(),
             retvals_gp: [0; 2],
-            retval_fp: unsafe { _mm_setzero_ps() },
+            retval_fp: fp_reg_zero(),
             parent_ctx: ptr::null_mut(),
             backstop_callback: Context::default_backstop_callback as *const _,
             callback_data: ptr::null_mut(),
+            retval_fp1: fp_reg_zero(),
         }
     }
 
@@ -362,6 +435,11 @@ impl Context {
     ///
     /// The bootstrap function must be careful, but is less constrained since it can clean up
     /// and prepare a context for `fptr`.
+    ///
+    /// The layout above is for x86-64. AArch64 calls do not push return addresses, so there the
+    /// stack only holds the spilled arguments (and any alignment padding), and the bootstrap finds
+    /// the general-purpose arguments and `fptr` in callee-saved registers. The frame pointer `x29`
+    /// plays the part of `rbp` for the backstop.
     pub fn init(
         stack: &mut [u64],
         child: &mut Context,
@@ -405,14 +483,14 @@ impl Context {
 
         let mut gp_args_ix = 0;
         let mut fp_args_ix = 0;
-        let mut gp_regs_values = [0u64; 6];
+        let mut gp_regs_values = [0u64; GP_ARG_REGS];
 
         let mut spilled_args = vec![];
 
         for arg in args {
             match val_to_reg(arg) {
                 RegVal::GpReg(v) => {
-                    if gp_args_ix >= 6 {
+                    if gp_args_ix >= GP_ARG_REGS {
                         spilled_args.push(val_to_stack(arg));
                     } else {
                         gp_regs_values[gp_args_ix] = v;
@@ -420,7 +498,10 @@ impl Context {
                     }
                 }
                 RegVal::FpReg(v) => {
-                    if fp_args_ix >= 8 {
+                    if fp_args_ix >= FP_ARG_REGS {
+                        if let Val::V128(_) = arg {
+                            return Err(Error::SpilledV128);
+                        }
                         spilled_args.push(val_to_stack(arg));
                     } else {
                         child.bootstrap_fp_ix_arg(fp_args_ix, v);
@@ -446,6 +527,21 @@ impl Context {
             "incorrect alignment for guest call frame"
         );
 
+        child.init_bootstrap(stack_builder, fptr, &gp_regs_values);
+
+        Ok(())
+    }
+
+    /// Prepare the registers and stack of a new child context so that swapping to it runs
+    /// `lucet_context_bootstrap`, which calls `fptr` with `gp_args`, and then returns to
+    /// `lucet_context_backstop`.
+    #[cfg(target_arch = "x86_64")]
+    fn init_bootstrap(
+        &mut self,
+        mut stack_builder: CallStackBuilder<'_>,
+        fptr: usize,
+        gp_args: &[u64; GP_ARG_REGS],
+    ) {
         // we execute the guest code via returns, so we make a "call stack" of routines like:
         // -> lucet_context_backstop()
         //    -> fptr()
@@ -457,7 +553,7 @@ impl Context {
         stack_builder.push(fptr as u64);
 
         // add all general purpose arguments for the guest to be bootstrapped
-        for arg in gp_regs_values.iter() {
+        for arg in gp_args.iter() {
             stack_builder.push(*arg);
         }
 
@@ -468,14 +564,86 @@ impl Context {
         // Stack pointer: this points to the return address that will be used by `swap`, in place
         // of the original (eg, in the host) return address. The return address this points to is
         // the address of the first function to run on `swap`: `lucet_context_bootstrap`.
-        child.gpr.rsp = &mut stack[stack.len() - stack_start] as *mut u64 as u64;
+        self.gpr.rsp = &mut stack[stack.len() - stack_start] as *mut u64 as u64;
 
         // Base pointer: `rbp` will be saved through all guest code, and preserved for when we
         // reach the backstop. This allows us to prepare an argument for `lucet_context_backstop`
         // even at the entrypoint of the guest.
-        child.gpr.rbp = child as *const Context as u64;
+        self.gpr.rbp = self as *const Context as u64;
+    }
 
-        Ok(())
+    /// Prepare the registers and stack of a new child context so that swapping to it runs
+    /// `lucet_context_bootstrap`, which calls `fptr` with `gp_args`, and then returns to
+    /// `lucet_context_backstop`.
+    ///
+    /// There are no return addresses on the stack in AArch64, so the arguments and the entrypoint
+    /// are passed to `lucet_context_bootstrap` in callee-saved registers instead: the arguments in
+    /// `x19` through `x26`, and `fptr` in `x27`. The stack only holds spilled arguments.
+    #[cfg(target_arch = "aarch64")]
+    fn init_bootstrap(
+        &mut self,
+        stack_builder: CallStackBuilder<'_>,
+        fptr: usize,
+        gp_args: &[u64; GP_ARG_REGS],
+    ) {
+        let (stack, stack_start) = stack_builder.into_inner();
+
+        // Stack pointer: this points to the first spilled argument, or the end of the stack if
+        // there are none, which is where `fptr` expects to find its stack arguments.
+        self.gpr.sp = unsafe { stack.as_mut_ptr().add(stack.len() - stack_start) } as u64;
+
+        self.gpr.callee_saved[..GP_ARG_REGS].copy_from_slice(gp_args);
+        self.gpr.callee_saved[GP_ARG_REGS] = fptr as u64;
+
+        // Link register: `swap` returns here, so the first thing to run is the bootstrap.
+        self.gpr.lr = lucet_context_bootstrap as u64;
+
+        // Frame pointer: like `rbp` on x86-64, `x29` is preserved through all guest code, so it
+        // holds the context for `lucet_context_backstop` to store return values in.
+        self.gpr.fp = self as *const Context as u64;
+    }
+
+    /// Arrange for `entry_callback` to be called with this context's callback data after swapping
+    /// to it, before the entrypoint the context was initialized with.
+    ///
+    /// This must only be called on a context freshly initialized by
+    /// [`init_with_callback()`](struct.Context.html#method.init_with_callback). See
+    /// `lucet_context_activate`.
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) unsafe fn install_activator(
+        &mut self,
+        entry_callback: unsafe extern "C" fn(*mut Instance),
+    ) {
+        // Get a raw pointer to the top of the guest stack.
+        let top_of_stack = self.gpr.rsp as *mut u64;
+        // Move the guest code address to rbx, and then put the address of the activation thunk
+        // at the top of the stack, so that we will start execution at `entry_callback`.
+        self.gpr.rbx = *top_of_stack;
+        *top_of_stack = lucet_context_activate as u64;
+        // Pass a pointer to the entry callback in `rsi`, and then put its first argument (the
+        // callback data) in `rdi`.
+        self.gpr.rsi = entry_callback as u64;
+        self.gpr.rdi = self.callback_data_ptr() as u64;
+    }
+
+    /// Arrange for `entry_callback` to be called with this context's callback data after swapping
+    /// to it, before the entrypoint the context was initialized with.
+    ///
+    /// This must only be called on a context freshly initialized by
+    /// [`init_with_callback()`](struct.Context.html#method.init_with_callback). See
+    /// `lucet_context_activate`.
+    #[cfg(target_arch = "aarch64")]
+    pub(crate) unsafe fn install_activator(
+        &mut self,
+        entry_callback: unsafe extern "C" fn(*mut Instance),
+    ) {
+        // Move the bootstrap address to x28, which the bootstrap does not use, and return to the
+        // activation thunk instead.
+        self.gpr.callee_saved[9] = self.gpr.lr;
+        self.gpr.lr = lucet_context_activate as u64;
+        // The entry callback goes in `x1`, and its first argument (the callback data) in `x0`.
+        self.gpr.x1 = entry_callback as u64;
+        self.gpr.x0 = self.callback_data_ptr() as u64;
     }
 
     /// Save the current context, and swap to another context.
@@ -622,8 +790,9 @@ impl Context {
     /// Clear (zero) return values.
     pub fn clear_retvals(&mut self) {
         self.retvals_gp = [0; 2];
-        let zero = unsafe { _mm_setzero_ps() };
+        let zero = fp_reg_zero();
         self.retval_fp = zero;
+        self.retval_fp1 = zero;
     }
 
     /// Get the general-purpose return value at index `idx`.
@@ -642,19 +811,34 @@ impl Context {
         self.retval_fp
     }
 
+    /// Get the floating point return value at index `idx`.
+    ///
+    /// Index `0` is the same value returned by
+    /// [`get_retval_fp()`](struct.Context.html#method.get_retval_fp); index `1` is only set by
+    /// functions that return multiple values.
+    pub fn get_retval_fp_ix(&self, idx: usize) -> __m128 {
+        match idx {
+            0 => self.retval_fp,
+            1 => self.retval_fp1,
+            _ => panic!("floating-point return value index out of bounds: {}", idx),
+        }
+    }
+
     /// Get the return value as an `UntypedRetVal`.
     ///
-    /// This combines the 0th general-purpose return value, and the single floating-point return value.
+    /// This combines both general-purpose and both floating-point return values. The first of each
+    /// are the values returned by functions with a single return value.
     pub fn get_untyped_retval(&self) -> UntypedRetVal {
-        let gp = self.get_retval_gp(0);
-        let fp = self.get_retval_fp();
-        UntypedRetVal::new(gp, fp)
+        let gp = [self.get_retval_gp(0), self.get_retval_gp(1)];
+        let fp = [self.get_retval_fp_ix(0), self.get_retval_fp_ix(1)];
+        UntypedRetVal::new_multi(gp, fp)
     }
 
     /// Put one of the first 8 floating-point arguments into a `Context` register.
     ///
     /// - `ix`: ABI floating-point argument number
     /// - `arg`: argument value
+    #[cfg(target_arch = "x86_64")]
     fn bootstrap_fp_ix_arg(&mut self, ix: usize, arg: __m128) {
         match ix {
             0 => self.fpr.xmm0 = arg,
@@ -668,6 +852,18 @@ impl Context {
             _ => panic!("unexpected fp register index {}", ix),
         }
     }
+
+    /// Put one of the first 8 floating-point arguments into a `Context` register.
+    ///
+    /// - `ix`: ABI floating-point argument number
+    /// - `arg`: argument value
+    #[cfg(target_arch = "aarch64")]
+    fn bootstrap_fp_ix_arg(&mut self, ix: usize, arg: __m128) {
+        match self.fpr.args.get_mut(ix) {
+            Some(reg) => *reg = arg,
+            None => panic!("unexpected fp register index {}", ix),
+        }
+    }
 }
 
 /// Errors that may arise when working with contexts.
@@ -676,6 +872,9 @@ pub enum Error {
     /// Raised when the bottom of the stack provided to `Context::init` is not 16-byte aligned
     #[error("context initialized with unaligned stack")]
     UnalignedStack,
+    /// Raised when a `v128` argument to `Context::init` would have to be passed on the stack
+    #[error("v128 arguments must be passed in registers")]
+    SpilledV128,
 }
 
 /// Check whether the bottom (highest address) of the stack is 16-byte aligned, as required by the
@@ -690,8 +889,9 @@ fn stack_is_aligned(stack: &[u64]) -> bool {
 extern "C" {
     /// Bootstraps arguments and calls the entrypoint via returning; implemented in assembly.
     ///
-    /// Loads general-purpose arguments from the callee-saved registers in a `Context` to the
-    /// appropriate argument registers for the AMD64 ABI, and then returns to the entrypoint.
+    /// Loads general-purpose arguments from the stack (on x86-64) or from callee-saved registers (on
+    /// AArch64) to the appropriate argument registers for the native ABI, and then jumps to the
+    /// entrypoint.
     fn lucet_context_bootstrap();
 
     /// Stores return values into the parent context, and then swaps to it; implemented in assembly.
@@ -718,9 +918,11 @@ extern "C" {
     ///   * rsi: the address of the entry callback.
     ///   * rbx: the address of the guest code to execute.
     ///
+    /// On AArch64, these are `x0`, `x1`, and `x28` respectively.
+    ///
     /// We do not actually define `lucet_context_activate` as having these arguments because we
     /// manually load these arguments, as well as a pointer to this function, into the context's
-    /// registers. See `Instance::with_activation_routine` for more information.
+    /// registers. See `Context::install_activator` for more information.
     ///
     /// Note that `rbx` is used to store the address of the guest code because it is a callee-saved
     /// register in the System V calling convention. It is also a non-violatile register on
@@ -5,11 +5,39 @@ use memoffset::offset_of;
 use std::slice;
 
 #[test]
+#[cfg(target_arch = "x86_64")]
 fn context_offsets_correct() {
     assert_eq!(offset_of!(Context, gpr), 0);
     assert_eq!(offset_of!(Context, fpr), 10 * 8);
     assert_eq!(offset_of!(Context, retvals_gp), 10 * 8 + 8 * 16);
     assert_eq!(offset_of!(Context, retval_fp), 10 * 8 + 8 * 16 + 8 * 2);
+    assert_eq!(
+        offset_of!(Context, retval_fp1),
+        10 * 8 + 8 * 16 + 8 * 2 + 16 + 8 + 8 + 8 + 8
+    );
+}
+
+/// These must match the `CTX_*` definitions in `context_asm_aarch64.S`.
+#[test]
+#[cfg(target_arch = "aarch64")]
+fn context_offsets_correct() {
+    use crate::context::{FpRegs, GpRegs};
+    assert_eq!(offset_of!(Context, gpr), 0);
+    assert_eq!(offset_of!(GpRegs, fp), 80);
+    assert_eq!(offset_of!(GpRegs, sp), 96);
+    assert_eq!(offset_of!(GpRegs, x0), 104);
+    assert_eq!(offset_of!(GpRegs, x1), 112);
+    assert_eq!(offset_of!(Context, fpr), 128);
+    assert_eq!(
+        offset_of!(Context, fpr) + offset_of!(FpRegs, callee_saved),
+        256
+    );
+    assert_eq!(offset_of!(Context, retvals_gp), 320);
+    assert_eq!(offset_of!(Context, retval_fp), 336);
+    assert_eq!(offset_of!(Context, parent_ctx), 352);
+    assert_eq!(offset_of!(Context, backstop_callback), 360);
+    assert_eq!(offset_of!(Context, callback_data), 368);
+    assert_eq!(offset_of!(Context, retval_fp1), 384);
 }
 
 #[test]
@@ -39,3 +67,30 @@ fn init_rejects_unaligned() {
         assert!(false, "init succeeded with unaligned stack");
     }
 }
+
+/// On AArch64 the bootstrap gets the entrypoint and its general-purpose arguments from
+/// callee-saved registers, and only spilled arguments go on the stack.
+#[test]
+#[cfg(target_arch = "aarch64")]
+fn init_passes_args_in_callee_saved_registers() {
+    use crate::context::lucet_context_bootstrap;
+    use crate::val::Val;
+
+    extern "C" fn dummy() {}
+    let mut stack = vec![0u64; 1024].into_boxed_slice();
+    let stack_end = unsafe { stack.as_ptr().add(stack.len()) } as u64;
+    let mut args: Vec<Val> = (1..=9).map(Val::U64).collect();
+    args.push(Val::F64(1.5));
+
+    let child = ContextHandle::create_and_init(&mut *stack, dummy as usize, &args).unwrap();
+
+    assert_eq!(&child.gpr.callee_saved[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(child.gpr.callee_saved[8], dummy as usize as u64);
+    assert_eq!(child.gpr.lr, lucet_context_bootstrap as usize as u64);
+    assert_eq!(child.gpr.fp, &*child as *const Context as u64);
+    // the ninth integer argument is the only one spilled, padded to keep `sp` 16-byte aligned
+    assert_eq!(child.gpr.sp % 16, 0);
+    assert_eq!(child.gpr.sp, stack_end - 16);
+    assert_eq!(unsafe { *(child.gpr.sp as *const u64) }, 9);
+    assert_eq!(crate::val::__m128_as_f64(child.fpr.args[0]), 1.5);
+}
@@ -3,6 +3,7 @@
 use crate::context::{Context, ContextHandle};
 use crate::val::{Val, __m128_as_f32, __m128_as_f64};
 use lazy_static::lazy_static;
+use lucet_module::ValueType;
 use std::cell::RefCell;
 use std::fmt::Write;
 use std::os::raw::{c_int, c_void};
@@ -429,6 +430,97 @@ child_n_fp_args!(child_10_fp_args, "10 args, hahaha very long boy", {
     arg10: 89.0
 });
 
+// Both x86-64 and AArch64 return a pair of floats in the first two floating-point return
+// registers, and a pair of integers in the first two general-purpose return registers.
+#[repr(C)]
+struct F64Pair(f64, f64);
+#[repr(C)]
+struct U64Pair(u64, u64);
+
+#[test]
+fn returning_two_f64s() {
+    extern "C" fn split(x: f64) -> F64Pair {
+        F64Pair(x.trunc(), x.fract())
+    }
+
+    test_body!(stack, {
+        init_and_swap!(stack, split, [Val::F64(12.5)]);
+        let retval = unsafe { CHILD.as_ref().unwrap().get_untyped_retval() }
+            .with_results(&[ValueType::F64, ValueType::F64]);
+        match retval.vals().as_slice() {
+            [Val::F64(a), Val::F64(b)] => assert_eq!((*a, *b), (12.0, 0.5)),
+            vals => panic!("unexpected return values: {:?}", vals),
+        }
+    });
+}
+
+#[test]
+fn returning_two_u64s() {
+    extern "C" fn split(x: u64) -> U64Pair {
+        U64Pair(x >> 32, x & 0xffff_ffff)
+    }
+
+    test_body!(stack, {
+        init_and_swap!(stack, split, [Val::U64(0x1234_5678_9abc_def0)]);
+        let retval = unsafe { CHILD.as_ref().unwrap().get_untyped_retval() }
+            .with_results(&[ValueType::I64, ValueType::I64]);
+        match retval.vals().as_slice() {
+            [Val::I64(a), Val::I64(b)] => assert_eq!((*a, *b), (0x1234_5678, 0x9abc_def0)),
+            vals => panic!("unexpected return values: {:?}", vals),
+        }
+    });
+}
+
+/// Nine arguments of each kind, interleaved, so that both kinds spill onto the stack on x86-64 and
+/// AArch64, and the spilled ones have to stay in argument order.
+#[test]
+fn child_mixed_spilled_args() {
+    extern "C" fn child_mixed_args_gen(
+        a1: u64,
+        b1: f64,
+        a2: u64,
+        b2: f64,
+        a3: u64,
+        b3: f64,
+        a4: u64,
+        b4: f64,
+        a5: u64,
+        b5: f64,
+        a6: u64,
+        b6: f64,
+        a7: u64,
+        b7: f64,
+        a8: u64,
+        b8: f64,
+        a9: u64,
+        b9: f64,
+    ) {
+        let mut out = OUTPUT_STRING.lock().unwrap();
+        for (a, b) in [a1, a2, a3, a4, a5, a6, a7, a8, a9]
+            .iter()
+            .zip([b1, b2, b3, b4, b5, b6, b7, b8, b9].iter())
+        {
+            write!(out, " {} {:.1}", a, b).unwrap();
+        }
+    }
+
+    test_body!(stack, {
+        unsafe {
+            let args: Vec<Val> = (1..=9)
+                .flat_map(|i| vec![Val::U64(i), Val::F64(i as f64 + 0.5)])
+                .collect();
+            let child =
+                ContextHandle::create_and_init(&mut *stack, child_mixed_args_gen as usize, &args)
+                    .unwrap();
+            CHILD = Some(child);
+
+            Context::swap(PARENT.as_mut().unwrap(), CHILD.as_mut().unwrap());
+        }
+
+        assert_output_eq!(" 1 1.5 2 2.5 3 3.5 4 4.5 5 5.5 6 6.5 7 7.5 8 8.5 9 9.5");
+    });
+}
+
 #[test]
 fn guest_realloc_string() {
     extern "C" fn guest_fn(
@@ -16,7 +16,7 @@ pub enum Error {
     ModuleError(ModuleError),
 
     /// A method call or module specification would exceed an instance's
-    /// [`Limit`s](struct.Limits.html).
+    /// [`Limit`s](struct.Limits.html), or what is left in its memory pool.
     #[error("Instance limits exceeded: {0}")]
     LimitsExceeded(String),
 
@@ -0,0 +1,205 @@
+//! Support for running instances asynchronously, and for hostcalls that await futures.
+//!
+//! Futures awaited by hostcalls are run on the host side of a yield: the guest suspends with the
+//! future as its yielded value, the host polls it with the waker of the task running the instance,
+//! and the guest is resumed with the output once the future is ready. This means that an instance
+//! waiting on I/O in a hostcall does not occupy an OS thread, and many instances can be driven by
+//! the same executor.
+
+use crate::error::Error;
+use crate::instance::{Instance, InstanceInternal, RunResult, TerminationDetails};
+use crate::module::FunctionHandle;
+use crate::val::{UntypedRetVal, Val};
+use crate::vmctx::{Vmctx, VmctxInternal};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread::{self, Thread};
+
+/// A future yielded by a hostcall to be run by the host.
+///
+/// The output of the future is type-erased so that it can be passed back to the guest with
+/// `Instance::resume_with_val()`.
+struct YieldedFuture(Pin<Box<dyn Future<Output = ResumeVal>>>);
+
+/// The output of a `YieldedFuture`, passed back to the guest when it is resumed.
+struct ResumeVal(Box<dyn Any + 'static>);
+
+impl Vmctx {
+    /// Run a future to completion from within a hostcall, returning its output.
+    ///
+    /// When the instance was run with
+    /// [`Instance::run_async()`](../instance/struct.Instance.html#method.run_async), the instance
+    /// yields back to the host while the future is pending, and is resumed with the output once the
+    /// future completes. The OS thread is therefore free to run other tasks in the meantime.
+    ///
+    /// When the instance was run synchronously, for example with
+    /// [`Instance::run()`](../instance/struct.Instance.html#method.run), this instead blocks the
+    /// current thread until the future completes. Futures that depend on a particular executor's
+    /// reactor may never complete in this case.
+    ///
+    /// If there are any live borrows of the heap view, globals view, or an embed_ctx, the function
+    /// will terminate the instance with `TerminationDetails::BorrowError`. Futures must not hold
+    /// such borrows either, as the host may modify the instance while it is suspended.
+    pub fn block_on<R: Any + 'static>(&self, f: impl Future<Output = R>) -> R {
+        if !self.instance().running_async {
+            return block_on_thread(f);
+        }
+
+        let f = async move { ResumeVal(Box::new(f.await) as Box<dyn Any + 'static>) };
+        let f: Pin<Box<dyn Future<Output = ResumeVal> + '_>> = Box::pin(f);
+        // The future may borrow from the stack of the hostcall, which lives on the guest stack. That
+        // stack is left untouched while the guest is yielded, and the future is either run to
+        // completion or dropped by `run_async()` before the guest can be resumed, so it is sound to
+        // erase the lifetime here.
+        let f = unsafe {
+            std::mem::transmute::<
+                Pin<Box<dyn Future<Output = ResumeVal> + '_>>,
+                Pin<Box<dyn Future<Output = ResumeVal> + 'static>>,
+            >(f)
+        };
+
+        let ResumeVal(val) = self.yield_val_expecting_val(YieldedFuture(f));
+        match val.downcast::<R>() {
+            Ok(val) => *val,
+            Err(_) => panic!(TerminationDetails::YieldTypeMismatch),
+        }
+    }
+}
+
+impl Instance {
+    /// Run a function with arguments in the guest context at the given entrypoint, returning a
+    /// future that completes when the guest returns.
+    ///
+    /// Hostcalls called by the guest may await futures using
+    /// [`Vmctx::block_on()`](../vmctx/struct.Vmctx.html#method.block_on). While such a future is
+    /// pending, the guest is suspended and this future returns `Poll::Pending`; the awaited future
+    /// is responsible for waking the task running the instance, as with any other future.
+    ///
+    /// Instances are not `Send`, so the returned future must be run on a single-threaded executor,
+    /// or on a thread-local task set such as `tokio::task::LocalSet`.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors that can be returned from
+    /// [`Instance::run()`](struct.Instance.html#method.run), this returns `Error::Unsupported` if
+    /// the guest yields with any of the other `Vmctx` yield methods. The instance is left yielded in
+    /// that case, and may be resumed or reset as usual.
+    ///
+    /// If the returned future is dropped while the guest is suspended, the instance is left yielded
+    /// and cannot be resumed; it must be reset before it can be run again.
+    ///
+    /// # Safety
+    ///
+    /// The same safety caveats of [`Instance::run()`](struct.Instance.html#method.run) apply.
+    pub async fn run_async(
+        &mut self,
+        entrypoint: &str,
+        args: &[Val],
+    ) -> Result<UntypedRetVal, Error> {
+        let func = self.module().get_export_func(entrypoint)?;
+        self.run_func_async(func, args).await
+    }
+
+    /// Run a function with arguments in the guest context from the [WebAssembly function
+    /// table](https://webassembly.github.io/spec/core/syntax/modules.html#tables), returning a
+    /// future that completes when the guest returns.
+    ///
+    /// # Safety
+    ///
+    /// The same safety caveats of [`Instance::run_async()`](struct.Instance.html#method.run_async)
+    /// apply.
+    pub async fn run_func_idx_async(
+        &mut self,
+        table_idx: u32,
+        func_idx: u32,
+        args: &[Val],
+    ) -> Result<UntypedRetVal, Error> {
+        let func = self.get_func_from_idx(table_idx, func_idx)?;
+        self.run_func_async(func, args).await
+    }
+
+    async fn run_func_async(
+        &mut self,
+        func: FunctionHandle,
+        args: &[Val],
+    ) -> Result<UntypedRetVal, Error> {
+        let mut res = self.with_running_async(|i| i.run_func(func, args));
+        loop {
+            match res? {
+                RunResult::Returned(retval) => return Ok(retval),
+                RunResult::Yielded(val) => match val.downcast::<YieldedFuture>() {
+                    Ok(fut) => {
+                        let val = fut.0.await;
+                        res = self.with_running_async(|i| i.resume_with_val(val));
+                    }
+                    Err(_) => {
+                        return Err(Error::Unsupported(
+                            "guests run asynchronously may only yield through `Vmctx::block_on()`"
+                                .to_string(),
+                        ));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Run `f` with the instance marked as running asynchronously, so that `Vmctx::block_on()`
+    /// yields its futures to `run_async()` rather than blocking the thread.
+    fn with_running_async<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Instance) -> R,
+    {
+        self.running_async = true;
+        let res = f(self);
+        self.running_async = false;
+        res
+    }
+}
+
+/// Run a future to completion on the current thread, parking it while the future is pending.
+fn block_on_thread<F: Future>(f: F) -> F::Output {
+    let mut f = Box::pin(f);
+    let waker = thread_waker(thread::current());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(val) => return val,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Create a `Waker` that unparks the given thread.
+fn thread_waker(thread: Thread) -> Waker {
+    let data = Arc::into_raw(Arc::new(thread)) as *const ();
+    unsafe { Waker::from_raw(RawWaker::new(data, &THREAD_WAKER_VTABLE)) }
+}
+
+static THREAD_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    thread_waker_clone,
+    thread_waker_wake,
+    thread_waker_wake_by_ref,
+    thread_waker_drop,
+);
+
+unsafe fn thread_waker_clone(data: *const ()) -> RawWaker {
+    let thread = Arc::from_raw(data as *const Thread);
+    let cloned = Arc::into_raw(thread.clone()) as *const ();
+    std::mem::forget(thread);
+    RawWaker::new(cloned, &THREAD_WAKER_VTABLE)
+}
+
+unsafe fn thread_waker_wake(data: *const ()) {
+    Arc::from_raw(data as *const Thread).unpark();
+}
+
+unsafe fn thread_waker_wake_by_ref(data: *const ()) {
+    (*(data as *const Thread)).unpark();
+}
+
+unsafe fn thread_waker_drop(data: *const ()) {
+    drop(Arc::from_raw(data as *const Thread));
+}
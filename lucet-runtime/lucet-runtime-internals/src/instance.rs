@@ -1,8 +1,12 @@
+mod backtrace;
+pub mod epoch;
 pub mod execution;
+pub mod profile;
 mod siginfo_ext;
 pub mod signals;
 pub mod state;
 
+pub use crate::instance::backtrace::Frame;
 pub use crate::instance::execution::{KillError, KillState, KillSuccess, KillSwitch};
 pub use crate::instance::signals::{signal_handler_none, SignalBehavior, SignalHandler};
 pub use crate::instance::state::State;
@@ -11,26 +15,38 @@ use crate::alloc::Alloc;
 use crate::context::Context;
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
+use crate::instance::profile::{Profile, Profiler};
 #[cfg(feature = "concurrent_testpoints")]
 use crate::lock_testpoints::LockTestpoints;
-use crate::module::{self, FunctionHandle, Global, GlobalValue, Module, TrapCode};
-use crate::region::RegionInternal;
-use crate::sysdeps::HOST_PAGE_SIZE_EXPECTED;
-use crate::val::{UntypedRetVal, Val};
+use crate::module::{
+    self, FunctionHandle, FunctionPointer, Global, GlobalValue, Module, Snapshot, TableElement,
+    TrapCode,
+};
+use crate::stats::{stack_high_water_mark, InstanceCounters, InstanceStats};
+use crate::sysdeps::{resident_pages, HOST_PAGE_SIZE_EXPECTED};
+use crate::val::{
+    results_fit_in_regs, v128_params_fit_in_regs, UntypedRetVal, Val, FP_ARG_REGS, MAX_RETURN_REGS,
+};
 use crate::WASM_PAGE_SIZE;
 use libc::{c_void, pthread_self, siginfo_t, uintptr_t};
-use lucet_module::InstanceRuntimeData;
+use lucet_module::{FunctionIndex, InstanceRuntimeData, TableElementType, TableRef};
 use memoffset::offset_of;
 use std::any::Any;
 use std::cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut, UnsafeCell};
+use std::collections::HashSet;
 use std::marker::PhantomData;
-use std::mem;
+use std::mem::{self, ManuallyDrop};
 use std::ops::{Deref, DerefMut};
 use std::ptr::{self, NonNull};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub const LUCET_INSTANCE_MAGIC: u64 = 746_932_922;
 
+/// The number of elements each table of an instance may grow to, unless set otherwise with
+/// [`InstanceBuilder::with_table_elements_limit()`](../region/struct.InstanceBuilder.html#method.with_table_elements_limit).
+pub const DEFAULT_TABLE_ELEMENTS_LIMIT: usize = 64 * 1024;
+
 thread_local! {
     /// The host context.
     ///
@@ -102,7 +118,7 @@ pub fn new_instance_handle(
 
     handle.needs_inst_drop = true;
 
-    handle.reset()?;
+    handle.reset_state()?;
 
     Ok(handle)
 }
@@ -145,20 +161,19 @@ impl Drop for InstanceHandle {
             unsafe {
                 let inst = self.inst.as_mut();
 
-                // Grab a handle to the region to ensure it outlives `inst`.
+                // Take the alloc out of the instance, so that it is dropped last.
                 //
-                // This ensures that the region won't be dropped by `inst` being
-                // dropped, which could result in `inst` being unmapped by the
-                // Region *during* drop of the Instance's fields.
-                let region: Arc<dyn RegionInternal> = inst.alloc().region.clone();
+                // The alloc owns the slot holding `inst`, and giving the slot back to the
+                // region can unmap it, as can dropping what may be the last Arc<Region>. Doing
+                // either while the rest of the Instance's fields are being dropped would free
+                // the memory out from under them.
+                let alloc = ManuallyDrop::take(&mut inst.alloc);
 
                 // drop the actual instance
                 std::ptr::drop_in_place(inst);
 
-                // and now we can drop what may be the last Arc<Region>. If it is
-                // it can safely do what it needs with memory; we're not running
-                // destructors on it anymore.
-                mem::drop(region);
+                // and now we can release the slot; we're not running destructors on it anymore.
+                mem::drop(alloc);
             }
         }
     }
@@ -232,7 +247,10 @@ pub struct Instance {
     pub lock_testpoints: Arc<LockTestpoints>,
 
     /// The memory allocated for this instance
-    alloc: Alloc,
+    ///
+    /// This is dropped by the `InstanceHandle` after the rest of the instance, since it owns the
+    /// memory the instance lives in.
+    alloc: ManuallyDrop<Alloc>,
 
     /// Handler run for signals that do not arise from a known WebAssembly trap, or that involve
     /// memory outside of the current instance.
@@ -264,6 +282,37 @@ pub struct Instance {
     /// The value passed back to the guest when resuming a yielded instance.
     pub(crate) resumed_val: Option<Box<dyn Any + 'static>>,
 
+    /// Whether the guest is being run by `Instance::run_async()`, in which case hostcalls yield the
+    /// futures they await rather than blocking the thread.
+    pub(crate) running_async: bool,
+
+    /// What happens when the guest runs out of fuel.
+    out_of_fuel_behavior: OutOfFuelBehavior,
+
+    /// The WebAssembly tables of the instance, initialized from those of the module.
+    tables: Vec<Vec<TableElement>>,
+
+    /// A pointer to and the length of each of `tables`, which is what generated code reads through
+    /// `InstanceRuntimeData::tables_ptr`.
+    ///
+    /// This must be updated whenever the length or storage of a table changes.
+    table_refs: Vec<TableRef>,
+
+    /// The data segments the guest has dropped with `data.drop`.
+    dropped_data_segments: HashSet<u32>,
+
+    /// The element segments the guest has dropped with `elem.drop`.
+    dropped_elem_segments: HashSet<u32>,
+
+    /// The number of elements each table may grow to with `table.grow`.
+    pub(crate) table_elements_limit: usize,
+
+    /// The sampling profiler, while the instance is being profiled.
+    pub(crate) profiler: Option<Profiler>,
+
+    /// The counters behind `Instance::stats()`.
+    pub(crate) counters: InstanceCounters,
+
     /// `_padding` must be the last member of the structure.
     /// This marks where the padding starts to make the structure exactly 4096 bytes long.
     /// It is also used to compute the size of the structure up to that point, i.e. without padding.
@@ -293,6 +342,10 @@ pub enum RunResult {
     /// The actual type of the contained value depends on the return type of the guest function that
     /// was called. For guest functions with no return value, it is undefined behavior to do
     /// anything with this value.
+    ///
+    /// For guest functions with multiple return values, use
+    /// [`UntypedRetVal::vals()`](../val/struct.UntypedRetVal.html#method.vals) to get all of the
+    /// values along with their types.
     Returned(UntypedRetVal),
     /// An instance yielded, potentially with a value.
     ///
@@ -444,6 +497,11 @@ impl Instance {
     /// let retval = instance.run("factorial", &[5u64.into()]).unwrap().unwrap_returned();
     /// assert_eq!(u64::from(retval), 120u64);
     ///
+    /// // functions with multiple return values yield all of them, typed by the function signature
+    /// let retval = instance.run("divmod", &[7u32.into(), 2u32.into()]).unwrap().unwrap_returned();
+    /// let vals = retval.vals();
+    /// assert_eq!(vals.len(), 2);
+    ///
     /// // runtime faults yield `Err(Error)`
     /// let result = instance.run("faulting_function", &[]);
     /// assert!(result.is_err());
@@ -482,7 +540,7 @@ impl Instance {
         func_idx: u32,
         args: &[Val],
     ) -> Result<RunResult, Error> {
-        let func = self.module.get_func_from_idx(table_idx, func_idx)?;
+        let func = self.get_func_from_idx(table_idx, func_idx)?;
         self.run_func(func, &args)
     }
 
@@ -592,6 +650,21 @@ impl Instance {
     ///
     /// [run_start]: struct.Instance.html#method.run
     pub fn reset(&mut self) -> Result<(), Error> {
+        let start = Instant::now();
+        self.reset_state()?;
+        let reset_time = start.elapsed();
+        self.counters.resets += 1;
+        self.counters.last_reset_time = Some(reset_time);
+        self.alloc.region.counters().record_reset(reset_time);
+        Ok(())
+    }
+
+    /// Reset the instance to its initial state, as when it is first created.
+    fn reset_state(&mut self) -> Result<(), Error> {
+        self.counters.peak_heap_size = self
+            .counters
+            .peak_heap_size
+            .max(self.alloc.heap_accessible_size);
         self.alloc.reset_heap(self.module.as_ref())?;
         let globals = unsafe { self.alloc.globals_mut() };
         let mod_globals = self.module.globals();
@@ -607,6 +680,16 @@ impl Instance {
             };
         }
 
+        self.tables = self
+            .module
+            .tables()
+            .into_iter()
+            .map(|table| table.to_vec())
+            .collect();
+        self.update_table_refs();
+        self.dropped_data_segments.clear();
+        self.dropped_elem_segments.clear();
+
         if self.module.get_start_func()?.is_some() {
             self.state = State::NotStarted;
         } else {
@@ -625,6 +708,37 @@ impl Instance {
         Ok(())
     }
 
+    /// Capture the instance's heap, globals, and tables in a [`Snapshot`](../module/struct.Snapshot.html).
+    ///
+    /// New instances can then be created from the snapshot with
+    /// [`InstanceBuilder::from_snapshot()`](../region/struct.InstanceBuilder.html#method.from_snapshot),
+    /// in this or any other region, without repeating the work it took to reach this state.
+    ///
+    /// The instance must be ready or not yet started; instances that are yielded, faulted, or
+    /// terminated cannot be captured. If the start function has not yet run, instances created from
+    /// the snapshot must run it with [`Instance::run_start()`][run_start] as usual.
+    ///
+    /// The embedder contexts of the instance are not captured.
+    ///
+    /// [run_start]: struct.Instance.html#method.run_start
+    pub fn snapshot(&self) -> Result<Arc<Snapshot>, Error> {
+        if !(self.state.is_ready() || self.state.is_not_started()) {
+            return Err(Error::InvalidArgument(
+                "can only snapshot an instance that is ready or not started",
+            ));
+        }
+        let snapshot = Snapshot::new(
+            self.module.clone(),
+            self.heap(),
+            self.globals(),
+            &self.tables,
+            &self.dropped_data_segments,
+            &self.dropped_elem_segments,
+            self.state.is_not_started(),
+        )?;
+        Ok(Arc::new(snapshot))
+    }
+
     /// Grow the guest memory by the given number of WebAssembly pages.
     ///
     /// On success, returns the number of pages that existed before the call.
@@ -632,12 +746,37 @@ impl Instance {
         let additional_bytes = additional_pages
             .checked_mul(WASM_PAGE_SIZE)
             .ok_or_else(|| lucet_format_err!("additional pages larger than wasm address space",))?;
+        self.counters.grow_memory_calls += 1;
+        self.alloc.region.counters().record_grow_memory();
         let orig_len = self
             .alloc
             .expand_heap(additional_bytes, self.module.as_ref())?;
         Ok(orig_len / WASM_PAGE_SIZE)
     }
 
+    /// Return statistics about the memory the instance uses and how long it took to create and
+    /// reset.
+    pub fn stats(&self) -> InstanceStats {
+        let slot = self.alloc.slot();
+        let heap_size = self.alloc.heap_accessible_size;
+        let resident_heap_pages =
+            unsafe { resident_pages(slot.heap, heap_size) }.expect("mincore succeeds on the heap");
+        let stack_high_water_mark =
+            unsafe { stack_high_water_mark(slot.stack as *const u8, slot.limits.stack_size) };
+        InstanceStats {
+            heap_size,
+            peak_heap_size: self.counters.peak_heap_size.max(heap_size),
+            resident_heap_pages: resident_heap_pages.private,
+            shared_heap_pages: resident_heap_pages.shared,
+            stack_high_water_mark,
+            grow_memory_calls: self.counters.grow_memory_calls,
+            instantiation_time: self.counters.instantiation_time,
+            resets: self.counters.resets,
+            last_reset_time: self.counters.last_reset_time,
+            page_faults: self.alloc.region.slot_page_faults(slot),
+        }
+    }
+
     /// Return the WebAssembly heap as a slice of bytes.
     pub fn heap(&self) -> &[u8] {
         unsafe { self.alloc.heap() }
@@ -674,6 +813,262 @@ impl Instance {
         self.alloc.mem_in_heap(ptr, len)
     }
 
+    /// Return the elements of a WebAssembly table, or `None` if there is no such table.
+    pub fn table(&self, table_idx: u32) -> Option<&[TableElement]> {
+        self.tables.get(table_idx as usize).map(|t| t.as_slice())
+    }
+
+    /// Get the number of elements in a WebAssembly table.
+    pub fn table_size(&self, table_idx: u32) -> Result<u32, Error> {
+        Ok(self.table_ref(table_idx)?.len() as u32)
+    }
+
+    /// Get the reference stored in a table element.
+    ///
+    /// References are represented as they are in guest code: a function pointer for elements of
+    /// `funcref` tables, or a host value for elements of `externref` tables, with `0` for null.
+    pub fn table_get(&self, table_idx: u32, elem_idx: u32) -> Result<u64, Error> {
+        self.table_ref(table_idx)?
+            .get(elem_idx as usize)
+            .map(|elem| elem.reference())
+            .ok_or(Error::InvalidArgument("table element index out of bounds"))
+    }
+
+    /// Store a reference in a table element.
+    ///
+    /// See [`Instance::table_get()`](struct.Instance.html#method.table_get) for the representation
+    /// of references.
+    pub fn table_set(&mut self, table_idx: u32, elem_idx: u32, value: u64) -> Result<(), Error> {
+        let elem = self.table_element(table_idx, value)?;
+        let slot = self.tables[table_idx as usize]
+            .get_mut(elem_idx as usize)
+            .ok_or(Error::InvalidArgument("table element index out of bounds"))?;
+        *slot = elem;
+        Ok(())
+    }
+
+    /// Store a reference in `len` table elements, starting at `dst`.
+    pub fn table_fill(
+        &mut self,
+        table_idx: u32,
+        dst: u32,
+        value: u64,
+        len: u32,
+    ) -> Result<(), Error> {
+        let elem = self.table_element(table_idx, value)?;
+        let table = &mut self.tables[table_idx as usize];
+        let end = dst as usize + len as usize;
+        if end > table.len() {
+            return Err(Error::InvalidArgument("table fill out of bounds"));
+        }
+        for slot in &mut table[dst as usize..end] {
+            *slot = elem.clone();
+        }
+        Ok(())
+    }
+
+    /// Grow a WebAssembly table by the given number of elements, each set to `init`.
+    ///
+    /// Tables may not grow beyond their declared maximum size, nor beyond the limit set with
+    /// [`InstanceBuilder::with_table_elements_limit()`](../region/struct.InstanceBuilder.html#method.with_table_elements_limit).
+    /// On success, returns the number of elements that existed before the call.
+    pub fn table_grow(&mut self, table_idx: u32, delta: u32, init: u64) -> Result<u32, Error> {
+        let elem = self.table_element(table_idx, init)?;
+        let max_len = self
+            .module
+            .tables_spec()
+            .get(table_idx as usize)
+            .and_then(|spec| spec.maximum)
+            .map(|max| std::cmp::min(max as usize, self.table_elements_limit))
+            .unwrap_or(self.table_elements_limit);
+        let table = &mut self.tables[table_idx as usize];
+        let orig_len = table.len();
+        let new_len = orig_len + delta as usize;
+        if new_len > max_len {
+            bail_limits_exceeded!(
+                "table {} would grow to {} elements, beyond its limit of {}",
+                table_idx,
+                new_len,
+                max_len
+            );
+        }
+        table.resize(new_len, elem);
+        self.update_table_refs();
+        Ok(orig_len as u32)
+    }
+
+    /// Get a handle to the function stored in an element of a WebAssembly table.
+    ///
+    /// This reflects any changes the guest has made to the table. For tables the instance does not
+    /// have, the function is looked up in the module instead.
+    pub fn get_func_from_idx(
+        &self,
+        table_idx: u32,
+        func_idx: u32,
+    ) -> Result<FunctionHandle, Error> {
+        match self.table(table_idx) {
+            Some(_) if self.table_element_type(table_idx) == TableElementType::ExternRef => {
+                Err(Error::FuncNotFound(table_idx, func_idx))
+            }
+            Some(table) => {
+                let elem = table
+                    .get(func_idx as usize)
+                    .filter(|elem| !elem.is_null())
+                    .ok_or(Error::FuncNotFound(table_idx, func_idx))?;
+                Ok(self
+                    .module
+                    .function_handle_from_ptr(elem.function_pointer()))
+            }
+            None => self.module.get_func_from_idx(table_idx, func_idx),
+        }
+    }
+
+    /// Get a reference to a guest function, as stored in `funcref` tables.
+    pub fn ref_func(&self, func_idx: u32) -> Result<u64, Error> {
+        self.module
+            .function_manifest()
+            .get(func_idx as usize)
+            .map(|func| func.ptr().as_usize() as u64)
+            .ok_or(Error::InvalidArgument("function index out of bounds"))
+    }
+
+    /// Copy `len` bytes of the heap from `src` to `dst`, as by `memory.copy`.
+    ///
+    /// The ranges may overlap. Nothing is copied if either range is out of bounds.
+    pub fn memory_copy(&mut self, dst: u32, src: u32, len: u32) -> Result<(), Error> {
+        let heap = self.heap_mut();
+        let src_end = src as usize + len as usize;
+        if src_end > heap.len() || dst as usize + len as usize > heap.len() {
+            return Err(Error::InvalidArgument("memory copy out of bounds"));
+        }
+        heap.copy_within(src as usize..src_end, dst as usize);
+        Ok(())
+    }
+
+    /// Set `len` bytes of the heap starting at `dst` to `val`, as by `memory.fill`.
+    pub fn memory_fill(&mut self, dst: u32, val: u8, len: u32) -> Result<(), Error> {
+        let heap = self.heap_mut();
+        let end = dst as usize + len as usize;
+        if end > heap.len() {
+            return Err(Error::InvalidArgument("memory fill out of bounds"));
+        }
+        for byte in &mut heap[dst as usize..end] {
+            *byte = val;
+        }
+        Ok(())
+    }
+
+    /// Copy `len` bytes of a data segment, starting at `src`, into the heap at `dst`, as by
+    /// `memory.init`.
+    ///
+    /// Active segments, and segments dropped with
+    /// [`Instance::data_drop()`](struct.Instance.html#method.data_drop), are empty.
+    pub fn memory_init(&mut self, seg_idx: u32, dst: u32, src: u32, len: u32) -> Result<(), Error> {
+        let module = self.module.clone();
+        let segment: &[u8] = if self.dropped_data_segments.contains(&seg_idx) {
+            &[]
+        } else {
+            module
+                .data_segment(seg_idx)
+                .ok_or(Error::InvalidArgument("data segment index out of bounds"))?
+        };
+        let src_end = src as usize + len as usize;
+        let dst_end = dst as usize + len as usize;
+        let heap = self.heap_mut();
+        if src_end > segment.len() || dst_end > heap.len() {
+            return Err(Error::InvalidArgument("memory init out of bounds"));
+        }
+        heap[dst as usize..dst_end].copy_from_slice(&segment[src as usize..src_end]);
+        Ok(())
+    }
+
+    /// Drop a data segment, as by `data.drop`, after which it is empty until the instance is
+    /// reset.
+    pub fn data_drop(&mut self, seg_idx: u32) -> Result<(), Error> {
+        if self.module.data_segment(seg_idx).is_none() {
+            return Err(Error::InvalidArgument("data segment index out of bounds"));
+        }
+        self.dropped_data_segments.insert(seg_idx);
+        Ok(())
+    }
+
+    /// Copy `len` elements from one WebAssembly table to another, as by `table.copy`.
+    ///
+    /// The tables may be the same, and the ranges may overlap. Nothing is copied if either range
+    /// is out of bounds.
+    pub fn table_copy(
+        &mut self,
+        dst_table_idx: u32,
+        src_table_idx: u32,
+        dst: u32,
+        src: u32,
+        len: u32,
+    ) -> Result<(), Error> {
+        let src_end = src as usize + len as usize;
+        let dst_end = dst as usize + len as usize;
+        if src_end > self.table_ref(src_table_idx)?.len()
+            || dst_end > self.table_ref(dst_table_idx)?.len()
+        {
+            return Err(Error::InvalidArgument("table copy out of bounds"));
+        }
+        let elems = self.tables[src_table_idx as usize][src as usize..src_end].to_vec();
+        self.tables[dst_table_idx as usize][dst as usize..dst_end].clone_from_slice(&elems);
+        Ok(())
+    }
+
+    /// Copy `len` functions of an element segment, starting at `src`, into a WebAssembly table at
+    /// `dst`, as by `table.init`.
+    ///
+    /// Active segments, and segments dropped with
+    /// [`Instance::elem_drop()`](struct.Instance.html#method.elem_drop), are empty.
+    pub fn table_init(
+        &mut self,
+        table_idx: u32,
+        seg_idx: u32,
+        dst: u32,
+        src: u32,
+        len: u32,
+    ) -> Result<(), Error> {
+        let module = self.module.clone();
+        let segment: &[FunctionIndex] = if self.dropped_elem_segments.contains(&seg_idx) {
+            &[]
+        } else {
+            module.elem_segment(seg_idx).ok_or(Error::InvalidArgument(
+                "element segment index out of bounds",
+            ))?
+        };
+        let src_end = src as usize + len as usize;
+        let dst_end = dst as usize + len as usize;
+        if src_end > segment.len() || dst_end > self.table_ref(table_idx)?.len() {
+            return Err(Error::InvalidArgument("table init out of bounds"));
+        }
+        let manifest = module.function_manifest();
+        let elems = segment[src as usize..src_end].iter().map(|func_idx| {
+            let sig = module.get_signature_index(*func_idx);
+            let ptr = manifest[func_idx.as_u32() as usize].ptr();
+            TableElement::new(sig.as_u32() as u64, ptr.as_usize() as u64)
+        });
+        for (slot, elem) in self.tables[table_idx as usize][dst as usize..dst_end]
+            .iter_mut()
+            .zip(elems)
+        {
+            *slot = elem;
+        }
+        Ok(())
+    }
+
+    /// Drop an element segment, as by `elem.drop`, after which it is empty until the instance is
+    /// reset.
+    pub fn elem_drop(&mut self, seg_idx: u32) -> Result<(), Error> {
+        if self.module.elem_segment(seg_idx).is_none() {
+            return Err(Error::InvalidArgument(
+                "element segment index out of bounds",
+            ));
+        }
+        self.dropped_elem_segments.insert(seg_idx);
+        Ok(())
+    }
+
     /// Check whether a context value of a particular type exists.
     pub fn contains_embed_ctx<T: Any>(&self) -> bool {
         self.embed_ctx.contains::<T>()
@@ -847,9 +1242,185 @@ impl Instance {
         None
     }
 
+    /// Set the instruction count.
+    ///
+    /// Any fuel remaining in the instance is preserved.
     #[inline]
     pub fn set_instruction_count(&mut self, instruction_count: u64) {
+        let fuel = self.get_fuel();
         self.get_instance_implicits_mut().instruction_count = instruction_count;
+        if let Some(fuel) = fuel {
+            self.set_fuel_bound(fuel);
+        }
+    }
+
+    /// Get the fuel remaining in the instance, or `None` if it has no fuel limit.
+    ///
+    /// Each WebAssembly operation executed by the guest consumes fuel according to the costs used
+    /// for instruction counting. Fuel is only accounted for in modules compiled with fuel checks
+    /// enabled.
+    pub fn get_fuel(&self) -> Option<u64> {
+        let implicits = self.get_instance_implicits();
+        if !self.module.is_fuel_checked() || implicits.instruction_count_bound == u64::MAX {
+            return None;
+        }
+        Some(
+            implicits
+                .instruction_count_bound
+                .saturating_sub(implicits.instruction_count),
+        )
+    }
+
+    /// Limit the guest to executing operations worth `fuel`, replacing any previous limit.
+    ///
+    /// What happens when the guest runs out of fuel is controlled by
+    /// [`Instance::set_out_of_fuel_behavior()`](struct.Instance.html#method.set_out_of_fuel_behavior).
+    /// The limit is checked when the instruction count is updated, at branches, calls, and the
+    /// boundaries of blocks, so a guest may run a handful of operations past its limit before it is
+    /// stopped.
+    ///
+    /// The fuel limit persists across calls to `Instance::reset()`.
+    ///
+    /// This fails with `Error::Unsupported` if the module was not compiled with fuel checks enabled.
+    pub fn set_fuel(&mut self, fuel: u64) -> Result<(), Error> {
+        if !self.module.is_fuel_checked() {
+            return Err(Error::Unsupported(
+                "fuel limits require a module compiled with fuel checks".to_string(),
+            ));
+        }
+        self.set_fuel_bound(fuel);
+        Ok(())
+    }
+
+    /// Add fuel to the instance's remaining fuel.
+    ///
+    /// This is typically used to top up an instance that has yielded with
+    /// [`OutOfFuel`](struct.OutOfFuel.html) before resuming it. Adding fuel to an instance without
+    /// a fuel limit has no effect.
+    ///
+    /// This fails with `Error::Unsupported` if the module was not compiled with fuel checks enabled.
+    pub fn add_fuel(&mut self, fuel: u64) -> Result<(), Error> {
+        if !self.module.is_fuel_checked() {
+            return Err(Error::Unsupported(
+                "fuel limits require a module compiled with fuel checks".to_string(),
+            ));
+        }
+        if let Some(remaining) = self.get_fuel() {
+            self.set_fuel_bound(remaining.saturating_add(fuel));
+        }
+        Ok(())
+    }
+
+    /// Remove the instance's fuel limit, if it has one.
+    pub fn remove_fuel_limit(&mut self) {
+        self.get_instance_implicits_mut().instruction_count_bound = u64::MAX;
+    }
+
+    /// Set what happens when the guest runs out of fuel.
+    ///
+    /// The default behavior is `OutOfFuelBehavior::Terminate`.
+    pub fn set_out_of_fuel_behavior(&mut self, behavior: OutOfFuelBehavior) {
+        self.out_of_fuel_behavior = behavior;
+    }
+
+    /// Get what happens when the guest runs out of fuel.
+    pub fn out_of_fuel_behavior(&self) -> OutOfFuelBehavior {
+        self.out_of_fuel_behavior
+    }
+
+    /// Get the epoch at which the guest will be interrupted, or `None` if it has no deadline.
+    pub fn get_epoch_deadline(&self) -> Option<u64> {
+        let deadline = self.get_instance_implicits().epoch_deadline;
+        if !self.module.is_epoch_interruptible() || deadline == u64::MAX {
+            return None;
+        }
+        Some(deadline)
+    }
+
+    /// Interrupt the guest once the epoch has advanced `ticks` times from the current epoch,
+    /// replacing any previous deadline.
+    ///
+    /// Once the deadline is reached, the guest traps with `TrapCode::Interrupt` the next time it
+    /// enters a function or loop, and the run returns `Error::RuntimeFault`. As with other
+    /// non-fatal faults, the instance can then be run again or reset; a new deadline must be set
+    /// first if the epoch has already passed the current one. Time spent in hostcalls is not
+    /// interrupted.
+    ///
+    /// See the [`epoch`](epoch/index.html) module for how to advance the epoch.
+    ///
+    /// This fails with `Error::Unsupported` if the module was not compiled with epoch
+    /// interruption enabled.
+    pub fn set_epoch_deadline(&mut self, ticks: u64) -> Result<(), Error> {
+        if !self.module.is_epoch_interruptible() {
+            return Err(Error::Unsupported(
+                "epoch deadlines require a module compiled with epoch interruption".to_string(),
+            ));
+        }
+        // saturate just short of the maximum, which means there is no deadline
+        self.get_instance_implicits_mut().epoch_deadline = epoch::current_epoch()
+            .saturating_add(ticks)
+            .min(u64::MAX - 1);
+        Ok(())
+    }
+
+    /// Remove the instance's epoch deadline, if it has one.
+    pub fn clear_epoch_deadline(&mut self) {
+        self.get_instance_implicits_mut().epoch_deadline = u64::MAX;
+    }
+
+    /// Start sampling the stack of the instance every `interval` of CPU time it uses, until
+    /// [`stop_profiling()`](#method.stop_profiling) is called.
+    ///
+    /// Samples are taken with `SIGPROF`, which the Lucet signal handler catches while the instance
+    /// runs. See the [`profile`](profile/index.html) module for how they are recorded.
+    ///
+    /// Starting to profile an instance that is already being profiled discards its profile so far.
+    /// This fails with `Error::Unsupported` on platforms other than Linux.
+    pub fn start_profiling(&mut self, interval: Duration) -> Result<(), Error> {
+        if !cfg!(target_os = "linux") {
+            return Err(Error::Unsupported(
+                "profiling is only supported on Linux".to_owned(),
+            ));
+        }
+        if interval == Duration::from_secs(0) {
+            return Err(Error::InvalidArgument(
+                "profiling interval must not be zero",
+            ));
+        }
+        self.stop_profiling();
+        self.profiler = Some(Profiler::new(interval));
+        Ok(())
+    }
+
+    /// Stop profiling the instance, returning its profile, or `None` if it was not being profiled.
+    ///
+    /// When called from a hostcall, the samples of the current run are discarded.
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        // the signal handler uses the profiler for as long as it is sampling
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.stop_timer();
+        }
+        self.profiler.take().map(Profiler::into_profile)
+    }
+
+    /// Get the profile of the instance so far, or `None` if it is not being profiled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profiler.as_ref().map(Profiler::profile)
+    }
+
+    /// Returns `true` if the guest has executed more operations than its fuel allows.
+    pub(crate) fn is_out_of_fuel(&self) -> bool {
+        let implicits = self.get_instance_implicits();
+        implicits.instruction_count > implicits.instruction_count_bound
+    }
+
+    fn set_fuel_bound(&mut self, fuel: u64) {
+        let implicits = self.get_instance_implicits_mut();
+        // saturate just short of the maximum, which means there is no limit
+        implicits.instruction_count_bound = implicits
+            .instruction_count
+            .saturating_add(fuel)
+            .min(u64::MAX - 1);
     }
 }
 
@@ -875,7 +1446,7 @@ impl Instance {
             kill_state,
             #[cfg(feature = "concurrent_testpoints")]
             lock_testpoints,
-            alloc,
+            alloc: ManuallyDrop::new(alloc),
             fatal_handler: default_fatal_handler,
             c_fatal_handler: None,
             signal_handler: Box::new(signal_handler_none) as Box<SignalHandler>,
@@ -883,14 +1454,26 @@ impl Instance {
             ensure_sigstack_installed: true,
             entrypoint: None,
             resumed_val: None,
+            running_async: false,
+            out_of_fuel_behavior: OutOfFuelBehavior::Terminate,
+            tables: vec![],
+            table_refs: vec![],
+            dropped_data_segments: HashSet::new(),
+            dropped_elem_segments: HashSet::new(),
+            table_elements_limit: DEFAULT_TABLE_ELEMENTS_LIMIT,
+            profiler: None,
+            counters: InstanceCounters::default(),
             _padding: (),
         };
         inst.set_globals_ptr(globals_ptr);
+        inst.get_instance_implicits_mut().epoch_ptr = epoch::epoch_ptr();
+        inst.clear_epoch_deadline();
+        inst.remove_fuel_limit();
         inst.set_instruction_count(0);
 
         assert_eq!(mem::size_of::<Instance>(), HOST_PAGE_SIZE_EXPECTED);
         let unpadded_size = offset_of!(Instance, _padding);
-        assert!(unpadded_size <= HOST_PAGE_SIZE_EXPECTED - mem::size_of::<*mut i64>());
+        assert!(unpadded_size <= HOST_PAGE_SIZE_EXPECTED - mem::size_of::<InstanceRuntimeData>());
         inst
     }
 
@@ -920,6 +1503,57 @@ impl Instance {
         }
     }
 
+    fn table_ref(&self, table_idx: u32) -> Result<&[TableElement], Error> {
+        self.table(table_idx)
+            .ok_or(Error::InvalidArgument("table index out of bounds"))
+    }
+
+    /// Make a table element holding `value` that is suitable for the given table.
+    ///
+    /// Elements of `funcref` tables carry the signature of the function as well, so that
+    /// `call_indirect` can check it.
+    fn table_element(&self, table_idx: u32, value: u64) -> Result<TableElement, Error> {
+        self.table_ref(table_idx)?;
+        Ok(match self.table_element_type(table_idx) {
+            _ if value == 0 => TableElement::null(),
+            TableElementType::ExternRef => TableElement::externref(value),
+            TableElementType::FuncRef => {
+                // the host may pass any value, so it is looked up rather than trusted
+                let ptr = FunctionPointer::from_usize(value as usize);
+                let func_id =
+                    self.module
+                        .function_index_from_ptr(ptr)
+                        .ok_or(Error::InvalidArgument(
+                            "function reference is not a function of the module",
+                        ))?;
+                let sig = self.module.get_signature_index(func_id);
+                TableElement::new(sig.as_u32() as u64, value)
+            }
+        })
+    }
+
+    fn table_element_type(&self, table_idx: u32) -> TableElementType {
+        self.module
+            .tables_spec()
+            .get(table_idx as usize)
+            .map(|spec| spec.element_type)
+            .unwrap_or(TableElementType::FuncRef)
+    }
+
+    /// Point the instance runtime data at the current storage of `tables`.
+    fn update_table_refs(&mut self) {
+        self.table_refs = self
+            .tables
+            .iter_mut()
+            .map(|table| TableRef {
+                elements: table.as_mut_ptr(),
+                len: table.len() as u64,
+            })
+            .collect();
+        let tables_ptr = self.table_refs.as_ptr();
+        self.get_instance_implicits_mut().tables_ptr = tables_ptr;
+    }
+
     #[allow(dead_code)]
     #[inline]
     fn get_globals_ptr(&self) -> *mut i64 {
@@ -932,7 +1566,11 @@ impl Instance {
     }
 
     /// Run a function in guest context at the given entrypoint.
-    fn run_func(&mut self, func: FunctionHandle, args: &[Val]) -> Result<RunResult, Error> {
+    pub(crate) fn run_func(
+        &mut self,
+        func: FunctionHandle,
+        args: &[Val],
+    ) -> Result<RunResult, Error> {
         let needs_start = self.state.is_not_started() && !func.is_start_func;
         if needs_start {
             return Err(Error::InstanceNeedsStart);
@@ -971,6 +1609,20 @@ impl Instance {
             }
         }
 
+        if !v128_params_fit_in_regs(&sig.params) {
+            return Err(Error::Unsupported(format!(
+                "entrypoint function passes v128 arguments beyond the first {} vector registers: {}",
+                FP_ARG_REGS, sig
+            )));
+        }
+
+        if !results_fit_in_regs(&sig.results) {
+            return Err(Error::Unsupported(format!(
+                "entrypoint function returns more than {} integer or {} floating-point values: {}",
+                MAX_RETURN_REGS, MAX_RETURN_REGS, sig
+            )));
+        }
+
         self.entrypoint = Some(func);
 
         let mut args_with_vmctx = vec![Val::from(self.alloc.slot().heap)];
@@ -1006,21 +1658,14 @@ impl Instance {
     /// (a raw pointer to the instance). This will be passed as the first argument to the entry
     /// function, which is responsible for updating the kill state's execution domain.
     ///
-    /// See `lucet_runtime_internals::context::lucet_context_activate`, and
-    /// `execution::enter_guest_region` for more info.
-    // TODO KTM 2020-03-13: This should be a method on `Context`.
+    /// On AArch64, the same arguments are passed in `x0`, `x1`, and `x28`, and the guest code
+    /// address comes from the context's link register rather than the top of the stack.
+    ///
+    /// See `lucet_runtime_internals::context::lucet_context_activate`,
+    /// `Context::install_activator`, and `execution::enter_guest_region` for more info.
     fn install_activator(&mut self) {
         unsafe {
-            // Get a raw pointer to the top of the guest stack.
-            let top_of_stack = self.ctx.gpr.rsp as *mut u64;
-            // Move the guest code address to rbx, and then put the address of the activation thunk
-            // at the top of the stack, so that we will start execution at `enter_guest_region`.
-            self.ctx.gpr.rbx = *top_of_stack;
-            *top_of_stack = crate::context::lucet_context_activate as u64;
-            // Pass a pointer to our guest-side entrypoint bootstrap code in `rsi`, and then put
-            // its first argument (a raw pointer to `self`) in `rdi`.
-            self.ctx.gpr.rsi = execution::enter_guest_region as u64;
-            self.ctx.gpr.rdi = self.ctx.callback_data_ptr() as u64;
+            self.ctx.install_activator(execution::enter_guest_region);
         }
     }
 
@@ -1106,7 +1751,11 @@ impl Instance {
 
         match st {
             State::Running => {
-                let retval = self.ctx.get_untyped_retval();
+                let results = match &self.entrypoint {
+                    Some(func) => self.module.get_signature(func.id).results.as_slice(),
+                    None => &[],
+                };
+                let retval = self.ctx.get_untyped_retval().with_results(results);
                 self.state = State::Ready;
                 Ok(RunResult::Returned(retval))
             }
@@ -1122,6 +1771,7 @@ impl Instance {
                 mut details,
                 siginfo,
                 context,
+                frame_ptr,
             } => {
                 // Sandbox is no longer runnable. It's unsafe to determine all error details in the signal
                 // handler, so we fill in extra details here.
@@ -1132,11 +1782,24 @@ impl Instance {
                     .module
                     .addr_details(details.rip_addr as *const c_void)?;
 
+                // safety: the guest stack is untouched since the fault, and stays mapped as long
+                // as the instance does
+                details.backtrace = unsafe {
+                    backtrace::walk(
+                        self.module.as_ref(),
+                        details.rip_addr,
+                        frame_ptr,
+                        self.alloc.slot().stack as usize,
+                        self.alloc.slot().stack_top() as usize,
+                    )
+                };
+
                 // fill the state back in with the updated details in case fatal handlers need it
                 self.state = State::Faulted {
                     details: details.clone(),
                     siginfo,
                     context,
+                    frame_ptr,
                 };
 
                 if details.fatal {
@@ -1207,10 +1870,19 @@ pub struct FaultDetails {
     pub fatal: bool,
     /// Information about the type of fault that occurred.
     pub trapcode: Option<TrapCode>,
+    /// The offset in the WebAssembly module of the instruction that faulted, if the module
+    /// records it.
+    pub wasm_offset: Option<u32>,
     /// The instruction pointer where the fault occurred.
     pub rip_addr: uintptr_t,
     /// Extra information about the instruction pointer's location, if available.
     pub rip_addr_details: Option<module::AddrDetails>,
+    /// The guest stack at the time of the fault, innermost frame first.
+    ///
+    /// The first frame is the faulting instruction, and the rest are the return addresses of the
+    /// guest functions that led to it. Empty until the fault has been handled and control is back
+    /// in the host.
+    pub backtrace: Vec<Frame>,
 }
 
 impl std::fmt::Display for FaultDetails {
@@ -1229,19 +1901,35 @@ impl std::fmt::Display for FaultDetails {
 
         write!(f, "code at address {:p}", self.rip_addr as *const c_void)?;
 
+        if let Some(wasm_offset) = self.wasm_offset {
+            write!(f, " (wasm offset {:#x}", wasm_offset)?;
+            match self.backtrace.first().and_then(|frame| frame.func_index) {
+                Some(func_index) => write!(f, " in function {})", func_index.as_u32())?,
+                None => write!(f, ")")?,
+            }
+        }
+
         if let Some(ref addr_details) = self.rip_addr_details {
             if let Some(ref fname) = addr_details.file_name {
                 let sname = addr_details.sym_name.as_deref().unwrap_or("<unknown>");
                 write!(f, " (symbol {}:{})", fname, sname)?;
             }
             if addr_details.in_module_code {
-                write!(f, " (inside module code)")
+                write!(f, " (inside module code)")?;
             } else {
-                write!(f, " (not inside module code)")
+                write!(f, " (not inside module code)")?;
             }
         } else {
-            write!(f, " (unknown whether in module)")
+            write!(f, " (unknown whether in module)")?;
+        }
+
+        if !self.backtrace.is_empty() {
+            write!(f, "\nguest backtrace:")?;
+            for (i, frame) in self.backtrace.iter().enumerate() {
+                write!(f, "\n  #{} {}", i, frame)?;
+            }
         }
+        Ok(())
     }
 }
 
@@ -1265,6 +1953,8 @@ pub enum TerminationDetails {
     Provided(Box<dyn Any + 'static>),
     /// The instance was terminated by its `KillSwitch`.
     Remote,
+    /// The instance ran out of fuel, and its `OutOfFuelBehavior` is `Terminate`.
+    OutOfFuel,
 }
 
 impl TerminationDetails {
@@ -1299,6 +1989,7 @@ impl PartialEq for TerminationDetails {
             (Signal, Signal) => true,
             (BorrowError(msg1), BorrowError(msg2)) => msg1 == msg2,
             (CtxNotFound, CtxNotFound) => true,
+            (OutOfFuel, OutOfFuel) => true,
             // can't compare `Any`
             _ => false,
         }
@@ -1315,6 +2006,7 @@ impl std::fmt::Debug for TerminationDetails {
             TerminationDetails::YieldTypeMismatch => write!(f, "YieldTypeMismatch"),
             TerminationDetails::Provided(_) => write!(f, "Provided(Any)"),
             TerminationDetails::Remote => write!(f, "Remote"),
+            TerminationDetails::OutOfFuel => write!(f, "OutOfFuel"),
         }
     }
 }
@@ -1369,6 +2061,26 @@ impl YieldedVal {
     }
 }
 
+/// What happens when an instance runs out of fuel.
+///
+/// See [`Instance::set_fuel()`](struct.Instance.html#method.set_fuel).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfFuelBehavior {
+    /// Terminate the instance with `TerminationDetails::OutOfFuel`.
+    Terminate,
+    /// Yield to the host with an [`OutOfFuel`](struct.OutOfFuel.html) value.
+    ///
+    /// The host may add fuel with `Instance::add_fuel()` and then resume the instance with
+    /// `Instance::resume()`. If the instance is resumed without enough fuel to continue, it yields
+    /// again.
+    Yield,
+}
+
+/// The value yielded by an instance that has run out of fuel, when its `OutOfFuelBehavior` is
+/// `Yield`.
+#[derive(Debug)]
+pub struct OutOfFuel;
+
 /// A marker value to indicate a yield or resume with no value.
 ///
 /// This exists to unify the implementations of the various operators, and should only ever be
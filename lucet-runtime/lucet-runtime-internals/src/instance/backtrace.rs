@@ -0,0 +1,251 @@
+//! Walking the guest stack after a fault.
+//!
+//! Cranelift keeps a frame pointer chain through all guest code: every function saves the frame
+//! pointer of its caller next to its return address, and points the frame pointer register at
+//! that pair. Following the chain from the frame pointer at the time of a fault recovers the
+//! return address of each guest frame without needing unwind tables.
+
+use crate::module::{FunctionIndex, Module};
+use libc::{c_void, uintptr_t};
+
+/// The most frames recorded in a backtrace, so that a corrupted chain cannot loop forever.
+const MAX_FRAMES: usize = 1024;
+
+/// A frame of the guest stack, as recorded in [`FaultDetails`](struct.FaultDetails.html).
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// The instruction pointer of this frame: the faulting instruction for the innermost frame,
+    /// and the return address for the others.
+    pub addr: uintptr_t,
+    /// The index of the function containing `addr`, if it lies in module code.
+    pub func_index: Option<FunctionIndex>,
+    /// The name the module records for that function, if any.
+    pub func_name: Option<String>,
+    /// The offset of `addr` from the start of the function, if it lies in module code.
+    pub offset: Option<u32>,
+    /// The offset in the WebAssembly module of the instruction this frame is executing: the
+    /// faulting instruction, or the call that `addr` returns from. Calls are only recorded for
+    /// modules compiled with `lucetc --call-site-offsets`.
+    pub wasm_offset: Option<u32>,
+}
+
+impl Frame {
+    /// Describe the frame at `addr`.
+    ///
+    /// A return address can be the first byte after a call at the very end of a function, so for
+    /// those the function is looked up by the address of the call instruction's last byte instead.
+    fn new(module: &dyn Module, addr: uintptr_t, is_return_addr: bool) -> Self {
+        let lookup_addr = if is_return_addr { addr - 1 } else { addr };
+        match module.lookup_function(lookup_addr as *const c_void) {
+            Some((func_index, offset)) => {
+                let offset = offset + (addr - lookup_addr) as u32;
+                let fn_spec = &module.function_manifest()[func_index.as_u32() as usize];
+                let wasm_offset = if is_return_addr {
+                    fn_spec
+                        .call_sites()
+                        .and_then(|call_sites| call_sites.lookup_addr(offset))
+                } else {
+                    fn_spec
+                        .traps()
+                        .and_then(|traps| traps.lookup_wasm_offset(offset))
+                };
+                Frame {
+                    addr,
+                    func_index: Some(func_index),
+                    func_name: module.get_function_name(func_index).map(str::to_owned),
+                    offset: Some(offset),
+                    wasm_offset,
+                }
+            }
+            None => Frame {
+                addr,
+                func_index: None,
+                func_name: None,
+                offset: None,
+                wasm_offset: None,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:p}", self.addr as *const c_void)?;
+        match (self.func_index, self.offset) {
+            (Some(func_index), Some(offset)) => {
+                let name = self.func_name.as_deref().unwrap_or("<unknown>");
+                write!(
+                    f,
+                    " in {}+{:#x} (function {}",
+                    name,
+                    offset,
+                    func_index.as_u32()
+                )?;
+                if let Some(wasm_offset) = self.wasm_offset {
+                    write!(f, ", wasm offset {:#x}", wasm_offset)?;
+                }
+                write!(f, ")")
+            }
+            _ => write!(f, " (not inside module code)"),
+        }
+    }
+}
+
+/// Walk the guest stack that occupies `stack_bottom..stack_top`, starting from a fault at `ip`
+/// with the frame pointer `fp`.
+///
+/// The innermost frame is always recorded. The walk stops at the first return address outside
+/// module code, which is normally the context bootstrap at the bottom of the guest stack, or at
+/// the first frame pointer that does not point further up the guest stack.
+///
+/// A function that faults before its prologue has saved the frame pointer, such as on a stack
+/// overflow, appears to return directly to its caller's caller, so its caller is missing from the
+/// backtrace.
+///
+/// # Safety
+///
+/// `stack_bottom..stack_top` must be readable memory holding the stack of the faulted guest.
+pub(crate) unsafe fn walk(
+    module: &dyn Module,
+    ip: uintptr_t,
+    fp: uintptr_t,
+    stack_bottom: uintptr_t,
+    stack_top: uintptr_t,
+) -> Vec<Frame> {
+    let mut frames = vec![Frame::new(module, ip, false)];
+    walk_return_addrs(fp, stack_bottom, stack_top, |ret_addr| {
+        if !returns_to_guest(module, ret_addr) {
+            return false;
+        }
+        frames.push(Frame::new(module, ret_addr, true));
+        frames.len() < MAX_FRAMES
+    });
+    frames
+}
+
+/// Whether `ret_addr` returns into module code.
+pub(crate) fn returns_to_guest(module: &dyn Module, ret_addr: uintptr_t) -> bool {
+    // a return address can be the first byte after the function that made the call
+    module
+        .lookup_function((ret_addr - 1) as *const c_void)
+        .is_some()
+}
+
+/// Call `f` with the return address of each frame on the stack that occupies
+/// `stack_bottom..stack_top`, innermost first, starting from the frame pointer `fp`, until `f`
+/// returns `false`.
+///
+/// This stops at the first frame pointer that does not point further up the stack, but does not
+/// check where the return addresses lead, so `f` must stop the walk once it leaves guest code,
+/// such as with [`returns_to_guest`](fn.returns_to_guest.html). It neither allocates nor looks at
+/// the module, so it can be used in a signal handler.
+///
+/// # Safety
+///
+/// `stack_bottom..stack_top` must be readable memory holding the stack of a guest.
+pub(crate) unsafe fn walk_return_addrs<F>(
+    fp: uintptr_t,
+    stack_bottom: uintptr_t,
+    stack_top: uintptr_t,
+    mut f: F,
+) where
+    F: FnMut(uintptr_t) -> bool,
+{
+    let word = std::mem::size_of::<usize>();
+    // the saved frame pointer and the return address must both lie on the guest stack
+    let frame_in_stack = |fp: uintptr_t| {
+        fp >= stack_bottom
+            && fp % word == 0
+            && fp
+                .checked_add(2 * word)
+                .map_or(false, |frame_end| frame_end <= stack_top)
+    };
+    if !frame_in_stack(fp) {
+        return;
+    }
+    let mut fp = fp;
+    loop {
+        let next_fp = *(fp as *const usize);
+        let ret_addr = *((fp + word) as *const usize);
+        if ret_addr == 0 || !f(ret_addr) {
+            break;
+        }
+        // the stack grows down, so callers' frames are always at higher addresses
+        if next_fp <= fp || !frame_in_stack(next_fp) {
+            break;
+        }
+        fp = next_fp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stand-in for a return address; the walk does not look at where it leads.
+    const RET_ADDR: uintptr_t = 0x1000;
+
+    fn return_addrs(
+        fp: uintptr_t,
+        stack_bottom: uintptr_t,
+        stack_top: uintptr_t,
+    ) -> Vec<uintptr_t> {
+        let mut ret_addrs = vec![];
+        unsafe {
+            walk_return_addrs(fp, stack_bottom, stack_top, |ret_addr| {
+                ret_addrs.push(ret_addr);
+                true
+            });
+        }
+        ret_addrs
+    }
+
+    #[test]
+    fn walk_follows_increasing_frame_pointers() {
+        let mut stack = [0usize; 6];
+        let base = stack.as_ptr() as usize;
+        let word = std::mem::size_of::<usize>();
+        stack[0] = base + 2 * word;
+        stack[1] = RET_ADDR;
+        stack[2] = base + 4 * word;
+        stack[3] = RET_ADDR;
+        // the outermost frame has no caller with a frame on this stack
+        stack[4] = 0;
+        stack[5] = RET_ADDR;
+        let stack_top = base + stack.len() * word;
+        assert_eq!(return_addrs(base, base, stack_top), vec![RET_ADDR; 3]);
+    }
+
+    #[test]
+    fn walk_stops_at_frame_pointer_that_does_not_increase() {
+        let mut stack = [0usize; 4];
+        let base = stack.as_ptr() as usize;
+        let word = std::mem::size_of::<usize>();
+        stack[0] = base + 2 * word;
+        stack[1] = RET_ADDR;
+        // the second frame points at itself, which would otherwise loop forever
+        stack[2] = base + 2 * word;
+        stack[3] = RET_ADDR;
+        let stack_top = base + stack.len() * word;
+        assert_eq!(return_addrs(base, base, stack_top), vec![RET_ADDR; 2]);
+    }
+
+    #[test]
+    fn walk_stops_at_frame_pointer_outside_stack() {
+        let mut stack = [0usize; 2];
+        let base = stack.as_ptr() as usize;
+        let word = std::mem::size_of::<usize>();
+        stack[0] = base + 3 * word;
+        stack[1] = RET_ADDR;
+        let stack_top = base + stack.len() * word;
+        assert_eq!(return_addrs(base, base, stack_top), vec![RET_ADDR]);
+    }
+
+    #[test]
+    fn walk_stops_at_frame_that_would_overflow() {
+        let word = std::mem::size_of::<usize>();
+        // a frame here would end past the top of the address space, so nothing may be read
+        let fp = usize::MAX - word + 1;
+        assert!(return_addrs(fp, fp, usize::MAX).is_empty());
+    }
+}
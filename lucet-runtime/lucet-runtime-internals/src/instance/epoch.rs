@@ -0,0 +1,34 @@
+//! A process-wide epoch counter for interrupting guests without signals.
+//!
+//! Modules compiled with epoch interruption check the current epoch against their instance's
+//! deadline at every function entry and loop header, trapping with `TrapCode::Interrupt` once the
+//! deadline is reached. Embedders typically run a thread that calls
+//! [`increment_epoch()`](fn.increment_epoch.html) on a fixed interval, and give each instance a
+//! deadline some number of ticks in the future with
+//! [`Instance::set_epoch_deadline()`](../struct.Instance.html#method.set_epoch_deadline).
+//!
+//! Unlike a [`KillSwitch`](../struct.KillSwitch.html), this does not rely on sending signals to
+//! the thread running the guest, so it does not interfere with other users of signals in the host
+//! process, and it works the same way on every platform.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Get the current epoch.
+pub fn current_epoch() -> u64 {
+    EPOCH.load(Ordering::Relaxed)
+}
+
+/// Advance the epoch by one tick, returning the new epoch.
+///
+/// Guests whose deadline is at or before the new epoch trap the next time they enter a function or
+/// loop.
+pub fn increment_epoch() -> u64 {
+    EPOCH.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// The address of the epoch counter, for use by generated code.
+pub(crate) fn epoch_ptr() -> *const u64 {
+    &EPOCH as *const AtomicU64 as *const u64
+}
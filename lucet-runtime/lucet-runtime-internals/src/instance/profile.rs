@@ -0,0 +1,314 @@
+//! A sampling profiler for guest code.
+//!
+//! While an instance is profiled, a timer measuring the CPU time of the thread running it sends
+//! that thread `SIGPROF` on a fixed interval. The Lucet signal handler records the stack it
+//! interrupted, following the frame pointer chain like the backtrace of a fault. The handler
+//! cannot allocate, and looking up the function containing an address means searching the whole
+//! function manifest, so the handler only copies raw addresses into a buffer allocated when
+//! profiling starts. Those are only attributed to functions, using the module's function manifest
+//! and `ModuleInternal::addr_details()`, once the instance has stopped running, which is also
+//! when each sample is cut off at the first frame outside guest code.
+//!
+//! Profiles can be written as folded stacks, which flame graph tools such as `flamegraph.pl`,
+//! `inferno`, and `speedscope` read.
+//!
+//! See [`Instance::start_profiling()`](../struct.Instance.html#method.start_profiling).
+
+use crate::error::Error;
+use crate::instance::backtrace;
+use crate::module::{FunctionIndex, Module};
+use libc::{c_void, uintptr_t};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The most frames recorded for a sample; deeper stacks lose their outermost frames.
+const MAX_SAMPLE_DEPTH: usize = 128;
+
+/// The number of words in the sample buffer, which holds the frames of each sample plus one word
+/// for its depth.
+///
+/// Samples are attributed and the buffer emptied every time the instance stops running, so it
+/// only needs to hold the samples of a single run.
+const SAMPLE_BUFFER_WORDS: usize = 256 * 1024;
+
+/// The stacks sampled from an instance, and how often each was seen.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    /// The number of samples of each stack, which is keyed by function name, outermost first.
+    stacks: BTreeMap<Vec<String>, u64>,
+    /// The number of samples that did not fit in the sample buffer.
+    dropped: u64,
+}
+
+impl Profile {
+    /// The number of samples recorded.
+    pub fn sample_count(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+
+    /// The number of samples lost because the instance ran for too long without returning to the
+    /// host.
+    pub fn dropped_samples(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Each stack sampled, with its functions outermost first, and how many samples it was seen
+    /// in.
+    ///
+    /// Time spent in host code, including hostcalls, is recorded as a stack holding only the host
+    /// function that was interrupted, or `[host]` if that function is unknown; the guest frames
+    /// that led to a hostcall are not recorded.
+    pub fn stacks(&self) -> impl Iterator<Item = (&[String], u64)> {
+        self.stacks
+            .iter()
+            .map(|(stack, count)| (stack.as_slice(), *count))
+    }
+
+    /// Add the samples of another profile to this one, such as one taken from another instance of
+    /// the same module.
+    pub fn merge(&mut self, other: &Profile) {
+        for (stack, count) in other.stacks.iter() {
+            *self.stacks.entry(stack.clone()).or_insert(0) += count;
+        }
+        self.dropped += other.dropped;
+    }
+
+    /// Write the profile as folded stacks: one line per stack, with its functions outermost first
+    /// and separated by semicolons, followed by a space and the number of samples.
+    pub fn write_folded<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (stack, count) in self.stacks.iter() {
+            writeln!(w, "{} {}", stack.join(";"), count)?;
+        }
+        Ok(())
+    }
+}
+
+/// The profiling state of an instance.
+pub(crate) struct Profiler {
+    interval: Duration,
+    /// The samples recorded since the instance last started running. Each is the number of its
+    /// frames, followed by the addresses of those frames, innermost first: the interrupted
+    /// instruction, then the return address of each frame on the frame pointer chain, which may
+    /// continue past the outermost guest frame.
+    ///
+    /// The signal handler pushes to this without ever exceeding its capacity, so it never
+    /// reallocates.
+    samples: Vec<uintptr_t>,
+    dropped: u64,
+    profile: Profile,
+    /// The timer sampling the thread running the instance, while it runs.
+    timer: Option<ThreadTimer>,
+}
+
+impl Profiler {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Profiler {
+            interval,
+            samples: Vec::with_capacity(SAMPLE_BUFFER_WORDS),
+            dropped: 0,
+            profile: Profile::default(),
+            timer: None,
+        }
+    }
+
+    /// Start sampling the current thread, which is about to run the instance.
+    pub(crate) fn start_timer(&mut self) -> Result<(), Error> {
+        self.timer = Some(ThreadTimer::start(self.interval)?);
+        Ok(())
+    }
+
+    /// Stop sampling the current thread.
+    ///
+    /// This must happen before the profiler is dropped or its samples are collected, since the
+    /// signal handler writes to the sample buffer until then.
+    pub(crate) fn stop_timer(&mut self) {
+        self.timer = None;
+    }
+
+    /// Record a sample of the stack interrupted at `ip` with the frame pointer `fp`.
+    ///
+    /// This is called from the signal handler, so it must not allocate, and it does not look up
+    /// which functions the addresses belong to, which `collect()` does instead.
+    ///
+    /// # Safety
+    ///
+    /// `stack_bottom..stack_top` must be readable memory holding the stack of the instance.
+    pub(crate) unsafe fn record(
+        &mut self,
+        ip: uintptr_t,
+        fp: uintptr_t,
+        stack_bottom: uintptr_t,
+        stack_top: uintptr_t,
+    ) {
+        let start = self.samples.len();
+        if self.samples.capacity() - start < MAX_SAMPLE_DEPTH + 1 {
+            self.dropped += 1;
+            return;
+        }
+        self.samples.push(0);
+        self.samples.push(ip);
+        let samples = &mut self.samples;
+        backtrace::walk_return_addrs(fp, stack_bottom, stack_top, |ret_addr| {
+            samples.push(ret_addr);
+            samples.len() - start <= MAX_SAMPLE_DEPTH
+        });
+        self.samples[start] = self.samples.len() - start - 1;
+    }
+
+    /// Attribute the samples recorded since the instance last started running, and add them to
+    /// the profile.
+    pub(crate) fn collect(&mut self, module: &dyn Module) {
+        debug_assert!(self.timer.is_none(), "samples are collected while sampling");
+        let mut i = 0;
+        while i < self.samples.len() {
+            let depth = self.samples[i];
+            let frames = guest_frames(module, &self.samples[i + 1..i + 1 + depth]);
+            let stack = frames
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(n, addr)| frame_name(module, *addr, n > 0))
+                .collect::<Vec<_>>();
+            *self.profile.stacks.entry(stack).or_insert(0) += 1;
+            i += depth + 1;
+        }
+        self.samples.clear();
+        self.profile.dropped += self.dropped;
+        self.dropped = 0;
+    }
+
+    pub(crate) fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub(crate) fn into_profile(self) -> Profile {
+        debug_assert!(self.timer.is_none(), "profiler is dropped while sampling");
+        self.profile
+    }
+}
+
+/// The frames of a sample that belong to its stack: the interrupted instruction, followed by the
+/// return addresses up to the outermost guest frame.
+///
+/// Only guest code is guaranteed to keep the frame pointer chain, so a sample interrupted outside
+/// of it only keeps the interrupted instruction.
+fn guest_frames<'a>(module: &dyn Module, frames: &'a [uintptr_t]) -> &'a [uintptr_t] {
+    let ip = frames[0];
+    if module.lookup_function(ip as *const c_void).is_none() {
+        return &frames[..1];
+    }
+    let ret_addrs = frames[1..]
+        .iter()
+        .take_while(|ret_addr| backtrace::returns_to_guest(module, **ret_addr))
+        .count();
+    &frames[..1 + ret_addrs]
+}
+
+/// The name of the function containing `addr`, for a profile.
+///
+/// Guest functions are named from the module's function manifest when it records a name, and from
+/// the symbol table otherwise; host functions are named from the symbol table.
+fn frame_name(module: &dyn Module, addr: uintptr_t, is_return_addr: bool) -> String {
+    // a return address can be the first byte after the function that made the call
+    let lookup_addr = if is_return_addr { addr - 1 } else { addr };
+    let func_index = module
+        .lookup_function(lookup_addr as *const c_void)
+        .map(|(func_index, _)| func_index);
+    if let Some(name) = func_index.and_then(|func_index| module.get_function_name(func_index)) {
+        return name.to_owned();
+    }
+    let sym_name = module
+        .addr_details(lookup_addr as *const c_void)
+        .ok()
+        .flatten()
+        .and_then(|details| details.sym_name);
+    match (sym_name, func_index) {
+        (Some(sym_name), _) => sym_name,
+        (None, Some(func_index)) => unnamed_function(func_index),
+        (None, None) => "[host]".to_owned(),
+    }
+}
+
+fn unnamed_function(func_index: FunctionIndex) -> String {
+    format!("function[{}]", func_index.as_u32())
+}
+
+/// A timer sending `SIGPROF` to the thread that started it, every interval of CPU time the thread
+/// uses.
+#[cfg(target_os = "linux")]
+struct ThreadTimer(libc::timer_t);
+
+#[cfg(target_os = "linux")]
+impl ThreadTimer {
+    fn start(interval: Duration) -> Result<Self, Error> {
+        let mut sev: libc::sigevent = unsafe { std::mem::zeroed() };
+        sev.sigev_notify = libc::SIGEV_THREAD_ID;
+        sev.sigev_signo = libc::SIGPROF;
+        sev.sigev_notify_thread_id = unsafe { libc::syscall(libc::SYS_gettid) } as libc::c_int;
+        let mut timer = std::mem::MaybeUninit::<libc::timer_t>::uninit();
+        if unsafe {
+            libc::timer_create(libc::CLOCK_THREAD_CPUTIME_ID, &mut sev, timer.as_mut_ptr())
+        } != 0
+        {
+            return Err(Error::InternalError(io::Error::last_os_error().into()));
+        }
+        let timer = ThreadTimer(unsafe { timer.assume_init() });
+        let period = libc::timespec {
+            tv_sec: interval.as_secs() as libc::time_t,
+            tv_nsec: interval.subsec_nanos() as libc::c_long,
+        };
+        let spec = libc::itimerspec {
+            it_interval: period,
+            it_value: period,
+        };
+        if unsafe { libc::timer_settime(timer.0, 0, &spec, std::ptr::null_mut()) } != 0 {
+            return Err(Error::InternalError(io::Error::last_os_error().into()));
+        }
+        Ok(timer)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for ThreadTimer {
+    fn drop(&mut self) {
+        use nix::sys::signal::{pthread_sigmask, SigSet, SigmaskHow, Signal};
+
+        // A signal the timer already sent stays pending after the timer is deleted, and could
+        // otherwise arrive once no instance is running, or once the Lucet signal handler has been
+        // removed, so it is consumed here.
+        let mut sigprof = SigSet::empty();
+        sigprof.add(Signal::SIGPROF);
+        let mut previous_mask = SigSet::empty();
+        pthread_sigmask(
+            SigmaskHow::SIG_BLOCK,
+            Some(&sigprof),
+            Some(&mut previous_mask),
+        )
+        .expect("pthread_sigmask succeeds");
+        unsafe {
+            libc::timer_delete(self.0);
+            let no_wait = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            while libc::sigtimedwait(sigprof.as_ref(), std::ptr::null_mut(), &no_wait)
+                == libc::SIGPROF
+            {}
+        }
+        pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&previous_mask), None)
+            .expect("pthread_sigmask succeeds");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct ThreadTimer(());
+
+#[cfg(not(target_os = "linux"))]
+impl ThreadTimer {
+    fn start(_interval: Duration) -> Result<Self, Error> {
+        Err(Error::Unsupported(
+            "profiling is only supported on Linux".to_owned(),
+        ))
+    }
+}
@@ -173,8 +173,24 @@ impl Instance {
             );
         }
 
-        // run the body
-        let res = f(self);
+        // run the body, sampling its stack if the instance is being profiled
+        let res = match self
+            .profiler
+            .as_mut()
+            .map(|profiler| profiler.start_timer())
+            .transpose()
+        {
+            Ok(_) => {
+                let res = f(self);
+                // a hostcall may have stopped or started profiling in the meantime
+                if let Some(profiler) = self.profiler.as_mut() {
+                    profiler.stop_timer();
+                    profiler.collect(self.module.as_ref());
+                }
+                res
+            }
+            Err(e) => Err(e),
+        };
 
         if self.ensure_signal_handler_installed {
             decrement_lucet_signal_state();
@@ -207,7 +223,8 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
         || signal == Signal::SIGSEGV
         || signal == Signal::SIGILL
         || signal == Signal::SIGFPE
-        || signal == Signal::SIGALRM)
+        || signal == Signal::SIGALRM
+        || signal == Signal::SIGPROF)
     {
         panic!("unexpected signal in guest signal handler: {:?}", signal);
     }
@@ -219,6 +236,11 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
     let ctx = UContextPtr::new(ucontext_ptr);
     let rip = ctx.get_ip();
 
+    if signal == Signal::SIGPROF {
+        unsafe { handle_profiling_signal(ctx, signal, signum, siginfo_ptr, ucontext_ptr) };
+        return;
+    }
+
     let switch_to_host = CURRENT_INSTANCE.with(|current_instance| {
         let mut current_instance = current_instance.borrow_mut();
 
@@ -261,6 +283,7 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
         }
 
         let trapcode = inst.module.lookup_trapcode(rip);
+        let wasm_offset = inst.module.lookup_trap_wasm_offset(rip);
 
         let behavior = (inst.signal_handler)(inst, &trapcode, signum, siginfo_ptr, ucontext_ptr);
         let switch_to_host = match behavior {
@@ -311,13 +334,17 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
                         details: FaultDetails {
                             fatal: unknown_fault || outside_guard,
                             trapcode,
+                            wasm_offset,
                             rip_addr,
                             // Details set to `None` here: have to wait until `verify_trap_safety` to
                             // fill in these details, because access may not be signal safe.
                             rip_addr_details: None,
+                            // Likewise, the stack is walked later because that allocates.
+                            backtrace: Vec::new(),
                         },
                         siginfo,
                         context: ctx.into(),
+                        frame_ptr: ctx.get_fp(),
                     };
                 };
 
@@ -371,18 +398,19 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
         // We must return from the signal handler for POSIX reasons, so instead prepare the context
         // that the signal handler will resume the program as if a call were made. First, by
         // pointing the instruction pointer at `lucet_context_set`, then by preparing the argument
-        // that `lucet_context_set` should read from `rdi` - the context to switch to.
+        // that `lucet_context_set` should read from `rdi` (or `x0` on AArch64) - the context to
+        // switch to.
         //
         // NOTE: it is absolutely critical that `lucet_context_set` does not use the guest stack!
         // If it did, and the signal being handled were a segfault from reaching the guard page,
         // there would be no stack available for the function we return to. By not using stack
         // space, `lucet_context_set` is safe for use even in handling guard page faults.
         //
-        // TODO: `rdi` is only correct for SysV (unixy) calling conventions! For Windows x86_64 this
-        // would be `rcx`, with other architectures being their own question.
+        // TODO: the first argument register (`rdi` or `x0`) is only correct for SysV (unixy)
+        // calling conventions! For Windows x86_64 this would be `rcx`.
         ctx.set_ip(crate::context::lucet_context_set as *const c_void);
         HOST_CTX.with(|host_ctx| {
-            ctx.set_rdi(host_ctx.get() as u64);
+            ctx.set_first_arg(host_ctx.get() as u64);
         });
 
         #[cfg(feature = "concurrent_testpoints")]
@@ -405,6 +433,48 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
     }
 }
 
+/// Record a profiling sample of the instance running on this thread.
+///
+/// Unlike the other signals, `SIGPROF` arrives asynchronously, and can interrupt host code as well
+/// as guest code. If this thread is not running an instance that is being profiled, the signal is
+/// meant for the host, and its handler is called.
+unsafe fn handle_profiling_signal(
+    ctx: UContextPtr,
+    signal: Signal,
+    signum: c_int,
+    siginfo_ptr: *mut siginfo_t,
+    ucontext_ptr: *mut c_void,
+) {
+    let recorded = CURRENT_INSTANCE.with(|current_instance| {
+        // host code may be looking at the current instance, so it can only be borrowed shared
+        let inst_ptr = match current_instance.try_borrow() {
+            Ok(current_instance) => *current_instance,
+            // drop the sample rather than risk a panic in the handler
+            Err(_) => return true,
+        };
+        let mut inst_ptr = match inst_ptr {
+            Some(inst_ptr) => inst_ptr,
+            None => return false,
+        };
+        let inst = inst_ptr.as_mut();
+        match inst.profiler.as_mut() {
+            Some(profiler) => {
+                profiler.record(
+                    ctx.get_ip() as usize,
+                    ctx.get_fp(),
+                    inst.alloc.slot().stack as usize,
+                    inst.alloc.slot().stack_top() as usize,
+                );
+                true
+            }
+            None => false,
+        }
+    });
+    if !recorded {
+        reraise_host_signal_in_handler(signal, signum, siginfo_ptr, ucontext_ptr);
+    }
+}
+
 struct SignalState {
     counter: usize,
     saved_sigbus: SigAction,
@@ -412,6 +482,7 @@ struct SignalState {
     saved_sigill: SigAction,
     saved_sigsegv: SigAction,
     saved_sigalrm: SigAction,
+    saved_sigprof: SigAction,
     saved_panic_hook: Option<Arc<Box<dyn Fn(&panic::PanicInfo<'_>) + Sync + Send + 'static>>>,
 }
 
@@ -425,6 +496,7 @@ unsafe fn setup_guest_signal_state(ostate: &mut Option<SignalState>) {
     masked_signals.add(Signal::SIGILL);
     masked_signals.add(Signal::SIGSEGV);
     masked_signals.add(Signal::SIGALRM);
+    masked_signals.add(Signal::SIGPROF);
 
     // setup signal handlers
     let sa = SigAction::new(
@@ -437,6 +509,7 @@ unsafe fn setup_guest_signal_state(ostate: &mut Option<SignalState>) {
     let saved_sigill = sigaction(Signal::SIGILL, &sa).expect("sigaction succeeds");
     let saved_sigsegv = sigaction(Signal::SIGSEGV, &sa).expect("sigaction succeeds");
     let saved_sigalrm = sigaction(Signal::SIGALRM, &sa).expect("sigaction succeeds");
+    let saved_sigprof = sigaction(Signal::SIGPROF, &sa).expect("sigaction succeeds");
 
     let saved_panic_hook = Some(setup_guest_panic_hook());
 
@@ -447,6 +520,7 @@ unsafe fn setup_guest_signal_state(ostate: &mut Option<SignalState>) {
         saved_sigill,
         saved_sigsegv,
         saved_sigalrm,
+        saved_sigprof,
         saved_panic_hook,
     });
 }
@@ -477,6 +551,7 @@ unsafe fn restore_host_signal_state(state: &mut SignalState) {
     sigaction(Signal::SIGILL, &state.saved_sigill).expect("sigaction succeeds");
     sigaction(Signal::SIGSEGV, &state.saved_sigsegv).expect("sigaction succeeds");
     sigaction(Signal::SIGALRM, &state.saved_sigalrm).expect("sigaction succeeds");
+    sigaction(Signal::SIGPROF, &state.saved_sigprof).expect("sigaction succeeds");
 
     // restore panic hook
     drop(panic::take_hook());
@@ -502,6 +577,7 @@ unsafe fn reraise_host_signal_in_handler(
                 Signal::SIGILL => state.saved_sigill.clone(),
                 Signal::SIGSEGV => state.saved_sigsegv.clone(),
                 Signal::SIGALRM => state.saved_sigalrm.clone(),
+                Signal::SIGPROF => state.saved_sigprof.clone(),
                 sig => panic!(
                     "unexpected signal in reraise_host_signal_in_handler: {:?}",
                     sig
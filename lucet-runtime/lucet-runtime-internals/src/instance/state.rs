@@ -32,6 +32,9 @@ pub enum State {
         details: FaultDetails,
         siginfo: libc::siginfo_t,
         context: UContext,
+        /// The guest's frame pointer at the time of the fault, for walking the guest stack once
+        /// control is back in the host.
+        frame_ptr: usize,
     },
 
     /// The instance is in the process of terminating.
@@ -18,11 +18,14 @@ pub mod alloc;
 pub mod c_api;
 pub mod context;
 pub mod embed_ctx;
+pub mod future;
 pub mod instance;
 #[cfg(feature = "concurrent_testpoints")]
 pub mod lock_testpoints;
+pub mod memory_pool;
 pub mod module;
 pub mod region;
+pub mod stats;
 pub mod sysdeps;
 pub mod val;
 pub mod vmctx;
@@ -0,0 +1,85 @@
+//! Memory budgets shared by groups of instances.
+//!
+//! `Limits::heap_memory_size` bounds the heap of each instance on its own. A
+//! [`MemoryPool`](struct.MemoryPool.html) bounds the heaps of a group of instances together, such
+//! as all of the instances run on behalf of one tenant, whichever regions they are created in.
+
+use crate::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A budget of heap memory that several instances draw from.
+///
+/// An instance given a pool with
+/// [`InstanceBuilder::with_memory_pool()`](../region/struct.InstanceBuilder.html#method.with_memory_pool)
+/// or created in a region whose pool is set with
+/// [`Region::set_memory_pool()`](../region/trait.Region.html#tymethod.set_memory_pool)
+/// takes its initial heap size out of the pool when it is created, and the size of every
+/// expansion when its heap grows. Instantiation and heap growth fail with
+/// `Error::LimitsExceeded` when the pool does not have enough left. Memory goes back to the pool
+/// when a reset shrinks the heap to its initial size, and when the instance is dropped.
+///
+/// Heaps are charged for their accessible size, whether or not all of it is backed by physical
+/// memory.
+#[derive(Debug)]
+pub struct MemoryPool {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryPool {
+    /// Create a pool of `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        MemoryPool {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// The size of the pool in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of bytes currently taken out of the pool.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes left in the pool.
+    pub fn available(&self) -> usize {
+        self.limit - self.used()
+    }
+
+    /// Take `bytes` out of the pool, if that many are left.
+    pub(crate) fn reserve(&self, bytes: usize) -> Result<(), Error> {
+        let mut used = self.used.load(Ordering::Relaxed);
+        loop {
+            if bytes > self.limit - used {
+                bail_limits_exceeded!(
+                    "memory pool has {} of {} bytes left, but {} were requested",
+                    self.limit - used,
+                    self.limit,
+                    bytes
+                );
+            }
+            match self.used.compare_exchange_weak(
+                used,
+                used + bytes,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(()),
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// Return `bytes` to the pool.
+    pub(crate) fn release(&self, bytes: usize) {
+        let previous = self.used.fetch_sub(bytes, Ordering::Relaxed);
+        debug_assert!(
+            previous >= bytes,
+            "memory pool released more than it reserved"
+        );
+    }
+}
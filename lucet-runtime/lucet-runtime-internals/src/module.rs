@@ -1,12 +1,19 @@
 mod dl;
+mod elf;
+mod gdb_jit;
+mod heap_image;
 mod mock;
+mod snapshot;
 mod sparse_page_data;
 
 pub use crate::module::dl::{DlError, DlModule};
+pub use crate::module::heap_image::HeapImage;
 pub use crate::module::mock::{MockExportBuilder, MockModuleBuilder};
+pub use crate::module::snapshot::Snapshot;
 pub use lucet_module::{
     FunctionHandle, FunctionIndex, FunctionPointer, FunctionSpec, Global, GlobalSpec, GlobalValue,
-    HeapSpec, Signature, TableElement, TrapCode, TrapManifest, ValueType,
+    HeapSpec, Signature, TableElement, TableElementType, TableSpec, TrapCode, TrapManifest,
+    UniqueSignatureIndex, ValueType,
 };
 
 use crate::alloc::Limits;
@@ -33,7 +40,7 @@ pub struct AddrDetails {
 pub trait Module: ModuleInternal {
     /// Calculate the initial size in bytes of the module's Wasm globals.
     fn initial_globals_size(&self) -> usize {
-        self.globals().len() * std::mem::size_of::<u64>()
+        self.globals().len() * std::mem::size_of::<GlobalValue>()
     }
 }
 
@@ -43,6 +50,14 @@ pub trait ModuleInternal: Send + Sync {
     /// during runtime.
     fn is_instruction_count_instrumented(&self) -> bool;
 
+    /// Determine whether this module has been instrumented to check the instance's fuel limit
+    /// whenever it updates the instruction count.
+    fn is_fuel_checked(&self) -> bool;
+
+    /// Determine whether this module has been instrumented to check the instance's epoch deadline
+    /// at function entries and loop headers.
+    fn is_epoch_interruptible(&self) -> bool;
+
     fn heap_spec(&self) -> Option<&HeapSpec>;
 
     /// Get the WebAssembly globals of the module.
@@ -56,8 +71,44 @@ pub trait ModuleInternal: Send + Sync {
     /// Get the number of pages in the sparse page data.
     fn sparse_page_data_len(&self) -> usize;
 
+    /// Get an image of the initial heap that regions may map copy-on-write, if the module has one.
+    ///
+    /// The image must have the same contents as the sparse page data. Regions that do not use
+    /// heap images fall back to the sparse page data.
+    fn heap_image(&self) -> Option<&HeapImage> {
+        None
+    }
+
     /// Get the table elements from the module.
-    fn table_elements(&self) -> Result<&[TableElement], Error>;
+    fn table_elements(&self) -> Result<&[TableElement], Error> {
+        match self.tables().get(0) {
+            Some(table) => Ok(table),
+            None => Err(lucet_incorrect_module!("table 0 is not present")),
+        }
+    }
+
+    /// Get the initial elements of each of the module's tables.
+    ///
+    /// Instances copy these into tables of their own, which the guest may then modify.
+    fn tables(&self) -> Vec<&[TableElement]>;
+
+    /// Get the element types and limits of the module's tables.
+    ///
+    /// Modules compiled before tables were described this way return an empty slice; their tables
+    /// are treated as `funcref` tables without a maximum size.
+    fn tables_spec(&self) -> &[TableSpec];
+
+    /// Get the contents of a data segment, for use by `memory.init`.
+    ///
+    /// Active segments are applied when an instance is created, and are empty here. Returns
+    /// `None` if the module has no such segment.
+    fn data_segment(&self, idx: u32) -> Option<&[u8]>;
+
+    /// Get the functions of an element segment, for use by `table.init`.
+    ///
+    /// As with data segments, active segments are empty here. Returns `None` if the module has no
+    /// such segment.
+    fn elem_segment(&self, idx: u32) -> Option<&[FunctionIndex]>;
 
     fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error>;
 
@@ -71,13 +122,26 @@ pub trait ModuleInternal: Send + Sync {
 
     fn get_signature(&self, fn_id: FunctionIndex) -> &Signature;
 
+    /// Get the unique signature index of a function, as stored in table elements.
+    fn get_signature_index(&self, fn_id: FunctionIndex) -> UniqueSignatureIndex;
+
+    /// Get the human-friendly name the module records for a function, if any.
+    fn get_function_name(&self, fn_id: FunctionIndex) -> Option<&str>;
+
+    /// Look up the index of the function at `ptr`, if it is a function of the module.
+    ///
+    /// This scans the function manifest; modules with many functions should override it with a
+    /// lookup that does not.
+    fn function_index_from_ptr(&self, ptr: FunctionPointer) -> Option<FunctionIndex> {
+        self.function_manifest()
+            .iter()
+            .position(|fn_spec| fn_spec.ptr() == ptr)
+            .map(|fn_id| FunctionIndex::from_u32(fn_id as u32))
+    }
+
     fn function_handle_from_ptr(&self, ptr: FunctionPointer) -> FunctionHandle {
         let id = self
-            .function_manifest()
-            .iter()
-            .enumerate()
-            .find(|(_, fn_spec)| fn_spec.ptr() == ptr)
-            .map(|(fn_id, _)| FunctionIndex::from_u32(fn_id as u32))
+            .function_index_from_ptr(ptr)
             .expect("valid function pointer");
 
         FunctionHandle {
@@ -103,6 +167,36 @@ pub trait ModuleInternal: Send + Sync {
         None
     }
 
+    /// Look up the offset in the WebAssembly module of the instruction that traps at an
+    /// instruction pointer.
+    ///
+    /// This function must be signal-safe.
+    fn lookup_trap_wasm_offset(&self, rip: *const c_void) -> Option<u32> {
+        for fn_spec in self.function_manifest() {
+            if let Some(offset) = fn_spec.relative_addr(rip as u64) {
+                return fn_spec
+                    .traps()
+                    .and_then(|traps| traps.lookup_wasm_offset(offset));
+            }
+        }
+        None
+    }
+
+    /// Look up the function containing an instruction pointer, and the offset of the pointer from
+    /// the start of that function.
+    ///
+    /// This function must be signal-safe.
+    fn lookup_function(&self, rip: *const c_void) -> Option<(FunctionIndex, u32)> {
+        self.function_manifest()
+            .iter()
+            .enumerate()
+            .find_map(|(idx, fn_spec)| {
+                fn_spec
+                    .relative_addr(rip as u64)
+                    .map(|offset| (FunctionIndex::from_u32(idx as u32), offset))
+            })
+    }
+
     /// Check that the specifications of the WebAssembly module are valid given certain `Limit`s.
     ///
     /// Returns a `Result<(), Error>` rather than a boolean in order to provide a richer accounting
@@ -150,7 +244,7 @@ pub trait ModuleInternal: Send + Sync {
             }
         }
 
-        if self.globals().len() * std::mem::size_of::<u64>() > limits.globals_size {
+        if self.globals().len() * std::mem::size_of::<GlobalValue>() > limits.globals_size {
             bail_limits_exceeded!("globals exceed limits");
         }
 
@@ -1,19 +1,26 @@
 use crate::error::Error;
-use crate::module::{AddrDetails, GlobalSpec, HeapSpec, Module, ModuleInternal, TableElement};
+use crate::module::elf::LoadedElf;
+use crate::module::gdb_jit::GdbJitRegistration;
+use crate::module::{
+    AddrDetails, GlobalSpec, HeapImage, HeapSpec, Module, ModuleInternal, TableElement, TableSpec,
+};
+use crate::sysdeps::host_page_size;
 use libc::c_void;
 use libloading::Library;
 use lucet_module::{
-    FunctionHandle, FunctionIndex, FunctionSpec, ModuleData, ModuleFeatures, ModuleSignature,
-    PublicKey, SerializedModule, Signature, VersionInfo, LUCET_MODULE_SYM,
+    FunctionHandle, FunctionIndex, FunctionPointer, FunctionSpec, ModuleData, ModuleFeatures,
+    ModuleSignature, PublicKey, SerializedModule, Signature, UniqueSignatureIndex, VersionInfo,
+    LUCET_MODULE_SYM,
 };
 use std::ffi::CStr;
-use std::mem::MaybeUninit;
+use std::mem::{self, MaybeUninit};
 use std::path::Path;
 use std::slice;
 use std::slice::from_raw_parts;
 use std::sync::Arc;
 use thiserror::Error;
 
+#[cfg(target_arch = "x86_64")]
 use raw_cpuid::CpuId;
 
 #[derive(Debug, Error)]
@@ -30,8 +37,11 @@ pub enum DlError {
         #[source]
         std::io::Error,
     ),
+    #[error("ELF: {0}")]
+    Elf(String),
 }
 
+#[cfg(target_arch = "x86_64")]
 fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error> {
     let cpuid = CpuId::new();
 
@@ -64,6 +74,10 @@ fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error>
     if module_features.popcnt && !info.has_popcnt() {
         return Err(missing_feature("POPCNT"));
     }
+    // Cranelift lowers wasm SIMD operations using SSSE3 and SSE4.1 instructions
+    if module_features.simd && !(info.has_ssse3() && info.has_sse41()) {
+        return Err(missing_feature("SIMD (SSSE3 and SSE4.1)"));
+    }
 
     if module_features.bmi1 || module_features.bmi2 {
         let info = cpuid.get_extended_feature_info().ok_or_else(|| {
@@ -93,22 +107,77 @@ fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error>
     Ok(())
 }
 
+#[cfg(target_arch = "aarch64")]
+fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error> {
+    // The optional features a module can require are all x86-64 extensions, which no AArch64
+    // module asks for. SIMD is not supported either: lucetc only lowers it for x86-64, so an
+    // AArch64 module that claims to use it cannot have been compiled correctly.
+    let unsupported_features = [
+        ("SIMD", module_features.simd),
+        ("SSE3", module_features.sse3),
+        ("SSSE3", module_features.ssse3),
+        ("SSE4.1", module_features.sse41),
+        ("SSE4.2", module_features.sse42),
+        ("AVX", module_features.avx),
+        ("BMI1", module_features.bmi1),
+        ("BMI2", module_features.bmi2),
+        ("LZCNT", module_features.lzcnt),
+        ("POPCNT", module_features.popcnt),
+    ];
+    match unsupported_features.iter().find(|(_, required)| *required) {
+        Some((feature, _)) => Err(Error::Unsupported(format!(
+            "Module requires feature host does not support: {}",
+            feature
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Where the code and data of a [`DlModule`](struct.DlModule.html) live.
+enum Backing {
+    /// A shared object opened with `dlopen(3)`.
+    Library {
+        /// A handle to the loaded object.
+        ///
+        /// This is never used after initialization, but we can't let the library close until
+        /// we're done with this module.
+        _lib: Library,
+
+        /// Base address of the dynamically-loaded module
+        fbase: *const c_void,
+    },
+    /// A shared object mapped from memory by our own loader, unmapped when the module is dropped.
+    Memory {
+        /// Tells debuggers about the object while it is mapped, if it has debug information.
+        ///
+        /// Debuggers find objects opened with `dlopen(3)` on their own.
+        _debug_registration: Option<GdbJitRegistration>,
+        elf: LoadedElf,
+    },
+}
+
 /// A Lucet module backed by a dynamically-loaded shared object.
+///
+/// On Linux, the initial contents of the heap are loaded into a `HeapImage` along with the module,
+/// which `MmapRegion` and `UffdRegion` map copy-on-write into each instance rather than copying the
+/// data in.
 pub struct DlModule {
-    /// A handle to the loaded object.
-    ///
-    /// This is never used after initialization, but we can't let the library close until we're done
-    /// with this module.
-    _lib: Library,
-
-    /// Base address of the dynamically-loaded module
-    fbase: *const c_void,
+    /// The loaded object, which must outlive the references in `module`.
+    backing: Backing,
 
     /// Metadata decoded from inside the module
     module: lucet_module::Module<'static>,
+
+    /// An image of the initial heap, built from the sparse page data when the module is loaded.
+    heap_image: Option<HeapImage>,
+
+    /// The address and index of each function in the function manifest, sorted by address, so
+    /// that function references passed in by the host can be checked without scanning the
+    /// manifest.
+    functions_by_ptr: Vec<(usize, FunctionIndex)>,
 }
 
-// for the one raw pointer only
+// for the raw pointers in `Backing` only
 unsafe impl Send for DlModule {}
 unsafe impl Sync for DlModule {}
 
@@ -124,6 +193,55 @@ impl DlModule {
         Self::load_and_maybe_verify(so_path, Some(pk))
     }
 
+    /// Create a module from the contents of a shared object produced by `lucetc`, without going
+    /// through `dlopen(3)` or the filesystem.
+    ///
+    /// The object is mapped and relocated by a minimal built-in loader, and unmapped once the last
+    /// reference to the module is dropped. Undefined symbols such as hostcalls are resolved
+    /// against the current executable, as they would be for [`load`](#method.load).
+    ///
+    /// If the object has debug information, such as from `lucetc --debug-info`, the module is
+    /// registered with debuggers through the GDB JIT interface while it is loaded, so that gdb and
+    /// lldb can find its functions and source lines.
+    pub fn load_from_bytes(bytes: &[u8]) -> Result<Arc<Self>, Error> {
+        Self::load_from_bytes_and_maybe_verify(bytes, None)
+    }
+
+    /// Create a module from the contents of a shared object, verifying it using a public key.
+    ///
+    /// Unlike [`load_and_verify`](#method.load_and_verify), the signature is checked against
+    /// exactly the bytes that get loaded.
+    pub fn load_from_bytes_and_verify(bytes: &[u8], pk: PublicKey) -> Result<Arc<Self>, Error> {
+        Self::load_from_bytes_and_maybe_verify(bytes, Some(pk))
+    }
+
+    fn load_from_bytes_and_maybe_verify(
+        bytes: &[u8],
+        pk: Option<PublicKey>,
+    ) -> Result<Arc<Self>, Error> {
+        let elf = LoadedElf::load(bytes)?;
+        let debug_registration = elf.debug_image(bytes)?.map(GdbJitRegistration::new);
+        let serialized_module_ptr = elf
+            .data_symbol(LUCET_MODULE_SYM, mem::size_of::<SerializedModule>())?
+            .ok_or_else(|| {
+                lucet_incorrect_module!("required symbol `{}` not found", LUCET_MODULE_SYM)
+            })? as *const SerializedModule;
+        let serialized_module: &'static SerializedModule =
+            unsafe { serialized_module_ptr.as_ref().unwrap() };
+        check_serialized_module_bounds(&elf, serialized_module)?;
+
+        let backing = Backing::Memory {
+            _debug_registration: debug_registration,
+            elf,
+        };
+        Self::from_serialized_module(backing, serialized_module, |module_data| {
+            if let Some(pk) = pk {
+                ModuleSignature::verify_bytes(bytes, &pk, module_data)?;
+            }
+            Ok(())
+        })
+    }
+
     fn load_and_maybe_verify<P: AsRef<Path>>(
         so_path: P,
         pk: Option<PublicKey>,
@@ -135,16 +253,48 @@ impl DlModule {
         let abs_so_path = so_path.as_ref().canonicalize().map_err(DlError::Io)?;
         let lib = Library::new(abs_so_path.as_os_str()).map_err(DlError::Loading)?;
 
-        let serialized_module_ptr = unsafe {
-            lib.get::<*const SerializedModule>(LUCET_MODULE_SYM.as_bytes())
+        let serialized_module_ptr: *const SerializedModule = unsafe {
+            *lib.get::<*const SerializedModule>(LUCET_MODULE_SYM.as_bytes())
                 .map_err(|e| {
                     lucet_incorrect_module!("error loading required symbol `lucet_module`: {}", e)
                 })?
         };
 
-        let serialized_module: &SerializedModule =
+        let serialized_module: &'static SerializedModule =
             unsafe { serialized_module_ptr.as_ref().unwrap() };
 
+        let fbase = if let Some(dli) =
+            dladdr(serialized_module as *const SerializedModule as *const c_void)
+        {
+            dli.dli_fbase
+        } else {
+            std::ptr::null()
+        };
+
+        Self::from_serialized_module(
+            Backing::Library { _lib: lib, fbase },
+            serialized_module,
+            |module_data| {
+                // If a public key has been provided, verify the module signature
+                // The TOCTOU issue is unavoidable without reimplenting `dlopen(3)`; use
+                // `load_from_bytes_and_verify` to avoid it
+                if let Some(pk) = pk {
+                    ModuleSignature::verify(so_path, &pk, module_data)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Finish loading a module whose `lucet_module` symbol has been found in `backing`.
+    fn from_serialized_module<F>(
+        backing: Backing,
+        serialized_module: &'static SerializedModule,
+        verify: F,
+    ) -> Result<Arc<Self>, Error>
+    where
+        F: FnOnce(&ModuleData<'static>) -> Result<(), Error>,
+    {
         let module_version = serialized_module.version.clone();
 
         let runtime_version =
@@ -177,19 +327,7 @@ impl DlModule {
 
         check_feature_support(module_data.features())?;
 
-        // If a public key has been provided, verify the module signature
-        // The TOCTOU issue is unavoidable without reimplenting `dlopen(3)`
-        if let Some(pk) = pk {
-            ModuleSignature::verify(so_path, &pk, &module_data)?;
-        }
-
-        let fbase = if let Some(dli) =
-            dladdr(serialized_module as *const SerializedModule as *const c_void)
-        {
-            dli.dli_fbase
-        } else {
-            std::ptr::null()
-        };
+        verify(&module_data)?;
 
         if serialized_module.tables_len > std::u32::MAX as u64 {
             lucet_incorrect_module!("table segment too long: {}", serialized_module.tables_len);
@@ -212,9 +350,24 @@ impl DlModule {
             &[]
         };
 
+        let heap_image = build_heap_image(&module_data);
+
+        let mut functions_by_ptr: Vec<(usize, FunctionIndex)> = function_manifest
+            .iter()
+            .enumerate()
+            .map(|(fn_id, fn_spec)| {
+                (
+                    fn_spec.ptr().as_usize(),
+                    FunctionIndex::from_u32(fn_id as u32),
+                )
+            })
+            .collect();
+        functions_by_ptr.sort_by_key(|(ptr, _)| *ptr);
+
         Ok(Arc::new(DlModule {
-            _lib: lib,
-            fbase,
+            backing,
+            heap_image,
+            functions_by_ptr,
             module: lucet_module::Module {
                 version: module_version,
                 module_data,
@@ -225,6 +378,94 @@ impl DlModule {
     }
 }
 
+/// Check that the data a module loaded from bytes points to lies within the object it was loaded
+/// from, before any of it is read. Unlike a module loaded with `dlopen`, such a module may not have
+/// been written by `lucetc`, so malformed pointers are an error rather than undefined behavior.
+fn check_serialized_module_bounds(
+    elf: &LoadedElf,
+    serialized_module: &SerializedModule,
+) -> Result<(), Error> {
+    fn check(elf: &LoadedElf, what: &str, ptr: u64, len: u64, size: usize) -> Result<(), Error> {
+        let bytes = len.checked_mul(size as u64);
+        match bytes {
+            Some(0) => Ok(()),
+            Some(bytes) if elf.is_readable(ptr as *const c_void, bytes as usize) => Ok(()),
+            _ => Err(lucet_incorrect_module!(
+                "{} are outside the loaded segments",
+                what
+            )),
+        }
+    }
+
+    check(
+        elf,
+        "module data",
+        serialized_module.module_data_ptr,
+        serialized_module.module_data_len,
+        1,
+    )?;
+    check(
+        elf,
+        "function manifest",
+        serialized_module.function_manifest_ptr,
+        serialized_module.function_manifest_len,
+        mem::size_of::<FunctionSpec>(),
+    )?;
+    check(
+        elf,
+        "tables",
+        serialized_module.tables_ptr,
+        serialized_module.tables_len,
+        mem::size_of::<&[TableElement]>(),
+    )?;
+    if serialized_module.tables_len > 0 {
+        let tables = unsafe {
+            from_raw_parts(
+                serialized_module.tables_ptr as *const &[TableElement],
+                serialized_module.tables_len as usize,
+            )
+        };
+        for table in tables.iter() {
+            check(
+                elf,
+                "table elements",
+                table.as_ptr() as u64,
+                table.len() as u64,
+                mem::size_of::<TableElement>(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Build an image of a module's initial heap from its sparse page data, so that regions can map it
+/// into each instance rather than copying the pages in.
+///
+/// A heap image is only an optimization, so if the module has no data, if its data does not fit in
+/// the initial heap, or if an image can't be made on this platform, instances fall back to having
+/// the sparse page data copied in.
+fn build_heap_image(module_data: &ModuleData<'_>) -> Option<HeapImage> {
+    let pages = module_data.sparse_data()?.pages();
+    if pages.iter().all(|page| page.is_none()) {
+        return None;
+    }
+    let len = pages.len() * host_page_size();
+    let initial_size = module_data
+        .heap_spec()
+        .map(|h| h.initial_size as usize)
+        .unwrap_or(0);
+    if len > initial_size {
+        return None;
+    }
+    match HeapImage::new(pages, len) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            tracing::debug!("not using a heap image for module: {}", e);
+            None
+        }
+    }
+}
+
 impl Module for DlModule {}
 
 impl ModuleInternal for DlModule {
@@ -232,6 +473,14 @@ impl ModuleInternal for DlModule {
         self.module.module_data.features().instruction_count
     }
 
+    fn is_fuel_checked(&self) -> bool {
+        self.module.module_data.features().fuel_checks
+    }
+
+    fn is_epoch_interruptible(&self) -> bool {
+        self.module.module_data.features().epoch_interruption
+    }
+
     fn heap_spec(&self) -> Option<&HeapSpec> {
         self.module.module_data.heap_spec()
     }
@@ -256,11 +505,24 @@ impl ModuleInternal for DlModule {
             .unwrap_or(0)
     }
 
-    fn table_elements(&self) -> Result<&[TableElement], Error> {
-        match self.module.tables.get(0) {
-            Some(table) => Ok(table),
-            None => Err(lucet_incorrect_module!("table 0 is not present")),
-        }
+    fn heap_image(&self) -> Option<&HeapImage> {
+        self.heap_image.as_ref()
+    }
+
+    fn tables(&self) -> Vec<&[TableElement]> {
+        self.module.tables.to_vec()
+    }
+
+    fn tables_spec(&self) -> &[TableSpec] {
+        self.module.module_data.tables_spec()
+    }
+
+    fn data_segment(&self, idx: u32) -> Option<&[u8]> {
+        self.module.module_data.passive_data(idx)
+    }
+
+    fn elem_segment(&self, idx: u32) -> Option<&[FunctionIndex]> {
+        self.module.module_data.passive_elements(idx)
     }
 
     fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error> {
@@ -306,7 +568,29 @@ impl ModuleInternal for DlModule {
         self.module.function_manifest
     }
 
+    fn function_index_from_ptr(&self, ptr: FunctionPointer) -> Option<FunctionIndex> {
+        self.functions_by_ptr
+            .binary_search_by_key(&ptr.as_usize(), |(ptr, _)| *ptr)
+            .ok()
+            .map(|i| self.functions_by_ptr[i].1)
+    }
+
     fn addr_details(&self, addr: *const c_void) -> Result<Option<AddrDetails>, Error> {
+        let fbase = match self.backing {
+            Backing::Library { fbase, .. } => fbase,
+            Backing::Memory { ref elf, .. } => {
+                // the dynamic linker knows nothing about this mapping, so only look addresses
+                // outside of it up with `dladdr`
+                if elf.contains(addr) {
+                    return Ok(Some(AddrDetails {
+                        in_module_code: true,
+                        file_name: None,
+                        sym_name: elf.function_name(addr).map(|name| name.to_owned()),
+                    }));
+                }
+                std::ptr::null()
+            }
+        };
         if let Some(dli) = dladdr(addr) {
             let file_name = if dli.dli_fname.is_null() {
                 None
@@ -319,7 +603,7 @@ impl ModuleInternal for DlModule {
                 Some(unsafe { CStr::from_ptr(dli.dli_sname).to_owned().into_string()? })
             };
             Ok(Some(AddrDetails {
-                in_module_code: dli.dli_fbase as *const c_void == self.fbase,
+                in_module_code: !fbase.is_null() && dli.dli_fbase as *const c_void == fbase,
                 file_name,
                 sym_name,
             }))
@@ -331,6 +615,14 @@ impl ModuleInternal for DlModule {
     fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
         self.module.module_data.get_signature(fn_id)
     }
+
+    fn get_signature_index(&self, fn_id: FunctionIndex) -> UniqueSignatureIndex {
+        self.module.module_data.get_signature_index(fn_id)
+    }
+
+    fn get_function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.module.module_data.get_function_name(fn_id)
+    }
 }
 
 // TODO: PR to nix or libloading?
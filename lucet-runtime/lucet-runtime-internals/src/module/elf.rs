@@ -0,0 +1,636 @@
+//! A minimal loader for the ELF shared objects produced by `lucetc`.
+//!
+//! This lets [`DlModule`](../struct.DlModule.html) map a module from a buffer instead of a file,
+//! without going through `dlopen(3)`. Only what `lucetc` output needs is supported: x86-64 and
+//! AArch64 position-independent objects for the host architecture, with `RELA` relocations against
+//! local data or symbols that the host executable exports. Initializers are not run, and no unwind
+//! information is registered, so panics must not unwind through guest code (hostcalls already catch
+//! them at the boundary).
+
+use crate::error::Error;
+use crate::module::DlError;
+use crate::sysdeps::host_page_size;
+use byteorder::{ByteOrder, LittleEndian};
+#[cfg(target_arch = "aarch64")]
+use libc::c_char;
+use libc::c_void;
+use lucet_module::{LeBytes, LeBytesError};
+use nix::sys::mman::{mmap, mprotect, munmap, MapFlags, ProtFlags};
+use std::ffi::{CStr, CString};
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_DYN: u16 = 3;
+#[cfg(target_arch = "x86_64")]
+const EM_HOST: u16 = 62;
+#[cfg(target_arch = "aarch64")]
+const EM_HOST: u16 = 183;
+
+const PT_LOAD: u32 = 1;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_DYNSYM: u32 = 11;
+const SHF_ALLOC: u64 = 2;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+const STB_WEAK: u8 = 2;
+const STT_FUNC: u8 = 2;
+
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    /// Provided by libgcc and compiler-rt.
+    fn __clear_cache(begin: *mut c_char, end: *mut c_char);
+}
+
+#[cfg(target_arch = "x86_64")]
+mod reloc {
+    pub const NONE: u32 = 0;
+    pub const ABS64: u32 = 1;
+    pub const GLOB_DAT: u32 = 6;
+    pub const JUMP_SLOT: u32 = 7;
+    pub const RELATIVE: u32 = 8;
+}
+
+#[cfg(target_arch = "aarch64")]
+mod reloc {
+    pub const NONE: u32 = 0;
+    pub const ABS64: u32 = 257;
+    pub const GLOB_DAT: u32 = 1025;
+    pub const JUMP_SLOT: u32 = 1026;
+    pub const RELATIVE: u32 = 1027;
+}
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+const RELA_SIZE: usize = 24;
+
+macro_rules! elf_error {
+    ($($arg:tt)*) => {
+        Error::DlError(DlError::Elf(format!($($arg)*)))
+    }
+}
+
+impl From<LeBytesError> for Error {
+    fn from(e: LeBytesError) -> Self {
+        elf_error!("{}", e)
+    }
+}
+
+struct ProgramHeader {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+}
+
+struct SectionHeader {
+    sh_name: u32,
+    sh_type: u32,
+    sh_flags: u64,
+    sh_addr: u64,
+    sh_offset: u64,
+    sh_size: u64,
+    sh_link: u32,
+    sh_info: u32,
+    sh_entsize: u64,
+}
+
+struct RawSymbol<'a> {
+    name: &'a str,
+    info: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+/// A symbol defined by the loaded object, with its address relative to the load bias.
+struct DefinedSymbol {
+    name: String,
+    value: usize,
+    size: usize,
+    is_func: bool,
+}
+
+/// An ELF shared object mapped into this process by hand.
+///
+/// The mapping is released when this is dropped.
+pub(crate) struct LoadedElf {
+    mapping: *mut u8,
+    len: usize,
+    /// Added to the object's virtual addresses to get addresses in this process.
+    bias: usize,
+    symbols: Vec<DefinedSymbol>,
+    /// The virtual address ranges of the loaded segments that are readable.
+    readable: Vec<(u64, u64)>,
+}
+
+impl LoadedElf {
+    /// Map `bytes` as a shared object, applying its dynamic relocations and setting the page
+    /// protections its segments ask for.
+    pub(crate) fn load(bytes: &[u8]) -> Result<Self, Error> {
+        let file = LeBytes(bytes);
+        if bytes.get(0..4) != Some(&b"\x7fELF"[..]) {
+            return Err(elf_error!("not an ELF file"));
+        }
+        let ident = file.slice(0, EHDR_SIZE as u64)?;
+        if ident[4] != ELFCLASS64 || ident[5] != ELFDATA2LSB {
+            return Err(elf_error!(
+                "only 64-bit little-endian objects are supported"
+            ));
+        }
+        if file.u16(16)? != ET_DYN {
+            return Err(elf_error!("not a shared object"));
+        }
+        if file.u16(18)? != EM_HOST {
+            return Err(elf_error!("object is not for the host architecture"));
+        }
+
+        let phdrs = Self::program_headers(&file)?;
+        let shdrs = Self::section_headers(&file)?;
+
+        let page_size = host_page_size() as u64;
+        let loads = phdrs.iter().filter(|ph| ph.p_type == PT_LOAD);
+        for ph in loads.clone() {
+            segment_end(ph, page_size)?;
+        }
+        let lo = loads.clone().map(|ph| ph.p_vaddr).min();
+        let hi = loads.clone().map(|ph| ph.p_vaddr + ph.p_memsz).max();
+        let (lo, hi) = match (lo, hi) {
+            (Some(lo), Some(hi)) => (round_down(lo, page_size), round_up(hi, page_size)),
+            _ => return Err(elf_error!("object has no loadable segments")),
+        };
+        let len = (hi - lo) as usize;
+
+        let mapping = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS,
+                -1,
+                0,
+            )?
+        } as *mut u8;
+        // from here on, dropping `elf` releases the mapping on early returns
+        let mut elf = LoadedElf {
+            mapping,
+            len,
+            bias: (mapping as usize).wrapping_sub(lo as usize),
+            symbols: vec![],
+            readable: loads
+                .clone()
+                .filter(|ph| ph.p_flags & PF_R != 0)
+                .map(|ph| (ph.p_vaddr, ph.p_vaddr + ph.p_memsz))
+                .collect(),
+        };
+
+        for ph in loads {
+            if ph.p_filesz > ph.p_memsz {
+                return Err(elf_error!("segment file size exceeds its memory size"));
+            }
+            let contents = file.slice(ph.p_offset, ph.p_filesz)?;
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    contents.as_ptr(),
+                    elf.addr(ph.p_vaddr),
+                    contents.len(),
+                );
+            }
+        }
+
+        for sh in shdrs.iter() {
+            if sh.sh_type == SHT_SYMTAB || sh.sh_type == SHT_DYNSYM {
+                for sym in Self::symbols(&file, &shdrs, sh)? {
+                    if sym.shndx != SHN_UNDEF && sym.shndx != SHN_ABS && !sym.name.is_empty() {
+                        elf.symbols.push(DefinedSymbol {
+                            name: sym.name.to_owned(),
+                            value: sym.value as usize,
+                            size: sym.size as usize,
+                            is_func: sym.info & 0xf == STT_FUNC,
+                        });
+                    }
+                }
+            }
+        }
+
+        for sh in shdrs
+            .iter()
+            .filter(|sh| sh.sh_type == SHT_RELA && sh.sh_flags & SHF_ALLOC != 0)
+        {
+            elf.relocate(&file, &shdrs, sh)?;
+        }
+
+        elf.protect(&phdrs, lo, page_size)?;
+
+        Ok(elf)
+    }
+
+    /// Look up the address of a data object defined by the object, checking that it, and the `len`
+    /// bytes at its address that will be read, lie within a readable segment.
+    pub(crate) fn data_symbol(
+        &self,
+        name: &str,
+        len: usize,
+    ) -> Result<Option<*const c_void>, Error> {
+        let sym = match self.symbols.iter().find(|sym| sym.name == name) {
+            Some(sym) => sym,
+            None => return Ok(None),
+        };
+        let addr = self.bias.wrapping_add(sym.value) as *const c_void;
+        if !self.is_readable(addr, sym.size.max(len)) {
+            return Err(elf_error!(
+                "symbol `{}` is outside the loaded segments",
+                name
+            ));
+        }
+        Ok(Some(addr))
+    }
+
+    /// Whether the `len` bytes at `addr` lie within one readable segment of the loaded object.
+    pub(crate) fn is_readable(&self, addr: *const c_void, len: usize) -> bool {
+        let start = (addr as usize).wrapping_sub(self.bias) as u64;
+        match start.checked_add(len as u64) {
+            Some(end) => self
+                .readable
+                .iter()
+                .any(|&(seg_start, seg_end)| start >= seg_start && end <= seg_end),
+            None => false,
+        }
+    }
+
+    /// Whether `addr` lies within the mapped object.
+    pub(crate) fn contains(&self, addr: *const c_void) -> bool {
+        let addr = addr as usize;
+        let start = self.mapping as usize;
+        addr >= start && addr < start + self.len
+    }
+
+    /// The name of the function containing `addr`, if any.
+    pub(crate) fn function_name(&self, addr: *const c_void) -> Option<&str> {
+        let offset = (addr as usize).wrapping_sub(self.bias);
+        self.symbols
+            .iter()
+            .filter(|sym| sym.is_func)
+            .find(|sym| offset >= sym.value && offset < sym.value + sym.size)
+            .map(|sym| sym.name.as_str())
+    }
+
+    /// A copy of `bytes`, the object this was loaded from, with its addresses moved to where it is
+    /// loaded, for registering with debuggers. Returns `None` if the object has no debug
+    /// information.
+    ///
+    /// Addresses in debug information are only adjusted where the object lists them as relative
+    /// relocations in `.rela` sections that are not loaded, which is how the `lucetc` built-in
+    /// linker records them.
+    pub(crate) fn debug_image(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let file = LeBytes(bytes);
+        let shdrs = Self::section_headers(&file)?;
+        let shstrtab = shdrs
+            .get(file.u16(0x3e)? as usize)
+            .ok_or_else(|| elf_error!("section name table is out of bounds"))?;
+        let mut has_debug_info = false;
+        for sh in shdrs.iter() {
+            has_debug_info |= file.str(shstrtab.sh_offset + sh.sh_name as u64)? == ".debug_info";
+        }
+        if !has_debug_info {
+            return Ok(None);
+        }
+
+        let bias = self.bias as u64;
+        let mut image = bytes.to_vec();
+        let mut write_u64 = |offset: u64, value: u64| -> Result<(), Error> {
+            file.slice(offset, 8)?;
+            LittleEndian::write_u64(&mut image[offset as usize..], value);
+            Ok(())
+        };
+
+        let phoff = file.u64(0x20)?;
+        let phentsize = file.u16(0x36)? as u64;
+        for i in 0..file.u16(0x38)? as u64 {
+            // `p_vaddr` and `p_paddr`
+            for field in &[16, 24] {
+                let offset = phoff + i * phentsize + field;
+                write_u64(offset, file.u64(offset)?.wrapping_add(bias))?;
+            }
+        }
+
+        let shoff = file.u64(0x28)?;
+        let shentsize = file.u16(0x3a)? as u64;
+        for (i, sh) in shdrs.iter().enumerate() {
+            if sh.sh_flags & SHF_ALLOC != 0 {
+                write_u64(
+                    shoff + i as u64 * shentsize + 16,
+                    sh.sh_addr.wrapping_add(bias),
+                )?;
+            }
+        }
+
+        for sh in shdrs.iter() {
+            match sh.sh_type {
+                SHT_SYMTAB | SHT_DYNSYM => {
+                    let (entsize, count) = Self::entries(sh, SYM_SIZE)?;
+                    for i in 0..count {
+                        let sym = sh.sh_offset + i * entsize;
+                        let shndx = file.u16(sym + 6)?;
+                        if shndx != SHN_UNDEF && shndx != SHN_ABS {
+                            write_u64(sym + 8, file.u64(sym + 8)?.wrapping_add(bias))?;
+                        }
+                    }
+                }
+                SHT_RELA if sh.sh_flags & SHF_ALLOC == 0 => {
+                    let target = shdrs
+                        .get(sh.sh_info as usize)
+                        .ok_or_else(|| elf_error!("relocated section is out of bounds"))?;
+                    let (entsize, count) = Self::entries(sh, RELA_SIZE)?;
+                    for i in 0..count {
+                        let rela = sh.sh_offset + i * entsize;
+                        let r_offset = file.u64(rela)?;
+                        let r_type = (file.u64(rela + 8)? & 0xffff_ffff) as u32;
+                        let r_addend = file.u64(rela + 16)?;
+                        if r_type != reloc::RELATIVE {
+                            continue;
+                        }
+                        if r_offset.saturating_add(8) > target.sh_size {
+                            return Err(elf_error!("relocation is outside its section"));
+                        }
+                        write_u64(target.sh_offset + r_offset, bias.wrapping_add(r_addend))?;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Some(image))
+    }
+
+    fn addr(&self, vaddr: u64) -> *mut u8 {
+        self.bias.wrapping_add(vaddr as usize) as *mut u8
+    }
+
+    fn program_headers(file: &LeBytes<'_>) -> Result<Vec<ProgramHeader>, Error> {
+        let phoff = file.u64(0x20)?;
+        let phentsize = file.u16(0x36)? as u64;
+        let phnum = file.u16(0x38)? as u64;
+        if phnum > 0 && phentsize < PHDR_SIZE as u64 {
+            return Err(elf_error!("program header entries are too small"));
+        }
+        (0..phnum)
+            .map(|i| {
+                let ph = phoff + i * phentsize;
+                Ok(ProgramHeader {
+                    p_type: file.u32(ph)?,
+                    p_flags: file.u32(ph + 4)?,
+                    p_offset: file.u64(ph + 8)?,
+                    p_vaddr: file.u64(ph + 16)?,
+                    p_filesz: file.u64(ph + 32)?,
+                    p_memsz: file.u64(ph + 40)?,
+                })
+            })
+            .collect()
+    }
+
+    fn section_headers(file: &LeBytes<'_>) -> Result<Vec<SectionHeader>, Error> {
+        let shoff = file.u64(0x28)?;
+        let shentsize = file.u16(0x3a)? as u64;
+        let shnum = file.u16(0x3c)? as u64;
+        if shnum > 0 && shentsize < SHDR_SIZE as u64 {
+            return Err(elf_error!("section header entries are too small"));
+        }
+        (0..shnum)
+            .map(|i| {
+                let sh = shoff + i * shentsize;
+                Ok(SectionHeader {
+                    sh_name: file.u32(sh)?,
+                    sh_type: file.u32(sh + 4)?,
+                    sh_flags: file.u64(sh + 8)?,
+                    sh_addr: file.u64(sh + 16)?,
+                    sh_offset: file.u64(sh + 24)?,
+                    sh_size: file.u64(sh + 32)?,
+                    sh_link: file.u32(sh + 40)?,
+                    sh_info: file.u32(sh + 44)?,
+                    sh_entsize: file.u64(sh + 56)?,
+                })
+            })
+            .collect()
+    }
+
+    fn linked_section<'s>(
+        shdrs: &'s [SectionHeader],
+        sh: &SectionHeader,
+    ) -> Result<&'s SectionHeader, Error> {
+        shdrs
+            .get(sh.sh_link as usize)
+            .ok_or_else(|| elf_error!("section link {} is out of bounds", sh.sh_link))
+    }
+
+    fn entries(sh: &SectionHeader, min_size: usize) -> Result<(u64, u64), Error> {
+        let entsize = if sh.sh_entsize == 0 {
+            min_size as u64
+        } else {
+            sh.sh_entsize
+        };
+        if entsize < min_size as u64 {
+            return Err(elf_error!("section entries are too small"));
+        }
+        Ok((entsize, sh.sh_size / entsize))
+    }
+
+    fn symbols<'a>(
+        file: &LeBytes<'a>,
+        shdrs: &[SectionHeader],
+        symtab: &SectionHeader,
+    ) -> Result<Vec<RawSymbol<'a>>, Error> {
+        let strtab = Self::linked_section(shdrs, symtab)?;
+        let (entsize, count) = Self::entries(symtab, SYM_SIZE)?;
+        (0..count)
+            .map(|i| {
+                let sym = symtab.sh_offset + i * entsize;
+                Ok(RawSymbol {
+                    name: file.str(strtab.sh_offset + file.u32(sym)? as u64)?,
+                    info: file.u8(sym + 4)?,
+                    shndx: file.u16(sym + 6)?,
+                    value: file.u64(sym + 8)?,
+                    size: file.u64(sym + 16)?,
+                })
+            })
+            .collect()
+    }
+
+    fn resolve(&self, sym: &RawSymbol<'_>) -> Result<u64, Error> {
+        match sym.shndx {
+            SHN_ABS => Ok(sym.value),
+            SHN_UNDEF => {
+                // hostcalls and libcalls are provided by the current executable, just as they would
+                // be for an object loaded with `dlopen(3)`
+                let name = CString::new(sym.name)
+                    .map_err(|_| elf_error!("symbol name contains a NUL byte"))?;
+                let addr = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+                if addr.is_null() && sym.info >> 4 != STB_WEAK {
+                    let reason = unsafe {
+                        let err = libc::dlerror();
+                        if err.is_null() {
+                            "not found".to_owned()
+                        } else {
+                            CStr::from_ptr(err).to_string_lossy().into_owned()
+                        }
+                    };
+                    return Err(elf_error!("undefined symbol `{}`: {}", sym.name, reason));
+                }
+                Ok(addr as u64)
+            }
+            _ => Ok(self.bias.wrapping_add(sym.value as usize) as u64),
+        }
+    }
+
+    fn relocate(
+        &self,
+        file: &LeBytes<'_>,
+        shdrs: &[SectionHeader],
+        rela: &SectionHeader,
+    ) -> Result<(), Error> {
+        let symbols = Self::symbols(file, shdrs, Self::linked_section(shdrs, rela)?)?;
+        let (entsize, count) = Self::entries(rela, RELA_SIZE)?;
+        for i in 0..count {
+            let entry = rela.sh_offset + i * entsize;
+            let r_offset = file.u64(entry)?;
+            let r_info = file.u64(entry + 8)?;
+            let r_addend = file.u64(entry + 16)?;
+            let r_type = (r_info & 0xffff_ffff) as u32;
+            let symbol = || {
+                symbols.get((r_info >> 32) as usize).ok_or_else(|| {
+                    elf_error!("relocation symbol {} is out of bounds", r_info >> 32)
+                })
+            };
+
+            let value = match r_type {
+                reloc::NONE => continue,
+                reloc::RELATIVE => (self.bias as u64).wrapping_add(r_addend),
+                reloc::ABS64 => self.resolve(symbol()?)?.wrapping_add(r_addend),
+                // x86-64 ignores the addend of these, but AArch64 adds it
+                #[cfg(target_arch = "x86_64")]
+                reloc::GLOB_DAT | reloc::JUMP_SLOT => self.resolve(symbol()?)?,
+                #[cfg(target_arch = "aarch64")]
+                reloc::GLOB_DAT | reloc::JUMP_SLOT => {
+                    self.resolve(symbol()?)?.wrapping_add(r_addend)
+                }
+                other => return Err(elf_error!("unsupported relocation type {}", other)),
+            };
+
+            let target = self.addr(r_offset);
+            if !self.contains(target as *const c_void)
+                || !self.contains(target.wrapping_add(7) as *const c_void)
+            {
+                return Err(elf_error!("relocation at {:#x} is out of bounds", r_offset));
+            }
+            unsafe { (target as *mut u64).write_unaligned(value) };
+        }
+        Ok(())
+    }
+
+    /// Give each page the union of the protections of the segments that cover it, then make the
+    /// `PT_GNU_RELRO` range read-only now that relocations are applied.
+    fn protect(&self, phdrs: &[ProgramHeader], lo: u64, page_size: u64) -> Result<(), Error> {
+        let mut page_flags = vec![0u32; self.len / page_size as usize];
+        for ph in phdrs.iter().filter(|ph| ph.p_type == PT_LOAD) {
+            let first = (round_down(ph.p_vaddr, page_size) - lo) / page_size;
+            let last = (round_up(ph.p_vaddr + ph.p_memsz, page_size) - lo) / page_size;
+            for flags in &mut page_flags[first as usize..last as usize] {
+                *flags |= ph.p_flags;
+            }
+        }
+
+        let mut start = 0;
+        while start < page_flags.len() {
+            let flags = page_flags[start];
+            let end = page_flags[start..]
+                .iter()
+                .position(|&f| f != flags)
+                .map(|n| start + n)
+                .unwrap_or_else(|| page_flags.len());
+            let mut prot = ProtFlags::PROT_NONE;
+            if flags & PF_R != 0 {
+                prot |= ProtFlags::PROT_READ;
+            }
+            if flags & PF_W != 0 {
+                prot |= ProtFlags::PROT_WRITE;
+            }
+            if flags & PF_X != 0 {
+                prot |= ProtFlags::PROT_EXEC;
+            }
+            unsafe {
+                mprotect(
+                    self.mapping.add(start * page_size as usize) as *mut c_void,
+                    (end - start) * page_size as usize,
+                    prot,
+                )?;
+            }
+            // AArch64 does not keep the instruction cache coherent with the stores that copied the
+            // code in, so it has to be invalidated before the code runs.
+            #[cfg(target_arch = "aarch64")]
+            {
+                if flags & PF_X != 0 {
+                    unsafe {
+                        __clear_cache(
+                            self.mapping.add(start * page_size as usize) as *mut c_char,
+                            self.mapping.add(end * page_size as usize) as *mut c_char,
+                        );
+                    }
+                }
+            }
+            start = end;
+        }
+
+        for ph in phdrs.iter().filter(|ph| ph.p_type == PT_GNU_RELRO) {
+            let start = round_down(ph.p_vaddr, page_size);
+            let end = round_down(segment_end(ph, page_size)?, page_size);
+            if start < lo || end > lo + self.len as u64 {
+                return Err(elf_error!("RELRO segment is outside the loaded object"));
+            }
+            if end > start {
+                unsafe {
+                    mprotect(
+                        self.addr(start) as *mut c_void,
+                        (end - start) as usize,
+                        ProtFlags::PROT_READ,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LoadedElf {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { munmap(self.mapping as *mut c_void, self.len) } {
+            tracing::warn!("error unmapping loaded module: {}", e);
+        }
+    }
+}
+
+/// The end of a segment's memory, checking that neither it nor the page boundary it is rounded up
+/// to overflows.
+fn segment_end(ph: &ProgramHeader, page_size: u64) -> Result<u64, Error> {
+    ph.p_vaddr
+        .checked_add(ph.p_memsz)
+        .filter(|&end| end <= std::u64::MAX - page_size)
+        .ok_or_else(|| elf_error!("segment at {:#x} overflows the address space", ph.p_vaddr))
+}
+
+fn round_down(n: u64, align: u64) -> u64 {
+    n & !(align - 1)
+}
+
+fn round_up(n: u64, align: u64) -> u64 {
+    round_down(n + align - 1, align)
+}
@@ -0,0 +1,160 @@
+//! Registration of modules with debuggers through the GDB JIT interface.
+//!
+//! Debuggers find shared objects opened with `dlopen(3)` through the dynamic linker, but not those
+//! mapped by our own loader. For those, we follow the protocol in the "JIT Compilation Interface"
+//! chapter of the GDB manual, which LLDB also implements: the debugger sets a breakpoint on
+//! `__jit_debug_register_code`, and whenever it is hit, reads the object file that
+//! `__jit_debug_descriptor` points at. The object must already have its addresses adjusted to
+//! where it is loaded.
+
+use lazy_static::lazy_static;
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+use std::sync::Mutex;
+
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// The list of registered objects, which the debugger reads.
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JIT_NOACTION,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// The debugger sets a breakpoint on this function to learn about changes to the list.
+#[no_mangle]
+#[inline(never)]
+extern "C" fn __jit_debug_register_code() {
+    // keep calls from being optimized away, since the function has no other effect
+    compiler_fence(Ordering::SeqCst);
+}
+
+lazy_static! {
+    /// Serializes changes to `__jit_debug_descriptor`.
+    static ref GDB_JIT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// An object file registered with the debugger, which is unregistered when this is dropped.
+pub(crate) struct GdbJitRegistration {
+    entry: *mut JitCodeEntry,
+    /// The object file the entry points to, which must stay put until it is unregistered.
+    _image: Box<[u8]>,
+}
+
+// the entry is only touched while holding `GDB_JIT_LOCK`
+unsafe impl Send for GdbJitRegistration {}
+unsafe impl Sync for GdbJitRegistration {}
+
+impl GdbJitRegistration {
+    /// Register `image`, an object file whose addresses are those of the loaded code.
+    pub(crate) fn new(image: Vec<u8>) -> Self {
+        let image = image.into_boxed_slice();
+        let entry = Box::into_raw(Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: image.as_ptr(),
+            symfile_size: image.len() as u64,
+        }));
+
+        let _lock = GDB_JIT_LOCK.lock().unwrap();
+        unsafe {
+            let first = __jit_debug_descriptor.first_entry;
+            (*entry).next_entry = first;
+            if !first.is_null() {
+                (*first).prev_entry = entry;
+            }
+            __jit_debug_descriptor.first_entry = entry;
+            __jit_debug_descriptor.relevant_entry = entry;
+            __jit_debug_descriptor.action_flag = JIT_REGISTER_FN;
+            __jit_debug_register_code();
+            __jit_debug_descriptor.action_flag = JIT_NOACTION;
+        }
+
+        GdbJitRegistration {
+            entry,
+            _image: image,
+        }
+    }
+}
+
+impl Drop for GdbJitRegistration {
+    fn drop(&mut self) {
+        let _lock = GDB_JIT_LOCK.lock().unwrap();
+        unsafe {
+            let entry = self.entry;
+            let prev = (*entry).prev_entry;
+            let next = (*entry).next_entry;
+            if prev.is_null() {
+                __jit_debug_descriptor.first_entry = next;
+            } else {
+                (*prev).next_entry = next;
+            }
+            if !next.is_null() {
+                (*next).prev_entry = prev;
+            }
+            __jit_debug_descriptor.relevant_entry = entry;
+            __jit_debug_descriptor.action_flag = JIT_UNREGISTER_FN;
+            __jit_debug_register_code();
+            __jit_debug_descriptor.action_flag = JIT_NOACTION;
+            __jit_debug_descriptor.relevant_entry = ptr::null_mut();
+            drop(Box::from_raw(entry));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered() -> Vec<*const u8> {
+        let _lock = GDB_JIT_LOCK.lock().unwrap();
+        let mut images = vec![];
+        unsafe {
+            let mut entry = __jit_debug_descriptor.first_entry;
+            while !entry.is_null() {
+                images.push((*entry).symfile_addr);
+                entry = (*entry).next_entry;
+            }
+        }
+        images
+    }
+
+    #[test]
+    fn registrations_are_listed_until_dropped() {
+        let first = GdbJitRegistration::new(vec![1, 2, 3]);
+        let second = GdbJitRegistration::new(vec![4, 5]);
+        let (first_addr, second_addr) = (first._image.as_ptr(), second._image.as_ptr());
+        let images = registered();
+        assert!(images.contains(&first_addr));
+        assert!(images.contains(&second_addr));
+
+        drop(first);
+        let images = registered();
+        assert!(!images.contains(&first_addr));
+        assert!(images.contains(&second_addr));
+
+        drop(second);
+        assert!(!registered().contains(&second_addr));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::Error;
+use crate::sysdeps::host_page_size;
+use std::os::unix::io::RawFd;
+
+/// An image of an initial heap, held in an anonymous in-memory file.
+///
+/// Regions that support it map the image copy-on-write over the start of an instance heap, rather
+/// than copying the heap contents in page by page. Populated pages are then shared between all of
+/// the instances using the image until an instance writes to them.
+///
+/// The image also keeps a read-only view of its own contents, so that the populated pages can be
+/// read back without holding a second copy of them.
+///
+/// Heap images are backed by `memfd_create(2)`, and so are currently only available on Linux.
+pub struct HeapImage {
+    fd: RawFd,
+    len: usize,
+    /// A shared, read-only mapping of the whole image, or null while it is being constructed.
+    contents: *const u8,
+    /// Whether each host page of the image was populated when the image was created.
+    populated: Vec<bool>,
+}
+
+// The image is never written to after it is created, and the mapping of its contents is
+// read-only, so it can be shared between threads.
+unsafe impl Send for HeapImage {}
+unsafe impl Sync for HeapImage {}
+
+impl HeapImage {
+    /// Create a heap image of `len` bytes from sparse page data.
+    ///
+    /// Entries in `pages` correspond to host pages from the start of the heap. Pages that are
+    /// `None`, along with any space in the image past the end of `pages`, read as zeros.
+    #[cfg(target_os = "linux")]
+    pub fn new(pages: &[Option<&[u8]>], len: usize) -> Result<Self, Error> {
+        use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+        use nix::sys::mman::{mmap, MapFlags, ProtFlags};
+        use nix::sys::uio::pwrite;
+        use nix::unistd::ftruncate;
+        use std::ffi::CStr;
+
+        if len % host_page_size() != 0 {
+            return Err(Error::InvalidArgument(
+                "heap image size must be a multiple of host page size",
+            ));
+        }
+
+        let name = CStr::from_bytes_with_nul(b"lucet_heap_image\0").expect("valid C string");
+        let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC)?;
+        // construct the image before filling it in, so the descriptor is closed on early returns
+        let mut image = HeapImage {
+            fd,
+            len,
+            contents: std::ptr::null(),
+            populated: vec![false; len / host_page_size()],
+        };
+
+        // the file starts out as a hole, so only the populated pages take up any memory
+        ftruncate(fd, len as libc::off_t)?;
+
+        for (page_num, page) in pages.iter().enumerate() {
+            if let Some(contents) = page {
+                let offset = page_num * host_page_size();
+                if offset + contents.len() > len {
+                    return Err(lucet_incorrect_module!(
+                        "sparse page data exceeds heap image size {}",
+                        len
+                    ));
+                }
+                let mut written = 0;
+                while written < contents.len() {
+                    written += pwrite(fd, &contents[written..], (offset + written) as libc::off_t)?;
+                }
+                image.populated[page_num] = true;
+            }
+        }
+
+        if len > 0 {
+            // a shared mapping reads straight from the file, so it takes up no memory of its own
+            image.contents = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    ProtFlags::PROT_READ,
+                    MapFlags::MAP_SHARED,
+                    fd,
+                    0,
+                )?
+            } as *const u8;
+        }
+
+        Ok(image)
+    }
+
+    /// Create a heap image of `len` bytes from sparse page data.
+    ///
+    /// Heap images are not supported on this platform, so this always fails with
+    /// `Error::Unsupported`.
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_pages: &[Option<&[u8]>], _len: usize) -> Result<Self, Error> {
+        Err(Error::Unsupported(
+            "heap images require memfd_create(2)".to_string(),
+        ))
+    }
+
+    /// The file descriptor of the in-memory file backing the image.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// The size of the image in bytes; always a multiple of the host page size.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The number of host pages in the image.
+    pub fn page_count(&self) -> usize {
+        self.populated.len()
+    }
+
+    /// The contents of a host page of the image, or `None` if the page was not populated when the
+    /// image was created and so reads as zeros.
+    pub fn page(&self, page_num: usize) -> Option<&[u8]> {
+        if self.populated.get(page_num).cloned().unwrap_or(false) {
+            let offset = page_num * host_page_size();
+            Some(unsafe { std::slice::from_raw_parts(self.contents.add(offset), host_page_size()) })
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for HeapImage {
+    fn drop(&mut self) {
+        if !self.contents.is_null() {
+            if let Err(e) = unsafe { nix::sys::mman::munmap(self.contents as *mut _, self.len) } {
+                tracing::warn!("error unmapping heap image: {}", e);
+            }
+        }
+        // any mappings of the image keep the file alive, so this is safe to do while instances
+        // still use it
+        if let Err(e) = nix::unistd::close(self.fd) {
+            tracing::warn!("error closing heap image: {}", e);
+        }
+    }
+}
@@ -7,7 +7,7 @@ use lucet_module::owned::{
 };
 use lucet_module::{
     FunctionHandle, FunctionIndex, FunctionPointer, FunctionSpec, ModuleData, ModuleFeatures,
-    Signature, TrapSite, UniqueSignatureIndex,
+    Signature, TableElementType, TableSpec, TrapSite, UniqueSignatureIndex,
 };
 use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
@@ -199,7 +199,7 @@ impl MockModuleBuilder {
             "heap must fit in heap spec initial size"
         );
 
-        let table_elements = self
+        let table_elements: Vec<TableElement> = self
             .table_elements
             .into_iter()
             .enumerate()
@@ -230,6 +230,17 @@ impl MockModuleBuilder {
                     .expect("sparse data pages are valid"),
             }),
             globals_spec,
+            if table_elements.is_empty() {
+                vec![]
+            } else {
+                vec![TableSpec {
+                    element_type: TableElementType::FuncRef,
+                    minimum: table_elements.len() as u32,
+                    maximum: None,
+                }]
+            },
+            vec![],
+            vec![],
             self.function_info.clone(),
             self.imports,
             self.exports,
@@ -278,6 +289,14 @@ impl ModuleInternal for MockModule {
         self.module_data.features().instruction_count
     }
 
+    fn is_fuel_checked(&self) -> bool {
+        self.module_data.features().fuel_checks
+    }
+
+    fn is_epoch_interruptible(&self) -> bool {
+        self.module_data.features().epoch_interruption
+    }
+
     fn heap_spec(&self) -> Option<&HeapSpec> {
         self.module_data.heap_spec()
     }
@@ -298,8 +317,26 @@ impl ModuleInternal for MockModule {
         self.module_data.sparse_data().map(|d| d.len()).unwrap_or(0)
     }
 
-    fn table_elements(&self) -> Result<&[TableElement], Error> {
-        Ok(&self.table_elements)
+    fn tables(&self) -> Vec<&[TableElement]> {
+        // Mock modules without table elements have no tables, so that functions added with
+        // `with_table_func()` are found through `get_func_from_idx()`.
+        if self.table_elements.is_empty() {
+            vec![]
+        } else {
+            vec![&self.table_elements]
+        }
+    }
+
+    fn tables_spec(&self) -> &[TableSpec] {
+        self.module_data.tables_spec()
+    }
+
+    fn data_segment(&self, idx: u32) -> Option<&[u8]> {
+        self.module_data.passive_data(idx)
+    }
+
+    fn elem_segment(&self, idx: u32) -> Option<&[FunctionIndex]> {
+        self.module_data.passive_elements(idx)
     }
 
     fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error> {
@@ -338,6 +375,14 @@ impl ModuleInternal for MockModule {
     fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
         self.module_data.get_signature(fn_id)
     }
+
+    fn get_signature_index(&self, fn_id: FunctionIndex) -> UniqueSignatureIndex {
+        self.module_data.get_signature_index(fn_id)
+    }
+
+    fn get_function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.module_data.get_function_name(fn_id)
+    }
 }
 
 pub struct MockExportBuilder {
@@ -357,7 +402,7 @@ impl MockExportBuilder {
             traps: None,
             sig: Signature {
                 params: vec![],
-                ret_ty: None,
+                results: vec![],
             },
         }
     }
@@ -0,0 +1,260 @@
+use crate::error::Error;
+use crate::module::{
+    AddrDetails, GlobalSpec, HeapImage, HeapSpec, Module, ModuleInternal, TableElement, TableSpec,
+};
+use crate::sysdeps::host_page_size;
+use libc::c_void;
+use lucet_module::{
+    FunctionHandle, FunctionIndex, FunctionPointer, FunctionSpec, Global, GlobalDef, GlobalValue,
+    Signature, UniqueSignatureIndex,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The captured state of a pre-initialized instance, from which new instances can be created.
+///
+/// Snapshots are taken with [`Instance::snapshot()`](../instance/struct.Instance.html#method.snapshot),
+/// typically after running the start function and any other expensive guest initialization.
+/// Instances created from a snapshot with
+/// [`InstanceBuilder::from_snapshot()`](../region/struct.InstanceBuilder.html#method.from_snapshot)
+/// begin with the heap, globals, tables, and dropped segments exactly as they were when the snapshot
+/// was taken, and [`Instance::reset()`](../instance/struct.Instance.html#method.reset) returns them
+/// to that state rather than to the module's initial state.
+///
+/// A snapshot is itself a [`Module`](trait.Module.html) that wraps the module of the instance it
+/// was taken from, so it can be used with any `Region`. The captured heap is held once, in a
+/// [`HeapImage`](struct.HeapImage.html) where the platform supports it, and is shared between all
+/// of the instances created from the snapshot: both `MmapRegion` and `UffdRegion` map the image
+/// copy-on-write.
+pub struct Snapshot {
+    module: Arc<dyn Module>,
+    heap_spec: Option<HeapSpec>,
+    /// A copy of the populated pages of the heap, kept only when there is no heap image to read
+    /// them from.
+    pages: Vec<Option<Vec<u8>>>,
+    heap_image: Option<HeapImage>,
+    /// The globals as they were when the snapshot was taken.
+    ///
+    /// The export names borrow from `module`, which the snapshot keeps alive, so we treat them as
+    /// `'static` in the same way the module itself does.
+    globals: Vec<GlobalSpec<'static>>,
+    /// The elements of each table as they were when the snapshot was taken.
+    tables: Vec<Vec<TableElement>>,
+    /// The data and element segments that had been dropped when the snapshot was taken.
+    dropped_data_segments: HashSet<u32>,
+    dropped_elem_segments: HashSet<u32>,
+    start_func: Option<FunctionHandle>,
+}
+
+impl Snapshot {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        module: Arc<dyn Module>,
+        heap: &[u8],
+        globals: &[GlobalValue],
+        tables: &[Vec<TableElement>],
+        dropped_data_segments: &HashSet<u32>,
+        dropped_elem_segments: &HashSet<u32>,
+        needs_start: bool,
+    ) -> Result<Self, Error> {
+        let heap_spec = module.heap_spec().map(|spec| HeapSpec {
+            initial_size: heap.len() as u64,
+            ..spec.clone()
+        });
+
+        let page_refs: Vec<Option<&[u8]>> = heap
+            .chunks(host_page_size())
+            .map(|page| {
+                if page.iter().all(|b| *b == 0) {
+                    None
+                } else {
+                    Some(page)
+                }
+            })
+            .collect();
+
+        // A heap image is only an optimization, so fall back to keeping a copy of the pages if we
+        // can't make one on this platform.
+        let heap_image = if heap.is_empty() {
+            None
+        } else {
+            HeapImage::new(&page_refs, heap.len()).ok()
+        };
+        let pages = if heap_image.is_some() {
+            vec![]
+        } else {
+            page_refs.iter().map(|p| p.map(|p| p.to_vec())).collect()
+        };
+
+        let globals = module
+            .globals()
+            .iter()
+            .zip(globals.iter())
+            .map(|(spec, val)| {
+                let global = match spec.global() {
+                    Global::Def(def) => Global::Def(unsafe {
+                        match def {
+                            GlobalDef::I32(_) => GlobalDef::I32(val.i_32),
+                            GlobalDef::I64(_) => GlobalDef::I64(val.i_64),
+                            GlobalDef::F32(_) => GlobalDef::F32(val.f_32),
+                            GlobalDef::F64(_) => GlobalDef::F64(val.f_64),
+                            GlobalDef::V128(_) => GlobalDef::V128(val.v_128),
+                        }
+                    }),
+                    Global::Import { .. } => {
+                        return Err(Error::Unsupported(format!(
+                            "global imports are unsupported; found: {:?}",
+                            spec
+                        )));
+                    }
+                };
+                let spec = GlobalSpec::new(global, spec.export_names().to_vec());
+                Ok(unsafe { std::mem::transmute::<GlobalSpec<'_>, GlobalSpec<'static>>(spec) })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let start_func = if needs_start {
+            module.get_start_func()?
+        } else {
+            None
+        };
+
+        Ok(Snapshot {
+            module,
+            heap_spec,
+            pages,
+            heap_image,
+            globals,
+            tables: tables.to_vec(),
+            dropped_data_segments: dropped_data_segments.clone(),
+            dropped_elem_segments: dropped_elem_segments.clone(),
+            start_func,
+        })
+    }
+
+    /// The module of the instance this snapshot was taken from.
+    pub fn module(&self) -> Arc<dyn Module> {
+        self.module.clone()
+    }
+
+    /// Returns `true` if this snapshot was taken from an instance of `module`.
+    pub fn is_snapshot_of(&self, module: &Arc<dyn Module>) -> bool {
+        // compare only the data pointers, as vtable pointers for the same type may differ
+        &*self.module as *const dyn Module as *const u8
+            == &**module as *const dyn Module as *const u8
+    }
+
+    /// The size of the captured heap, in bytes.
+    pub fn heap_len(&self) -> usize {
+        self.heap_spec
+            .as_ref()
+            .map(|h| h.initial_size as usize)
+            .unwrap_or(0)
+    }
+}
+
+impl Module for Snapshot {}
+
+impl ModuleInternal for Snapshot {
+    fn is_instruction_count_instrumented(&self) -> bool {
+        self.module.is_instruction_count_instrumented()
+    }
+
+    fn is_fuel_checked(&self) -> bool {
+        self.module.is_fuel_checked()
+    }
+
+    fn is_epoch_interruptible(&self) -> bool {
+        self.module.is_epoch_interruptible()
+    }
+
+    fn heap_spec(&self) -> Option<&HeapSpec> {
+        self.heap_spec.as_ref()
+    }
+
+    fn globals(&self) -> &[GlobalSpec<'_>] {
+        &self.globals
+    }
+
+    fn get_sparse_page_data(&self, page: usize) -> Option<&[u8]> {
+        match &self.heap_image {
+            Some(image) => image.page(page),
+            None => self
+                .pages
+                .get(page)
+                .and_then(|p| p.as_ref().map(|p| p.as_slice())),
+        }
+    }
+
+    fn sparse_page_data_len(&self) -> usize {
+        match &self.heap_image {
+            Some(image) => image.page_count(),
+            None => self.pages.len(),
+        }
+    }
+
+    fn heap_image(&self) -> Option<&HeapImage> {
+        self.heap_image.as_ref()
+    }
+
+    fn tables(&self) -> Vec<&[TableElement]> {
+        self.tables.iter().map(|table| table.as_slice()).collect()
+    }
+
+    fn tables_spec(&self) -> &[TableSpec] {
+        self.module.tables_spec()
+    }
+
+    fn data_segment(&self, idx: u32) -> Option<&[u8]> {
+        // a segment dropped before the snapshot was taken stays dropped
+        if self.dropped_data_segments.contains(&idx) {
+            self.module.data_segment(idx).map(|_| &[][..])
+        } else {
+            self.module.data_segment(idx)
+        }
+    }
+
+    fn elem_segment(&self, idx: u32) -> Option<&[FunctionIndex]> {
+        if self.dropped_elem_segments.contains(&idx) {
+            self.module.elem_segment(idx).map(|_| &[][..])
+        } else {
+            self.module.elem_segment(idx)
+        }
+    }
+
+    fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error> {
+        self.module.get_export_func(sym)
+    }
+
+    fn get_func_from_idx(&self, table_id: u32, func_id: u32) -> Result<FunctionHandle, Error> {
+        self.module.get_func_from_idx(table_id, func_id)
+    }
+
+    fn get_start_func(&self) -> Result<Option<FunctionHandle>, Error> {
+        Ok(self.start_func)
+    }
+
+    fn function_manifest(&self) -> &[FunctionSpec] {
+        self.module.function_manifest()
+    }
+
+    fn function_index_from_ptr(&self, ptr: FunctionPointer) -> Option<FunctionIndex> {
+        self.module.function_index_from_ptr(ptr)
+    }
+
+    fn addr_details(&self, addr: *const c_void) -> Result<Option<AddrDetails>, Error> {
+        self.module.addr_details(addr)
+    }
+
+    fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
+        self.module.get_signature(fn_id)
+    }
+
+    fn get_signature_index(&self, fn_id: FunctionIndex) -> UniqueSignatureIndex {
+        self.module.get_signature_index(fn_id)
+    }
+
+    fn get_function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.module.get_function_name(fn_id)
+    }
+}
@@ -3,13 +3,16 @@ pub mod mmap;
 #[cfg(all(target_os = "linux", feature = "uffd"))]
 pub mod uffd;
 
-use crate::alloc::{Alloc, AllocStrategy, Limits, Slot};
+use crate::alloc::{Alloc, AllocStrategy, CleanupStrategy, Limits, Slot};
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
-use crate::instance::InstanceHandle;
-use crate::module::Module;
+use crate::instance::{InstanceHandle, OutOfFuelBehavior, DEFAULT_TABLE_ELEMENTS_LIMIT};
+use crate::memory_pool::MemoryPool;
+use crate::module::{Module, Snapshot};
+use crate::stats::{RegionCounters, RegionStats};
 use std::any::Any;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// A memory region in which Lucet instances are created and run.
 ///
@@ -31,6 +34,16 @@ pub trait Region: RegionInternal {
         InstanceBuilder::new(self.as_dyn_internal(), module)
     }
 
+    /// Create a new instance within the region from a [`Snapshot`](../module/struct.Snapshot.html).
+    ///
+    /// Calling `region.new_instance_from_snapshot(snapshot)` is shorthand for
+    /// `region.new_instance_builder(snapshot.module()).from_snapshot(snapshot).build()`.
+    fn new_instance_from_snapshot(&self, snapshot: Arc<Snapshot>) -> Result<InstanceHandle, Error> {
+        self.new_instance_builder(snapshot.module())
+            .from_snapshot(snapshot)
+            .build()
+    }
+
     /// Return the number of instance slots that are currently free in the region.
     ///
     /// A value greater than zero does not guarantee that a subsequent call to
@@ -47,16 +60,34 @@ pub trait Region: RegionInternal {
 
     /// Return the total instance slot capacity of the region.
     fn capacity(&self) -> usize;
+
+    /// Return statistics about the memory the region holds and the instances created in it.
+    ///
+    /// Counting resident memory walks every slot in the region, so this is meant to be called
+    /// periodically for monitoring rather than on every instantiation.
+    fn stats(&self) -> RegionStats;
+
+    /// Charge the heaps of instances created in the region from now on to the given
+    /// [`MemoryPool`](../memory_pool/struct.MemoryPool.html), or to no pool if `pool` is `None`.
+    ///
+    /// An instance given a pool with
+    /// [`InstanceBuilder::with_memory_pool()`](struct.InstanceBuilder.html#method.with_memory_pool)
+    /// is charged to that pool instead. Instances already in the region keep the pool they were
+    /// created with.
+    fn set_memory_pool(&self, pool: Option<Arc<MemoryPool>>);
 }
 
 /// A `RegionInternal` is a collection of `Slot`s which are managed as a whole.
 pub trait RegionInternal: Send + Sync {
+    #[allow(clippy::too_many_arguments)]
     fn new_instance_with(
         &self,
         module: Arc<dyn Module>,
         embed_ctx: CtxMap,
         heap_memory_size_limit: usize,
         alloc_strategy: AllocStrategy,
+        cleanup_strategy: CleanupStrategy,
+        memory_pool: Option<Arc<MemoryPool>>,
     ) -> Result<InstanceHandle, Error>;
 
     /// Unmaps the heap, stack, and globals of an `Alloc`, while retaining the virtual address
@@ -71,6 +102,19 @@ pub trait RegionInternal: Send + Sync {
     /// Get the runtime memory size limits
     fn get_limits(&self) -> &Limits;
 
+    /// Get the pool set with `Region::set_memory_pool()`, which new instances are charged to unless
+    /// they are given one of their own.
+    fn memory_pool(&self) -> Option<Arc<MemoryPool>>;
+
+    /// Get the counters the instances in the region update for `Region::stats()`.
+    fn counters(&self) -> &RegionCounters;
+
+    /// Get the number of page faults the region has handled for the instance in the given slot,
+    /// if the region handles page faults itself.
+    fn slot_page_faults(&self, _slot: &Slot) -> Option<u64> {
+        None
+    }
+
     fn as_dyn_internal(&self) -> &dyn RegionInternal;
 }
 
@@ -94,7 +138,14 @@ pub struct InstanceBuilder<'a> {
     module: Arc<dyn Module>,
     embed_ctx: CtxMap,
     heap_memory_size_limit: usize,
+    table_elements_limit: usize,
     alloc_strategy: AllocStrategy,
+    cleanup_strategy: CleanupStrategy,
+    snapshot: Option<Arc<Snapshot>>,
+    fuel: Option<u64>,
+    out_of_fuel_behavior: OutOfFuelBehavior,
+    epoch_deadline: Option<u64>,
+    memory_pool: Option<Arc<MemoryPool>>,
 }
 
 impl<'a> InstanceBuilder<'a> {
@@ -104,7 +155,14 @@ impl<'a> InstanceBuilder<'a> {
             module,
             embed_ctx: CtxMap::default(),
             heap_memory_size_limit: region.get_limits().heap_memory_size,
+            table_elements_limit: DEFAULT_TABLE_ELEMENTS_LIMIT,
             alloc_strategy: AllocStrategy::Linear,
+            cleanup_strategy: CleanupStrategy::default(),
+            snapshot: None,
+            fuel: None,
+            out_of_fuel_behavior: OutOfFuelBehavior::Terminate,
+            epoch_deadline: None,
+            memory_pool: region.memory_pool(),
         }
     }
 
@@ -119,6 +177,16 @@ impl<'a> InstanceBuilder<'a> {
         self
     }
 
+    /// Clear the memory of the instance using the supplied `CleanupStrategy` when it is dropped or
+    /// reset.
+    ///
+    /// This call is optional. The default cleanup strategy is `CleanupStrategy::Decommit`. Regions
+    /// that only support the default fail to build the instance with `Error::Unsupported`.
+    pub fn with_cleanup_strategy(mut self, cleanup_strategy: CleanupStrategy) -> Self {
+        self.cleanup_strategy = cleanup_strategy;
+        self
+    }
+
     /// Add a smaller, custom limit for the heap memory size to the built instance.
     ///
     /// This call is optional. Attempts to build a new instance fail if the
@@ -128,6 +196,18 @@ impl<'a> InstanceBuilder<'a> {
         self
     }
 
+    /// Limit the number of elements each table of the built instance may grow to with
+    /// `table.grow`.
+    ///
+    /// This call is optional. The default limit is
+    /// [`DEFAULT_TABLE_ELEMENTS_LIMIT`](../instance/constant.DEFAULT_TABLE_ELEMENTS_LIMIT.html).
+    /// Tables may never grow beyond the maximum size the module declares for them, and this does
+    /// not limit their initial size, which is fixed by the module.
+    pub fn with_table_elements_limit(mut self, table_elements_limit: usize) -> Self {
+        self.table_elements_limit = table_elements_limit;
+        self
+    }
+
     /// Add an embedder context to the built instance.
     ///
     /// Up to one context value of any particular type may exist in the instance. If a context value
@@ -137,13 +217,95 @@ impl<'a> InstanceBuilder<'a> {
         self
     }
 
+    /// Create the instance from a [`Snapshot`](../module/struct.Snapshot.html) rather than from the
+    /// initial state of the module.
+    ///
+    /// This call is optional. The snapshot must have been taken from an instance of the module this
+    /// builder was created with, otherwise building the instance fails with
+    /// `Error::InvalidArgument`.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_snapshot(mut self, snapshot: Arc<Snapshot>) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Limit the built instance to executing operations worth `fuel`.
+    ///
+    /// This call is optional. The module must be compiled with fuel checks enabled, otherwise
+    /// building the instance fails with `Error::Unsupported`. See
+    /// [`Instance::set_fuel()`](../instance/struct.Instance.html#method.set_fuel) for details.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Set what happens when the built instance runs out of fuel.
+    ///
+    /// This call is optional. The default behavior is `OutOfFuelBehavior::Terminate`.
+    pub fn with_out_of_fuel_behavior(mut self, behavior: OutOfFuelBehavior) -> Self {
+        self.out_of_fuel_behavior = behavior;
+        self
+    }
+
+    /// Interrupt the built instance once the epoch has advanced `ticks` times from when it is
+    /// built.
+    ///
+    /// This call is optional. The module must be compiled with epoch interruption enabled,
+    /// otherwise building the instance fails with `Error::Unsupported`. See
+    /// [`Instance::set_epoch_deadline()`](../instance/struct.Instance.html#method.set_epoch_deadline)
+    /// for details.
+    pub fn with_epoch_deadline(mut self, ticks: u64) -> Self {
+        self.epoch_deadline = Some(ticks);
+        self
+    }
+
+    /// Charge the heap of the built instance to the given `MemoryPool`, which other instances may
+    /// share, including those of other regions.
+    ///
+    /// This call is optional. The heap is charged to the region's pool by default, if it has one;
+    /// see [`Region::set_memory_pool()`](trait.Region.html#tymethod.set_memory_pool). Building the instance fails with `Error::LimitsExceeded` if the pool
+    /// does not have enough left for the module's initial heap, as does growing the heap beyond
+    /// what is left.
+    pub fn with_memory_pool(mut self, pool: Arc<MemoryPool>) -> Self {
+        self.memory_pool = Some(pool);
+        self
+    }
+
     /// Build the instance.
     pub fn build(self) -> Result<InstanceHandle, Error> {
-        self.region.new_instance_with(
-            self.module,
+        let start = Instant::now();
+        let module = match self.snapshot {
+            Some(snapshot) => {
+                if !snapshot.is_snapshot_of(&self.module) {
+                    return Err(Error::InvalidArgument(
+                        "snapshot was not taken from an instance of the builder's module",
+                    ));
+                }
+                snapshot as Arc<dyn Module>
+            }
+            None => self.module,
+        };
+        let mut inst = self.region.new_instance_with(
+            module,
             self.embed_ctx,
             self.heap_memory_size_limit,
             self.alloc_strategy,
-        )
+            self.cleanup_strategy,
+            self.memory_pool,
+        )?;
+        if let Some(fuel) = self.fuel {
+            inst.set_fuel(fuel)?;
+        }
+        inst.set_out_of_fuel_behavior(self.out_of_fuel_behavior);
+        inst.table_elements_limit = self.table_elements_limit;
+        if let Some(ticks) = self.epoch_deadline {
+            inst.set_epoch_deadline(ticks)?;
+        }
+        let instantiation_time = start.elapsed();
+        inst.counters.instantiation_time = instantiation_time;
+        self.region
+            .counters()
+            .record_instantiation(instantiation_time);
+        Ok(inst)
     }
 }
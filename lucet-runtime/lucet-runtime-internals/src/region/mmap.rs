@@ -1,16 +1,21 @@
-use crate::alloc::{instance_heap_offset, Alloc, AllocStrategy, Limits, Slot};
+use crate::alloc::{instance_heap_offset, Alloc, AllocStrategy, CleanupStrategy, Limits, Slot};
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
 use crate::instance::{new_instance_handle, Instance, InstanceHandle};
+use crate::memory_pool::MemoryPool;
 use crate::module::Module;
 use crate::region::{Region, RegionCreate, RegionInternal};
-use crate::sysdeps::host_page_size;
+use crate::stats::{stack_high_water_mark, RegionCounters, RegionStats};
+use crate::sysdeps::{host_page_size, resident_pages};
 use libc::c_void;
-#[cfg(not(target_os = "linux"))]
 use libc::memset;
 use nix::sys::mman::{madvise, mmap, munmap, MapFlags, MmapAdvise, ProtFlags};
+use std::ops::Range;
 use std::ptr;
-use std::sync::{Arc, RwLock, Weak};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// A [`Region`](../trait.Region.html) backed by `mmap`.
 ///
@@ -53,34 +58,160 @@ use std::sync::{Arc, RwLock, Weak};
 /// 0xSXXX: |  ......sigstack....   | // sigstack is governed by limits.signal_stack_size
 /// 0xSXXX: +-----------------------|
 /// ```
+///
+/// A region created with [`MmapRegion::create()`](#method.create) maps all of its slots up front.
+/// One created with [`MmapRegion::create_growable()`](#method.create_growable) instead maps slots
+/// as instances need them, and unmaps them again once they have been idle for a while, according to
+/// a [`GrowthPolicy`](struct.GrowthPolicy.html).
 pub struct MmapRegion {
     capacity: usize,
-    freelist: RwLock<Vec<Slot>>,
+    freelist: RwLock<SlotPool>,
     limits: Limits,
     min_heap_alignment: usize,
+    growth: Option<GrowthPolicy>,
+    counters: RegionCounters,
+    /// The pool instances are charged to unless they are given one of their own.
+    memory_pool: RwLock<Option<Arc<MemoryPool>>>,
+    /// Dropped with the region, which disconnects the idle slot reaper thread of a growable region
+    /// and so tells it to exit.
+    reaper_shutdown: Option<Mutex<mpsc::Sender<()>>>,
+}
+
+/// The slots mapped by an `MmapRegion`.
+struct SlotPool {
+    /// The slots not in use by an instance, each with the time it was last freed.
+    free: Vec<(Slot, Instant)>,
+    /// The start addresses of the slots currently mapped, whether free or in use.
+    mapped: Vec<usize>,
+    /// The region these slots belong to, for the slots it maps after it has been created.
+    region: Weak<MmapRegion>,
+}
+
+/// How a growable [`MmapRegion`](struct.MmapRegion.html) maps and unmaps instance slots.
+///
+/// A growable region starts with `initial_slots` slots. When an instance is created and none are
+/// free, it maps `chunk_slots` more, up to a total of `max_slots`; beyond that, creating an instance
+/// fails with `Error::RegionFull`. Free slots above `initial_slots` that have not been used for
+/// `idle_cooldown` are unmapped, returning their memory and address space to the operating system.
+/// A background thread unmaps idle slots as their cooldown runs out, so a region shrinks even when
+/// no instances are created or dropped; they can also be released explicitly with
+/// [`MmapRegion::release_idle_slots()`](struct.MmapRegion.html#method.release_idle_slots).
+///
+/// Only `MmapRegion`s can grow; a `UffdRegion` always has the fixed capacity passed to
+/// `UffdRegion::create()`.
+#[derive(Clone, Debug)]
+pub struct GrowthPolicy {
+    /// The number of slots mapped when the region is created, below which idle slots are not
+    /// unmapped.
+    pub initial_slots: usize,
+    /// The number of slots mapped at a time when the region runs out of free slots.
+    pub chunk_slots: usize,
+    /// The most slots the region maps at once, which is its capacity.
+    pub max_slots: usize,
+    /// How long a free slot beyond the initial slots stays mapped after it was last used.
+    pub idle_cooldown: Duration,
+}
+
+impl GrowthPolicy {
+    /// A policy for a region of up to `max_slots` slots, which starts with none, maps one at a time,
+    /// and unmaps slots after a minute without use.
+    pub fn new(max_slots: usize) -> GrowthPolicy {
+        GrowthPolicy {
+            initial_slots: 0,
+            chunk_slots: 1,
+            max_slots,
+            idle_cooldown: Duration::from_secs(60),
+        }
+    }
+
+    /// Set the number of slots mapped when the region is created.
+    pub fn with_initial_slots(mut self, initial_slots: usize) -> Self {
+        self.initial_slots = initial_slots;
+        self
+    }
+
+    /// Set the number of slots mapped at a time.
+    pub fn with_chunk_slots(mut self, chunk_slots: usize) -> Self {
+        self.chunk_slots = chunk_slots;
+        self
+    }
+
+    /// Set how long a free slot stays mapped after it was last used.
+    pub fn with_idle_cooldown(mut self, idle_cooldown: Duration) -> Self {
+        self.idle_cooldown = idle_cooldown;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.max_slots == 0 {
+            return Err(Error::InvalidArgument(
+                "maximum slot count must be greater than 0",
+            ));
+        }
+        if self.chunk_slots == 0 {
+            return Err(Error::InvalidArgument(
+                "chunk slot count must be greater than 0",
+            ));
+        }
+        if self.initial_slots > self.max_slots {
+            return Err(Error::InvalidArgument(
+                "initial slot count must not exceed the maximum slot count",
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Region for MmapRegion {
     fn free_slots(&self) -> usize {
-        self.freelist.read().unwrap().len()
+        // slots a growable region has yet to map are free too
+        self.capacity() - self.used_slots()
     }
 
     fn used_slots(&self) -> usize {
-        self.capacity() - self.free_slots()
+        let pool = self.freelist.read().unwrap();
+        pool.mapped.len() - pool.free.len()
     }
 
     fn capacity(&self) -> usize {
         self.capacity
     }
+
+    fn stats(&self) -> RegionStats {
+        let pool = self.freelist.read().unwrap();
+        // slots are taken out of `mapped` before they are unmapped, so these stay mapped meanwhile
+        let resident_pages = pool
+            .mapped
+            .iter()
+            .map(|start| unsafe {
+                resident_pages(*start as *mut c_void, self.limits.total_memory_size())
+                    .expect("mincore succeeds on a slot")
+                    .private
+            })
+            .sum();
+        self.counters.stats(
+            self.capacity,
+            pool.mapped.len() - pool.free.len(),
+            resident_pages,
+            None,
+        )
+    }
+
+    fn set_memory_pool(&self, pool: Option<Arc<MemoryPool>>) {
+        *self.memory_pool.write().unwrap() = pool;
+    }
 }
 
 impl RegionInternal for MmapRegion {
+    #[allow(clippy::too_many_arguments)]
     fn new_instance_with(
         &self,
         module: Arc<dyn Module>,
         embed_ctx: CtxMap,
         heap_memory_size_limit: usize,
         mut alloc_strategy: AllocStrategy,
+        cleanup_strategy: CleanupStrategy,
+        memory_pool: Option<Arc<MemoryPool>>,
     ) -> Result<InstanceHandle, Error> {
         let limits = self.get_limits();
 
@@ -88,12 +219,30 @@ impl RegionInternal for MmapRegion {
 
         // Use the supplied alloc_strategy to get the next available slot
         // for this new instance.
-        let slot;
+        let mut slot;
+        let idle;
         {
-            let mut free_slot_vector = self.freelist.write().unwrap();
-            let slot_index = alloc_strategy.next(free_slot_vector.len(), self.capacity)?;
-            slot = free_slot_vector.swap_remove(slot_index);
+            let mut pool = self.freelist.write().unwrap();
+            if pool.free.is_empty() {
+                self.grow(&mut pool)?;
+            }
+            let affine_slot = match alloc_strategy {
+                AllocStrategy::ModuleAffinity => pool
+                    .free
+                    .iter()
+                    .rposition(|(slot, _)| last_used_by(slot, &module)),
+                _ => None,
+            };
+            let slot_index = match affine_slot {
+                Some(slot_index) => slot_index,
+                None => alloc_strategy.next(pool.free.len(), self.capacity)?,
+            };
+            slot = pool.free.swap_remove(slot_index).0;
+            idle = self.take_idle(&mut pool);
         }
+        // unmap outside of the lock, so that other instances aren't held up by it
+        idle.into_iter().for_each(MmapRegion::free_slot);
+        slot.last_module = Some(Arc::downgrade(&module));
 
         assert_eq!(
             slot.heap as usize % host_page_size(),
@@ -134,6 +283,11 @@ impl RegionInternal for MmapRegion {
             heap_accessible_size: 0, // the `reset` call in `new_instance_handle` will set this
             heap_inaccessible_size: slot.limits.heap_address_space_size,
             heap_memory_size_limit,
+            heap_image_mapped: false,
+            cleanup_strategy,
+            globals_size: module.initial_globals_size(),
+            memory_pool,
+            memory_pool_reservation: 0,
             slot: Some(slot),
             region,
         };
@@ -155,32 +309,62 @@ impl RegionInternal for MmapRegion {
             panic!("heap is not page-aligned");
         }
 
+        // We don't ever shrink the heap, so we only need to clear up until the accessible size
+        let mut heap_dirty_size = alloc.heap_accessible_size;
+
+        if alloc.heap_image_mapped {
+            unsafe {
+                remap_anonymous(slot.heap, alloc.heap_accessible_size)
+                    .expect("heap image unmapped during drop");
+            }
+            alloc.heap_image_mapped = false;
+            heap_dirty_size = 0;
+        }
+
+        // the stacks grow down, so everything below their high-water marks is still zero; finding
+        // the marks means reading the resident pages of the stacks, so it is only worth it when they
+        // are to be zeroed. Pages that are not resident are skipped, so this does not fault them in.
+        let stack_used = |stack: *mut c_void, len: usize| match alloc.cleanup_strategy {
+            CleanupStrategy::Zero => {
+                len - unsafe { stack_high_water_mark(stack as *const u8, len) }..len
+            }
+            _ => 0..len,
+        };
+
         // clear and disable access to the heap, stack, globals, and sigstack
-        for (ptr, len) in [
-            // We don't ever shrink the heap, so we only need to zero up until the accessible size
-            (slot.heap, alloc.heap_accessible_size),
-            (slot.stack, slot.limits.stack_size),
-            (slot.globals, slot.limits.globals_size),
-            (slot.sigstack, slot.limits.signal_stack_size),
+        for (ptr, len, used) in [
+            (slot.heap, heap_dirty_size, 0..heap_dirty_size),
+            (
+                slot.stack,
+                slot.limits.stack_size,
+                stack_used(slot.stack, slot.limits.stack_size),
+            ),
+            (
+                slot.globals,
+                slot.limits.globals_size,
+                0..alloc.globals_size.min(slot.limits.globals_size),
+            ),
+            (
+                slot.sigstack,
+                slot.limits.signal_stack_size,
+                stack_used(slot.sigstack, slot.limits.signal_stack_size),
+            ),
         ]
         .iter()
         {
             // eprintln!("setting none {:p}[{:x}]", *ptr, len);
             unsafe {
-                // MADV_DONTNEED is not guaranteed to clear pages on non-Linux systems
-                #[cfg(not(target_os = "linux"))]
-                {
-                    mprotect(*ptr, *len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
-                        .expect("mprotect succeeds during drop");
-                    memset(*ptr, 0, *len);
-                }
-                mprotect(*ptr, *len, ProtFlags::PROT_NONE).expect("mprotect succeeds during drop");
-                madvise(*ptr, *len, MmapAdvise::MADV_DONTNEED)
-                    .expect("madvise succeeds during drop");
+                clear_memory(*ptr, *len, used.clone(), alloc.cleanup_strategy)
+                    .expect("memory cleared during drop");
             }
         }
 
-        self.freelist.write().unwrap().push(slot);
+        let idle = {
+            let mut pool = self.freelist.write().unwrap();
+            pool.free.push((slot, Instant::now()));
+            self.take_idle(&mut pool)
+        };
+        idle.into_iter().for_each(MmapRegion::free_slot);
     }
 
     fn expand_heap(&self, slot: &Slot, start: u32, len: u32) -> Result<(), Error> {
@@ -198,23 +382,19 @@ impl RegionInternal for MmapRegion {
         let heap = alloc.slot().heap;
 
         if alloc.heap_accessible_size > 0 {
-            // zero the whole heap, if any of it is currently accessible
-            let heap_size = alloc.slot().limits.heap_address_space_size;
-
+            // zero the heap, if any of it is currently accessible; the rest of it never is
             unsafe {
-                // `mprotect()` and `madvise()` are sufficient to zero a page on Linux,
-                // but not necessarily on all POSIX operating systems, and on macOS in particular.
-                #[cfg(not(target_os = "linux"))]
-                {
-                    mprotect(
+                if alloc.heap_image_mapped {
+                    remap_anonymous(heap, alloc.heap_accessible_size)?;
+                    alloc.heap_image_mapped = false;
+                } else {
+                    clear_memory(
                         heap,
                         alloc.heap_accessible_size,
-                        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                        0..alloc.heap_accessible_size,
+                        alloc.cleanup_strategy,
                     )?;
-                    memset(heap, 0, alloc.heap_accessible_size);
                 }
-                mprotect(heap, heap_size, ProtFlags::PROT_NONE)?;
-                madvise(heap, heap_size, MmapAdvise::MADV_DONTNEED)?;
             }
         }
 
@@ -223,12 +403,38 @@ impl RegionInternal for MmapRegion {
             .map(|h| h.initial_size as usize)
             .unwrap_or(0);
 
+        // If the module has a heap image, map it copy-on-write over the start of the heap rather
+        // than copying in the sparse page data. The rest of the initial heap is left zeroed.
+        let image_len = if let Some(image) = module.heap_image() {
+            if image.len() > initial_size {
+                return Err(lucet_incorrect_module!(
+                    "heap image size {} exceeds initial heap size {}",
+                    image.len(),
+                    initial_size
+                ));
+            }
+            unsafe {
+                mmap(
+                    heap,
+                    image.len(),
+                    ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                    MapFlags::MAP_PRIVATE | MapFlags::MAP_FIXED,
+                    image.fd(),
+                    0,
+                )?;
+            }
+            alloc.heap_image_mapped = true;
+            image.len()
+        } else {
+            0
+        };
+
         // reset the heap to the initial size, and mprotect those pages appropriately
-        if initial_size > 0 {
+        if initial_size > image_len {
             unsafe {
                 mprotect(
-                    heap,
-                    initial_size,
+                    (heap as usize + image_len) as *mut c_void,
+                    initial_size - image_len,
                     ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
                 )?
             };
@@ -236,6 +442,10 @@ impl RegionInternal for MmapRegion {
         alloc.heap_accessible_size = initial_size;
         alloc.heap_inaccessible_size = alloc.slot().limits.heap_address_space_size - initial_size;
 
+        if alloc.heap_image_mapped {
+            return Ok(());
+        }
+
         // Initialize the heap using the module sparse page data. There cannot be more pages in the
         // sparse page data than will fit in the initial heap size.
         //
@@ -273,6 +483,14 @@ impl RegionInternal for MmapRegion {
         &self.limits
     }
 
+    fn memory_pool(&self) -> Option<Arc<MemoryPool>> {
+        self.memory_pool.read().unwrap().clone()
+    }
+
+    fn counters(&self) -> &RegionCounters {
+        &self.counters
+    }
+
     fn as_dyn_internal(&self) -> &dyn RegionInternal {
         self
     }
@@ -280,7 +498,9 @@ impl RegionInternal for MmapRegion {
 
 impl Drop for MmapRegion {
     fn drop(&mut self) {
-        for slot in self.freelist.get_mut().unwrap().drain(0..) {
+        // stop the reaper; it only ever holds the region briefly, and may be the one dropping it
+        drop(self.reaper_shutdown.take());
+        for (slot, _) in self.freelist.get_mut().unwrap().free.drain(0..) {
             Self::free_slot(slot);
         }
     }
@@ -303,20 +523,27 @@ impl MmapRegion {
     pub fn create(instance_capacity: usize, limits: &Limits) -> Result<Arc<Self>, Error> {
         limits.validate()?;
 
-        let region = Arc::new(MmapRegion {
-            capacity: instance_capacity,
-            freelist: RwLock::new(Vec::with_capacity(instance_capacity)),
-            limits: limits.clone(),
-            min_heap_alignment: 0, // No constaints on heap alignment by default
-        });
-        {
-            let mut freelist = region.freelist.write().unwrap();
-            for _ in 0..instance_capacity {
-                freelist.push(MmapRegion::create_slot(&region)?);
-            }
-        }
+        MmapRegion::create_inner(
+            instance_capacity,
+            limits,
+            0, // No constaints on heap alignment by default
+            None,
+        )
+    }
 
-        Ok(region)
+    /// Create a new `MmapRegion` that maps slots for instances as they are needed, and unmaps them
+    /// once they have been idle for a while, as set by a [`GrowthPolicy`](struct.GrowthPolicy.html).
+    /// Each instance is subject to the same runtime limits.
+    ///
+    /// The region's capacity is `growth.max_slots`, and slots it has not mapped yet count as free.
+    ///
+    /// The region is returned in an `Arc`, because any instances created from it carry a reference
+    /// back to the region.
+    pub fn create_growable(growth: &GrowthPolicy, limits: &Limits) -> Result<Arc<Self>, Error> {
+        limits.validate()?;
+        growth.validate()?;
+
+        MmapRegion::create_inner(growth.max_slots, limits, 0, Some(growth.clone()))
     }
 
     /// Create a new `MmapRegion` that can support a given number instances, each subject to the
@@ -340,22 +567,159 @@ impl MmapRegion {
             ));
         }
 
+        MmapRegion::create_inner(instance_capacity, limits, heap_alignment, None)
+    }
+
+    fn create_inner(
+        instance_capacity: usize,
+        limits: &Limits,
+        min_heap_alignment: usize,
+        growth: Option<GrowthPolicy>,
+    ) -> Result<Arc<Self>, Error> {
+        let initial_slots = growth
+            .as_ref()
+            .map(|growth| growth.initial_slots)
+            .unwrap_or(instance_capacity);
+
+        // a zero cooldown releases slots as soon as they go idle, which needs no reaper
+        let reaper = match &growth {
+            Some(growth) if growth.idle_cooldown > Duration::from_secs(0) => Some(mpsc::channel()),
+            _ => None,
+        };
+        let (reaper_shutdown, reaper_shutdown_recv) = match reaper {
+            Some((send, recv)) => (Some(Mutex::new(send)), Some(recv)),
+            None => (None, None),
+        };
+
         let region = Arc::new(MmapRegion {
             capacity: instance_capacity,
-            freelist: RwLock::new(Vec::with_capacity(instance_capacity)),
+            freelist: RwLock::new(SlotPool {
+                free: Vec::with_capacity(initial_slots),
+                mapped: Vec::with_capacity(initial_slots),
+                region: Weak::new(),
+            }),
             limits: limits.clone(),
-            min_heap_alignment: heap_alignment,
+            min_heap_alignment,
+            growth,
+            counters: RegionCounters::default(),
+            memory_pool: RwLock::new(None),
+            reaper_shutdown,
         });
         {
-            let mut freelist = region.freelist.write().unwrap();
-            for _ in 0..instance_capacity {
-                freelist.push(MmapRegion::create_slot(&region)?);
+            let mut pool = region.freelist.write().unwrap();
+            pool.region = Arc::downgrade(&region);
+            for _ in 0..initial_slots {
+                let slot = MmapRegion::create_slot(&region)?;
+                pool.mapped.push(slot.start as usize);
+                pool.free.push((slot, Instant::now()));
             }
         }
 
+        if let Some(shutdown) = reaper_shutdown_recv {
+            let reaper_region = Arc::downgrade(&region);
+            thread::Builder::new()
+                .name("mmap region idle slot reaper".into())
+                .spawn(move || reap_idle_slots(reaper_region, shutdown))
+                .expect("error spawning mmap region idle slot reaper");
+        }
+
         Ok(region)
     }
 
+    /// Return the number of instance slots currently mapped, whether free or in use.
+    ///
+    /// This is the region's capacity, unless it was created with
+    /// [`MmapRegion::create_growable()`](#method.create_growable).
+    pub fn mapped_slots(&self) -> usize {
+        self.freelist.read().unwrap().mapped.len()
+    }
+
+    /// Unmap the free slots of a growable region that have been idle for at least the cooldown of
+    /// its [`GrowthPolicy`](struct.GrowthPolicy.html), while keeping its initial slots mapped, and
+    /// return the number of slots unmapped.
+    ///
+    /// Idle slots are also released in the background once their cooldown runs out, so a region
+    /// shrinks without calling this. It does nothing for a region that was not created with
+    /// [`MmapRegion::create_growable()`](#method.create_growable).
+    pub fn release_idle_slots(&self) -> usize {
+        let idle = self.take_idle(&mut self.freelist.write().unwrap());
+        let released = idle.len();
+        idle.into_iter().for_each(MmapRegion::free_slot);
+        released
+    }
+
+    /// How long until the next free slot of a growable region can be released, as far as is known
+    /// now. Slots freed later go idle later, so waiting this long never misses one.
+    fn next_release_in(&self) -> Duration {
+        let growth = match &self.growth {
+            Some(growth) => growth,
+            None => return Duration::from_secs(0),
+        };
+        let pool = self.freelist.read().unwrap();
+        if pool.mapped.len() > growth.initial_slots {
+            if let Some(idle_since) = pool.free.iter().map(|(_, idle_since)| *idle_since).min() {
+                return (idle_since + growth.idle_cooldown)
+                    .saturating_duration_since(Instant::now());
+            }
+        }
+        growth.idle_cooldown
+    }
+
+    /// Map another chunk of slots, if the region is growable and has not reached its capacity.
+    fn grow(&self, pool: &mut SlotPool) -> Result<(), Error> {
+        let growth = match &self.growth {
+            Some(growth) => growth,
+            None => return Ok(()),
+        };
+        let region = pool
+            .region
+            .upgrade()
+            .expect("region exists while its slots are allocated");
+        let chunk = growth.chunk_slots.min(self.capacity - pool.mapped.len());
+        for _ in 0..chunk {
+            match MmapRegion::create_slot(&region) {
+                Ok(slot) => {
+                    pool.mapped.push(slot.start as usize);
+                    pool.free.push((slot, Instant::now()));
+                }
+                // make do with the part of the chunk that could be mapped
+                Err(_) if !pool.free.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Take the free slots of a growable region that have been idle for the cooldown out of the
+    /// pool, longest idle first, until only the initial slots remain mapped.
+    ///
+    /// The slots are returned for the caller to unmap once it has released the pool's lock.
+    fn take_idle(&self, pool: &mut SlotPool) -> Vec<Slot> {
+        let growth = match &self.growth {
+            Some(growth) => growth,
+            None => return vec![],
+        };
+        let now = Instant::now();
+        let mut idle = vec![];
+        while pool.mapped.len() > growth.initial_slots {
+            let oldest = pool
+                .free
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, idle_since))| *idle_since)
+                .map(|(i, (_, idle_since))| (i, *idle_since));
+            match oldest {
+                Some((i, idle_since)) if now.duration_since(idle_since) >= growth.idle_cooldown => {
+                    let (slot, _) = pool.free.swap_remove(i);
+                    pool.mapped.retain(|start| *start != slot.start as usize);
+                    idle.push(slot);
+                }
+                _ => break,
+            }
+        }
+        idle
+    }
+
     fn create_slot(region: &Arc<MmapRegion>) -> Result<Slot, Error> {
         // get the chunk of virtual memory that the `Slot` will manage
         let mem = if region.min_heap_alignment == 0 {
@@ -411,6 +775,7 @@ impl MmapRegion {
             globals: globals as *mut c_void,
             sigstack: sigstack as *mut c_void,
             limits: region.limits.clone(),
+            last_module: None,
             region: Arc::downgrade(region) as Weak<dyn RegionInternal>,
         })
     }
@@ -426,6 +791,27 @@ impl MmapRegion {
     }
 }
 
+/// Release the idle slots of a growable region as their cooldown runs out, until the region is
+/// dropped.
+///
+/// Only a weak reference is held between releases, so that the reaper doesn't keep the region alive.
+fn reap_idle_slots(region: Weak<MmapRegion>, shutdown: mpsc::Receiver<()>) {
+    loop {
+        let wait = match region.upgrade() {
+            Some(region) => {
+                region.release_idle_slots();
+                region.next_release_in()
+            }
+            None => return,
+        };
+        match shutdown.recv_timeout(wait) {
+            Err(RecvTimeoutError::Timeout) => (),
+            // the region has been dropped
+            _ => return,
+        }
+    }
+}
+
 // Note alignment must be a power of 2
 // Offset must be a multiple of 4Kb (page size)
 unsafe fn mmap_aligned(
@@ -486,6 +872,70 @@ unsafe fn mmap_aligned(
     Ok(aligned as *mut c_void)
 }
 
+/// Whether `slot` was last used by an instance of `module`.
+fn last_used_by(slot: &Slot, module: &Arc<dyn Module>) -> bool {
+    // compare addresses alone, as the vtables of two pointers to the same module may differ
+    slot.last_module
+        .as_ref()
+        .and_then(|last_module| last_module.upgrade())
+        .map(|last_module| {
+            &*last_module as *const dyn Module as *const u8
+                == &**module as *const dyn Module as *const u8
+        })
+        .unwrap_or(false)
+}
+
+/// Clear read/write memory as set by a `CleanupStrategy`, and make it inaccessible.
+///
+/// `used` is the part of the memory, relative to `ptr`, that may have been written to since it was
+/// last cleared; the rest of it is known to still be zero.
+unsafe fn clear_memory(
+    ptr: *mut c_void,
+    len: usize,
+    used: Range<usize>,
+    strategy: CleanupStrategy,
+) -> Result<(), Error> {
+    if len == 0 {
+        return Ok(());
+    }
+    let used_ptr = (ptr as usize + used.start) as *mut c_void;
+    match strategy {
+        CleanupStrategy::Decommit => {
+            // MADV_DONTNEED is not guaranteed to clear pages on non-Linux systems, and on macOS in
+            // particular
+            #[cfg(not(target_os = "linux"))]
+            {
+                memset(used_ptr, 0, used.len());
+            }
+            mprotect(ptr, len, ProtFlags::PROT_NONE)?;
+            madvise(ptr, len, MmapAdvise::MADV_DONTNEED)?;
+        }
+        CleanupStrategy::Zero => {
+            memset(used_ptr, 0, used.len());
+            mprotect(ptr, len, ProtFlags::PROT_NONE)?;
+        }
+        CleanupStrategy::Unmap => remap_anonymous(ptr, len)?,
+    }
+    Ok(())
+}
+
+/// Replace memory, such as a heap image mapped over the start of the heap, with fresh,
+/// inaccessible anonymous memory.
+///
+/// `madvise(MADV_DONTNEED)` is not enough to clear a private file mapping, as the pages would read
+/// back the contents of the image rather than zeros.
+unsafe fn remap_anonymous(ptr: *mut c_void, len: usize) -> Result<(), Error> {
+    mmap(
+        ptr,
+        len,
+        ProtFlags::PROT_NONE,
+        MapFlags::MAP_ANON | MapFlags::MAP_PRIVATE | MapFlags::MAP_FIXED,
+        0,
+        0,
+    )?;
+    Ok(())
+}
+
 // TODO: remove this once `nix` PR https://github.com/nix-rust/nix/pull/991 is merged
 unsafe fn mprotect(addr: *mut c_void, length: libc::size_t, prot: ProtFlags) -> nix::Result<()> {
     nix::errno::Errno::result(libc::mprotect(addr, length, prot.bits())).map(drop)
@@ -559,3 +1009,330 @@ mod tests2 {
         }
     }
 }
+
+#[cfg(test)]
+mod growth_tests {
+    use super::*;
+    use crate::instance::InstanceInternal;
+    use crate::module::{HeapSpec, MockModuleBuilder};
+
+    const LIMITS: Limits = Limits {
+        heap_memory_size: 64 * 1024,
+        heap_address_space_size: 8 * 1024 * 1024,
+        stack_size: 64 * 1024,
+        globals_size: 4 * 1024,
+        ..Limits::default()
+    };
+
+    const ONE_PAGE_HEAP: HeapSpec = HeapSpec {
+        reserved_size: 4 * 1024 * 1024,
+        guard_size: 4 * 1024 * 1024,
+        initial_size: 64 * 1024,
+        max_size: Some(64 * 1024),
+    };
+
+    fn module() -> Arc<dyn Module> {
+        MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build()
+    }
+
+    #[test]
+    fn grows_in_chunks_up_to_max_slots() {
+        let growth = GrowthPolicy::new(3).with_chunk_slots(2);
+        let region = MmapRegion::create_growable(&growth, &LIMITS).expect("region created");
+        assert_eq!(region.capacity(), 3);
+        assert_eq!(region.mapped_slots(), 0);
+        assert_eq!(region.free_slots(), 3);
+        assert_eq!(region.used_slots(), 0);
+
+        let inst1 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        assert_eq!(region.mapped_slots(), 2);
+        assert_eq!(region.free_slots(), 2);
+        assert_eq!(region.used_slots(), 1);
+
+        let inst2 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        assert_eq!(region.mapped_slots(), 2);
+
+        // only one more slot fits under the maximum
+        let inst3 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        assert_eq!(region.mapped_slots(), 3);
+        assert_eq!(region.free_slots(), 0);
+        assert_eq!(region.used_slots(), 3);
+
+        match region.new_instance(module()) {
+            Err(Error::RegionFull(3)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("instance created beyond the maximum slot count"),
+        }
+
+        drop((inst1, inst2, inst3));
+        assert_eq!(region.free_slots(), 3);
+        assert_eq!(region.used_slots(), 0);
+    }
+
+    #[test]
+    fn idle_slots_stay_mapped_during_cooldown() {
+        let growth = GrowthPolicy::new(2).with_idle_cooldown(Duration::from_secs(3600));
+        let region = MmapRegion::create_growable(&growth, &LIMITS).expect("region created");
+
+        let inst1 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        let inst2 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        drop((inst1, inst2));
+        assert_eq!(region.mapped_slots(), 2);
+        assert_eq!(region.release_idle_slots(), 0);
+        assert_eq!(region.mapped_slots(), 2);
+
+        // a free slot is reused rather than mapping another
+        let _inst = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        assert_eq!(region.mapped_slots(), 2);
+    }
+
+    #[test]
+    fn idle_slots_are_released_down_to_initial_slots() {
+        let growth = GrowthPolicy::new(3)
+            .with_initial_slots(1)
+            .with_idle_cooldown(Duration::from_secs(0));
+        let region = MmapRegion::create_growable(&growth, &LIMITS).expect("region created");
+        assert_eq!(region.mapped_slots(), 1);
+
+        let inst1 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        let inst2 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        let mut inst3 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        assert_eq!(region.mapped_slots(), 3);
+
+        drop(inst1);
+        assert_eq!(region.mapped_slots(), 2);
+        drop(inst2);
+        assert_eq!(region.mapped_slots(), 1);
+        assert_eq!(region.used_slots(), 1);
+
+        inst3.heap_mut()[0] = 0xaa;
+        drop(inst3);
+        assert_eq!(region.mapped_slots(), 1);
+        assert_eq!(region.free_slots(), 3);
+
+        // the slot that stays mapped is cleared like any other
+        let inst = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        assert_eq!(inst.heap()[0], 0);
+        assert_eq!(region.mapped_slots(), 1);
+    }
+
+    #[test]
+    fn idle_slots_are_released_without_further_calls() {
+        let growth = GrowthPolicy::new(3)
+            .with_initial_slots(1)
+            .with_idle_cooldown(Duration::from_millis(50));
+        let region = MmapRegion::create_growable(&growth, &LIMITS).expect("region created");
+
+        let inst1 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        let inst2 = region
+            .new_instance(module())
+            .expect("new_instance succeeds");
+        drop((inst1, inst2));
+        assert_eq!(region.mapped_slots(), 2);
+
+        // nothing touches the region from here on; the cooldown alone shrinks it
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while region.mapped_slots() > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(region.mapped_slots(), 1);
+        assert_eq!(region.free_slots(), 3);
+    }
+
+    #[test]
+    fn module_affinity_prefers_slots_of_the_same_module() {
+        let region = MmapRegion::create(3, &LIMITS).expect("region created");
+        let module_a = module();
+        let module_b = module();
+
+        let inst_a = region
+            .new_instance(module_a.clone())
+            .expect("new_instance succeeds");
+        let heap_a = inst_a.heap().as_ptr();
+        let inst_b = region
+            .new_instance(module_b)
+            .expect("new_instance succeeds");
+        let heap_b = inst_b.heap().as_ptr();
+        drop(inst_a);
+        drop(inst_b);
+
+        // `Linear` would take the slot freed last, which `module_b` used
+        let inst = region
+            .new_instance_builder(module_a.clone())
+            .with_alloc_strategy(AllocStrategy::ModuleAffinity)
+            .build()
+            .expect("new_instance succeeds");
+        assert_eq!(inst.heap().as_ptr(), heap_a);
+
+        // without a slot last used by the module, the next slot available is taken, as with
+        // `Linear`
+        let inst2 = region
+            .new_instance_builder(module())
+            .with_alloc_strategy(AllocStrategy::ModuleAffinity)
+            .build()
+            .expect("new_instance succeeds");
+        assert_eq!(inst2.heap().as_ptr(), heap_b);
+    }
+
+    #[test]
+    fn cleanup_strategies_zero_memory() {
+        for strategy in &[
+            CleanupStrategy::Decommit,
+            CleanupStrategy::Zero,
+            CleanupStrategy::Unmap,
+        ] {
+            let region = MmapRegion::create(1, &LIMITS).expect("region created");
+            let new_instance = || {
+                region
+                    .new_instance_builder(module())
+                    .with_cleanup_strategy(*strategy)
+                    .build()
+                    .expect("new_instance succeeds")
+            };
+
+            let mut inst = new_instance();
+            inst.heap_mut()[0] = 0xaa;
+            inst.reset().expect("instance resets");
+            assert_eq!(inst.heap()[0], 0, "heap zeroed on reset by {:?}", strategy);
+
+            inst.heap_mut()[0] = 0xaa;
+            drop(inst);
+            let inst = new_instance();
+            assert_eq!(inst.heap()[0], 0, "heap zeroed on drop by {:?}", strategy);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn zero_cleanup_clears_stacks_below_high_water_mark() {
+        let region = MmapRegion::create(1, &LIMITS).expect("region created");
+        let new_instance = || {
+            region
+                .new_instance_builder(module())
+                .with_cleanup_strategy(CleanupStrategy::Zero)
+                .build()
+                .expect("new_instance succeeds")
+        };
+
+        let mut inst = new_instance();
+        {
+            let stack = unsafe { inst.alloc_mut().stack_mut() };
+            let len = stack.len();
+            stack[len / 2] = 0xaa;
+            stack[len - 1] = 0xbb;
+        }
+        {
+            let sigstack = unsafe { inst.alloc_mut().sigstack_mut() };
+            let len = sigstack.len();
+            sigstack[len - 1] = 0xcc;
+        }
+        drop(inst);
+
+        let mut inst = new_instance();
+        assert!(unsafe { inst.alloc_mut().stack_mut() }
+            .iter()
+            .all(|b| *b == 0));
+        assert!(unsafe { inst.alloc_mut().sigstack_mut() }
+            .iter()
+            .all(|b| *b == 0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn zero_cleanup_does_not_fault_in_stack_pages() {
+        let region = MmapRegion::create(1, &LIMITS).expect("region created");
+        let mut inst = region
+            .new_instance_builder(module())
+            .with_cleanup_strategy(CleanupStrategy::Zero)
+            .build()
+            .expect("new_instance succeeds");
+        let (stack, stack_len) = {
+            let stack = unsafe { inst.alloc_mut().stack_mut() };
+            let len = stack.len();
+            stack[len - 1] = 0xaa;
+            (stack.as_mut_ptr() as *mut c_void, len)
+        };
+        let (sigstack, sigstack_len) = {
+            let sigstack = unsafe { inst.alloc_mut().sigstack_mut() };
+            (sigstack.as_mut_ptr() as *mut c_void, sigstack.len())
+        };
+        let resident = |ptr, len| unsafe { resident_pages(ptr, len) }.expect("mincore succeeds");
+        let stack_resident = resident(stack, stack_len);
+        let sigstack_resident = resident(sigstack, sigstack_len);
+        drop(inst);
+
+        // finding the high-water marks only reads the pages that were already resident
+        assert_eq!(resident(stack, stack_len), stack_resident);
+        assert_eq!(resident(sigstack, sigstack_len), sigstack_resident);
+    }
+
+    #[test]
+    fn only_zero_cleanup_keeps_pages_resident() {
+        for (strategy, resident) in &[
+            (CleanupStrategy::Decommit, false),
+            (CleanupStrategy::Zero, true),
+            (CleanupStrategy::Unmap, false),
+        ] {
+            let region = MmapRegion::create(1, &LIMITS).expect("region created");
+            let mut inst = region
+                .new_instance_builder(module())
+                .with_cleanup_strategy(*strategy)
+                .build()
+                .expect("new_instance succeeds");
+            inst.heap_mut()[0] = 0xaa;
+            let heap = inst.heap_mut().as_mut_ptr() as *mut c_void;
+            drop(inst);
+
+            let mut pages = [0u8; 1];
+            assert_eq!(
+                unsafe { libc::mincore(heap, host_page_size(), pages.as_mut_ptr()) },
+                0
+            );
+            assert_eq!(
+                pages[0] & 1 == 1,
+                *resident,
+                "residency after {:?}",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_growth_policies_are_rejected() {
+        for growth in &[
+            GrowthPolicy::new(0),
+            GrowthPolicy::new(2).with_chunk_slots(0),
+            GrowthPolicy::new(2).with_initial_slots(3),
+        ] {
+            match MmapRegion::create_growable(growth, &LIMITS) {
+                Err(Error::InvalidArgument(_)) => (),
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("region created with invalid growth policy {:?}", growth),
+            }
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use crate::alloc::{instance_heap_offset, AddrLocation, Alloc, AllocStrategy, Limits, Slot};
+use crate::alloc::{
+    instance_heap_offset, AddrLocation, Alloc, AllocStrategy, CleanupStrategy, Limits, Slot,
+};
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
 use crate::instance::{new_instance_handle, Instance, InstanceHandle, InstanceInternal};
+use crate::memory_pool::MemoryPool;
 use crate::module::Module;
 use crate::region::{Region, RegionCreate, RegionInternal};
-use crate::sysdeps::host_page_size;
+use crate::stats::{RegionCounters, RegionStats};
+use crate::sysdeps::{host_page_size, resident_pages};
 use crate::WASM_PAGE_SIZE;
 use crate::{lucet_bail, lucet_ensure, lucet_format_err};
 use libc::c_void;
@@ -12,7 +16,8 @@ use nix::poll;
 use nix::sys::mman::{madvise, mmap, munmap, MapFlags, MmapAdvise, ProtFlags};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::ptr;
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::thread::{self, JoinHandle};
 use userfaultfd::{IoctlFlags, Uffd, UffdBuilder};
 
@@ -37,10 +42,20 @@ use userfaultfd::{IoctlFlags, Uffd, UffdBuilder};
 /// it should be blank we again just zero it out. In the case that it should contain data, we copy
 /// the data into the page. In any case we finish by reawakening the guest thread.
 ///
+/// If the module has a [`HeapImage`](../module/struct.HeapImage.html), as a
+/// [`Snapshot`](../module/struct.Snapshot.html) does, the image is instead mapped copy-on-write over
+/// the start of the heap when an instance is created or reset, in the same way as in an
+/// `MmapRegion`. Those pages are then handled by the kernel rather than `userfaultfd`, and are
+/// shared between all of the instances using the image until an instance writes to them.
+///
 /// If the fault occurs in a guard page, we do nothing, and reawaken the thread without allocating
 /// the backing physical memory. This ends up causing the guest thread to raise a SIGBUS, which is
 /// treated as a fatal error by the Lucet signal handler.
 ///
+/// Unlike an `MmapRegion`, a `UffdRegion` cannot grow: its whole capacity is reserved and
+/// registered with `userfaultfd` as a single range when it is created. Only the memory instances
+/// touch is ever backed, though.
+///
 /// [userfaultfd]: http://man7.org/linux/man-pages/man2/userfaultfd.2.html
 pub struct UffdRegion {
     uffd: Arc<Uffd>,
@@ -50,12 +65,18 @@ pub struct UffdRegion {
     instance_capacity: usize,
     handler: Option<JoinHandle<Result<(), Error>>>,
     handler_pipe: RawFd,
+    counters: Arc<RegionCounters>,
+    /// The number of page faults handled for the instance in each slot.
+    slot_faults: Arc<Vec<AtomicU64>>,
+    /// The pool instances are charged to unless they are given one of their own.
+    memory_pool: RwLock<Option<Arc<MemoryPool>>>,
 }
 
 // the start pointer prevents these from auto-deriving
 unsafe impl Send for UffdRegion {}
 unsafe impl Sync for UffdRegion {}
 
+#[allow(clippy::too_many_arguments)]
 fn uffd_handler(
     uffd_strategy: impl UffdStrategy,
     uffd: Arc<Uffd>,
@@ -63,6 +84,8 @@ fn uffd_handler(
     instance_capacity: usize,
     handler_pipe: RawFd,
     limits: Limits,
+    counters: Arc<RegionCounters>,
+    slot_faults: Arc<Vec<AtomicU64>>,
 ) -> Result<(), Error> {
     use userfaultfd::Event;
 
@@ -159,14 +182,22 @@ fn uffd_handler(
                             .map_err(|e| Error::InternalError(e.into()))?;
                     }
                     AddrLocation::Stack => {
-                        uffd_strategy.stack_fault(&uffd, fault_page as *mut c_void)?
+                        // count the fault before waking the faulting thread, so that the fault is
+                        // already in the instance's statistics once it carries on
+                        counters.record_page_fault();
+                        slot_faults[fault_base / instance_size].fetch_add(1, Ordering::Relaxed);
+                        uffd_strategy.stack_fault(&uffd, fault_page as *mut c_void)?;
+                    }
+                    AddrLocation::Heap => {
+                        counters.record_page_fault();
+                        slot_faults[fault_base / instance_size].fetch_add(1, Ordering::Relaxed);
+                        uffd_strategy.heap_fault(
+                            &uffd,
+                            inst.module(),
+                            alloc,
+                            fault_page as *mut c_void,
+                        )?;
                     }
-                    AddrLocation::Heap => uffd_strategy.heap_fault(
-                        &uffd,
-                        inst.module(),
-                        alloc,
-                        fault_page as *mut c_void,
-                    )?,
                 }
             }
             Ok(Some(ev)) => panic!("unexpected uffd event: {:?}", ev),
@@ -188,16 +219,43 @@ impl Region for UffdRegion {
     fn capacity(&self) -> usize {
         self.instance_capacity
     }
+
+    fn stats(&self) -> RegionStats {
+        let total_region_size = self.instance_capacity * self.limits.total_memory_size();
+        let resident_pages = unsafe { resident_pages(self.start, total_region_size) }
+            .expect("mincore succeeds on the region")
+            .private;
+        self.counters.stats(
+            self.instance_capacity,
+            self.used_slots(),
+            resident_pages,
+            Some(self.counters.page_faults()),
+        )
+    }
+
+    fn set_memory_pool(&self, pool: Option<Arc<MemoryPool>>) {
+        *self.memory_pool.write().unwrap() = pool;
+    }
 }
 
 impl RegionInternal for UffdRegion {
+    #[allow(clippy::too_many_arguments)]
     fn new_instance_with(
         &self,
         module: Arc<dyn Module>,
         embed_ctx: CtxMap,
         heap_memory_size_limit: usize,
         mut alloc_strategy: AllocStrategy,
+        cleanup_strategy: CleanupStrategy,
+        memory_pool: Option<Arc<MemoryPool>>,
     ) -> Result<InstanceHandle, Error> {
+        // pages left in place would not be filled in by the fault handler for the next instance
+        if cleanup_strategy != CleanupStrategy::Decommit {
+            return Err(Error::Unsupported(format!(
+                "CleanupStrategy::{:?} is not supported by UffdRegion",
+                cleanup_strategy
+            )));
+        }
         let limits = self.get_limits();
         module.validate_runtime_spec(&limits, heap_memory_size_limit)?;
 
@@ -209,6 +267,7 @@ impl RegionInternal for UffdRegion {
             let slot_index = alloc_strategy.next(free_slot_vector.len(), self.capacity())?;
             slot = free_slot_vector.swap_remove(slot_index);
         }
+        self.slot_faults[self.slot_index(&slot)].store(0, Ordering::Relaxed);
 
         assert_eq!(
             slot.heap as usize % host_page_size(),
@@ -253,6 +312,11 @@ impl RegionInternal for UffdRegion {
                 .unwrap_or(0),
             heap_inaccessible_size: slot.limits.heap_address_space_size,
             heap_memory_size_limit,
+            heap_image_mapped: false,
+            cleanup_strategy,
+            globals_size: module.initial_globals_size(),
+            memory_pool,
+            memory_pool_reservation: 0,
             slot: Some(slot),
             region,
         };
@@ -272,6 +336,14 @@ impl RegionInternal for UffdRegion {
             panic!("heap is not page-aligned");
         }
 
+        if alloc.heap_image_mapped {
+            unsafe {
+                self.unmap_heap_image(slot.heap, alloc.heap_accessible_size)
+                    .expect("heap image can be unmapped during drop");
+            }
+            alloc.heap_image_mapped = false;
+        }
+
         // set dontneed for everything past the `Instance` page
         let ptr = (slot.start as usize + instance_heap_offset()) as *mut c_void;
         let len = slot.limits.total_memory_size() - instance_heap_offset();
@@ -283,6 +355,18 @@ impl RegionInternal for UffdRegion {
         self.freelist.lock().unwrap().push(slot);
     }
 
+    fn memory_pool(&self) -> Option<Arc<MemoryPool>> {
+        self.memory_pool.read().unwrap().clone()
+    }
+
+    fn counters(&self) -> &RegionCounters {
+        &self.counters
+    }
+
+    fn slot_page_faults(&self, slot: &Slot) -> Option<u64> {
+        Some(self.slot_faults[self.slot_index(slot)].load(Ordering::Relaxed))
+    }
+
     fn expand_heap(&self, _slot: &Slot, _start: u32, _len: u32) -> Result<(), Error> {
         // the actual work of heap expansion for UFFD is done in the worker thread; we just need the
         // `Alloc` to validate the new limits and update the metadata
@@ -290,14 +374,17 @@ impl RegionInternal for UffdRegion {
     }
 
     fn reset_heap(&self, alloc: &mut Alloc, module: &dyn Module) -> Result<(), Error> {
+        let heap = alloc.slot().heap;
+
         // zero the heap, if any of it is currently accessible
         if alloc.heap_accessible_size > 0 {
             unsafe {
-                madvise(
-                    alloc.slot().heap,
-                    alloc.heap_accessible_size,
-                    MmapAdvise::MADV_DONTNEED,
-                )?;
+                if alloc.heap_image_mapped {
+                    self.unmap_heap_image(heap, alloc.heap_accessible_size)?;
+                    alloc.heap_image_mapped = false;
+                } else {
+                    madvise(heap, alloc.heap_accessible_size, MmapAdvise::MADV_DONTNEED)?;
+                }
             }
         }
 
@@ -306,6 +393,29 @@ impl RegionInternal for UffdRegion {
             .heap_spec()
             .map(|h| h.initial_size as usize)
             .unwrap_or(0);
+
+        // If the module has a heap image, map it copy-on-write over the start of the heap. The
+        // mapping replaces the registered memory there, so the handler only sees faults past it.
+        if let Some(image) = module.heap_image() {
+            if image.len() > initial_size {
+                return Err(lucet_incorrect_module!(
+                    "heap image size {} exceeds initial heap size {}",
+                    image.len(),
+                    initial_size
+                ));
+            }
+            unsafe {
+                mmap(
+                    heap,
+                    image.len(),
+                    ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                    MapFlags::MAP_PRIVATE | MapFlags::MAP_FIXED,
+                    image.fd(),
+                    0,
+                )?;
+            }
+            alloc.heap_image_mapped = true;
+        }
         alloc.heap_accessible_size = initial_size;
         alloc.heap_inaccessible_size = alloc.slot().limits.heap_address_space_size - initial_size;
         Ok(())
@@ -417,10 +527,19 @@ impl UffdRegion {
 
         let (handler_pipe_recv, handler_pipe) = nix::unistd::pipe()?;
 
+        let counters = Arc::new(RegionCounters::default());
+        let slot_faults = Arc::new(
+            (0..instance_capacity)
+                .map(|_| AtomicU64::new(0))
+                .collect::<Vec<_>>(),
+        );
+
         let handler_uffd = uffd.clone();
         // morally equivalent to `unsafe impl Send`
         let handler_start = start as usize;
         let handler_limits = limits.clone();
+        let handler_counters = counters.clone();
+        let handler_slot_faults = slot_faults.clone();
         let handler = thread::Builder::new()
             .name("uffd region handler".into())
             .spawn(move || {
@@ -431,6 +550,8 @@ impl UffdRegion {
                     instance_capacity,
                     handler_pipe_recv,
                     handler_limits,
+                    handler_counters,
+                    handler_slot_faults,
                 );
                 // clean up the shutdown pipe before terminating
                 if let Err(e) = nix::unistd::close(handler_pipe_recv) {
@@ -463,6 +584,9 @@ impl UffdRegion {
             instance_capacity,
             handler: Some(handler),
             handler_pipe,
+            counters,
+            slot_faults,
+            memory_pool: RwLock::new(None),
         });
 
         {
@@ -475,6 +599,33 @@ impl UffdRegion {
         Ok(region)
     }
 
+    /// Replace a heap image mapped over the start of a heap with fresh memory, and register it with
+    /// `userfaultfd` again so that the handler fills its pages in like the rest of the heap.
+    ///
+    /// `len` must cover the whole image, but may extend past it.
+    unsafe fn unmap_heap_image(&self, heap: *mut c_void, len: usize) -> Result<(), Error> {
+        mmap(
+            heap,
+            len,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            MapFlags::MAP_ANONYMOUS
+                | MapFlags::MAP_PRIVATE
+                | MapFlags::MAP_NORESERVE
+                | MapFlags::MAP_FIXED,
+            0,
+            0,
+        )?;
+        self.uffd
+            .register(heap, len)
+            .map_err(|e| Error::InternalError(e.into()))?;
+        Ok(())
+    }
+
+    /// The index of a slot in the region.
+    fn slot_index(&self, slot: &Slot) -> usize {
+        (slot.start as usize - self.start as usize) / self.limits.total_memory_size()
+    }
+
     fn create_slot(region: &Arc<UffdRegion>, index: usize) -> Result<Slot, Error> {
         // get the memory from the offset into the overall region
         let start =
@@ -501,6 +652,7 @@ impl UffdRegion {
             globals: globals as *mut c_void,
             sigstack: sigstack as *mut c_void,
             limits: region.limits.clone(),
+            last_module: None,
             region: Arc::downgrade(region) as Weak<dyn RegionInternal>,
         })
     }
@@ -602,6 +754,17 @@ impl UffdStrategy for WasmPageSizedUffdStrategy {
                 break;
             }
 
+            // pages mapped from a heap image are not registered with uffd, and already hold their
+            // contents
+            if alloc.heap_image_mapped
+                && module
+                    .heap_image()
+                    .map(|image| pages_into_heap * host_page_size() < image.len())
+                    .unwrap_or(false)
+            {
+                continue;
+            }
+
             // page fault occurred in the heap; copy or zero
             if let Some(page) = module.get_sparse_page_data(pages_into_heap) {
                 // we are in the sparse data area, with a non-empty page; copy it in
@@ -0,0 +1,178 @@
+//! Memory and timing statistics for regions and instances.
+//!
+//! These are meant for monitoring: they attribute memory use to the instances and regions holding
+//! it, which process-wide sources such as `/proc/self/smaps` cannot.
+
+use crate::sysdeps::host_page_size;
+use libc::c_void;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Statistics about an instance, from
+/// [`Instance::stats()`](../instance/struct.Instance.html#method.stats).
+#[derive(Clone, Debug)]
+pub struct InstanceStats {
+    /// The size of the WebAssembly heap in bytes.
+    pub heap_size: usize,
+    /// The largest the heap has been since the instance was created, in bytes.
+    pub peak_heap_size: usize,
+    /// The number of host pages of the heap that are backed by physical memory of the instance's
+    /// own.
+    pub resident_heap_pages: usize,
+    /// The number of host pages of the heap that are backed by physical memory shared with other
+    /// instances: the pages of a heap image that the instance has not written to.
+    ///
+    /// Shared memory is held once however many instances map it, so these pages are not included
+    /// in `resident_heap_pages`, and should not be added up across instances. Shared pages are only
+    /// told apart on Linux, using `/proc/self/pagemap`; where it cannot be read, they are counted
+    /// in `resident_heap_pages`.
+    pub shared_heap_pages: usize,
+    /// An estimate of the most stack the instance has used since it was created, in bytes.
+    ///
+    /// This is a heuristic: it is measured by finding the lowest byte of the stack that is not
+    /// zero, so it undercounts if the deepest stack frames only ever held zeros, and it is not
+    /// reduced when the stack unwinds.
+    pub stack_high_water_mark: usize,
+    /// The number of times the heap has been asked to grow, whether or not it could.
+    pub grow_memory_calls: u64,
+    /// How long it took to create the instance.
+    pub instantiation_time: Duration,
+    /// The number of times the instance has been reset since it was created.
+    pub resets: u64,
+    /// How long the last reset of the instance took, if it has been reset.
+    pub last_reset_time: Option<Duration>,
+    /// The number of page faults the region has handled for the instance, for regions that handle
+    /// page faults themselves, such as `UffdRegion`.
+    pub page_faults: Option<u64>,
+}
+
+/// The counters behind an instance's statistics.
+#[derive(Debug, Default)]
+pub(crate) struct InstanceCounters {
+    pub(crate) peak_heap_size: usize,
+    pub(crate) grow_memory_calls: u64,
+    pub(crate) instantiation_time: Duration,
+    pub(crate) resets: u64,
+    pub(crate) last_reset_time: Option<Duration>,
+}
+
+/// Statistics about a region, from
+/// [`Region::stats()`](../region/trait.Region.html#method.stats).
+///
+/// The counts of instances, resets, and `grow_memory` calls cover every instance created in the
+/// region since it was created.
+#[derive(Clone, Debug)]
+pub struct RegionStats {
+    /// The total instance slot capacity of the region.
+    pub capacity: usize,
+    /// The number of instance slots currently in use.
+    pub used_slots: usize,
+    /// The number of host pages of the region's memory backed by physical memory, including that
+    /// of slots not in use.
+    ///
+    /// Pages shared with memory outside the region, such as the pages of heap images that
+    /// instances have not written to, are not included, as they are held once however many
+    /// instances map them.
+    pub resident_pages: usize,
+    /// The number of instances created.
+    pub instances_created: u64,
+    /// How long it took to create those instances, in total.
+    pub instantiation_time: Duration,
+    /// The number of times instances were reset.
+    pub resets: u64,
+    /// How long those resets took, in total.
+    pub reset_time: Duration,
+    /// The number of times instance heaps were asked to grow.
+    pub grow_memory_calls: u64,
+    /// The number of page faults the region has handled, for regions that handle page faults
+    /// themselves, such as `UffdRegion`.
+    pub page_faults: Option<u64>,
+}
+
+/// The counters behind a region's statistics, which are updated by the instances in the region.
+#[derive(Debug, Default)]
+pub struct RegionCounters {
+    instances_created: AtomicU64,
+    instantiation_nanos: AtomicU64,
+    resets: AtomicU64,
+    reset_nanos: AtomicU64,
+    grow_memory_calls: AtomicU64,
+    page_faults: AtomicU64,
+}
+
+impl RegionCounters {
+    pub(crate) fn record_instantiation(&self, time: Duration) {
+        self.instances_created.fetch_add(1, Ordering::Relaxed);
+        self.instantiation_nanos
+            .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_reset(&self, time: Duration) {
+        self.resets.fetch_add(1, Ordering::Relaxed);
+        self.reset_nanos
+            .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_grow_memory(&self) {
+        self.grow_memory_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_page_fault(&self) {
+        self.page_faults.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn page_faults(&self) -> u64 {
+        self.page_faults.load(Ordering::Relaxed)
+    }
+
+    /// Combine the counters with the current state of the region.
+    pub(crate) fn stats(
+        &self,
+        capacity: usize,
+        used_slots: usize,
+        resident_pages: usize,
+        page_faults: Option<u64>,
+    ) -> RegionStats {
+        RegionStats {
+            capacity,
+            used_slots,
+            resident_pages,
+            instances_created: self.instances_created.load(Ordering::Relaxed),
+            instantiation_time: Duration::from_nanos(
+                self.instantiation_nanos.load(Ordering::Relaxed),
+            ),
+            resets: self.resets.load(Ordering::Relaxed),
+            reset_time: Duration::from_nanos(self.reset_nanos.load(Ordering::Relaxed)),
+            grow_memory_calls: self.grow_memory_calls.load(Ordering::Relaxed),
+            page_faults,
+        }
+    }
+}
+
+/// The number of bytes between the top of a stack and its lowest byte that is not zero.
+///
+/// This is only an estimate of the most stack that has been used, as frames may hold zeros.
+///
+/// Pages not backed by physical memory are skipped rather than read, as reading them would fault
+/// them in, which in a `UffdRegion` means handling the fault.
+///
+/// # Safety
+///
+/// `stack..stack + len` must be mapped and readable, and `stack` must be page-aligned.
+pub(crate) unsafe fn stack_high_water_mark(stack: *const u8, len: usize) -> usize {
+    let page_size = host_page_size();
+    let mut residency = vec![0u8; len / page_size];
+    nix::errno::Errno::result(libc::mincore(
+        stack as *mut c_void,
+        len,
+        residency.as_mut_ptr() as *mut _,
+    ))
+    .expect("mincore succeeds on the stack");
+    for (page_num, _) in residency.iter().enumerate().filter(|(_, r)| *r & 1 != 0) {
+        let page = std::slice::from_raw_parts(stack.add(page_num * page_size), page_size);
+        if let Some(offset) = page.iter().position(|b| *b != 0) {
+            return len - (page_num * page_size + offset);
+        }
+    }
+    0
+}
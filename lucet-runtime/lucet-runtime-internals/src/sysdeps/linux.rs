@@ -1,4 +1,6 @@
-use libc::{c_void, ucontext_t, REG_RDI, REG_RIP};
+use libc::{c_void, ucontext_t};
+#[cfg(target_arch = "x86_64")]
+use libc::{REG_RBP, REG_RDI, REG_RIP};
 
 #[derive(Clone, Copy, Debug)]
 pub struct UContextPtr(*mut ucontext_t);
@@ -10,23 +12,73 @@ impl UContextPtr {
         UContextPtr(ptr as *mut ucontext_t)
     }
 
+    #[cfg(target_arch = "x86_64")]
     #[inline]
     pub fn get_ip(self) -> *const c_void {
         let mcontext = &unsafe { self.0.as_ref().unwrap() }.uc_mcontext;
         mcontext.gregs[REG_RIP as usize] as *const _
     }
 
+    #[cfg(target_arch = "x86_64")]
     #[inline]
     pub fn set_ip(self, new_ip: *const c_void) {
         let mut mcontext = &mut unsafe { self.0.as_mut().unwrap() }.uc_mcontext;
         mcontext.gregs[REG_RIP as usize] = new_ip as i64;
     }
 
+    /// Get the frame pointer, `rbp`.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub fn get_fp(self) -> usize {
+        let mcontext = &unsafe { self.0.as_ref().unwrap() }.uc_mcontext;
+        mcontext.gregs[REG_RBP as usize] as usize
+    }
+
+    #[cfg(target_arch = "x86_64")]
     #[inline]
     pub fn set_rdi(self, new_rdi: u64) {
         let mut mcontext = &mut unsafe { self.0.as_mut().unwrap() }.uc_mcontext;
         mcontext.gregs[REG_RDI as usize] = new_rdi as i64;
     }
+
+    /// Set the register holding the first argument of a function, for when the context resumes
+    /// at the start of one.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub fn set_first_arg(self, arg: u64) {
+        self.set_rdi(arg)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    pub fn get_ip(self) -> *const c_void {
+        let mcontext = &unsafe { self.0.as_ref().unwrap() }.uc_mcontext;
+        mcontext.pc as *const _
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    pub fn set_ip(self, new_ip: *const c_void) {
+        let mut mcontext = &mut unsafe { self.0.as_mut().unwrap() }.uc_mcontext;
+        mcontext.pc = new_ip as u64;
+    }
+
+    /// Get the frame pointer, `x29`.
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    pub fn get_fp(self) -> usize {
+        let mcontext = &unsafe { self.0.as_ref().unwrap() }.uc_mcontext;
+        mcontext.regs[29] as usize
+    }
+
+    /// Set the register holding the first argument of a function, for when the context resumes
+    /// at the start of one.
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    pub fn set_first_arg(self, arg: u64) {
+        let mut mcontext = &mut unsafe { self.0.as_mut().unwrap() }.uc_mcontext;
+        mcontext.regs[0] = arg;
+    }
 }
 
 #[repr(C)]
@@ -54,3 +106,123 @@ impl Into<UContext> for UContextPtr {
         UContext { context: self.0 }
     }
 }
+
+/// Counts the pages of a range that are mapped from a file or shared memory, using
+/// `/proc/self/pagemap`.
+pub(crate) struct SharedPageCounter {
+    pagemap: Option<std::fs::File>,
+}
+
+impl SharedPageCounter {
+    pub(crate) fn new() -> Self {
+        SharedPageCounter {
+            pagemap: std::fs::File::open("/proc/self/pagemap").ok(),
+        }
+    }
+
+    /// Count the resident pages in `addr..addr + pages * host_page_size()` that are mapped from a
+    /// file or shared memory, rather than being anonymous memory of the process's own.
+    ///
+    /// Without access to `/proc/self/pagemap`, no pages are counted as shared.
+    pub(crate) fn count(&mut self, addr: usize, pages: usize) -> nix::Result<usize> {
+        use std::os::unix::fs::FileExt;
+
+        // bits of a pagemap entry; see Documentation/admin-guide/mm/pagemap.rst
+        const PRESENT: u64 = 1 << 63;
+        const FILE_OR_SHARED: u64 = 1 << 61;
+        const CHUNK_PAGES: usize = 4096;
+
+        let pagemap = match &self.pagemap {
+            Some(pagemap) => pagemap,
+            None => return Ok(0),
+        };
+        let first_page = addr / super::host_page_size();
+        let mut entries = vec![0u8; 8 * CHUNK_PAGES.min(pages)];
+        let mut shared = 0;
+        let mut page = 0;
+        while page < pages {
+            let chunk = CHUNK_PAGES.min(pages - page);
+            pagemap
+                .read_exact_at(&mut entries[..8 * chunk], 8 * (first_page + page) as u64)
+                .map_err(|e| {
+                    nix::Error::Sys(nix::errno::Errno::from_i32(e.raw_os_error().unwrap_or(0)))
+                })?;
+            shared += entries[..8 * chunk]
+                .chunks(8)
+                .map(|entry| {
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(entry);
+                    u64::from_ne_bytes(bytes)
+                })
+                .filter(|entry| entry & PRESENT != 0 && entry & FILE_OR_SHARED != 0)
+                .count();
+            page += chunk;
+        }
+        Ok(shared)
+    }
+}
+
+#[cfg(all(test, target_arch = "aarch64"))]
+mod tests {
+    use super::UContextPtr;
+    use crate::sysdeps::host_page_size;
+    use libc::{c_char, c_int, c_void, siginfo_t, ucontext_t};
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    extern "C" {
+        fn __clear_cache(start: *mut c_char, end: *mut c_char);
+    }
+
+    /// `brk #0`, which raises `SIGTRAP` with the program counter still pointing at it.
+    const BRK_0: [u8; 4] = [0x00, 0x00, 0x20, 0xd4];
+
+    static TRAP_IP: AtomicUsize = AtomicUsize::new(0);
+
+    /// Make the trapping function return 42, by resuming at its return address with the return
+    /// value in `x0`.
+    extern "C" fn return_42(_signum: c_int, _siginfo: *mut siginfo_t, ucontext: *mut c_void) {
+        let ctx = UContextPtr::new(ucontext);
+        TRAP_IP.store(ctx.get_ip() as usize, Ordering::SeqCst);
+        let lr = unsafe { (*(ucontext as *mut ucontext_t)).uc_mcontext.regs[30] };
+        ctx.set_first_arg(42);
+        ctx.set_ip(lr as *const c_void);
+    }
+
+    #[test]
+    fn signal_handler_redirects_execution() {
+        let page_size = host_page_size();
+        unsafe {
+            let code = libc::mmap(
+                ptr::null_mut(),
+                page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(code, libc::MAP_FAILED);
+            ptr::copy_nonoverlapping(BRK_0.as_ptr(), code as *mut u8, BRK_0.len());
+            __clear_cache(code as *mut c_char, (code as *mut c_char).add(BRK_0.len()));
+            assert_eq!(
+                libc::mprotect(code, page_size, libc::PROT_READ | libc::PROT_EXEC),
+                0
+            );
+
+            let sa = SigAction::new(
+                SigHandler::SigAction(return_42),
+                SaFlags::SA_SIGINFO,
+                SigSet::empty(),
+            );
+            let saved = sigaction(Signal::SIGTRAP, &sa).expect("sigaction succeeds");
+            let trapping_fn: extern "C" fn() -> u64 = std::mem::transmute(code);
+            let res = trapping_fn();
+            sigaction(Signal::SIGTRAP, &saved).expect("sigaction succeeds");
+
+            assert_eq!(res, 42);
+            assert_eq!(TRAP_IP.load(Ordering::SeqCst), code as usize);
+            assert_eq!(libc::munmap(code, page_size), 0);
+        }
+    }
+}
@@ -143,11 +143,25 @@ impl UContextPtr {
         mcontext.ss.rip = new_ip as u64;
     }
 
+    /// Get the frame pointer, `rbp`.
+    #[inline]
+    pub fn get_fp(self) -> usize {
+        let mcontext = unsafe { (*self.0).uc_mcontext.as_ref().unwrap() };
+        mcontext.ss.rbp as usize
+    }
+
     #[inline]
     pub fn set_rdi(self, new_rdi: u64) {
         let mcontext: &mut mcontext64 = unsafe { &mut (*self.0).uc_mcontext.as_mut().unwrap() };
         mcontext.ss.rdi = new_rdi;
     }
+
+    /// Set the register holding the first argument of a function, for when the context resumes
+    /// at the start of one.
+    #[inline]
+    pub fn set_first_arg(self, arg: u64) {
+        self.set_rdi(arg)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -174,3 +188,19 @@ impl Into<UContext> for UContextPtr {
         UContext::new(self.0 as *mut _)
     }
 }
+
+/// Counts the pages of a range that are mapped from a file or shared memory.
+///
+/// Heap images need `memfd_create(2)`, so are not available on macOS, and
+/// instance memory is never shared.
+pub(crate) struct SharedPageCounter;
+
+impl SharedPageCounter {
+    pub(crate) fn new() -> Self {
+        SharedPageCounter
+    }
+
+    pub(crate) fn count(&mut self, _addr: usize, _pages: usize) -> nix::Result<usize> {
+        Ok(0)
+    }
+}
@@ -1,3 +1,4 @@
+use super::SharedPageCounter;
 use nix::unistd::{sysconf, SysconfVar};
 use std::sync::Once;
 
@@ -5,7 +6,8 @@ pub const HOST_PAGE_SIZE_EXPECTED: usize = 4096;
 static mut HOST_PAGE_SIZE: usize = 0;
 static HOST_PAGE_SIZE_INIT: Once = Once::new();
 
-/// Linux x86-64 and Mac x86-64 hosts should always use a 4K page.
+/// Linux x86-64 and Mac x86-64 hosts should always use a 4K page. AArch64 Linux kernels can be
+/// configured with larger pages; those are not supported.
 ///
 /// We double check the expected value using `sysconf` at runtime.
 pub fn host_page_size() -> usize {
@@ -26,3 +28,57 @@ pub fn host_page_size() -> usize {
         HOST_PAGE_SIZE
     }
 }
+
+/// The host pages of a range of memory that are backed by physical memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResidentPages {
+    /// Pages that belong to the range alone.
+    pub private: usize,
+    /// Pages of a file or shared memory mapped into the range, such as the pages of a heap image
+    /// that have not been written to, which every other mapping of the same memory shares.
+    pub shared: usize,
+}
+
+/// Count the host pages in `addr..addr + len` that are backed by physical memory.
+///
+/// # Safety
+///
+/// `addr` must be page-aligned, and the whole range must be mapped.
+pub unsafe fn resident_pages(addr: *mut libc::c_void, len: usize) -> nix::Result<ResidentPages> {
+    // `mincore()` needs a byte for every page it reports on, so large ranges are counted in parts
+    const CHUNK_PAGES: usize = 64 * 1024;
+
+    let page_size = host_page_size();
+    let pages = (len + page_size - 1) / page_size;
+    let mut residency = vec![0u8; CHUNK_PAGES.min(pages)];
+    let mut resident = ResidentPages::default();
+    let mut shared_pages = SharedPageCounter::new();
+    let mut page = 0;
+    while page < pages {
+        let chunk = CHUNK_PAGES.min(pages - page);
+        nix::errno::Errno::result(libc::mincore(
+            (addr as usize + page * page_size) as *mut libc::c_void,
+            chunk * page_size,
+            residency.as_mut_ptr() as *mut _,
+        ))?;
+        // only look up whether pages are shared for runs of resident pages, which are usually few
+        let mut run_start = None;
+        for i in 0..=chunk {
+            let is_resident = i < chunk && residency[i] & 1 != 0;
+            match (run_start, is_resident) {
+                (None, true) => run_start = Some(i),
+                (Some(start), false) => {
+                    let run = i - start;
+                    let shared =
+                        shared_pages.count(addr as usize + (page + start) * page_size, run)?;
+                    resident.shared += shared;
+                    resident.private += run - shared;
+                    run_start = None;
+                }
+                _ => (),
+            }
+        }
+        page += chunk;
+    }
+    Ok(resident)
+}
@@ -2,13 +2,112 @@
 //! programs.
 
 use libc::c_void;
-use std::arch::x86_64::{
-    __m128, _mm_castpd_ps, _mm_castps_pd, _mm_load_pd1, _mm_load_ps1, _mm_setzero_ps,
-    _mm_storeu_pd, _mm_storeu_ps,
-};
 
 use lucet_module::ValueType;
 
+/// The contents of a floating-point or vector register: an SSE register on x86-64.
+#[cfg(target_arch = "x86_64")]
+pub use std::arch::x86_64::__m128;
+
+/// The contents of a floating-point or vector register: a SIMD and floating-point register on
+/// AArch64.
+///
+/// The NEON types in `std::arch` are not stable, so this holds the register's 128 bits laid out as
+/// they are in memory, with scalar values in the low bits. The name matches the x86-64 type so that
+/// code handling return values does not depend on the architecture.
+#[cfg(target_arch = "aarch64")]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(16))]
+pub struct __m128(u128);
+
+#[cfg(target_arch = "x86_64")]
+mod fp_reg {
+    use std::arch::x86_64::{
+        __m128, _mm_castpd_ps, _mm_castps_pd, _mm_load_pd1, _mm_load_ps1, _mm_loadu_ps,
+        _mm_setzero_ps, _mm_storeu_pd, _mm_storeu_ps,
+    };
+
+    pub fn zero() -> __m128 {
+        unsafe { _mm_setzero_ps() }
+    }
+
+    pub fn from_f32(v: f32) -> __m128 {
+        unsafe { _mm_load_ps1(&v as *const f32) }
+    }
+
+    pub fn from_f64(v: f64) -> __m128 {
+        unsafe { _mm_castpd_ps(_mm_load_pd1(&v as *const f64)) }
+    }
+
+    pub fn from_u128(v: u128) -> __m128 {
+        unsafe { _mm_loadu_ps(&v as *const u128 as *const f32) }
+    }
+
+    pub fn as_f32(v: __m128) -> f32 {
+        let mut out: [f32; 4] = [0.0; 4];
+        unsafe {
+            _mm_storeu_ps(&mut out[0] as *mut f32, v);
+        }
+        out[0]
+    }
+
+    pub fn as_f64(v: __m128) -> f64 {
+        let mut out: [f64; 2] = [0.0; 2];
+        unsafe {
+            let vd = _mm_castps_pd(v);
+            _mm_storeu_pd(&mut out[0] as *mut f64, vd);
+        }
+        out[0]
+    }
+
+    pub fn as_u128(v: __m128) -> u128 {
+        let mut out: u128 = 0;
+        unsafe {
+            _mm_storeu_ps(&mut out as *mut u128 as *mut f32, v);
+        }
+        out
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod fp_reg {
+    use super::__m128;
+
+    pub fn zero() -> __m128 {
+        __m128(0)
+    }
+
+    pub fn from_f32(v: f32) -> __m128 {
+        __m128(v.to_bits() as u128)
+    }
+
+    pub fn from_f64(v: f64) -> __m128 {
+        __m128(v.to_bits() as u128)
+    }
+
+    pub fn from_u128(v: u128) -> __m128 {
+        __m128(v)
+    }
+
+    pub fn as_f32(v: __m128) -> f32 {
+        f32::from_bits(v.0 as u32)
+    }
+
+    pub fn as_f64(v: __m128) -> f64 {
+        f64::from_bits(v.0 as u64)
+    }
+
+    pub fn as_u128(v: __m128) -> u128 {
+        v.0
+    }
+}
+
+/// A floating-point register with all bits clear.
+pub(crate) fn fp_reg_zero() -> __m128 {
+    fp_reg::zero()
+}
+
 impl Val {
     pub fn value_type(&self) -> ValueType {
         match self {
@@ -23,6 +122,9 @@ impl Val {
             Val::Bool(_) => ValueType::I32,
             Val::F32(_) => ValueType::F32,
             Val::F64(_) => ValueType::F64,
+            Val::ExternRef(_) => ValueType::ExternRef,
+            Val::FuncRef(_) => ValueType::FuncRef,
+            Val::V128(_) => ValueType::V128,
         }
     }
 }
@@ -46,6 +148,12 @@ pub enum Val {
     Bool(bool),
     F32(f32),
     F64(f64),
+    /// A WebAssembly `externref`: a host value that is opaque to the guest, or `0` for null.
+    ExternRef(u64),
+    /// A WebAssembly `funcref`: a pointer to a guest function, or `0` for null.
+    FuncRef(u64),
+    /// A WebAssembly `v128`, from the SIMD proposal, with its lanes in little-endian order.
+    V128(u128),
 }
 
 // the pointer variant is just a wrapper; the caller will know they're still responsible for their
@@ -92,14 +200,15 @@ impl_from_scalars!({
     ISize: isize,
     Bool: bool,
     F32: f32,
-    F64: f64
+    F64: f64,
+    V128: u128
 });
 
 /// Register representation of `Val`.
 ///
-/// When mapping `Val`s to x86_64 registers, we map floating point
-/// values into the SSE registers _xmmN_, and all other values into
-/// general-purpose (integer) registers.
+/// When mapping `Val`s to registers, we map floating point and vector values into the SSE registers
+/// _xmmN_ on x86-64 or the SIMD and floating-point registers _vN_ on AArch64, and all other values
+/// into general-purpose (integer) registers.
 pub enum RegVal {
     GpReg(u64),
     FpReg(__m128),
@@ -125,13 +234,19 @@ pub fn val_to_reg(val: &Val) -> RegVal {
         ISize(v) => GpReg(v as u64),
         Bool(false) => GpReg(0u64),
         Bool(true) => GpReg(1u64),
-        Val::F32(v) => FpReg(unsafe { _mm_load_ps1(&v as *const f32) }),
-        Val::F64(v) => FpReg(unsafe { _mm_castpd_ps(_mm_load_pd1(&v as *const f64)) }),
+        ExternRef(v) => GpReg(v),
+        FuncRef(v) => GpReg(v),
+        Val::F32(v) => FpReg(fp_reg::from_f32(v)),
+        Val::F64(v) => FpReg(fp_reg::from_f64(v)),
+        Val::V128(v) => FpReg(fp_reg::from_u128(v)),
     }
 }
 
 /// Convert a `Val` to its representation when spilled onto the
 /// stack.
+///
+/// `V128` values do not fit in a stack slot, and must always be passed in
+/// registers; this panics if given one.
 pub fn val_to_stack(val: &Val) -> u64 {
     use self::Val::*;
     match *val {
@@ -151,17 +266,33 @@ pub fn val_to_stack(val: &Val) -> u64 {
         Bool(true) => 1u64,
         F32(v) => v.to_bits() as u64,
         F64(v) => v.to_bits(),
+        ExternRef(v) => v,
+        FuncRef(v) => v,
+        V128(_) => panic!("v128 values cannot be passed on the stack"),
     }
 }
 
+/// The number of return registers of each class (general-purpose and floating-point) in the native
+/// calling convention.
+///
+/// A guest function called from the host may return at most this many integer values, and at most
+/// this many floating-point values.
+pub const MAX_RETURN_REGS: usize = 2;
+
 /// A value returned by a guest function.
 ///
 /// Since the Rust type system cannot know the type of the returned value, the user must use the
 /// appropriate `From` implementation or `as_T` method.
+///
+/// Guest functions that return multiple values are also supported. Since the instance knows the
+/// signature of the function it ran, the values can be retrieved with their types using
+/// [`vals()`](struct.UntypedRetVal.html#method.vals).
 #[derive(Clone, Copy, Debug)]
 pub struct UntypedRetVal {
-    fp: __m128,
-    gp: u64,
+    fp: [__m128; MAX_RETURN_REGS],
+    gp: [u64; MAX_RETURN_REGS],
+    results: [ValueType; 2 * MAX_RETURN_REGS],
+    results_len: usize,
 }
 
 impl std::fmt::Display for UntypedRetVal {
@@ -172,14 +303,108 @@ impl std::fmt::Display for UntypedRetVal {
 
 impl UntypedRetVal {
     pub(crate) fn new(gp: u64, fp: __m128) -> UntypedRetVal {
-        UntypedRetVal { gp, fp }
+        UntypedRetVal::new_multi([gp, 0], [fp, fp_reg_zero()])
     }
+
+    pub(crate) fn new_multi(
+        gp: [u64; MAX_RETURN_REGS],
+        fp: [__m128; MAX_RETURN_REGS],
+    ) -> UntypedRetVal {
+        UntypedRetVal {
+            fp,
+            gp,
+            results: [ValueType::I32; 2 * MAX_RETURN_REGS],
+            results_len: 0,
+        }
+    }
+
+    /// Attach the result types of the function that returned this value, so that the values can be
+    /// retrieved with `vals()`.
+    ///
+    /// Result types that do not fit in the return registers are ignored; callers must check this
+    /// with `results_fit_in_regs()` before running the function.
+    pub(crate) fn with_results(mut self, results: &[ValueType]) -> UntypedRetVal {
+        if results_fit_in_regs(results) {
+            self.results[..results.len()].copy_from_slice(results);
+            self.results_len = results.len();
+        }
+        self
+    }
+
+    /// Get the values returned by the guest function, in order, typed according to its signature.
+    ///
+    /// This is empty for functions with no return values, and for return values that were not
+    /// produced by running a guest function with a known signature.
+    pub fn vals(&self) -> Vec<Val> {
+        let mut gp = self.gp.iter();
+        let mut fp = self.fp.iter();
+        self.results[..self.results_len]
+            .iter()
+            .map(|ty| match ty {
+                ValueType::I32 => Val::I32(*gp.next().expect("results fit in registers") as i32),
+                ValueType::I64 => Val::I64(*gp.next().expect("results fit in registers") as i64),
+                ValueType::ExternRef => {
+                    Val::ExternRef(*gp.next().expect("results fit in registers"))
+                }
+                ValueType::FuncRef => Val::FuncRef(*gp.next().expect("results fit in registers")),
+                ValueType::F32 => {
+                    Val::F32(__m128_as_f32(*fp.next().expect("results fit in registers")))
+                }
+                ValueType::F64 => {
+                    Val::F64(__m128_as_f64(*fp.next().expect("results fit in registers")))
+                }
+                ValueType::V128 => Val::V128(__m128_as_u128(
+                    *fp.next().expect("results fit in registers"),
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Check whether values of the given types can all be returned in registers, and can therefore be
+/// returned from a guest function to the host.
+pub(crate) fn results_fit_in_regs(results: &[ValueType]) -> bool {
+    let fp_results = results
+        .iter()
+        .filter(|ty| match ty {
+            ValueType::F32 | ValueType::F64 | ValueType::V128 => true,
+            ValueType::I32 | ValueType::I64 | ValueType::ExternRef | ValueType::FuncRef => false,
+        })
+        .count();
+    let gp_results = results.len() - fp_results;
+    gp_results <= MAX_RETURN_REGS && fp_results <= MAX_RETURN_REGS
+}
+
+/// The number of general-purpose argument registers in the native calling convention.
+#[cfg(target_arch = "x86_64")]
+pub(crate) const GP_ARG_REGS: usize = 6;
+
+/// The number of general-purpose argument registers in the native calling convention.
+#[cfg(target_arch = "aarch64")]
+pub(crate) const GP_ARG_REGS: usize = 8;
+
+/// The number of floating-point and vector argument registers in the native calling convention.
+pub(crate) const FP_ARG_REGS: usize = 8;
+
+/// Check whether all of the `v128` parameters of a guest function would be passed in registers.
+///
+/// Arguments beyond the first `FP_ARG_REGS` floating-point or vector arguments are spilled onto
+/// the stack, which is not supported for `v128`s.
+pub(crate) fn v128_params_fit_in_regs(params: &[ValueType]) -> bool {
+    params
+        .iter()
+        .filter(|ty| match ty {
+            ValueType::F32 | ValueType::F64 | ValueType::V128 => true,
+            ValueType::I32 | ValueType::I64 | ValueType::ExternRef | ValueType::FuncRef => false,
+        })
+        .skip(FP_ARG_REGS)
+        .all(|ty| *ty != ValueType::V128)
 }
 
 impl From<RegVal> for UntypedRetVal {
     fn from(reg: RegVal) -> UntypedRetVal {
         match reg {
-            RegVal::GpReg(r) => UntypedRetVal::new(r, unsafe { _mm_setzero_ps() }),
+            RegVal::GpReg(r) => UntypedRetVal::new(r, fp_reg_zero()),
             RegVal::FpReg(r) => UntypedRetVal::new(0, r),
         }
     }
@@ -187,7 +412,8 @@ impl From<RegVal> for UntypedRetVal {
 
 impl<T: Into<Val>> From<T> for UntypedRetVal {
     fn from(v: T) -> UntypedRetVal {
-        val_to_reg(&v.into()).into()
+        let v = v.into();
+        UntypedRetVal::from(val_to_reg(&v)).with_results(&[v.value_type()])
     }
 }
 
@@ -195,19 +421,19 @@ macro_rules! impl_from_fp {
     ( $ty:ty, $f:ident, $as:ident ) => {
         impl From<UntypedRetVal> for $ty {
             fn from(retval: UntypedRetVal) -> $ty {
-                $f(retval.fp)
+                $f(retval.fp[0])
             }
         }
 
         impl From<&UntypedRetVal> for $ty {
             fn from(retval: &UntypedRetVal) -> $ty {
-                $f(retval.fp)
+                $f(retval.fp[0])
             }
         }
 
         impl UntypedRetVal {
             pub fn $as(&self) -> $ty {
-                $f(self.fp)
+                $f(self.fp[0])
             }
         }
     };
@@ -216,23 +442,30 @@ macro_rules! impl_from_fp {
 impl_from_fp!(f32, __m128_as_f32, as_f32);
 impl_from_fp!(f64, __m128_as_f64, as_f64);
 
+impl UntypedRetVal {
+    /// Interpret the first floating-point return register as a `v128`.
+    pub fn as_v128(&self) -> u128 {
+        __m128_as_u128(self.fp[0])
+    }
+}
+
 macro_rules! impl_from_gp {
     ( $ty:ty, $as:ident ) => {
         impl From<UntypedRetVal> for $ty {
             fn from(retval: UntypedRetVal) -> $ty {
-                retval.gp as $ty
+                retval.gp[0] as $ty
             }
         }
 
         impl From<&UntypedRetVal> for $ty {
             fn from(retval: &UntypedRetVal) -> $ty {
-                retval.gp as $ty
+                retval.gp[0] as $ty
             }
         }
 
         impl UntypedRetVal {
             pub fn $as(&self) -> $ty {
-                self.gp as $ty
+                self.gp[0] as $ty
             }
         }
     };
@@ -250,34 +483,33 @@ impl_from_gp!(i64, as_i64);
 
 impl From<UntypedRetVal> for bool {
     fn from(retval: UntypedRetVal) -> bool {
-        retval.gp != 0
+        retval.gp[0] != 0
     }
 }
 
 impl From<&UntypedRetVal> for bool {
     fn from(retval: &UntypedRetVal) -> bool {
-        retval.gp != 0
+        retval.gp[0] != 0
     }
 }
 
 impl UntypedRetVal {
     pub fn as_bool(&self) -> bool {
-        self.gp != 0
+        self.gp[0] != 0
     }
 
     pub fn as_ptr<T>(&self) -> *const T {
-        self.gp as *const T
+        self.gp[0] as *const T
     }
 
     pub fn as_mut<T>(&self) -> *mut T {
-        self.gp as *mut T
+        self.gp[0] as *mut T
     }
 }
 
 impl Default for UntypedRetVal {
     fn default() -> UntypedRetVal {
-        let fp = unsafe { _mm_setzero_ps() };
-        UntypedRetVal { fp, gp: 0 }
+        UntypedRetVal::new(0, fp_reg_zero())
     }
 }
 
@@ -288,11 +520,11 @@ pub trait UntypedRetValInternal {
 
 impl UntypedRetValInternal for UntypedRetVal {
     fn fp(&self) -> __m128 {
-        self.fp
+        self.fp[0]
     }
 
     fn gp(&self) -> u64 {
-        self.gp
+        self.gp[0]
     }
 }
 
@@ -300,19 +532,68 @@ impl UntypedRetValInternal for UntypedRetVal {
 
 /// Interpret the contents of a `__m128` register as an `f32`.
 pub fn __m128_as_f32(v: __m128) -> f32 {
-    let mut out: [f32; 4] = [0.0; 4];
-    unsafe {
-        _mm_storeu_ps(&mut out[0] as *mut f32, v);
-    }
-    out[0]
+    fp_reg::as_f32(v)
 }
 
 /// Interpret the contents of a `__m128` register as an `f64`.
 pub fn __m128_as_f64(v: __m128) -> f64 {
-    let mut out: [f64; 2] = [0.0; 2];
-    unsafe {
-        let vd = _mm_castps_pd(v);
-        _mm_storeu_pd(&mut out[0] as *mut f64, vd);
+    fp_reg::as_f64(v)
+}
+
+/// Interpret the contents of a `__m128` register as a `u128`.
+pub fn __m128_as_u128(v: __m128) -> u128 {
+    fp_reg::as_u128(v)
+}
+
+#[cfg(all(test, target_arch = "aarch64"))]
+mod tests {
+    use super::*;
+
+    /// The context switching assembly stores whole `q` registers into `__m128`s, and scalar values
+    /// live in the low bits of those.
+    #[test]
+    fn fp_regs_hold_scalars_in_low_bits() {
+        assert_eq!(std::mem::size_of::<__m128>(), 16);
+        assert_eq!(std::mem::align_of::<__m128>(), 16);
+
+        assert_eq!(
+            __m128_as_u128(fp_reg::from_f32(1.5)),
+            1.5f32.to_bits() as u128
+        );
+        assert_eq!(
+            __m128_as_u128(fp_reg::from_f64(1.5)),
+            1.5f64.to_bits() as u128
+        );
+
+        // whatever is left in the upper lanes does not change the scalar
+        let reg = fp_reg::from_u128(0xdead_beef_u128 << 64 | 2.5f64.to_bits() as u128);
+        assert_eq!(__m128_as_f64(reg), 2.5);
+        let reg = fp_reg::from_u128(0xdead_beef_u128 << 32 | 2.5f32.to_bits() as u128);
+        assert_eq!(__m128_as_f32(reg), 2.5);
+    }
+
+    #[test]
+    fn untyped_retval_reads_registers() {
+        let v128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+        let retval = UntypedRetVal::new_multi(
+            [7, u64::max_value()],
+            [fp_reg::from_f32(0.25), fp_reg::from_u128(v128)],
+        )
+        .with_results(&[
+            ValueType::I32,
+            ValueType::F32,
+            ValueType::I64,
+            ValueType::V128,
+        ]);
+
+        assert_eq!(retval.as_u32(), 7);
+        assert_eq!(retval.as_f32(), 0.25);
+        match retval.vals().as_slice() {
+            [Val::I32(7), Val::F32(f), Val::I64(-1), Val::V128(v)] => {
+                assert_eq!(*f, 0.25);
+                assert_eq!(*v, v128);
+            }
+            vals => panic!("unexpected return values: {:?}", vals),
+        }
     }
-    out[0]
 }
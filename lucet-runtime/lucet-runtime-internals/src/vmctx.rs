@@ -9,8 +9,8 @@ use crate::alloc::instance_heap_offset;
 use crate::context::Context;
 use crate::error::Error;
 use crate::instance::{
-    EmptyYieldVal, Instance, InstanceInternal, State, TerminationDetails, YieldedVal,
-    CURRENT_INSTANCE, HOST_CTX,
+    EmptyYieldVal, Instance, InstanceInternal, OutOfFuel, OutOfFuelBehavior, State,
+    TerminationDetails, YieldedVal, CURRENT_INSTANCE, HOST_CTX,
 };
 use lucet_module::{FunctionHandle, GlobalValue};
 use std::any::Any;
@@ -82,6 +82,13 @@ pub trait VmctxInternal {
     /// The dynamic type checks used by the other yield methods should make this explicit option
     /// type redundant, however this interface is used to avoid exposing a panic to the C API.
     fn yield_val_try_val<A: Any + 'static, R: Any + 'static>(&self, val: A) -> Option<R>;
+
+    /// Handle the guest running out of fuel, according to the instance's `OutOfFuelBehavior`.
+    ///
+    /// This terminates the instance with `TerminationDetails::OutOfFuel`, or yields an `OutOfFuel`
+    /// value until the host has added enough fuel for the guest to continue. It must only be
+    /// called from within a hostcall.
+    fn out_of_fuel(&self);
 }
 
 impl VmctxInternal for Vmctx {
@@ -112,6 +119,15 @@ impl VmctxInternal for Vmctx {
         self.yield_impl::<A, R>(val);
         self.try_take_resumed_val()
     }
+
+    fn out_of_fuel(&self) {
+        while self.instance().is_out_of_fuel() {
+            match self.instance().out_of_fuel_behavior() {
+                OutOfFuelBehavior::Terminate => panic!(TerminationDetails::OutOfFuel),
+                OutOfFuelBehavior::Yield => self.yield_val(OutOfFuel),
+            }
+        }
+    }
 }
 
 impl Vmctx {
@@ -324,9 +340,7 @@ impl Vmctx {
         table_idx: u32,
         func_idx: u32,
     ) -> Result<FunctionHandle, Error> {
-        self.instance()
-            .module()
-            .get_func_from_idx(table_idx, func_idx)
+        self.instance().get_func_from_idx(table_idx, func_idx)
     }
 
     /// Suspend the instance, returning an empty
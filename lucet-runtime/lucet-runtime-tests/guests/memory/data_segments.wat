@@ -0,0 +1,10 @@
+(module
+  (import "env" "memory" (memory 2))
+  (data (i32.const 0) "lucet")
+  (data (i32.const 70000) "heap image")
+  (func $main (export "main")
+    (i32.store (i32.const 0) (i32.const -1))
+    (i32.store (i32.const 70000) (i32.const -1))
+    (i32.store (i32.const 100000) (i32.const 1))
+  )
+)
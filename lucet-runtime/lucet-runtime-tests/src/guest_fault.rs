@@ -109,11 +109,13 @@ macro_rules! guest_fault_common_defs {
                 static ILLEGAL_INSTR_TRAPS: &[TrapSite] = &[TrapSite {
                     offset: 8,
                     code: TrapCode::BadSignature,
+                    wasm_offset: 0,
                 }];
 
                 static OOB_TRAPS: &[TrapSite] = &[TrapSite {
                     offset: 29,
                     code: TrapCode::HeapOutOfBounds,
+                    wasm_offset: 0x1a2f,
                 }];
 
                 MockModuleBuilder::new()
@@ -277,6 +279,7 @@ macro_rules! guest_fault_tests {
                         match inst.run("illegal_instr", &[]) {
                             Err(Error::RuntimeFault(details)) => {
                                 assert_eq!(details.trapcode, Some(TrapCode::BadSignature));
+                                assert_eq!(details.wasm_offset, None);
                             }
                             res => panic!("unexpected result: {:?}", res),
                         }
@@ -411,6 +414,7 @@ macro_rules! guest_fault_tests {
                         match inst.run("illegal_instr", &[]) {
                             Err(Error::RuntimeFault(details)) => {
                                 assert_eq!(details.trapcode, Some(TrapCode::BadSignature));
+                                assert_eq!(details.wasm_offset, None);
                             }
                             res => panic!("unexpected result: {:?}", res),
                         }
@@ -443,6 +447,7 @@ macro_rules! guest_fault_tests {
                         match inst.run("oob", &[]) {
                             Err(Error::RuntimeFault(details)) => {
                                 assert_eq!(details.trapcode, Some(TrapCode::HeapOutOfBounds));
+                                assert_eq!(details.wasm_offset, Some(0x1a2f));
                             }
                             res => panic!("unexpected result: {:?}", res),
                         }
@@ -469,6 +474,7 @@ macro_rules! guest_fault_tests {
                         match inst.run("oob", &[]) {
                             Err(Error::RuntimeFault(details)) => {
                                 assert_eq!(details.trapcode, Some(TrapCode::HeapOutOfBounds));
+                                assert_eq!(details.wasm_offset, Some(0x1a2f));
                             }
                             res => panic!("unexpected result: {:?}", res),
                         }
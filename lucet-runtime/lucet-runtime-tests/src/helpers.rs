@@ -3,6 +3,9 @@ pub use lucet_runtime_internals::module::{
     FunctionPointer, HeapSpec, MockExportBuilder, MockModuleBuilder,
 };
 
+// a minimal executor for tests of async instances
+pub use futures_executor::block_on;
+
 use lazy_static::lazy_static;
 use std::sync::RwLock;
 
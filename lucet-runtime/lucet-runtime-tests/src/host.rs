@@ -108,6 +108,38 @@ macro_rules! host_tests {
             vmctx.yield_val(5u64);
         }
 
+        /// A future that is pending the first time it is polled, and ready every time after.
+        struct PendingOnce<T: Unpin> {
+            val: Option<T>,
+            polled: bool,
+        }
+
+        impl<T: Unpin> std::future::Future for PendingOnce<T> {
+            type Output = T;
+
+            fn poll(
+                mut self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<T> {
+                if self.polled {
+                    std::task::Poll::Ready(self.val.take().expect("polled after completion"))
+                } else {
+                    self.polled = true;
+                    cx.waker().wake_by_ref();
+                    std::task::Poll::Pending
+                }
+            }
+        }
+
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_block_on_5(vmctx: &Vmctx) -> u64 {
+            vmctx.block_on(PendingOnce {
+                val: Some(5u64),
+                polled: false,
+            })
+        }
+
         #[lucet_hostcall]
         #[no_mangle]
         pub fn hostcall_yield_facts(vmctx: &Vmctx, n: u64) -> u64 {
@@ -196,7 +228,7 @@ macro_rules! host_tests {
                 };
                 use std::sync::{Arc, Mutex};
                 use $crate::build::test_module_c;
-                use $crate::helpers::{FunctionPointer, HeapSpec, MockExportBuilder, MockModuleBuilder};
+                use $crate::helpers::{block_on, FunctionPointer, HeapSpec, MockExportBuilder, MockModuleBuilder};
                 use $TestRegion as TestRegion;
 
                 lazy_static! {
@@ -478,6 +510,94 @@ macro_rules! host_tests {
                     assert_eq!(u64::from(retval), 5u64);
                 }
 
+                #[test]
+                fn run_async_hostcall_block_on() {
+                    extern "C" {
+                        fn hostcall_block_on_5(vmctx: *const lucet_vmctx) -> u64;
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) -> u64 {
+                        hostcall_block_on_5(vmctx)
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let retval = block_on(inst.run_async("f", &[])).expect("instance runs");
+                    assert_eq!(u64::from(retval), 5u64);
+                    assert!(inst.is_ready());
+                }
+
+                #[test]
+                fn run_hostcall_block_on_sync() {
+                    extern "C" {
+                        fn hostcall_block_on_5(vmctx: *const lucet_vmctx) -> u64;
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) -> u64 {
+                        hostcall_block_on_5(vmctx)
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let retval = inst
+                        .run("f", &[])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(u64::from(retval), 5u64);
+                }
+
+                #[test]
+                fn run_async_yield_unsupported() {
+                    extern "C" {
+                        fn hostcall_yields_5(vmctx: *const lucet_vmctx);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_yields_5(vmctx);
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    match block_on(inst.run_async("f", &[])) {
+                        Err(Error::Unsupported(_)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+
+                    // the instance is left yielded, and can still be resumed synchronously
+                    assert!(inst.is_yielded());
+                    inst.resume().expect("instance resumes").unwrap_returned();
+                }
+
                 #[test]
                 fn yield_factorials() {
                     extern "C" {
@@ -49,6 +49,38 @@ macro_rules! memory_tests {
                     // guest then puts the result of the current memory call in heap[4] (indexed by bytes)
                     assert_eq!(heap[1], 5);
                 }
+
+                fn assert_initial_heap(heap: &[u8]) {
+                    assert_eq!(&heap[0..5], b"lucet");
+                    assert_eq!(&heap[70000..70010], b"heap image");
+                    assert_eq!(&heap[100000..100004], &[0; 4]);
+                }
+
+                #[test]
+                fn data_segments_initialize_heap() {
+                    let module = test_module_wasm("memory", "data_segments.wat")
+                        .expect("compile and load data_segments.wasm");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module.clone())
+                        .expect("instance can be created");
+                    assert_initial_heap(inst.heap());
+
+                    inst.run("main", &[]).expect("instance runs");
+                    assert_eq!(&inst.heap()[0..5], b"\xff\xff\xff\xfft");
+                    assert_eq!(&inst.heap()[100000..100004], &[1, 0, 0, 0]);
+
+                    inst.reset().expect("instance resets");
+                    assert_initial_heap(inst.heap());
+
+                    // a slot reused by a new instance is initialized the same way
+                    inst.run("main", &[]).expect("instance runs");
+                    drop(inst);
+                    let inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+                    assert_initial_heap(inst.heap());
+                }
             }
         )*
     };
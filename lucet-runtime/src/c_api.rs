@@ -2,7 +2,7 @@ use crate::{DlModule, Instance, Limits, MmapRegion, Module, Region};
 #[cfg(all(target_os = "linux", feature = "uffd"))]
 use crate::{UffdRegion, WasmPageSizedUffdStrategy};
 use libc::{c_char, c_int, c_void};
-use lucet_module::TrapCode;
+use lucet_module::{GlobalValue, TrapCode};
 use lucet_runtime_internals::c_api::*;
 use lucet_runtime_internals::instance::{
     instance_handle_from_raw, instance_handle_to_raw, InstanceInternal,
@@ -385,22 +385,12 @@ pub unsafe extern "C" fn lucet_retval_gp(retval: *const lucet_untyped_retval) ->
 
 #[no_mangle]
 pub unsafe extern "C" fn lucet_retval_f32(retval: *const lucet_untyped_retval) -> f32 {
-    let mut v = 0.0f32;
-    core::arch::x86_64::_mm_storeu_ps(
-        &mut v as *mut f32,
-        core::arch::x86_64::_mm_loadu_ps((*retval).fp.as_ptr() as *const f32),
-    );
-    v
+    std::ptr::read_unaligned((*retval).fp.as_ptr() as *const f32)
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn lucet_retval_f64(retval: *const lucet_untyped_retval) -> f64 {
-    let mut v = 0.0f64;
-    core::arch::x86_64::_mm_storeu_pd(
-        &mut v as *mut f64,
-        core::arch::x86_64::_mm_loadu_pd((*retval).fp.as_ptr() as *const f64),
-    );
-    v
+    std::ptr::read_unaligned((*retval).fp.as_ptr() as *const f64)
 }
 
 static C_API_INIT: Once = Once::new();
@@ -412,6 +402,20 @@ pub fn ensure_linked() {
             lucet_vmctx_get_heap as _,
             lucet_vmctx_current_memory as _,
             lucet_vmctx_grow_memory as _,
+            lucet_vmctx_out_of_fuel as _,
+            lucet_vmctx_table_get as _,
+            lucet_vmctx_table_set as _,
+            lucet_vmctx_table_size as _,
+            lucet_vmctx_table_grow as _,
+            lucet_vmctx_table_fill as _,
+            lucet_vmctx_ref_func as _,
+            lucet_vmctx_memory_copy as _,
+            lucet_vmctx_memory_fill as _,
+            lucet_vmctx_memory_init as _,
+            lucet_vmctx_data_drop as _,
+            lucet_vmctx_table_copy as _,
+            lucet_vmctx_table_init as _,
+            lucet_vmctx_elem_drop as _,
         ];
         for func in funcs {
             assert_ne!(*func, std::ptr::null(), "hostcall address is not null");
@@ -427,8 +431,8 @@ pub unsafe extern "C" fn lucet_vmctx_get_heap(vmctx: &Vmctx) -> *mut u8 {
 
 #[lucet_hostcall]
 #[no_mangle]
-pub unsafe extern "C" fn lucet_vmctx_get_globals(vmctx: &Vmctx) -> *mut i64 {
-    vmctx.instance().alloc().slot().globals as *mut i64
+pub unsafe extern "C" fn lucet_vmctx_get_globals(vmctx: &Vmctx) -> *mut GlobalValue {
+    vmctx.instance().alloc().slot().globals as *mut GlobalValue
 }
 
 #[lucet_hostcall]
@@ -451,6 +455,218 @@ pub unsafe extern "C" fn lucet_vmctx_grow_memory(vmctx: &Vmctx, additional_pages
     }
 }
 
+#[lucet_hostcall]
+#[no_mangle]
+/// Called by code compiled with fuel checks when the guest runs out of fuel.
+///
+/// Terminates the instance or yields until more fuel is available, depending on the instance's
+/// `OutOfFuelBehavior`.
+pub unsafe extern "C" fn lucet_vmctx_out_of_fuel(vmctx: &Vmctx) {
+    vmctx.out_of_fuel();
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Get the reference stored in a table element.
+///
+/// Generated code checks the bounds of the table before calling this function.
+pub unsafe extern "C" fn lucet_vmctx_table_get(
+    vmctx: &Vmctx,
+    table_idx: u32,
+    elem_idx: u32,
+) -> u64 {
+    vmctx
+        .instance()
+        .table_get(table_idx, elem_idx)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("table.get failed: {}", e))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Store a reference in a table element.
+///
+/// Generated code checks the bounds of the table before calling this function.
+pub unsafe extern "C" fn lucet_vmctx_table_set(
+    vmctx: &Vmctx,
+    table_idx: u32,
+    elem_idx: u32,
+    value: u64,
+) {
+    vmctx
+        .instance_mut()
+        .table_set(table_idx, elem_idx, value)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("table.set failed: {}", e))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Get the number of elements in a table.
+pub unsafe extern "C" fn lucet_vmctx_table_size(vmctx: &Vmctx, table_idx: u32) -> u32 {
+    vmctx
+        .instance()
+        .table_size(table_idx)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("table.size failed: {}", e))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Grows a table by the given number of elements, each set to `init`.
+///
+/// On success, returns the number of elements that existed before the call. On failure, returns
+/// `-1`.
+pub unsafe extern "C" fn lucet_vmctx_table_grow(
+    vmctx: &Vmctx,
+    table_idx: u32,
+    delta: u32,
+    init: u64,
+) -> i32 {
+    if let Ok(old_len) = vmctx.instance_mut().table_grow(table_idx, delta, init) {
+        old_len as i32
+    } else {
+        -1
+    }
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Store a reference in `len` table elements, starting at `dst`.
+///
+/// Generated code checks the bounds of the table before calling this function.
+pub unsafe extern "C" fn lucet_vmctx_table_fill(
+    vmctx: &Vmctx,
+    table_idx: u32,
+    dst: u32,
+    value: u64,
+    len: u32,
+) {
+    vmctx
+        .instance_mut()
+        .table_fill(table_idx, dst, value, len)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("table.fill failed: {}", e))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Get a reference to the function with the given index in the function manifest.
+pub unsafe extern "C" fn lucet_vmctx_ref_func(vmctx: &Vmctx, func_idx: u32) -> u64 {
+    vmctx
+        .instance()
+        .ref_func(func_idx)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("ref.func failed: {}", e))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Copy `len` bytes of the heap from `src` to `dst`.
+///
+/// Returns `0` on success, or `1` if either range is out of bounds, in which case generated code
+/// traps.
+pub unsafe extern "C" fn lucet_vmctx_memory_copy(
+    vmctx: &Vmctx,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> i32 {
+    vmctx.instance_mut().memory_copy(dst, src, len).is_err() as i32
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Set `len` bytes of the heap starting at `dst` to the low byte of `val`.
+///
+/// Returns `0` on success, or `1` if the range is out of bounds, in which case generated code
+/// traps.
+pub unsafe extern "C" fn lucet_vmctx_memory_fill(
+    vmctx: &Vmctx,
+    dst: u32,
+    val: u32,
+    len: u32,
+) -> i32 {
+    vmctx
+        .instance_mut()
+        .memory_fill(dst, val as u8, len)
+        .is_err() as i32
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Copy `len` bytes of a data segment, starting at `src`, into the heap at `dst`.
+///
+/// Returns `0` on success, or `1` if either range is out of bounds, in which case generated code
+/// traps.
+pub unsafe extern "C" fn lucet_vmctx_memory_init(
+    vmctx: &Vmctx,
+    seg_idx: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> i32 {
+    vmctx
+        .instance_mut()
+        .memory_init(seg_idx, dst, src, len)
+        .is_err() as i32
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Drop a data segment, after which it is empty.
+pub unsafe extern "C" fn lucet_vmctx_data_drop(vmctx: &Vmctx, seg_idx: u32) {
+    vmctx
+        .instance_mut()
+        .data_drop(seg_idx)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("data.drop failed: {}", e))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Copy `len` elements from one table to another.
+///
+/// Returns `0` on success, or `1` if either range is out of bounds, in which case generated code
+/// traps.
+pub unsafe extern "C" fn lucet_vmctx_table_copy(
+    vmctx: &Vmctx,
+    dst_table_idx: u32,
+    src_table_idx: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> i32 {
+    vmctx
+        .instance_mut()
+        .table_copy(dst_table_idx, src_table_idx, dst, src, len)
+        .is_err() as i32
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Copy `len` functions of an element segment, starting at `src`, into a table at `dst`.
+///
+/// Returns `0` on success, or `1` if either range is out of bounds, in which case generated code
+/// traps.
+pub unsafe extern "C" fn lucet_vmctx_table_init(
+    vmctx: &Vmctx,
+    table_idx: u32,
+    seg_idx: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> i32 {
+    vmctx
+        .instance_mut()
+        .table_init(table_idx, seg_idx, dst, src, len)
+        .is_err() as i32
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+/// Drop an element segment, after which it is empty.
+pub unsafe extern "C" fn lucet_vmctx_elem_drop(vmctx: &Vmctx, seg_idx: u32) {
+    vmctx
+        .instance_mut()
+        .elem_drop(seg_idx)
+        .unwrap_or_else(|e| lucet_hostcall_terminate!("elem.drop failed: {}", e))
+}
+
 #[lucet_hostcall]
 #[no_mangle]
 /// Check if a memory region is inside the instance heap.
@@ -471,7 +687,6 @@ pub unsafe extern "C" fn lucet_vmctx_get_func_from_idx(
 ) -> *const c_void {
     vmctx
         .instance()
-        .module()
         .get_func_from_idx(table_idx, func_idx)
         .map(|fptr| fptr.ptr.as_usize() as *const c_void)
         .unwrap_or(std::ptr::null())
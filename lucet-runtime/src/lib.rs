@@ -26,7 +26,12 @@
 //! includes [`MmapRegion`](struct.MmapRegion.html), an implementation backed by `mmap`, and
 //! optionally [`UffdRegion`](struct.UffdRegion.html), which is backed by the
 //! [`userfaultfd`](http://man7.org/linux/man-pages/man2/userfaultfd.2.html) feature available on
-//! newer Linux kernels ([see below](index.html#userfaultfd-backed-region)).
+//! newer Linux kernels ([see below](index.html#userfaultfd-backed-region)). An `MmapRegion` can
+//! also be created with
+//! [`MmapRegion::create_growable()`](struct.MmapRegion.html#method.create_growable), which maps
+//! memory for instances as they are created rather than all at once, and releases it when it goes
+//! unused, as set by a [`GrowthPolicy`](struct.GrowthPolicy.html). A `UffdRegion` cannot grow,
+//! and keeps the fixed capacity it is created with.
 //!
 //! - [`Limits`](struct.Limits.html): upper bounds for the resources a Lucet instance may
 //! consume. These may be larger or smaller than the limits described in the WebAssembly module
@@ -46,7 +51,9 @@
 //!
 //! - [`UntypedRetVal`](struct.UntypedRetVal.html): values returned from WebAssembly
 //! functions. These must be interpreted at the correct type by the user via `From` implementations
-//! or `retval.as_T()` methods, for example `u64::from(retval)` in the example below.
+//! or `retval.as_T()` methods, for example `u64::from(retval)` in the example below. Functions
+//! with multiple return values return all of them, typed according to the function signature, from
+//! `retval.vals()`.
 //!
 //! - [`YieldedVal`](struct.YieldedVal.html): dynamically-values yielded by WebAssembly
 //! programs. Not all yield points are given values, so this may be empty. To use the values, if
@@ -269,6 +276,32 @@
 //! assert_eq!(u64::from(res.unwrap_returned()), 120u64);
 //! ```
 //!
+//! ### Async hostcalls
+//!
+//! Hostcalls that perform I/O can await futures with
+//! [`Vmctx::block_on()`](vmctx/struct.Vmctx.html#method.block_on) rather than hand-rolling a yield
+//! and resume protocol. If the instance is run with
+//! [`Instance::run_async()`](struct.Instance.html#method.run_async), the guest yields to the host
+//! whenever the awaited future is pending, and the future returned by `run_async()` resumes it once
+//! the awaited future is ready:
+//!
+//! ```no_run
+//! use lucet_runtime::lucet_hostcall;
+//! use lucet_runtime::vmctx::Vmctx;
+//!
+//! #[lucet_hostcall]
+//! #[no_mangle]
+//! pub fn hostcall_read_config(vmctx: &Vmctx) -> u64 {
+//!     vmctx.block_on(async {
+//!         // await some I/O here
+//!         42u64
+//!     })
+//! }
+//! ```
+//!
+//! If the instance is run synchronously with `Instance::run()`, `block_on()` instead blocks the
+//! current thread until the future is ready.
+//!
 //! ## Custom Signal Handlers
 //!
 //! Since Lucet programs are run as native machine code, signals such as `SIGSEGV` and `SIGFPE` can
@@ -322,9 +355,9 @@
 //! ## Interaction With Host Signal Handlers
 //!
 //! Great care must be taken if a host application installs or otherwise modifies signal handlers
-//! anywhere in the process. Lucet installs handlers for `SIGBUS`, `SIGFPE`, `SIGILL`, and `SIGSEGV`
-//! when the first Lucet instance begins running, and restores the preëxisting handlers when the
-//! last Lucet instance terminates. During this time, other threads in the host process *must not*
+//! anywhere in the process. Lucet installs handlers for `SIGBUS`, `SIGFPE`, `SIGILL`, `SIGSEGV`,
+//! `SIGALRM`, and `SIGPROF` when the first Lucet instance begins running, and restores the
+//! preëxisting handlers when the last Lucet instance terminates. During this time, other threads in the host process *must not*
 //! modify those signal handlers, since signal handlers can only be installed on a process-wide
 //! basis.
 //!
@@ -344,6 +377,79 @@
 //! [remove-handler]: fn.remove_lucet_signal_handler.html
 //! [instance-ensure-handler]: struct.Instance.html#method.ensure_signal_handler_installed
 //!
+//! ## Profiling
+//!
+//! [`Instance::start_profiling()`][start-profiling] samples the stack of an instance on a fixed
+//! interval of the CPU time it uses, with a per-thread timer that sends `SIGPROF` to the thread
+//! running the instance. Samples are attributed to guest functions when the instance returns to
+//! the host, and the resulting [`Profile`][profile] can be written as folded stacks for flame graph
+//! tools:
+//!
+//! ```no_run
+//! # use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+//! # use std::time::Duration;
+//! # let module = DlModule::load("/my/lucet/module.so").unwrap();
+//! # let region = MmapRegion::create(1, &Limits::default()).unwrap();
+//! let mut inst = region.new_instance(module).unwrap();
+//! inst.start_profiling(Duration::from_millis(1)).unwrap();
+//! inst.run("factorial", &[5u64.into()]).unwrap();
+//! let profile = inst.stop_profiling().unwrap();
+//! profile.write_folded(&mut std::io::stdout()).unwrap();
+//! ```
+//!
+//! A `SIGPROF` caught on a thread that is not running a profiled instance is passed on to the host
+//! handler, so profilers of the host process keep working, but they will not see into guest code.
+//! Profiling is only supported on Linux.
+//!
+//! [start-profiling]: struct.Instance.html#method.start_profiling
+//! [profile]: struct.Profile.html
+//!
+//! ## Memory Pools
+//!
+//! `Limits::heap_memory_size` bounds the heap of each instance on its own. To bound the memory of a
+//! group of instances together, such as those run for one tenant, give each of them the same
+//! [`MemoryPool`][memory-pool] when building it:
+//!
+//! ```no_run
+//! # use lucet_runtime::{DlModule, Limits, MemoryPool, MmapRegion, Region};
+//! # use std::sync::Arc;
+//! # let module = DlModule::load("/my/lucet/module.so").unwrap();
+//! # let region = MmapRegion::create(2, &Limits::default()).unwrap();
+//! let pool = Arc::new(MemoryPool::new(64 * 1024 * 1024));
+//! let inst_a = region
+//!     .new_instance_builder(module.clone())
+//!     .with_memory_pool(pool.clone())
+//!     .build()
+//!     .unwrap();
+//! let inst_b = region
+//!     .new_instance_builder(module)
+//!     .with_memory_pool(pool.clone())
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Each instance takes its heap out of the pool as it is created and grows, and building an
+//! instance or growing its heap fails with `Error::LimitsExceeded` once the pool is used up. The
+//! instances sharing a pool may belong to different regions.
+//!
+//! To charge every instance of a region to a pool without passing it to each builder, set it as
+//! the region's pool with [`Region::set_memory_pool()`][set-memory-pool]. Instances built with
+//! `with_memory_pool()` are still charged to the pool they are given.
+//!
+//! [memory-pool]: struct.MemoryPool.html
+//! [set-memory-pool]: trait.Region.html#tymethod.set_memory_pool
+//!
+//! ## Statistics
+//!
+//! [`Region::stats()`][region-stats] and [`Instance::stats()`][instance-stats] report how much
+//! memory a region and each of its instances keep resident, how far an instance's heap and stack
+//! have grown, and how long instantiation and resets take. Resident pages are counted with
+//! `mincore(2)`, so these are cheap enough to sample periodically for monitoring, but not on every
+//! call into an instance.
+//!
+//! [region-stats]: trait.Region.html#tymethod.stats
+//! [instance-stats]: struct.Instance.html#method.stats
+//!
 //! ## Signal Handler Stacks
 //!
 //! Lucet instances must run on threads that have an [alternate signal stack][sigaltstack]
@@ -403,25 +509,32 @@ extern crate self as lucet_runtime;
 
 pub mod c_api;
 
-pub use lucet_module::{PublicKey, TrapCode};
-pub use lucet_runtime_internals::alloc::{AllocStrategy, Limits, DEFAULT_SIGNAL_STACK_SIZE};
+pub use lucet_module::{FunctionIndex, GlobalValue, PublicKey, TableElement, TrapCode};
+pub use lucet_runtime_internals::alloc::{
+    AllocStrategy, CleanupStrategy, Limits, DEFAULT_SIGNAL_STACK_SIZE,
+};
 pub use lucet_runtime_internals::error::Error;
+pub use lucet_runtime_internals::instance::epoch::{current_epoch, increment_epoch};
+pub use lucet_runtime_internals::instance::profile::Profile;
 pub use lucet_runtime_internals::instance::signals::{
     install_lucet_signal_handler, remove_lucet_signal_handler,
 };
 pub use lucet_runtime_internals::instance::{
-    FaultDetails, Instance, InstanceHandle, KillError, KillSuccess, KillSwitch, RunResult,
-    SignalBehavior, TerminationDetails, YieldedVal,
+    FaultDetails, Frame, Instance, InstanceHandle, KillError, KillSuccess, KillSwitch, OutOfFuel,
+    OutOfFuelBehavior, RunResult, SignalBehavior, TerminationDetails, YieldedVal,
+    DEFAULT_TABLE_ELEMENTS_LIMIT,
 };
 #[allow(deprecated)]
 pub use lucet_runtime_internals::lucet_hostcalls;
-pub use lucet_runtime_internals::module::{DlModule, Module};
-pub use lucet_runtime_internals::region::mmap::MmapRegion;
+pub use lucet_runtime_internals::memory_pool::MemoryPool;
+pub use lucet_runtime_internals::module::{DlModule, Module, Snapshot};
+pub use lucet_runtime_internals::region::mmap::{GrowthPolicy, MmapRegion};
 #[cfg(all(target_os = "linux", feature = "uffd"))]
 pub use lucet_runtime_internals::region::uffd::{
     HostPageSizedUffdStrategy, UffdRegion, UffdStrategy, WasmPageSizedUffdStrategy,
 };
 pub use lucet_runtime_internals::region::{InstanceBuilder, Region, RegionCreate};
+pub use lucet_runtime_internals::stats::{InstanceStats, RegionStats};
 pub use lucet_runtime_internals::val::{UntypedRetVal, Val};
 pub use lucet_runtime_internals::{lucet_hostcall, lucet_hostcall_terminate, WASM_PAGE_SIZE};
 
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{wasm_test, wasm_test_with};
+use lucet_runtime::{Error as RuntimeError, FunctionIndex, Limits, MmapRegion, Region, Val};
+use lucetc::LucetcOpts;
+
+#[test]
+fn fault_records_guest_backtrace() {
+    let module = wasm_test("./tests/backtrace/backtrace.wat").expect("can load backtrace module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    // the heap is one page, so this load is out of bounds
+    match inst.run("outer", &[Val::U32(0x10000)]) {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            let frames = details
+                .backtrace
+                .iter()
+                .map(|frame| (frame.func_index, frame.func_name.as_deref()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                frames,
+                vec![
+                    (Some(FunctionIndex::from_u32(0)), Some("guest_func_inner")),
+                    (Some(FunctionIndex::from_u32(1)), Some("guest_func_middle")),
+                    (Some(FunctionIndex::from_u32(2)), Some("guest_func_outer")),
+                ]
+            );
+            assert_eq!(details.backtrace[0].addr, details.rip_addr);
+            assert!(details.backtrace.iter().all(|frame| frame.offset.is_some()));
+
+            let message = details.to_string();
+            assert!(message.contains("guest backtrace:"));
+            assert!(message.contains("#2 "));
+            assert!(message.contains("guest_func_outer+0x"));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn backtrace_starts_at_faulting_function() {
+    let module = wasm_test("./tests/backtrace/backtrace.wat").expect("can load backtrace module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    match inst.run("inner", &[Val::U32(0x10000)]) {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            assert_eq!(details.backtrace.len(), 1);
+            assert_eq!(
+                details.backtrace[0].func_name.as_deref(),
+                Some("guest_func_inner")
+            );
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+const I32_LOAD: u8 = 0x28;
+const CALL: u8 = 0x10;
+
+#[test]
+fn fault_records_wasm_offsets() {
+    let wasm = lucetc::read_module("./tests/backtrace/backtrace.wat", &None, false)
+        .expect("can read backtrace module");
+    let module = wasm_test_with("./tests/backtrace/backtrace.wat", |lucetc| {
+        lucetc.with_call_site_offsets(true)
+    })
+    .expect("can load backtrace module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    match inst.run("outer", &[Val::U32(0x10000)]) {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            let fault_offset = details.wasm_offset.expect("fault has a wasm offset");
+            assert_eq!(wasm[fault_offset as usize], I32_LOAD);
+            assert_eq!(details.backtrace[0].wasm_offset, Some(fault_offset));
+
+            let call_offsets = details.backtrace[1..]
+                .iter()
+                .map(|frame| frame.wasm_offset.expect("frame has a wasm offset"))
+                .collect::<Vec<_>>();
+            assert_eq!(call_offsets.len(), 2);
+            assert!(call_offsets
+                .iter()
+                .all(|offset| wasm[*offset as usize] == CALL));
+            // `middle` comes before `outer` in the module
+            assert!(call_offsets[0] < call_offsets[1]);
+
+            let message = details.to_string();
+            assert!(message.contains(&format!("(wasm offset {:#x} in function 0)", fault_offset)));
+            assert!(message.contains(&format!("wasm offset {:#x})", call_offsets[1])));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn call_sites_are_only_recorded_on_request() {
+    let module = wasm_test("./tests/backtrace/backtrace.wat").expect("can load backtrace module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    match inst.run("outer", &[Val::U32(0x10000)]) {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            // trap sites always record their wasm offset
+            assert!(details.wasm_offset.is_some());
+            assert!(details.backtrace[1..]
+                .iter()
+                .all(|frame| frame.wasm_offset.is_none()));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
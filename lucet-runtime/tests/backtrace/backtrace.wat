@@ -0,0 +1,9 @@
+(module
+  (memory 1)
+  (func $inner (export "inner") (param i32) (result i32)
+    (i32.load (local.get 0)))
+  (func $middle (export "middle") (param i32) (result i32)
+    (i32.add (call $inner (local.get 0)) (i32.const 1)))
+  (func $outer (export "outer") (param i32) (result i32)
+    (i32.add (call $middle (local.get 0)) (i32.const 1)))
+)
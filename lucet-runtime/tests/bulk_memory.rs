@@ -0,0 +1,133 @@
+mod common;
+
+use common::wasm_test;
+use lucet_runtime::{
+    Error as RuntimeError, InstanceHandle, Limits, MmapRegion, Region, TrapCode, Val,
+};
+use std::sync::Arc;
+
+fn new_instance(region: &Arc<MmapRegion>) -> InstanceHandle {
+    let module =
+        wasm_test("./tests/bulk_memory/bulk_memory.wat").expect("can load bulk memory module");
+    region
+        .new_instance(module)
+        .expect("instance can be created")
+}
+
+fn run_i32(inst: &mut InstanceHandle, entrypoint: &str, args: &[Val]) -> i32 {
+    inst.run(entrypoint, args)
+        .expect("instance runs")
+        .unwrap_returned()
+        .as_i32()
+}
+
+fn assert_trap(inst: &mut InstanceHandle, entrypoint: &str, args: &[Val], trapcode: TrapCode) {
+    match inst.run(entrypoint, args) {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            assert_eq!(details.trapcode, Some(trapcode));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn memory_copy_and_fill() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    // overlapping copy of the active segment "abcd"
+    inst.run("copy", &[Val::U32(1), Val::U32(0), Val::U32(4)])
+        .expect("instance runs");
+    assert_eq!(&inst.heap()[0..5], b"aabcd");
+
+    inst.run("fill", &[Val::U32(2), Val::U32(0x1ff), Val::U32(2)])
+        .expect("instance runs");
+    assert_eq!(&inst.heap()[0..5], b"aa\xff\xffd");
+
+    // out-of-bounds operations trap without writing anything
+    assert_trap(
+        &mut inst,
+        "fill",
+        &[Val::U32(0xff00), Val::U32(1), Val::U32(0x101)],
+        TrapCode::HeapOutOfBounds,
+    );
+    assert_eq!(run_i32(&mut inst, "load8_u", &[Val::U32(0xff00)]), 0);
+}
+
+#[test]
+fn memory_init_and_data_drop() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    inst.run("init_hello", &[Val::U32(16)])
+        .expect("instance runs");
+    assert_eq!(&inst.heap()[16..21], b"hello");
+
+    // a dropped segment is empty, so initializing from it is out of bounds
+    inst.run("drop_hello", &[]).expect("instance runs");
+    assert_trap(
+        &mut inst,
+        "init_hello",
+        &[Val::U32(32)],
+        TrapCode::HeapOutOfBounds,
+    );
+
+    // and resetting the instance restores it
+    inst.reset().expect("instance resets");
+    inst.run("init_hello", &[Val::U32(32)])
+        .expect("instance runs");
+    assert_eq!(&inst.heap()[32..37], b"hello");
+}
+
+#[test]
+fn table_init_copy_and_elem_drop() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    inst.run("init_fns", &[Val::U32(2)]).expect("instance runs");
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(2)]), 1);
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(3)]), 2);
+
+    inst.run("copy_table", &[Val::U32(0), Val::U32(3), Val::U32(1)])
+        .expect("instance runs");
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(0)]), 2);
+
+    assert_trap(
+        &mut inst,
+        "init_fns",
+        &[Val::U32(3)],
+        TrapCode::TableOutOfBounds,
+    );
+
+    inst.run("drop_fns", &[]).expect("instance runs");
+    assert_trap(
+        &mut inst,
+        "init_fns",
+        &[Val::U32(0)],
+        TrapCode::TableOutOfBounds,
+    );
+}
+
+#[test]
+fn dropped_segments_in_snapshot() {
+    let region = MmapRegion::create(2, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    inst.run("drop_hello", &[]).expect("instance runs");
+    let snapshot = inst.snapshot().expect("snapshot can be taken");
+
+    let mut from_snapshot = region
+        .new_instance_from_snapshot(snapshot)
+        .expect("instance can be created from snapshot");
+    assert_trap(
+        &mut from_snapshot,
+        "init_hello",
+        &[Val::U32(0)],
+        TrapCode::HeapOutOfBounds,
+    );
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
@@ -0,0 +1,53 @@
+(module
+  (type $ret_i32 (func (result i32)))
+
+  (memory 1)
+  (table $funcs 4 funcref)
+
+  (data (i32.const 0) "abcd")
+  (data $hello "hello")
+
+  (elem (i32.const 0) $one)
+  (elem $fns func $one $two)
+
+  (func $one (type $ret_i32) (i32.const 1))
+  (func $two (type $ret_i32) (i32.const 2))
+
+  (func (export "load8_u") (param i32) (result i32)
+    (i32.load8_u (local.get 0))
+  )
+
+  (func (export "copy") (param i32 i32 i32)
+    (memory.copy (local.get 0) (local.get 1) (local.get 2))
+  )
+
+  (func (export "fill") (param i32 i32 i32)
+    (memory.fill (local.get 0) (local.get 1) (local.get 2))
+  )
+
+  ;; Copy the passive segment "hello" into memory at the given address.
+  (func (export "init_hello") (param i32)
+    (memory.init $hello (local.get 0) (i32.const 0) (i32.const 5))
+  )
+
+  (func (export "drop_hello")
+    (data.drop $hello)
+  )
+
+  ;; Copy the passive segment of `$one` and `$two` into the table at the given index.
+  (func (export "init_fns") (param i32)
+    (table.init $fns (local.get 0) (i32.const 0) (i32.const 2))
+  )
+
+  (func (export "drop_fns")
+    (elem.drop $fns)
+  )
+
+  (func (export "copy_table") (param i32 i32 i32)
+    (table.copy (local.get 0) (local.get 1) (local.get 2))
+  )
+
+  (func (export "call_indirect") (param i32) (result i32)
+    (call_indirect (type $ret_i32) (local.get 0))
+  )
+)
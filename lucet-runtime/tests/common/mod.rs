@@ -0,0 +1,48 @@
+//! Helpers shared by the integration tests, which each include this module with `mod common;`.
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use anyhow::Error;
+use lucet_runtime::DlModule;
+use lucetc::Lucetc;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Compile a WebAssembly file to a shared object, and load it as a module.
+pub fn wasm_test<P: AsRef<Path>>(wasm_file: P) -> Result<Arc<DlModule>, Error> {
+    wasm_test_with(wasm_file, |lucetc| lucetc)
+}
+
+/// Like [`wasm_test`](fn.wasm_test.html), but with the compiler set up by `configure`.
+pub fn wasm_test_with<P, F>(wasm_file: P, configure: F) -> Result<Arc<DlModule>, Error>
+where
+    P: AsRef<Path>,
+    F: FnOnce(Lucetc) -> Lucetc,
+{
+    let workdir = TempDir::new().expect("create working directory");
+
+    let native_build = configure(Lucetc::new(wasm_file));
+
+    let so_file = workdir.path().join("out.so");
+
+    native_build.shared_object_file(so_file.clone())?;
+
+    let dlmodule = DlModule::load(so_file)?;
+
+    Ok(dlmodule)
+}
+
+/// Compile a WebAssembly file to a shared object, and return its bytes.
+pub fn so_bytes<P: AsRef<Path>>(wasm_file: P) -> Result<Vec<u8>, Error> {
+    let workdir = TempDir::new().expect("create working directory");
+
+    let native_build = Lucetc::new(wasm_file);
+
+    let so_file = workdir.path().join("out.so");
+
+    native_build.shared_object_file(so_file.clone())?;
+
+    Ok(std::fs::read(&so_file)?)
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{wasm_test, wasm_test_with};
+use lucet_runtime::{increment_epoch, Error as RuntimeError, Limits, MmapRegion, Region, TrapCode};
+use lucetc::LucetcOpts;
+use std::thread;
+use std::time::Duration;
+
+fn assert_interrupted<T: std::fmt::Debug>(res: Result<T, RuntimeError>) {
+    match res {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            assert_eq!(details.trapcode, Some(TrapCode::Interrupt));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn no_deadline_runs_to_completion() {
+    let module = wasm_test_with("./tests/instruction_counting/loops.wat", |lucetc| {
+        lucetc.with_epoch_interruption(true)
+    })
+    .expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    assert_eq!(inst.get_epoch_deadline(), None);
+    inst.run("test_function", &[]).expect("instance runs");
+}
+
+#[test]
+fn reached_deadline_interrupts() {
+    let module = wasm_test_with("./tests/fuel/loop_forever.wat", |lucetc| {
+        lucetc.with_epoch_interruption(true)
+    })
+    .expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_epoch_deadline(0)
+        .build()
+        .expect("instance can be created");
+
+    assert_interrupted(inst.run("loop_forever", &[]));
+}
+
+#[test]
+fn incrementing_epoch_interrupts() {
+    let module = wasm_test_with("./tests/fuel/loop_forever.wat", |lucetc| {
+        lucetc.with_epoch_interruption(true)
+    })
+    .expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_epoch_deadline(1)
+        .build()
+        .expect("instance can be created");
+
+    let ticker = thread::spawn(|| {
+        thread::sleep(Duration::from_millis(10));
+        increment_epoch();
+    });
+    assert_interrupted(inst.run("loop_forever", &[]));
+    ticker.join().unwrap();
+
+    // the instance can run again once it has a new deadline
+    inst.set_epoch_deadline(0).expect("can set deadline");
+    assert_interrupted(inst.run("loop_forever", &[]));
+}
+
+#[test]
+fn epoch_deadline_requires_instrumentation() {
+    let module = wasm_test("./tests/fuel/loop_forever.wat").expect("can load module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    match region
+        .new_instance_builder(module)
+        .with_epoch_deadline(1)
+        .build()
+    {
+        Err(RuntimeError::Unsupported(_)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("instance built without epoch interruption"),
+    }
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
@@ -0,0 +1,7 @@
+(module
+  (func $loop_forever (export "loop_forever")
+    loop $inner
+      br $inner
+    end
+  )
+)
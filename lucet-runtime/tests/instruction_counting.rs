@@ -1,5 +1,8 @@
 use anyhow::Error;
-use lucet_runtime::{DlModule, Limits, MmapRegion, Region, RunResult};
+use lucet_runtime::{
+    DlModule, Error as RuntimeError, Limits, MmapRegion, OutOfFuel, OutOfFuelBehavior, Region,
+    RunResult, TerminationDetails,
+};
 use lucetc::{Lucetc, LucetcOpts};
 use rayon::prelude::*;
 use std::fs::DirEntry;
@@ -11,9 +14,16 @@ pub fn wasm_test<P: AsRef<Path>>(
     wasm_file: P,
     icount_option: bool,
 ) -> Result<Arc<DlModule>, Error> {
-    let workdir = TempDir::new().expect("create working directory");
+    load(Lucetc::new(wasm_file).with_count_instructions(icount_option))
+}
+
+/// Compile a module with fuel checks, which also count instructions.
+pub fn fuel_test<P: AsRef<Path>>(wasm_file: P) -> Result<Arc<DlModule>, Error> {
+    load(Lucetc::new(wasm_file).with_fuel_checks(true))
+}
 
-    let native_build = Lucetc::new(wasm_file).with_count_instructions(icount_option);
+fn load(native_build: Lucetc) -> Result<Arc<DlModule>, Error> {
+    let workdir = TempDir::new().expect("create working directory");
 
     let so_file = workdir.path().join("out.so");
 
@@ -110,6 +120,104 @@ pub fn check_instruction_count() {
     });
 }
 
+#[test]
+pub fn fuel_limit_allows_exact_budget() {
+    // `loops.wat` executes exactly 91 operations
+    let module =
+        fuel_test("./tests/instruction_counting/loops.wat").expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_fuel(91)
+        .build()
+        .expect("instance can be created");
+
+    inst.run("test_function", &[]).expect("instance runs");
+    assert_eq!(inst.get_fuel(), Some(0));
+}
+
+#[test]
+pub fn fuel_exhausted_terminates() {
+    let module = fuel_test("./tests/fuel/loop_forever.wat").expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_fuel(1000)
+        .build()
+        .expect("instance can be created");
+
+    match inst.run("loop_forever", &[]) {
+        Err(RuntimeError::RuntimeTerminated(TerminationDetails::OutOfFuel)) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+    assert!(inst.get_instruction_count().unwrap() > 1000);
+}
+
+#[test]
+pub fn fuel_exhausted_yields() {
+    let module = fuel_test("./tests/fuel/loop_forever.wat").expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_fuel(1000)
+        .with_out_of_fuel_behavior(OutOfFuelBehavior::Yield)
+        .build()
+        .expect("instance can be created");
+
+    let res = inst.run("loop_forever", &[]).expect("instance runs");
+    assert!(res.unwrap_yielded().downcast::<OutOfFuel>().is_ok());
+    let first_count = inst.get_instruction_count().unwrap();
+    assert!(first_count > 1000);
+
+    // resuming without more fuel yields again immediately
+    let res = inst.resume().expect("instance resumes");
+    assert!(res.unwrap_yielded().downcast::<OutOfFuel>().is_ok());
+    assert_eq!(inst.get_instruction_count().unwrap(), first_count);
+
+    inst.add_fuel(1000).expect("can add fuel");
+    let res = inst.resume().expect("instance resumes");
+    assert!(res.unwrap_yielded().downcast::<OutOfFuel>().is_ok());
+    assert!(inst.get_instruction_count().unwrap() > first_count + 1000);
+}
+
+#[test]
+pub fn fuel_requires_fuel_checks() {
+    // counting instructions alone does not make a module check its fuel
+    let module =
+        wasm_test("./tests/fuel/loop_forever.wat", true).expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    match region.new_instance_builder(module).with_fuel(1000).build() {
+        Err(RuntimeError::Unsupported(_)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("instance built without fuel checks"),
+    }
+}
+
+#[test]
+pub fn fuel_checks_count_instructions() {
+    let module =
+        fuel_test("./tests/instruction_counting/loops.wat").expect("can load instrumented module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    inst.run("test_function", &[]).expect("instance runs");
+    assert_eq!(inst.get_instruction_count(), Some(91));
+    assert_eq!(inst.get_fuel(), None);
+}
+
+#[test]
+pub fn fuel_requires_instrumentation() {
+    let module = wasm_test("./tests/fuel/loop_forever.wat", false).expect("can load module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    match region.new_instance_builder(module).with_fuel(1000).build() {
+        Err(RuntimeError::Unsupported(_)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("instance built without instruction counting"),
+    }
+}
+
 #[test]
 fn ensure_linked() {
     lucet_runtime::lucet_internal_ensure_linked();
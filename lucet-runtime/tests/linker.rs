@@ -0,0 +1,96 @@
+use anyhow::Error;
+use lucet_runtime::{lucet_hostcall, vmctx::Vmctx, DlModule, Limits, MmapRegion, Region, Val};
+use lucetc::{Bindings, Linker, Lucetc, LucetcOpts};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn linker_test_add_one(_vmctx: &Vmctx, x: u32) -> u32 {
+    x + 1
+}
+
+/// Compile the test module with `linker`, returning the contents of the shared object and the
+/// module loaded with `dlopen(3)`.
+fn wasm_test(linker: Linker) -> Result<(Vec<u8>, Arc<DlModule>), Error> {
+    let workdir = TempDir::new().expect("create working directory");
+
+    let mut env = HashMap::new();
+    env.insert("add_one".to_owned(), "linker_test_add_one".to_owned());
+    let native_build = Lucetc::new("./tests/linker/linker.wat")
+        .with_bindings(Bindings::env(env))
+        .with_linker(linker);
+
+    let so_file = workdir.path().join("out.so");
+    native_build.shared_object_file(so_file.clone())?;
+
+    let bytes = std::fs::read(&so_file)?;
+    let dlmodule = DlModule::load(&so_file)?;
+
+    Ok((bytes, dlmodule))
+}
+
+fn run_module(module: Arc<DlModule>) {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let retval = inst
+        .run("load", &[Val::U32(0)])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+
+    for expected in 1..=2 {
+        let retval = inst
+            .run("bump", &[])
+            .expect("instance runs")
+            .unwrap_returned();
+        assert_eq!(retval.as_i32(), expected);
+    }
+
+    let retval = inst
+        .run("call_indirect", &[Val::U32(0), Val::U32(21)])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+    let retval = inst
+        .run("call_indirect", &[Val::U32(1), Val::U32(41)])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+
+    let retval = inst
+        .run("grow", &[Val::U32(2)])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 3);
+}
+
+#[test]
+fn builtin_linker() {
+    let (bytes, module) = wasm_test(Linker::Builtin).expect("can link and load module");
+    assert_eq!(&bytes[0..4], b"\x7fELF");
+    run_module(module);
+    run_module(DlModule::load_from_bytes(&bytes).expect("can load module from bytes"));
+}
+
+#[test]
+fn external_linker() {
+    let (_, module) = wasm_test(Linker::External).expect("can link and load module");
+    run_module(module);
+}
+
+#[test]
+fn builtin_linker_is_reproducible() {
+    let (first, _) = wasm_test(Linker::Builtin).expect("can link and load module");
+    let (second, _) = wasm_test(Linker::Builtin).expect("can link and load module");
+    assert!(first == second);
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
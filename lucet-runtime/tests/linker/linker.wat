@@ -0,0 +1,27 @@
+(module
+  (import "env" "add_one" (func $add_one (param i32) (result i32)))
+  (memory 1 4)
+  (data (i32.const 0) "\2a\00\00\00")
+  (global $counter (mut i32) (i32.const 0))
+  (table 2 funcref)
+  (elem (i32.const 0) $double $add_one)
+
+  (func $double (param i32) (result i32)
+    (i32.mul (local.get 0) (i32.const 2)))
+
+  ;; calls through the table exercise relocations in data, and calling the import exercises
+  ;; symbols that are resolved when the module is loaded
+  (func (export "call_indirect") (param i32 i32) (result i32)
+    (call_indirect (param i32) (result i32) (local.get 1) (local.get 0)))
+
+  (func (export "load") (param i32) (result i32)
+    (i32.load (local.get 0)))
+
+  (func (export "bump") (result i32)
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+    (global.get $counter))
+
+  (func (export "grow") (param i32) (result i32)
+    (drop (memory.grow (local.get 0)))
+    (memory.size))
+)
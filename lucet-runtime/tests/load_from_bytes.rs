@@ -0,0 +1,134 @@
+mod common;
+
+use byteorder::{ByteOrder, LittleEndian};
+use common::so_bytes;
+use lucet_runtime::{DlModule, Error as RuntimeError, Limits, MmapRegion, Region, Val};
+
+#[test]
+fn run_module_loaded_from_bytes() {
+    let bytes =
+        so_bytes("./tests/load_from_bytes/load_from_bytes.wat").expect("can compile module");
+    // the module does not depend on the shared object file, which is already gone
+    let module = DlModule::load_from_bytes(&bytes).expect("can load module from bytes");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let retval = inst
+        .run("load", &[Val::U32(0)])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+
+    for expected in 1..=3 {
+        let retval = inst
+            .run("bump", &[])
+            .expect("instance runs")
+            .unwrap_returned();
+        assert_eq!(retval.as_i32(), expected);
+    }
+
+    let retval = inst
+        .run("grow", &[Val::U32(2)])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 3);
+}
+
+#[test]
+fn load_and_unload_repeatedly() {
+    for _ in 0..16 {
+        let bytes =
+            so_bytes("./tests/load_from_bytes/load_from_bytes.wat").expect("can compile module");
+        let module = DlModule::load_from_bytes(&bytes).expect("can load module from bytes");
+        let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+        let mut inst = region
+            .new_instance(module)
+            .expect("instance can be created");
+        let retval = inst
+            .run("bump", &[])
+            .expect("instance runs")
+            .unwrap_returned();
+        assert_eq!(retval.as_i32(), 1);
+    }
+}
+
+fn assert_rejected(bytes: &[u8], message: &str) {
+    match DlModule::load_from_bytes(bytes) {
+        Err(e @ RuntimeError::DlError(_)) | Err(e @ RuntimeError::ModuleError(_)) => {
+            assert!(
+                format!("{}", e).contains(message),
+                "error `{}` does not mention `{}`",
+                e,
+                message
+            );
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("malformed bytes were loaded as a module"),
+    }
+}
+
+#[test]
+fn reject_non_elf_bytes() {
+    assert_rejected(b"definitely not a shared object", "not an ELF file");
+}
+
+#[test]
+fn reject_truncated_elf_header() {
+    assert_rejected(b"\x7fELF\x02\x01\x01", "out of bounds");
+}
+
+#[test]
+fn reject_overflowing_segment() {
+    let mut bytes =
+        so_bytes("./tests/load_from_bytes/load_from_bytes.wat").expect("can compile module");
+    let phoff = LittleEndian::read_u64(&bytes[0x20..]) as usize;
+    let phentsize = LittleEndian::read_u16(&bytes[0x36..]) as usize;
+    let phnum = LittleEndian::read_u16(&bytes[0x38..]) as usize;
+    let load = (0..phnum)
+        .map(|n| phoff + n * phentsize)
+        .find(|&ph| LittleEndian::read_u32(&bytes[ph..]) == 1)
+        .expect("object has a PT_LOAD segment");
+    // p_memsz
+    LittleEndian::write_u64(&mut bytes[load + 40..], std::u64::MAX);
+    assert_rejected(&bytes, "overflows the address space");
+}
+
+#[test]
+fn reject_module_symbol_outside_segments() {
+    let mut bytes =
+        so_bytes("./tests/load_from_bytes/load_from_bytes.wat").expect("can compile module");
+    let shoff = LittleEndian::read_u64(&bytes[0x28..]) as usize;
+    let shentsize = LittleEndian::read_u16(&bytes[0x3a..]) as usize;
+    let shnum = LittleEndian::read_u16(&bytes[0x3c..]) as usize;
+    let section = |n: usize| shoff + n * shentsize;
+    let mut patched = 0;
+    for n in 0..shnum {
+        let sh = section(n);
+        // SHT_SYMTAB or SHT_DYNSYM
+        let sh_type = LittleEndian::read_u32(&bytes[sh + 4..]);
+        if sh_type != 2 && sh_type != 11 {
+            continue;
+        }
+        let offset = LittleEndian::read_u64(&bytes[sh + 24..]) as usize;
+        let size = LittleEndian::read_u64(&bytes[sh + 32..]) as usize;
+        let link = LittleEndian::read_u32(&bytes[sh + 40..]) as usize;
+        let strtab = LittleEndian::read_u64(&bytes[section(link) + 24..]) as usize;
+        for sym in (offset..offset + size).step_by(24) {
+            let name = strtab + LittleEndian::read_u32(&bytes[sym..]) as usize;
+            if bytes[name..].starts_with(b"lucet_module\0") {
+                // st_value
+                LittleEndian::write_u64(&mut bytes[sym + 8..], 0x7fff_0000_0000);
+                patched += 1;
+            }
+        }
+    }
+    assert!(patched > 0, "object defines `lucet_module`");
+    assert_rejected(&bytes, "outside the loaded segments");
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
@@ -0,0 +1,18 @@
+(module
+  (memory 1 4)
+  (data (i32.const 0) "\2a\00\00\00")
+  (global $counter (mut i32) (i32.const 0))
+
+  (func (export "load") (param i32) (result i32)
+    (i32.load (local.get 0)))
+
+  (func (export "bump") (result i32)
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+    (global.get $counter))
+
+  ;; `memory.grow` and `memory.size` are hostcalls, so this exercises resolving symbols exported by
+  ;; the runtime
+  (func (export "grow") (param i32) (result i32)
+    (drop (memory.grow (local.get 0)))
+    (memory.size))
+)
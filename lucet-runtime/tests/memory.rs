@@ -11,6 +11,40 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(target_os = "linux")]
+mod heap_image {
+    use lucet_runtime::{Limits, MmapRegion, Region};
+    use lucet_runtime_internals::module::ModuleInternal;
+    use lucet_runtime_tests::build::test_module_wasm;
+
+    #[test]
+    fn ensure_linked() {
+        lucet_runtime::lucet_internal_ensure_linked();
+    }
+
+    #[test]
+    fn modules_with_data_have_heap_images() {
+        let module = test_module_wasm("memory", "data_segments.wat")
+            .expect("compile and load data_segments.wasm");
+        let image = module.heap_image().expect("module has a heap image");
+        // the sparse page data, and so the image, covers the whole initial heap
+        assert_eq!(image.len(), 2 * 65536);
+
+        let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+        let inst = region
+            .new_instance(module.clone())
+            .expect("instance can be created");
+        assert_eq!(&inst.heap()[0..5], b"lucet");
+    }
+
+    #[test]
+    fn modules_without_data_have_no_heap_image() {
+        let module = test_module_wasm("memory", "current_memory.wat")
+            .expect("compile and load current_memory.wasm");
+        assert!(module.heap_image().is_none());
+    }
+}
+
 #[cfg(all(target_os = "linux", feature = "uffd"))]
 mod uffd_specific {
     use libc::{c_void, mincore};
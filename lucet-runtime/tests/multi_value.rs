@@ -0,0 +1,85 @@
+mod common;
+
+use common::wasm_test;
+use lucet_runtime::{Error as RuntimeError, Limits, MmapRegion, Region, Val};
+
+#[test]
+fn multi_value_returns_all_values() {
+    let module =
+        wasm_test("./tests/multi_value/multi_value.wat").expect("can load multi-value module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let retval = inst
+        .run("divmod", &[Val::U32(7), Val::U32(2)])
+        .expect("instance runs")
+        .unwrap_returned();
+
+    // the first value is still available from the untyped accessors
+    assert_eq!(retval.as_i32(), 3);
+    match retval.vals().as_slice() {
+        [Val::I32(3), Val::I32(1)] => (),
+        vals => panic!("unexpected return values: {:?}", vals),
+    }
+}
+
+#[test]
+fn multi_value_mixed_types() {
+    let module =
+        wasm_test("./tests/multi_value/multi_value.wat").expect("can load multi-value module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let retval = inst
+        .run("split", &[Val::F64(-2.5)])
+        .expect("instance runs")
+        .unwrap_returned();
+
+    match retval.vals().as_slice() {
+        [Val::I64(-2), Val::F64(f), Val::F32(g), Val::I32(7)] => {
+            assert_eq!(*f, -2.5);
+            assert_eq!(*g, -2.5);
+        }
+        vals => panic!("unexpected return values: {:?}", vals),
+    }
+}
+
+#[test]
+fn multi_value_internal_calls() {
+    let module =
+        wasm_test("./tests/multi_value/multi_value.wat").expect("can load multi-value module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let retval = inst
+        .run("call_sum5", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i64(), 15);
+}
+
+#[test]
+fn multi_value_too_many_results_for_host() {
+    let module =
+        wasm_test("./tests/multi_value/multi_value.wat").expect("can load multi-value module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    match inst.run("too_many_results", &[]) {
+        Err(RuntimeError::Unsupported(_)) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
@@ -0,0 +1,29 @@
+(module
+  (func $divmod (export "divmod") (param i32 i32) (result i32 i32)
+    (i32.div_u (local.get 0) (local.get 1))
+    (i32.rem_u (local.get 0) (local.get 1))
+  )
+
+  (func $split (export "split") (param f64) (result i64 f64 f32 i32)
+    (i64.trunc_f64_s (local.get 0))
+    (local.get 0)
+    (f32.demote_f64 (local.get 0))
+    (i32.const 7)
+  )
+
+  ;; Calls between guest functions may return any number of values.
+  (func $sum5 (result i64 i64 i64 i64 i64)
+    (i64.const 1) (i64.const 2) (i64.const 3) (i64.const 4) (i64.const 5)
+  )
+  (func (export "call_sum5") (result i64)
+    (call $sum5)
+    (i64.add)
+    (i64.add)
+    (i64.add)
+    (i64.add)
+  )
+
+  (func (export "too_many_results") (result i32 i32 i32)
+    (i32.const 1) (i32.const 2) (i32.const 3)
+  )
+)
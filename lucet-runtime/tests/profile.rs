@@ -0,0 +1,117 @@
+mod common;
+
+use common::wasm_test;
+use lucet_runtime::{Limits, MmapRegion, Region, Val};
+use std::time::Duration;
+
+/// Enough iterations of `spin` to keep the guest busy for many sampling intervals.
+const SPIN_ITERATIONS: u32 = 100_000_000;
+
+#[test]
+fn profile_attributes_samples_to_guest_functions() {
+    let module = wasm_test("./tests/profile/profile.wat").expect("can load profile module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    inst.start_profiling(Duration::from_millis(1))
+        .expect("profiling can start");
+    inst.run("outer", &[Val::U32(SPIN_ITERATIONS)])
+        .expect("instance runs");
+    let profile = inst.stop_profiling().expect("instance was profiled");
+
+    assert!(profile.sample_count() > 0);
+    assert_eq!(profile.dropped_samples(), 0);
+    let (hottest, _) = profile
+        .stacks()
+        .max_by_key(|(_, count)| *count)
+        .expect("a stack was sampled");
+    assert_eq!(hottest, &["guest_func_outer", "guest_func_spin"]);
+
+    let mut folded = vec![];
+    profile
+        .write_folded(&mut folded)
+        .expect("profile can be written");
+    let folded = String::from_utf8(folded).expect("folded stacks are utf-8");
+    assert!(folded
+        .lines()
+        .any(|line| line.starts_with("guest_func_outer;guest_func_spin ")));
+    let total = folded
+        .lines()
+        .map(|line| {
+            line.rsplit(' ')
+                .next()
+                .unwrap()
+                .parse::<u64>()
+                .expect("each line ends with a count")
+        })
+        .sum::<u64>();
+    assert_eq!(total, profile.sample_count());
+}
+
+#[test]
+fn profile_accumulates_across_runs() {
+    let module = wasm_test("./tests/profile/profile.wat").expect("can load profile module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    assert!(inst.profile().is_none());
+    inst.start_profiling(Duration::from_millis(1))
+        .expect("profiling can start");
+
+    inst.run("spin", &[Val::U32(SPIN_ITERATIONS)])
+        .expect("instance runs");
+    let first = inst.profile().expect("instance is profiled").sample_count();
+    assert!(first > 0);
+
+    inst.run("spin", &[Val::U32(SPIN_ITERATIONS)])
+        .expect("instance runs");
+    let profile = inst.stop_profiling().expect("instance was profiled");
+    assert!(profile.sample_count() > first);
+    assert!(inst.stop_profiling().is_none());
+
+    // runs after profiling stops are not sampled
+    inst.run("spin", &[Val::U32(SPIN_ITERATIONS)])
+        .expect("instance runs");
+    assert!(inst.profile().is_none());
+}
+
+#[test]
+fn profiles_merge() {
+    let module = wasm_test("./tests/profile/profile.wat").expect("can load profile module");
+    let region = MmapRegion::create(2, &Limits::default()).expect("region can be created");
+
+    let mut profiles = (0..2)
+        .map(|_| {
+            let mut inst = region
+                .new_instance(module.clone())
+                .expect("instance can be created");
+            inst.start_profiling(Duration::from_millis(1))
+                .expect("profiling can start");
+            inst.run("outer", &[Val::U32(SPIN_ITERATIONS)])
+                .expect("instance runs");
+            inst.stop_profiling().expect("instance was profiled")
+        })
+        .collect::<Vec<_>>();
+
+    let second = profiles.pop().unwrap();
+    let mut merged = profiles.pop().unwrap();
+    let first_count = merged.sample_count();
+    merged.merge(&second);
+    assert_eq!(merged.sample_count(), first_count + second.sample_count());
+}
+
+#[test]
+fn profiling_interval_must_not_be_zero() {
+    let module = wasm_test("./tests/profile/profile.wat").expect("can load profile module");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    assert!(inst.start_profiling(Duration::from_secs(0)).is_err());
+    assert!(inst.profile().is_none());
+}
@@ -0,0 +1,13 @@
+(module
+  (func $spin (export "spin") (param i32) (result i32)
+    (local i32)
+    (block
+      (loop
+        (br_if 1 (i32.eqz (local.get 0)))
+        (local.set 1 (i32.add (local.get 1) (i32.mul (local.get 0) (local.get 0))))
+        (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+        (br 0)))
+    (local.get 1))
+  (func $outer (export "outer") (param i32) (result i32)
+    (i32.add (call $spin (local.get 0)) (i32.const 1)))
+)
@@ -0,0 +1,155 @@
+mod common;
+
+use common::wasm_test;
+use lucet_runtime::{
+    Error as RuntimeError, InstanceHandle, Limits, MmapRegion, Region, TrapCode, Val,
+};
+use std::sync::Arc;
+
+fn new_instance(region: &Arc<MmapRegion>) -> InstanceHandle {
+    let module = wasm_test("./tests/reference_types/reference_types.wat")
+        .expect("can load reference types module");
+    region
+        .new_instance(module)
+        .expect("instance can be created")
+}
+
+fn run_i32(inst: &mut InstanceHandle, entrypoint: &str, args: &[Val]) -> i32 {
+    inst.run(entrypoint, args)
+        .expect("instance runs")
+        .unwrap_returned()
+        .as_i32()
+}
+
+#[test]
+fn table_get_and_set() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(0)]), 1);
+    assert_eq!(run_i32(&mut inst, "copy_and_call", &[]), 2);
+
+    // the host sees the guest's changes to the table
+    let retval = inst
+        .run_func_idx(0, 0, &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 2);
+
+    // and resetting the instance restores the table
+    inst.reset().expect("instance resets");
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(0)]), 1);
+}
+
+#[test]
+fn table_grow_within_maximum() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    assert_eq!(run_i32(&mut inst, "size_funcs", &[]), 2);
+    assert_eq!(run_i32(&mut inst, "grow_funcs", &[Val::U32(2)]), 2);
+    assert_eq!(run_i32(&mut inst, "size_funcs", &[]), 4);
+    assert_eq!(inst.table(0).map(|t| t.len()), Some(4));
+
+    // new elements are initialized with the given reference
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(3)]), 1);
+    inst.run("set_from_ref_func", &[Val::U32(3)])
+        .expect("instance runs");
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(3)]), 2);
+
+    // the table declares a maximum of 4 elements
+    assert_eq!(run_i32(&mut inst, "grow_funcs", &[Val::U32(1)]), -1);
+    assert_eq!(run_i32(&mut inst, "size_funcs", &[]), 4);
+}
+
+#[test]
+fn table_grow_limited_by_instance_limit() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let module = wasm_test("./tests/reference_types/reference_types.wat")
+        .expect("can load reference types module");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_table_elements_limit(3)
+        .build()
+        .expect("instance can be created");
+
+    assert_eq!(run_i32(&mut inst, "grow_funcs", &[Val::U32(2)]), -1);
+    assert_eq!(run_i32(&mut inst, "grow_funcs", &[Val::U32(1)]), 2);
+    assert_eq!(run_i32(&mut inst, "size_funcs", &[]), 3);
+}
+
+#[test]
+fn externref_round_trip() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    let retval = inst
+        .run("get_extern", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    match retval.vals().as_slice() {
+        [Val::ExternRef(0)] => (),
+        vals => panic!("unexpected return values: {:?}", vals),
+    }
+
+    inst.run("set_extern", &[Val::ExternRef(0xdead_beef)])
+        .expect("instance runs");
+    let retval = inst
+        .run("get_extern", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    match retval.vals().as_slice() {
+        [Val::ExternRef(0xdead_beef)] => (),
+        vals => panic!("unexpected return values: {:?}", vals),
+    }
+    assert_eq!(inst.table_get(1, 0).expect("element exists"), 0xdead_beef);
+
+    // externref tables do not hold functions the host could call
+    match inst.run_func_idx(1, 0, &[]) {
+        Err(RuntimeError::FuncNotFound(1, 0)) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn host_rejects_unknown_function_references() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+    let bogus = 0xdead_beef;
+
+    match inst.table_set(0, 0, bogus) {
+        Err(RuntimeError::InvalidArgument(_)) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+    match inst.table_fill(0, 0, bogus, 2) {
+        Err(RuntimeError::InvalidArgument(_)) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+    match inst.table_grow(0, 1, bogus) {
+        Err(RuntimeError::InvalidArgument(_)) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // the table is left as it was
+    assert_eq!(run_i32(&mut inst, "size_funcs", &[]), 2);
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(0)]), 1);
+    assert_eq!(run_i32(&mut inst, "call_indirect", &[Val::U32(1)]), 2);
+}
+
+#[test]
+fn table_get_out_of_bounds() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    match inst.run("get_out_of_bounds", &[]) {
+        Err(RuntimeError::RuntimeFault(details)) => {
+            assert_eq!(details.trapcode, Some(TrapCode::TableOutOfBounds));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
@@ -0,0 +1,46 @@
+(module
+  (type $ret_i32 (func (result i32)))
+
+  (table $funcs 2 4 funcref)
+  ;; `anyref` is the name this version of the text format uses for `externref`.
+  (table $externs 1 anyref)
+
+  (elem (i32.const 0) $one $two)
+
+  (func $one (type $ret_i32) (i32.const 1))
+  (func $two (type $ret_i32) (i32.const 2))
+
+  ;; Copy the second element of the function table over the first, then call the first.
+  (func (export "copy_and_call") (result i32)
+    (table.set $funcs (i32.const 0) (table.get $funcs (i32.const 1)))
+    (call_indirect (type $ret_i32) (i32.const 0))
+  )
+
+  (func (export "set_from_ref_func") (param i32)
+    (table.set $funcs (local.get 0) (ref.func $two))
+  )
+
+  (func (export "grow_funcs") (param i32) (result i32)
+    (table.grow $funcs (ref.func $one) (local.get 0))
+  )
+
+  (func (export "size_funcs") (result i32)
+    (table.size $funcs)
+  )
+
+  (func (export "call_indirect") (param i32) (result i32)
+    (call_indirect (type $ret_i32) (local.get 0))
+  )
+
+  (func (export "set_extern") (param anyref)
+    (table.set $externs (i32.const 0) (local.get 0))
+  )
+
+  (func (export "get_extern") (result anyref)
+    (table.get $externs (i32.const 0))
+  )
+
+  (func (export "get_out_of_bounds") (result anyref)
+    (table.get $externs (i32.const 1))
+  )
+)
@@ -0,0 +1,77 @@
+mod common;
+
+use common::wasm_test;
+use lucet_runtime::{InstanceHandle, Limits, MmapRegion, Region, Val};
+use std::sync::Arc;
+
+fn new_instance(region: &Arc<MmapRegion>) -> InstanceHandle {
+    let module = wasm_test("./tests/simd/simd.wat").expect("can load simd module");
+    region
+        .new_instance(module)
+        .expect("instance can be created")
+}
+
+/// Pack four `i32` lanes into a `v128`, lowest lane first.
+fn i32x4(lanes: [i32; 4]) -> u128 {
+    lanes
+        .iter()
+        .rev()
+        .fold(0, |v, lane| (v << 32) | (*lane as u32 as u128))
+}
+
+#[test]
+fn v128_arguments_and_results() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    let retval = inst
+        .run(
+            "add",
+            &[
+                Val::V128(i32x4([1, 2, 3, 4])),
+                Val::V128(i32x4([10, 20, 30, -4])),
+            ],
+        )
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_v128(), i32x4([11, 22, 33, 0]));
+
+    let retval = inst
+        .run("splat", &[Val::I32(7)])
+        .expect("instance runs")
+        .unwrap_returned();
+    match retval.vals().as_slice() {
+        [Val::V128(v)] => assert_eq!(*v, i32x4([7, 7, 7, 7])),
+        vals => panic!("unexpected return values: {:?}", vals),
+    }
+}
+
+#[test]
+fn v128_globals() {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = new_instance(&region);
+
+    let sum = inst
+        .run("sum_global", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(sum.as_i32(), 10);
+    assert_eq!(unsafe { inst.globals()[0].v_128 }, i32x4([1, 2, 3, 4]));
+
+    inst.run("set_global", &[Val::V128(i32x4([5, 6, 7, 8]))])
+        .expect("instance runs");
+    let retval = inst
+        .run("get_global", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_v128(), i32x4([5, 6, 7, 8]));
+
+    // resetting the instance restores the global's initial value
+    inst.reset().expect("instance resets");
+    assert_eq!(unsafe { inst.globals()[0].v_128 }, i32x4([1, 2, 3, 4]));
+}
+
+#[test]
+fn ensure_linked() {
+    lucet_runtime::lucet_internal_ensure_linked();
+}
@@ -0,0 +1,28 @@
+(module
+  (global $g (mut v128) (v128.const i32x4 1 2 3 4))
+
+  (func (export "add") (param v128 v128) (result v128)
+    (i32x4.add (local.get 0) (local.get 1))
+  )
+
+  (func (export "splat") (param i32) (result v128)
+    (i32x4.splat (local.get 0))
+  )
+
+  ;; Sum the lanes of the global.
+  (func (export "sum_global") (result i32)
+    (local $v v128)
+    (local.set $v (global.get $g))
+    (i32.add
+      (i32.add (i32x4.extract_lane 0 (local.get $v)) (i32x4.extract_lane 1 (local.get $v)))
+      (i32.add (i32x4.extract_lane 2 (local.get $v)) (i32x4.extract_lane 3 (local.get $v))))
+  )
+
+  (func (export "set_global") (param v128)
+    (global.set $g (local.get 0))
+  )
+
+  (func (export "get_global") (result v128)
+    (global.get $g)
+  )
+)
@@ -270,13 +270,44 @@ fn check_retval(expected: &[Value], got: UntypedRetVal) -> Result<(), Error> {
             }
         },
         n => {
-            let message = format!("{} expected return values not supported", n);
-            return Err(Error::UnsupportedCommand(message));
+            let got = got.vals();
+            if got.len() != n {
+                let message = format!("expected {} return values, got {}", n, got.len());
+                return Err(Error::IncorrectResult(message));
+            }
+            for (expected, got) in expected.iter().zip(got.iter()) {
+                check_val(expected, got)?;
+            }
         }
     }
     Ok(())
 }
 
+fn check_val(expected: &Value, got: &Val) -> Result<(), Error> {
+    match (expected, got) {
+        (Value::I32(expected), Val::I32(got)) if expected == got => Ok(()),
+        (Value::I64(expected), Val::I64(got)) if expected == got => Ok(()),
+        (Value::F32(expected), Val::F32(got))
+            if expected == got || expected.is_nan() || got.is_nan() =>
+        {
+            Ok(())
+        }
+        (Value::F64(expected), Val::F64(got))
+            if expected == got || expected.is_nan() || got.is_nan() =>
+        {
+            Ok(())
+        }
+        (Value::V128(v), _) => {
+            let message = format!("got unsupported SIMD V128 value: {}", v);
+            Err(Error::UnsupportedCommand(message))
+        }
+        (expected, got) => {
+            let message = format!("expected {:?}, got {:?}", expected, got);
+            Err(Error::IncorrectResult(message))
+        }
+    }
+}
+
 fn translate_args(args: &[Value]) -> Vec<Val> {
     let mut out = Vec::new();
     for a in args {
@@ -125,7 +125,7 @@ impl Validator {
         let start_name = "_start";
         let expected = FuncSignature {
             args: vec![],
-            ret: None,
+            rets: vec![],
         };
         if let Some(startfunc) = moduletype.export(start_name) {
             if startfunc != &expected {
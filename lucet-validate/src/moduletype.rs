@@ -66,21 +66,15 @@ impl ModuleType {
                                 params,
                                 returns,
                             } => {
-                                let ret = match returns.len() {
-                                    0 => None,
-                                    1 => Some(wasmparser_to_atomtype(&returns[0])?),
-                                    _ => {
-                                        return Err(Error::Unsupported(format!(
-                                            "more than 1 return value: {:?}",
-                                            returns,
-                                        )))
-                                    }
-                                };
+                                let rets = returns
+                                    .iter()
+                                    .map(|r| wasmparser_to_atomtype(r))
+                                    .collect::<Result<Vec<_>, _>>()?;
                                 let args = params
                                     .iter()
                                     .map(|a| wasmparser_to_atomtype(a))
                                     .collect::<Result<Vec<_>, _>>()?;
-                                module.types.push(FuncSignature { args, ret });
+                                module.types.push(FuncSignature { args, rets });
                             }
                             _ => return Err(Error::Unsupported("type section entry".to_string())),
                         }
@@ -4,14 +4,14 @@ use witx::CoreFuncType;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FuncSignature {
     pub args: Vec<AtomType>,
-    pub ret: Option<AtomType>,
+    pub rets: Vec<AtomType>,
 }
 
 impl From<CoreFuncType> for FuncSignature {
     fn from(m: CoreFuncType) -> FuncSignature {
         FuncSignature {
             args: m.args.iter().map(|a| a.repr()).collect(),
-            ret: m.ret.map(|r| r.repr()),
+            rets: m.ret.iter().map(|r| r.repr()).collect(),
         }
     }
 }
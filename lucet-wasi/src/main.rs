@@ -22,6 +22,7 @@ struct Config<'a> {
     timeout: Option<Duration>,
     verify: bool,
     pk_path: Option<PathBuf>,
+    profile_path: Option<PathBuf>,
 }
 
 fn parse_humansized(desc: &str) -> Result<u64, Error> {
@@ -120,6 +121,12 @@ fn main() {
                 .takes_value(true)
                 .help("Path to the public key to verify the source code signature")
         )
+        .arg(
+            Arg::with_name("profile_path")
+                .long("--profile")
+                .takes_value(true)
+                .help("Sample the guest every millisecond of CPU time, and write the samples to this file as folded stacks")
+        )
         .get_matches();
 
     let entrypoint = matches.value_of("entrypoint").unwrap();
@@ -188,6 +195,7 @@ fn main() {
 
     let verify = matches.is_present("verify");
     let pk_path = matches.value_of("pk_path").map(PathBuf::from);
+    let profile_path = matches.value_of("profile_path").map(PathBuf::from);
 
     let config = Config {
         lucet_module,
@@ -198,6 +206,7 @@ fn main() {
         timeout,
         verify,
         pk_path,
+        profile_path,
     };
 
     run(config)
@@ -258,9 +267,24 @@ fn run(config: Config<'_>) {
             });
         }
 
+        if config.profile_path.is_some() {
+            inst.start_profiling(Duration::from_millis(1))
+                .expect("profiling can start");
+        }
+
         inst.run_start().expect("Wasm start function runs");
 
-        match inst.run(config.entrypoint, &[]) {
+        let res = inst.run(config.entrypoint, &[]);
+
+        if let Some(profile_path) = config.profile_path {
+            let profile = inst.stop_profiling().expect("instance was profiled");
+            let mut profile_file = File::create(profile_path).expect("profile can be created");
+            profile
+                .write_folded(&mut profile_file)
+                .expect("profile can be written");
+        }
+
+        match res {
             // normal termination implies 0 exit code
             Ok(RunResult::Returned(_)) => 0,
             // none of the WASI hostcalls use yield yet, so this shouldn't happen
@@ -276,6 +300,10 @@ fn run(config: Config<'_>) {
                 println!("Terminated via remote kill switch (likely a timeout)");
                 std::u32::MAX
             }
+            // print the fault with `Display` rather than `Debug`, so the guest backtrace is legible
+            Err(lucet_runtime::Error::RuntimeFault(details)) => {
+                panic!("lucet-wasi guest faulted: {}", details)
+            }
             Err(e) => panic!("lucet-wasi runtime error: {}", e),
         }
     };
@@ -143,10 +143,47 @@ pub fn run(opts: &Options) -> Result<(), Error> {
         c.count_instructions(true);
     }
 
-    match opts.codegen {
-        CodegenOutput::Obj => c.object_file(&opts.output)?,
-        CodegenOutput::SharedObj => c.shared_object_file(&opts.output)?,
-        CodegenOutput::Clif => c.clif_ir(&opts.output)?,
+    if opts.fuel_checks {
+        c.fuel_checks(true);
+    }
+
+    if opts.epoch_interruption {
+        c.epoch_interruption(true);
+    }
+
+    if let Some(threads) = opts.threads {
+        c.threads(threads);
+    }
+
+    if opts.debug_info {
+        c.debug_info(true);
+    }
+
+    if opts.call_site_offsets {
+        c.call_site_offsets(true);
+    }
+
+    if let Some(cache_dir) = &opts.cache_dir {
+        c.cache_dir(cache_dir.clone());
+    }
+
+    if let Some(linker) = opts.linker {
+        c.linker(linker);
+    }
+
+    match (opts.codegen, opts.check_reproducible) {
+        (CodegenOutput::Obj, false) => c.object_file(&opts.output)?,
+        (CodegenOutput::Obj, true) => c.check_reproducible_object_file(&opts.output)?,
+        (CodegenOutput::SharedObj, false) => c.shared_object_file(&opts.output)?,
+        (CodegenOutput::SharedObj, true) => {
+            c.check_reproducible_shared_object_file(&opts.output)?
+        }
+        (CodegenOutput::Clif, false) => c.clif_ir(&opts.output)?,
+        (CodegenOutput::Clif, true) => {
+            return Err(format_err!(
+                "--check-reproducible requires --emit obj or --emit so"
+            ))
+        }
     }
     Ok(())
 }
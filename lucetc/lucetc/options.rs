@@ -1,6 +1,6 @@
 use anyhow::Error;
 use clap::{Arg, ArgMatches, Values};
-use lucetc::{CpuFeatures, HeapSettings, OptLevel, SpecificFeature, TargetCpu};
+use lucetc::{CpuFeatures, HeapSettings, Linker, OptLevel, SpecificFeature, TargetCpu};
 use std::path::PathBuf;
 use std::str::FromStr;
 use target_lexicon::{Architecture, Triple};
@@ -120,8 +120,16 @@ pub struct Options {
     pub pk_path: Option<PathBuf>,
     pub sk_path: Option<PathBuf>,
     pub count_instructions: bool,
+    pub fuel_checks: bool,
+    pub epoch_interruption: bool,
     pub error_style: ErrorStyle,
     pub target: Triple,
+    pub cache_dir: Option<PathBuf>,
+    pub threads: Option<usize>,
+    pub debug_info: bool,
+    pub call_site_offsets: bool,
+    pub check_reproducible: bool,
+    pub linker: Option<Linker>,
 }
 
 impl Options {
@@ -201,8 +209,9 @@ impl Options {
             m.values_of("target-feature").unwrap_or_default(),
         )?;
 
-        if target.architecture != Architecture::X86_64 {
-            panic!("architectures other than x86-64 are unsupported");
+        match target.architecture {
+            Architecture::X86_64 | Architecture::Aarch64(_) => {}
+            _ => panic!("architectures other than x86-64 and aarch64 are unsupported"),
         }
 
         let keygen = m.is_present("keygen");
@@ -211,6 +220,26 @@ impl Options {
         let sk_path = m.value_of("sk_path").map(PathBuf::from);
         let pk_path = m.value_of("pk_path").map(PathBuf::from);
         let count_instructions = m.is_present("count_instructions");
+        let fuel_checks = m.is_present("fuel_checks");
+        let epoch_interruption = m.is_present("epoch_interruption");
+        let cache_dir = m.value_of("cache_dir").map(PathBuf::from);
+        let check_reproducible = m.is_present("check_reproducible");
+        let debug_info = m.is_present("debug_info");
+        let call_site_offsets = m.is_present("call_site_offsets");
+        let threads = match m.value_of("threads") {
+            None => None,
+            Some(threads) => match threads.parse::<usize>() {
+                Ok(threads) if threads > 0 => Some(threads),
+                _ => panic!("number of threads must be a positive integer"),
+            },
+        };
+
+        let linker = match m.value_of("linker") {
+            None => None,
+            Some("builtin") => Some(Linker::Builtin),
+            Some("external") => Some(Linker::External),
+            Some(_) => panic!("unknown value for linker"),
+        };
 
         let error_style = match m.value_of("error_style") {
             None => ErrorStyle::default(),
@@ -239,8 +268,16 @@ impl Options {
             sk_path,
             pk_path,
             count_instructions,
+            fuel_checks,
+            epoch_interruption,
             error_style,
             target,
+            cache_dir,
+            threads,
+            debug_info,
+            call_site_offsets,
+            check_reproducible,
+            linker,
         })
     }
     pub fn get() -> Result<Self, Error> {
@@ -452,6 +489,55 @@ SSE3 but not AVX:
                     .takes_value(false)
                     .help("Instrument the produced binary to count the number of wasm operations the translated program executes")
             )
+            .arg(
+                Arg::with_name("fuel_checks")
+                    .long("--fuel-checks")
+                    .takes_value(false)
+                    .help("Count wasm operations as --count-instructions does, and check the instance's fuel limit whenever the count is updated")
+            )
+            .arg(
+                Arg::with_name("epoch_interruption")
+                    .long("--epoch-interruption")
+                    .takes_value(false)
+                    .help("Instrument the produced binary to check the instance's epoch deadline at function entries and loop headers")
+            )
+            .arg(
+                Arg::with_name("cache_dir")
+                    .long("--cache-dir")
+                    .takes_value(true)
+                    .help("Directory in which to cache compiled artifacts, reusing them when the same input is compiled again with the same settings")
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .long("--threads")
+                    .takes_value(true)
+                    .help("Number of threads to translate and compile functions with (default: one per CPU). The output does not depend on it")
+            )
+            .arg(
+                Arg::with_name("debug_info")
+                    .long("--debug-info")
+                    .takes_value(false)
+                    .help("Translate the DWARF debug info of the input, so native debuggers can step through guest source. Only supported for x86-64 ELF targets")
+            )
+            .arg(
+                Arg::with_name("call_site_offsets")
+                    .long("--call-site-offsets")
+                    .takes_value(false)
+                    .help("Record the wasm offset of every call, so backtraces can locate each guest frame. Only supported for x86-64 targets")
+            )
+            .arg(
+                Arg::with_name("check_reproducible")
+                    .long("--check-reproducible")
+                    .takes_value(false)
+                    .help("Compile the input twice, without using the cache, and fail unless both compilations produce identical output")
+            )
+            .arg(
+                Arg::with_name("linker")
+                    .long("--linker")
+                    .takes_value(true)
+                    .possible_values(&["builtin", "external"])
+                    .help("How to link shared objects: with lucetc's own linker, or by running $LD with $LDFLAGS (default: builtin for x86-64 ELF targets, external otherwise)")
+            )
             .arg(
                 Arg::with_name("error_style")
                     .long("error-style")
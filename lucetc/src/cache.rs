@@ -0,0 +1,142 @@
+//! A content-addressed cache of compiled artifacts.
+//!
+//! Artifacts are stored in a directory under a key derived from the input WebAssembly, the
+//! bindings, every `CompilerBuilder` setting that affects code generation, and the version of
+//! `lucetc`, so an artifact is only reused for a compilation that would produce the same output.
+
+use crate::compiler::CompilerBuilder;
+use crate::link::Linker;
+use log::{debug, warn};
+use lucet_module::bindings::Bindings;
+use lucet_module::VersionInfo;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The kinds of artifact `Lucetc` can cache.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ArtifactKind {
+    Object,
+    SharedObject(Linker),
+}
+
+impl ArtifactKind {
+    fn extension(self) -> &'static str {
+        match self {
+            ArtifactKind::Object => "o",
+            ArtifactKind::SharedObject(_) => "so",
+        }
+    }
+}
+
+/// The key an artifact is stored under.
+pub(crate) struct CacheKey {
+    digest: String,
+    kind: ArtifactKind,
+}
+
+impl CacheKey {
+    pub(crate) fn new(
+        kind: ArtifactKind,
+        module_binary: &[u8],
+        bindings: &Bindings,
+        builder: &CompilerBuilder,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        let mut field = |bytes: &[u8]| {
+            // length-prefix each field so that adjacent fields cannot run into each other
+            hasher.input(&(bytes.len() as u64).to_le_bytes());
+            hasher.input(bytes);
+        };
+
+        let version =
+            VersionInfo::current(include_str!(concat!(env!("OUT_DIR"), "/commit_hash")).as_bytes());
+        field(version.to_string().as_bytes());
+        field(format!("{:?}", kind).as_bytes());
+        field(module_binary);
+
+        // `Bindings` are backed by `HashMap`s, so sort them for a stable key
+        let bindings = bindings
+            .hash_map()
+            .iter()
+            .map(|(module, symbols)| (module, symbols.iter().collect::<BTreeMap<_, _>>()))
+            .collect::<BTreeMap<_, _>>();
+        field(format!("{:?}", bindings).as_bytes());
+        field(builder.fingerprint().as_bytes());
+
+        if let ArtifactKind::SharedObject(Linker::External) = kind {
+            // the external linker and its flags are part of how a shared object is produced
+            for var in &["LD", "LDFLAGS"] {
+                field(env::var(var).unwrap_or_default().as_bytes());
+            }
+        }
+
+        let digest = hasher
+            .result()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        CacheKey { digest, kind }
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.{}", self.digest, self.kind.extension())
+    }
+}
+
+/// A directory of compiled artifacts.
+///
+/// Errors reading from or writing to the cache are logged rather than returned, since the cache
+/// can always be bypassed by compiling.
+#[derive(Clone, Debug)]
+pub(crate) struct ArtifactCache {
+    dir: PathBuf,
+}
+
+impl ArtifactCache {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        ArtifactCache { dir }
+    }
+
+    /// Copy the artifact stored under `key` to `output`, returning whether it was found.
+    pub(crate) fn get(&self, key: &CacheKey, output: &Path) -> bool {
+        let path = self.dir.join(key.file_name());
+        if !path.is_file() {
+            debug!("cache miss for {}", path.display());
+            return false;
+        }
+        match fs::copy(&path, output) {
+            Ok(_) => {
+                debug!("cache hit for {}", path.display());
+                true
+            }
+            Err(e) => {
+                warn!("could not read cached artifact {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+
+    /// Store a copy of the artifact at `artifact` under `key`.
+    pub(crate) fn insert(&self, key: &CacheKey, artifact: &Path) {
+        let path = self.dir.join(key.file_name());
+        if let Err(e) = self.try_insert(&path, artifact) {
+            warn!("could not cache artifact at {}: {}", path.display(), e);
+        }
+    }
+
+    fn try_insert(&self, path: &Path, artifact: &Path) -> Result<(), io::Error> {
+        fs::create_dir_all(&self.dir)?;
+        // write to a temporary file and rename it into place, so that concurrent compilations
+        // never observe a partially-written artifact
+        let mut tmp = tempfile::Builder::new()
+            .prefix(".lucetc-cache")
+            .tempfile_in(&self.dir)?;
+        io::copy(&mut File::open(artifact)?, tmp.as_file_mut())?;
+        tmp.persist(path)?;
+        Ok(())
+    }
+}
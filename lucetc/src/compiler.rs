@@ -1,16 +1,18 @@
 mod cpu_features;
 
 pub use self::cpu_features::{CpuFeatures, SpecificFeature, TargetCpu};
+use crate::debug::{self, FunctionMap};
 use crate::decls::ModuleDecls;
 use crate::error::Error;
 use crate::function::FuncInfo;
 use crate::heap::HeapSettings;
 use crate::module::ModuleInfo;
+use crate::name::Name;
 use crate::output::{CraneliftFuncs, ObjectFile, FUNCTION_MANIFEST_SYM};
 use crate::runtime::Runtime;
 use crate::stack_probe;
 use crate::table::write_table_data;
-use crate::traps::{translate_trapcode, trap_sym_for_func};
+use crate::traps::{call_sites_sym_for_func, translate_trapcode, trap_sym_for_func};
 use byteorder::{LittleEndian, WriteBytesExt};
 use cranelift_codegen::{
     binemit, ir,
@@ -20,18 +22,21 @@ use cranelift_codegen::{
 };
 use cranelift_module::{
     Backend as ClifBackend, DataContext as ClifDataContext, DataId, FuncId, FuncOrDataId,
-    Linkage as ClifLinkage, Module as ClifModule,
+    Linkage as ClifLinkage, Module as ClifModule, ModuleError as ClifModuleError,
 };
-use cranelift_object::{ObjectBackend, ObjectBuilder};
+use cranelift_object::{ObjectBackend, ObjectBuilder, ObjectProduct};
 use cranelift_wasm::{translate_module, FuncTranslator, ModuleTranslationState, WasmError};
 use lucet_module::bindings::Bindings;
 use lucet_module::{
     ModuleData, ModuleFeatures, SerializedModule, VersionInfo, LUCET_MODULE_SYM, MODULE_DATA_SYM,
 };
 use lucet_validate::Validator;
+use object::write::{Relocation, SymbolSection};
+use object::{elf, RelocationEncoding, RelocationKind};
+use rayon::prelude::*;
 use std::collections::HashMap;
 use std::io::Cursor;
-use target_lexicon::Triple;
+use target_lexicon::{Architecture, BinaryFormat, Triple};
 
 #[derive(Debug, Clone, Copy)]
 pub enum OptLevel {
@@ -62,8 +67,13 @@ pub struct CompilerBuilder {
     cpu_features: CpuFeatures,
     heap_settings: HeapSettings,
     count_instructions: bool,
+    fuel_checks: bool,
+    epoch_interruption: bool,
     canonicalize_nans: bool,
     validator: Option<Validator>,
+    threads: Option<usize>,
+    debug_info: bool,
+    call_site_offsets: bool,
 }
 
 impl CompilerBuilder {
@@ -74,8 +84,13 @@ impl CompilerBuilder {
             cpu_features: CpuFeatures::default(),
             heap_settings: HeapSettings::default(),
             count_instructions: false,
+            fuel_checks: false,
+            epoch_interruption: false,
             canonicalize_nans: false,
             validator: None,
+            threads: None,
+            debug_info: false,
+            call_site_offsets: false,
         }
     }
 
@@ -83,6 +98,28 @@ impl CompilerBuilder {
         &self.target
     }
 
+    pub(crate) fn validator_ref(&self) -> Option<&Validator> {
+        self.validator.as_ref()
+    }
+
+    /// Return a description of every setting that affects the generated code, for use in cache
+    /// keys.
+    pub(crate) fn fingerprint(&self) -> String {
+        format!(
+            "target={} opt_level={:?} cpu_features={} heap_settings={:?} count_instructions={} fuel_checks={} epoch_interruption={} canonicalize_nans={} debug_info={} call_site_offsets={}",
+            self.target,
+            self.opt_level,
+            self.cpu_features.fingerprint(),
+            self.heap_settings,
+            self.count_instructions,
+            self.fuel_checks,
+            self.epoch_interruption,
+            self.canonicalize_nans,
+            self.debug_info,
+            self.call_site_offsets,
+        )
+    }
+
     pub fn target(&mut self, target: Triple) {
         self.target = target;
     }
@@ -136,6 +173,27 @@ impl CompilerBuilder {
         self
     }
 
+    /// Check the instance's fuel limit whenever the instruction count is updated.
+    ///
+    /// Fuel is consumed by counted operations, so this implies `count_instructions`.
+    pub fn fuel_checks(&mut self, fuel_checks: bool) {
+        self.fuel_checks = fuel_checks;
+    }
+
+    pub fn with_fuel_checks(mut self, fuel_checks: bool) -> Self {
+        self.fuel_checks(fuel_checks);
+        self
+    }
+
+    pub fn epoch_interruption(&mut self, epoch_interruption: bool) {
+        self.epoch_interruption = epoch_interruption;
+    }
+
+    pub fn with_epoch_interruption(mut self, epoch_interruption: bool) -> Self {
+        self.epoch_interruption(epoch_interruption);
+        self
+    }
+
     pub fn canonicalize_nans(&mut self, canonicalize_nans: bool) {
         self.canonicalize_nans = canonicalize_nans;
     }
@@ -154,34 +212,116 @@ impl CompilerBuilder {
         self
     }
 
+    /// Set the number of threads functions are translated and compiled with.
+    ///
+    /// By default, as many threads are used as there are CPUs. The output does not depend on the
+    /// number of threads.
+    pub fn threads(&mut self, threads: usize) {
+        self.threads = Some(threads);
+    }
+
+    /// Set the number of threads functions are translated and compiled with.
+    ///
+    /// See [`threads`](#method.threads).
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads(threads);
+        self
+    }
+
+    /// Translate the DWARF sections of the module into native debug info.
+    ///
+    /// This is only supported for x86-64 ELF targets.
+    pub fn debug_info(&mut self, debug_info: bool) {
+        self.debug_info = debug_info;
+    }
+
+    /// Translate the DWARF sections of the module into native debug info.
+    ///
+    /// See [`debug_info`](#method.debug_info).
+    pub fn with_debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info(debug_info);
+        self
+    }
+
+    /// Record the WebAssembly offset of every call, so backtraces can locate each guest frame in
+    /// the module, and not only the faulting instruction.
+    ///
+    /// This is only supported for x86-64 targets.
+    pub fn call_site_offsets(&mut self, call_site_offsets: bool) {
+        self.call_site_offsets = call_site_offsets;
+    }
+
+    /// Record the WebAssembly offset of every call.
+    ///
+    /// See [`call_site_offsets`](#method.call_site_offsets).
+    pub fn with_call_site_offsets(mut self, call_site_offsets: bool) -> Self {
+        self.call_site_offsets(call_site_offsets);
+        self
+    }
+
     pub fn create<'a>(
         &'a self,
         wasm_binary: &'a [u8],
         bindings: &'a Bindings,
     ) -> Result<Compiler<'a>, Error> {
-        Compiler::new(
+        if self.debug_info
+            && (self.target.architecture != Architecture::X86_64
+                || self.target.binary_format != BinaryFormat::Elf)
+        {
+            return Err(Error::Unsupported(format!(
+                "debug info is only supported for x86-64 ELF targets, not {}",
+                self.target
+            )));
+        }
+        if self.call_site_offsets && self.target.architecture != Architecture::X86_64 {
+            return Err(Error::Unsupported(format!(
+                "call site offsets are only supported for x86-64 targets, not {}",
+                self.target
+            )));
+        }
+        let mut compiler = Compiler::new(
             wasm_binary,
             self.target.clone(),
             self.opt_level,
             self.cpu_features.clone(),
             bindings,
             self.heap_settings.clone(),
-            self.count_instructions,
+            self.count_instructions || self.fuel_checks,
+            self.epoch_interruption,
             &self.validator,
             self.canonicalize_nans,
-        )
+        )?;
+        compiler.fuel_checks = self.fuel_checks;
+        if let Some(threads) = self.threads {
+            compiler.thread_pool = Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()?,
+            );
+        }
+        compiler.debug_info = self.debug_info;
+        compiler.call_site_offsets = self.call_site_offsets;
+        Ok(compiler)
     }
 }
 
 pub struct Compiler<'a> {
+    wasm_binary: &'a [u8],
     decls: ModuleDecls<'a>,
     clif_module: ClifModule<ObjectBackend>,
     target: Triple,
     opt_level: OptLevel,
     cpu_features: CpuFeatures,
     count_instructions: bool,
+    fuel_checks: bool,
+    epoch_interruption: bool,
     module_translation_state: ModuleTranslationState,
     canonicalize_nans: bool,
+    simd: bool,
+    /// The pool functions are translated and compiled on, if a number of threads was set.
+    thread_pool: Option<rayon::ThreadPool>,
+    debug_info: bool,
+    call_site_offsets: bool,
 }
 
 impl<'a> Compiler<'a> {
@@ -193,10 +333,17 @@ impl<'a> Compiler<'a> {
         bindings: &'a Bindings,
         heap_settings: HeapSettings,
         count_instructions: bool,
+        epoch_interruption: bool,
         validator: &Option<Validator>,
         canonicalize_nans: bool,
     ) -> Result<Self, Error> {
-        let isa = Self::target_isa(target.clone(), opt_level, &cpu_features, canonicalize_nans)?;
+        let isa = Self::target_isa(
+            target.clone(),
+            opt_level,
+            &cpu_features,
+            canonicalize_nans,
+            false,
+        )?;
 
         let frontend_config = isa.frontend_config();
         let mut module_info = ModuleInfo::new(frontend_config.clone());
@@ -207,7 +354,8 @@ impl<'a> Compiler<'a> {
             // As of cranelift-wasm 0.43 which uses wasmparser 0.39.1, the parser used inside
             // cranelift-wasm does not validate. We need to run the validating parser on the binary
             // first. The InvalidWebAssembly error below will never trigger.
-            wasmparser::validate(wasm_binary, None).map_err(Error::WasmValidation)?;
+            wasmparser::validate(wasm_binary, Some(validation_config()))
+                .map_err(Error::WasmValidation)?;
         }
 
         let module_translation_state =
@@ -223,12 +371,34 @@ impl<'a> Compiler<'a> {
                 WasmError::ImplLimitExceeded { .. } => Error::ClifWasmError(e),
             })?;
 
-        let libcalls = Box::new(move |libcall| match libcall {
-            ir::LibCall::Probestack => stack_probe::STACK_PROBE_SYM.to_owned(),
-            _ => (cranelift_module::default_libcall_names())(libcall),
-        });
+        // SIMD codegen is only enabled for modules that need it, and only if the target CPU
+        // features support it.
+        let simd = module_info.uses_simd;
+        let isa = if simd {
+            if target.architecture != Architecture::X86_64 {
+                return Err(Error::Unsupported(format!(
+                    "module uses SIMD, which is only supported for x86-64 targets, not {}",
+                    target
+                )));
+            }
+            if !cpu_features.supports_simd() {
+                return Err(Error::Unsupported(
+                    "module uses SIMD, which requires the SSSE3 and SSE4.1 CPU features"
+                        .to_string(),
+                ));
+            }
+            Self::target_isa(
+                target.clone(),
+                opt_level,
+                &cpu_features,
+                canonicalize_nans,
+                true,
+            )?
+        } else {
+            isa
+        };
 
-        let mut builder = ObjectBuilder::new(isa, "lucet_guest".to_owned(), libcalls);
+        let mut builder = ObjectBuilder::new(isa, "lucet_guest".to_owned(), Box::new(libcall_name));
         builder.function_alignment(16);
         let mut clif_module: ClifModule<ObjectBackend> = ClifModule::new(builder);
 
@@ -242,14 +412,21 @@ impl<'a> Compiler<'a> {
         )?;
 
         Ok(Self {
+            wasm_binary,
             decls,
             clif_module,
             opt_level,
             cpu_features,
             count_instructions,
+            fuel_checks: false,
+            epoch_interruption,
             module_translation_state,
             target,
             canonicalize_nans,
+            simd,
+            thread_pool: None,
+            debug_info: false,
+            call_site_offsets: false,
         })
     }
 
@@ -258,8 +435,11 @@ impl<'a> Compiler<'a> {
     }
 
     pub fn module_features(&self) -> ModuleFeatures {
-        let mut mf: ModuleFeatures = (&self.cpu_features).into();
+        let mut mf = self.cpu_features.module_features(&self.target);
         mf.instruction_count = self.count_instructions;
+        mf.fuel_checks = self.fuel_checks;
+        mf.epoch_interruption = self.epoch_interruption;
+        mf.simd = self.simd;
         mf
     }
 
@@ -267,45 +447,194 @@ impl<'a> Compiler<'a> {
         self.decls.get_module_data(self.module_features())
     }
 
-    pub fn object_file(mut self) -> Result<ObjectFile, Error> {
-        let mut func_translator = FuncTranslator::new();
-        let mut function_manifest_ctx = ClifDataContext::new();
-        let mut function_manifest_bytes = Cursor::new(Vec::new());
-        let mut function_map: HashMap<FuncId, (u32, DataId, usize)> = HashMap::new();
-
-        for (ref func, (code, code_offset)) in self.decls.function_bodies() {
-            let mut func_info = FuncInfo::new(&self.decls, self.count_instructions);
-            let mut clif_context = ClifContext::new();
-            clif_context.func.name = func.name.as_externalname();
-            clif_context.func.signature = func.signature.clone();
-
-            func_translator
-                .translate(
-                    &self.module_translation_state,
-                    code,
-                    *code_offset,
-                    &mut clif_context.func,
-                    &mut func_info,
+    /// Translate the body of every function defined by the module to Cranelift IR.
+    ///
+    /// Functions are translated in parallel, but returned in the order of
+    /// `ModuleDecls::function_bodies()`, so the results do not depend on the number of threads.
+    fn translate_functions(&self) -> Result<Vec<(Name, ir::Function)>, Error> {
+        // bind the fields the workers share, rather than sharing all of `self`
+        let decls = &self.decls;
+        let module_translation_state = &self.module_translation_state;
+        let count_instructions = self.count_instructions;
+        let fuel_checks = self.fuel_checks;
+        let epoch_interruption = self.epoch_interruption;
+        let debug_info = self.debug_info;
+
+        let bodies = decls.function_bodies().collect::<Vec<_>>();
+        let translate = || {
+            bodies
+                .par_iter()
+                .map_init(
+                    FuncTranslator::new,
+                    |func_translator, (func, (code, code_offset))| {
+                        let mut func_info = FuncInfo::new(
+                            decls,
+                            count_instructions,
+                            fuel_checks,
+                            epoch_interruption,
+                            debug_info,
+                        );
+                        let mut clif_func = ir::Function::with_name_signature(
+                            func.name.as_externalname(),
+                            func.signature.clone(),
+                        );
+                        func_translator.translate(
+                            module_translation_state,
+                            code,
+                            *code_offset,
+                            &mut clif_func,
+                            &mut func_info,
+                        )?;
+                        Ok(clif_func)
+                    },
                 )
-                .map_err(|source| Error::FunctionTranslation {
+                .collect::<Vec<Result<ir::Function, WasmError>>>()
+        };
+        let translated = self.in_thread_pool(translate);
+
+        // collecting sequentially reports the error for the first function that failed, no
+        // matter which thread finished first
+        bodies
+            .iter()
+            .zip(translated)
+            .map(|((func, _), clif_func)| {
+                let clif_func = clif_func.map_err(|source| Error::FunctionTranslation {
                     symbol: func.name.symbol().to_string(),
                     source,
                 })?;
-            let func_id = func.name.as_funcid().unwrap();
-            let mut traps = TrapSites::new();
+                Ok((func.name.clone(), clif_func))
+            })
+            .collect()
+    }
+
+    /// Compile every function defined by the module to machine code.
+    ///
+    /// Like translation, compilation runs in parallel, and the results are returned in the order of
+    /// `ModuleDecls::function_bodies()`. Relocations are recorded rather than resolved, as that
+    /// needs the object module, which only one thread can use at a time.
+    fn compile_functions(&self) -> Result<Vec<CompiledFunction>, Error> {
+        let isa = self.clif_module.isa();
+
+        let funcs = self.translate_functions()?;
+        let compile = || {
+            funcs
+                .into_par_iter()
+                .map(|(name, clif_func)| {
+                    let mut clif_context = ClifContext::for_function(clif_func);
+                    let mut code = vec![];
+                    let mut relocs = RelocSites::new();
+                    let mut traps = TrapSites::new();
+                    let mut stackmaps = binemit::NullStackmapSink {};
+                    let compiled = clif_context.compile_and_emit(
+                        isa,
+                        &mut code,
+                        &mut relocs,
+                        &mut traps,
+                        &mut stackmaps,
+                    );
+                    if let Some(reloc) = relocs.unsupported.take() {
+                        return Err(Error::Unsupported(format!(
+                            "{} in {}",
+                            reloc,
+                            name.symbol()
+                        )));
+                    }
+                    match compiled {
+                        Ok(_) => Ok(CompiledFunction {
+                            name,
+                            clif_func: clif_context.func,
+                            code,
+                            relocs,
+                            traps,
+                        }),
+                        Err(e) => Err(Error::FunctionDefinition {
+                            symbol: name.symbol().to_string(),
+                            source: ClifModuleError::Compilation(e),
+                        }),
+                    }
+                })
+                .collect::<Vec<Result<CompiledFunction, Error>>>()
+        };
+
+        // as with translation, report the error for the first function that failed
+        self.in_thread_pool(compile).into_iter().collect()
+    }
+
+    /// Run `op` on the thread pool the compiler was configured with, or on the global pool if no
+    /// thread count was set.
+    fn in_thread_pool<T: Send>(&self, op: impl FnOnce() -> T + Send) -> T {
+        match self.thread_pool {
+            Some(ref pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    pub fn object_file(mut self) -> Result<ObjectFile, Error> {
+        let mut function_manifest_ctx = ClifDataContext::new();
+        let mut function_manifest_bytes = Cursor::new(Vec::new());
+        let mut function_map: HashMap<FuncId, (u32, DataId, usize)> = HashMap::new();
+        let mut call_site_map: HashMap<FuncId, (DataId, usize)> = HashMap::new();
+        let mut debug_maps = vec![];
+
+        let body_ends = self
+            .decls
+            .function_bodies()
+            .map(|(_, (code, code_offset))| (code_offset + code.len()) as u32)
+            .collect::<Vec<_>>();
+
+        // the relocations of each function, applied once the object has been laid out
+        let mut func_relocs = vec![];
+        // the library functions called by the module, with their signatures, in order of first use
+        let mut libcalls: Vec<(ir::LibCall, ir::Signature)> = vec![];
+
+        // Functions are compiled in parallel, but defined in the module in index order, which needs
+        // exclusive access to the module and keeps their layout deterministic.
+        for (func, body_end) in self.compile_functions()?.into_iter().zip(body_ends) {
+            let name = func.name;
+            let func_id = name.as_funcid().unwrap();
             let compiled = self
                 .clif_module
-                .define_function(func_id, &mut clif_context, &mut traps)
+                .define_function_bytes(func_id, &func.code)
                 .map_err(|source| Error::FunctionDefinition {
-                    symbol: func.name.symbol().to_string(),
+                    symbol: name.symbol().to_string(),
                     source,
                 })?;
 
             let size = compiled.size;
 
-            let trap_data_id = traps.write(&mut self.clif_module, func.name.symbol())?;
+            if self.debug_info {
+                debug_maps.push(FunctionMap::new(
+                    func_id,
+                    body_end,
+                    &func.clif_func,
+                    self.clif_module.isa(),
+                    size,
+                ));
+            }
+
+            if self.call_site_offsets {
+                let call_sites = call_sites(&func.clif_func, self.clif_module.isa());
+                if !call_sites.is_empty() {
+                    let call_sites_data_id =
+                        write_call_sites(&mut self.clif_module, name.symbol(), &call_sites)?;
+                    call_site_map.insert(func_id, (call_sites_data_id, call_sites.len()));
+                }
+            }
+
+            let trap_data_id = func.traps.write(&mut self.clif_module, name.symbol())?;
+
+            function_map.insert(func_id, (size, trap_data_id, func.traps.len()));
+
+            for ext_func in func.clif_func.dfg.ext_funcs.values() {
+                if let ir::ExternalName::LibCall(libcall) = ext_func.name {
+                    if libcalls.iter().all(|(used, _)| *used != libcall) {
+                        let signature = func.clif_func.dfg.signatures[ext_func.signature].clone();
+                        libcalls.push((libcall, signature));
+                    }
+                }
+            }
 
-            function_map.insert(func_id, (size, trap_data_id, traps.len()));
+            func_relocs.push((func_id, name, func.relocs));
         }
 
         // Write out the stack probe and associated data.
@@ -314,10 +643,10 @@ impl<'a> Compiler<'a> {
         let probe_func_id = probe_func.name.as_funcid().unwrap();
         let compiled = self
             .clif_module
-            .define_function_bytes(probe_func_id, stack_probe::STACK_PROBE_BINARY)?;
+            .define_function_bytes(probe_func_id, stack_probe::binary(&self.target))?;
 
         let size = compiled.size;
-        let stack_probe_traps: TrapSites = stack_probe::trap_sites().into();
+        let stack_probe_traps: TrapSites = stack_probe::trap_sites(&self.target).into();
 
         let trap_data_id =
             stack_probe_traps.write(&mut self.clif_module, probe_func.name.symbol())?;
@@ -365,6 +694,7 @@ impl<'a> Compiler<'a> {
                 size,
                 trap_data_id,
                 traps_len,
+                call_site_map.get(&func_id).cloned(),
             )?;
         }
 
@@ -437,36 +767,29 @@ impl<'a> Compiler<'a> {
         self.clif_module
             .define_data(native_data_id, &native_data_ctx)?;
 
-        let obj = ObjectFile::new(self.clif_module.finish())?;
+        // Library functions are declared last, so that they are left out of the function manifest
+        // like they would be had Cranelift referred to them itself.
+        let libcall_ids = declare_libcalls(&mut self.clif_module, libcalls)?;
+
+        let mut product = self.clif_module.finish();
+        for (func_id, name, relocs) in func_relocs {
+            relocs.write(&mut product, func_id, name.symbol(), &libcall_ids)?;
+        }
+        if self.debug_info {
+            debug::write_debug_sections(
+                &mut product.object,
+                &product.functions,
+                self.wasm_binary,
+                &debug_maps,
+            )?;
+        }
+        let obj = ObjectFile::new(product)?;
 
         Ok(obj)
     }
 
     pub fn cranelift_funcs(self) -> Result<CraneliftFuncs, Error> {
-        let mut funcs = HashMap::new();
-        let mut func_translator = FuncTranslator::new();
-
-        for (ref func, (code, code_offset)) in self.decls.function_bodies() {
-            let mut func_info = FuncInfo::new(&self.decls, self.count_instructions);
-            let mut clif_context = ClifContext::new();
-            clif_context.func.name = func.name.as_externalname();
-            clif_context.func.signature = func.signature.clone();
-
-            func_translator
-                .translate(
-                    &self.module_translation_state,
-                    code,
-                    *code_offset,
-                    &mut clif_context.func,
-                    &mut func_info,
-                )
-                .map_err(|source| Error::FunctionTranslation {
-                    symbol: func.name.symbol().to_string(),
-                    source,
-                })?;
-
-            funcs.insert(func.name.clone(), clif_context.func);
-        }
+        let funcs = self.translate_functions()?;
         Ok(CraneliftFuncs::new(
             funcs,
             Self::target_isa(
@@ -474,6 +797,7 @@ impl<'a> Compiler<'a> {
                 self.opt_level,
                 &self.cpu_features,
                 self.canonicalize_nans,
+                self.simd,
             )?,
         ))
     }
@@ -483,6 +807,7 @@ impl<'a> Compiler<'a> {
         opt_level: OptLevel,
         cpu_features: &CpuFeatures,
         canonicalize_nans: bool,
+        simd: bool,
     ) -> Result<Box<dyn TargetIsa>, Error> {
         let mut flags_builder = settings::builder();
         let isa_builder = cpu_features.isa_builder(target)?;
@@ -492,10 +817,40 @@ impl<'a> Compiler<'a> {
         if canonicalize_nans {
             flags_builder.enable("enable_nan_canonicalization").unwrap();
         }
+        if simd {
+            flags_builder.enable("enable_simd").unwrap();
+        }
         Ok(isa_builder.finish(settings::Flags::new(flags_builder)))
     }
 }
 
+/// The symbol name of a library function called by compiled code.
+fn libcall_name(libcall: ir::LibCall) -> String {
+    match libcall {
+        ir::LibCall::Probestack => stack_probe::STACK_PROBE_SYM.to_owned(),
+        _ => (cranelift_module::default_libcall_names())(libcall),
+    }
+}
+
+/// Declare the library functions called by compiled code as imports, unless the module already
+/// declares them, as it does the stack probe.
+fn declare_libcalls(
+    clif_module: &mut ClifModule<ObjectBackend>,
+    libcalls: Vec<(ir::LibCall, ir::Signature)>,
+) -> Result<HashMap<ir::LibCall, FuncId>, Error> {
+    libcalls
+        .into_iter()
+        .map(|(libcall, signature)| {
+            let name = libcall_name(libcall);
+            let func_id = match clif_module.get_name(&name) {
+                Some(FuncOrDataId::Func(func_id)) => func_id,
+                _ => clif_module.declare_function(&name, ClifLinkage::Import, &signature)?,
+            };
+            Ok((libcall, func_id))
+        })
+        .collect()
+}
+
 fn write_module_data<B: ClifBackend>(
     clif_module: &mut ClifModule<B>,
     module_data_bytes: Vec<u8>,
@@ -515,6 +870,193 @@ fn write_module_data<B: ClifBackend>(
     Ok(module_data_decl)
 }
 
+/// A function compiled to machine code, which has yet to be defined in the object module.
+struct CompiledFunction {
+    name: Name,
+    /// The compiled function, whose instruction offsets describe `code`.
+    clif_func: ir::Function,
+    code: Vec<u8>,
+    relocs: RelocSites,
+    traps: TrapSites,
+}
+
+/// A relocation in the code of a function, against a symbol of the object module.
+struct RelocSite {
+    offset: binemit::CodeOffset,
+    reloc: binemit::Reloc,
+    name: ir::ExternalName,
+    addend: binemit::Addend,
+}
+
+/// Collect relocations from cranelift codegen, to be written once the object is laid out:
+struct RelocSites {
+    relocs: Vec<RelocSite>,
+    /// The first relocation that could not be recorded, as the sink cannot return an error.
+    unsupported: Option<String>,
+}
+
+impl RelocSites {
+    /// Empty
+    fn new() -> Self {
+        Self {
+            relocs: Vec::new(),
+            unsupported: None,
+        }
+    }
+
+    /// Record that a relocation is not supported, unless an earlier one was not either.
+    fn unsupported(&mut self, what: String) {
+        if self.unsupported.is_none() {
+            self.unsupported = Some(what);
+        }
+    }
+
+    /// Write the relocations of the function `func_id` into the object:
+    fn write(
+        &self,
+        product: &mut ObjectProduct,
+        func_id: FuncId,
+        func_name: &str,
+        libcalls: &HashMap<ir::LibCall, FuncId>,
+    ) -> Result<(), Error> {
+        let func_symbol = product.functions[func_id]
+            .ok_or_else(|| Error::Output(format!("function {} is not defined", func_name)))?;
+        let (section, func_offset) = {
+            let symbol = product.object.symbol(func_symbol);
+            match symbol.section {
+                SymbolSection::Section(section) => (section, symbol.value),
+                _ => {
+                    return Err(Error::Output(format!(
+                        "function {} is not defined in a section",
+                        func_name
+                    )))
+                }
+            }
+        };
+        for site in self.relocs.iter() {
+            let symbol = match site.name {
+                ir::ExternalName::User {
+                    namespace: 0,
+                    index,
+                } => product.functions[FuncId::from_u32(index)],
+                ir::ExternalName::User {
+                    namespace: 1,
+                    index,
+                } => product.data_objects[DataId::from_u32(index)],
+                ir::ExternalName::LibCall(libcall) => libcalls
+                    .get(&libcall)
+                    .and_then(|func_id| product.functions[*func_id]),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                Error::Output(format!(
+                    "{} refers to undeclared name {}",
+                    func_name, site.name
+                ))
+            })?;
+            let (kind, encoding, size) = match site.reloc {
+                binemit::Reloc::Abs4 => (RelocationKind::Absolute, RelocationEncoding::Generic, 32),
+                binemit::Reloc::Abs8 => (RelocationKind::Absolute, RelocationEncoding::Generic, 64),
+                binemit::Reloc::X86PCRel4 => {
+                    (RelocationKind::Relative, RelocationEncoding::Generic, 32)
+                }
+                binemit::Reloc::X86CallPCRel4 => {
+                    (RelocationKind::Relative, RelocationEncoding::X86Branch, 32)
+                }
+                binemit::Reloc::X86CallPLTRel4 => (
+                    RelocationKind::PltRelative,
+                    RelocationEncoding::X86Branch,
+                    32,
+                ),
+                binemit::Reloc::X86GOTPCRel4 => {
+                    (RelocationKind::GotRelative, RelocationEncoding::Generic, 32)
+                }
+                binemit::Reloc::Arm64Call => (
+                    RelocationKind::Elf(elf::R_AARCH64_CALL26),
+                    RelocationEncoding::Generic,
+                    32,
+                ),
+                reloc => {
+                    return Err(Error::Unsupported(format!(
+                        "relocation {} in {}",
+                        reloc, func_name
+                    )))
+                }
+            };
+            product
+                .object
+                .add_relocation(
+                    section,
+                    Relocation {
+                        offset: func_offset + u64::from(site.offset),
+                        size,
+                        kind,
+                        encoding,
+                        symbol,
+                        addend: site.addend,
+                    },
+                )
+                .map_err(|source| {
+                    Error::ObjectArtifact(source, format!("Relocating {}", func_name))
+                })?;
+        }
+        Ok(())
+    }
+}
+
+impl binemit::RelocSink for RelocSites {
+    fn reloc_block(
+        &mut self,
+        _offset: binemit::CodeOffset,
+        reloc: binemit::Reloc,
+        _block_offset: binemit::CodeOffset,
+    ) {
+        self.unsupported(format!("block relocation {}", reloc));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: binemit::CodeOffset,
+        _srcloc: ir::SourceLoc,
+        reloc: binemit::Reloc,
+        name: &ir::ExternalName,
+        addend: binemit::Addend,
+    ) {
+        self.relocs.push(RelocSite {
+            offset,
+            reloc,
+            name: name.clone(),
+            addend,
+        });
+    }
+
+    fn reloc_constant(
+        &mut self,
+        _offset: binemit::CodeOffset,
+        reloc: binemit::Reloc,
+        _constant_offset: ir::ConstantOffset,
+    ) {
+        // constants are emitted with the function, and referred to relative to it
+        match reloc {
+            binemit::Reloc::X86PCRelRodata4 => (),
+            _ => self.unsupported(format!("constant relocation {}", reloc)),
+        }
+    }
+
+    fn reloc_jt(
+        &mut self,
+        _offset: binemit::CodeOffset,
+        reloc: binemit::Reloc,
+        _jt: ir::JumpTable,
+    ) {
+        // jump tables are emitted with the function, and referred to relative to it
+        match reloc {
+            binemit::Reloc::X86PCRelRodata4 => (),
+            _ => self.unsupported(format!("jump table relocation {}", reloc)),
+        }
+    }
+}
+
 /// Collect traps from cranelift_module codegen:
 struct TrapSites {
     traps: Vec<cranelift_module::TrapSite>,
@@ -540,6 +1082,11 @@ impl TrapSites {
             .map(|site| lucet_module::TrapSite {
                 offset: site.offset,
                 code: translate_trapcode(site.code),
+                wasm_offset: if site.srcloc.is_default() {
+                    0
+                } else {
+                    site.srcloc.bits()
+                },
             })
             .collect();
 
@@ -589,6 +1136,55 @@ impl cranelift_codegen::binemit::TrapSink for TrapSites {
     }
 }
 
+/// Find the return address of each call in a compiled function, with the offset in the module of
+/// the WebAssembly instruction that made the call.
+fn call_sites(func: &ir::Function, isa: &dyn TargetIsa) -> Vec<lucet_module::CallSite> {
+    let encinfo = isa.encoding_info();
+    let mut blocks = func.layout.blocks().collect::<Vec<_>>();
+    blocks.sort_by_key(|block| func.offsets[*block]);
+    let mut call_sites = vec![];
+    for block in blocks {
+        for (offset, inst, size) in func.inst_offsets(block, &encinfo) {
+            let srcloc = func.srclocs[inst];
+            // calls without a source location, like the stack probe, are not guest calls
+            if func.dfg[inst].opcode().is_call() && !srcloc.is_default() {
+                call_sites.push(lucet_module::CallSite {
+                    ret_offset: offset + size,
+                    wasm_offset: srcloc.bits(),
+                });
+            }
+        }
+    }
+    call_sites
+}
+
+/// Write the call sites of a function into the cranelift module.
+fn write_call_sites(
+    module: &mut ClifModule<ObjectBackend>,
+    func_name: &str,
+    call_sites: &[lucet_module::CallSite],
+) -> Result<DataId, Error> {
+    let call_site_bytes = unsafe {
+        std::slice::from_raw_parts(
+            call_sites.as_ptr() as *const u8,
+            call_sites.len() * std::mem::size_of::<lucet_module::CallSite>(),
+        )
+    };
+    let mut call_sites_ctx = ClifDataContext::new();
+    call_sites_ctx.define(call_site_bytes.to_vec().into());
+
+    let call_sites_data_id = module.declare_data(
+        &call_sites_sym_for_func(func_name),
+        ClifLinkage::Local,
+        false,
+        false,
+        None,
+    )?;
+    module.define_data(call_sites_data_id, &call_sites_ctx)?;
+
+    Ok(call_sites_data_id)
+}
+
 fn write_function_spec(
     module: &mut ClifModule<ObjectBackend>,
     mut manifest_ctx: &mut ClifDataContext,
@@ -597,6 +1193,7 @@ fn write_function_spec(
     size: binemit::CodeOffset,
     trap_data_id: Option<DataId>,
     n_traps: usize,
+    call_sites: Option<(DataId, usize)>,
 ) -> Result<(), Error> {
     // This code has implicit knowledge of the layout of `FunctionSpec`!
     //
@@ -616,6 +1213,33 @@ fn write_function_spec(
     }
     manifest_bytes.write_u64::<LittleEndian>(0 as u64)?;
     manifest_bytes.write_u64::<LittleEndian>(n_traps as u64)?;
+    // Write a (ptr, len) pair with relocation for the call sites, if they were recorded.
+    let n_call_sites = match call_sites {
+        Some((call_sites_data_id, n_call_sites)) => {
+            let data_ref = module.declare_data_in_data(call_sites_data_id, &mut manifest_ctx);
+            let offset = manifest_bytes.position() as u32;
+            manifest_ctx.write_data_addr(offset, data_ref, 0);
+            n_call_sites
+        }
+        None => 0,
+    };
+    manifest_bytes.write_u64::<LittleEndian>(0 as u64)?;
+    manifest_bytes.write_u64::<LittleEndian>(n_call_sites as u64)?;
 
     Ok(())
 }
+
+/// The configuration of the validating parser, which determines the WebAssembly proposals that
+/// `lucetc` accepts.
+fn validation_config() -> wasmparser::ValidatingParserConfig {
+    wasmparser::ValidatingParserConfig {
+        operator_config: wasmparser::OperatorValidatorConfig {
+            enable_threads: false,
+            enable_reference_types: true,
+            enable_simd: true,
+            enable_bulk_memory: true,
+            enable_multi_value: true,
+            enable_tail_call: false,
+        },
+    }
+}
@@ -2,8 +2,9 @@ use crate::error::Error;
 use cranelift_codegen::{isa, settings::Configurable};
 use lucet_module::ModuleFeatures;
 use std::collections::{HashMap, HashSet};
-use target_lexicon::Triple;
+use target_lexicon::{Architecture, Triple};
 
+#[cfg(target_arch = "x86_64")]
 use raw_cpuid::CpuId;
 
 /// x86 CPU families used as shorthand for different CPU feature configurations.
@@ -68,6 +69,7 @@ pub struct CpuFeatures {
     specific_features: HashMap<SpecificFeature, bool>,
 }
 
+#[cfg(target_arch = "x86_64")]
 fn detect_features(features: &mut ModuleFeatures) {
     let cpuid = CpuId::new();
 
@@ -90,6 +92,10 @@ fn detect_features(features: &mut ModuleFeatures) {
     }
 }
 
+/// There are no x86 features to detect on other hosts.
+#[cfg(not(target_arch = "x86_64"))]
+fn detect_features(_features: &mut ModuleFeatures) {}
+
 impl From<&CpuFeatures> for ModuleFeatures {
     fn from(cpu_features: &CpuFeatures) -> ModuleFeatures {
         let mut module_features = ModuleFeatures::none();
@@ -182,12 +188,61 @@ impl CpuFeatures {
         self.specific_features.insert(sf, enabled);
     }
 
+    /// Return whether these features allow compiling WebAssembly SIMD instructions, which
+    /// Cranelift lowers using SSSE3 and SSE4.1.
+    pub fn supports_simd(&self) -> bool {
+        let features = ModuleFeatures::from(self);
+        features.ssse3 && features.sse41
+    }
+
+    /// Return the `ModuleFeatures` these CPU features produce for the given target.
+    ///
+    /// The features only describe x86 CPUs, so other targets get none of them.
+    pub(crate) fn module_features(&self, target: &Triple) -> ModuleFeatures {
+        if target.architecture == Architecture::X86_64 {
+            self.into()
+        } else {
+            ModuleFeatures::none()
+        }
+    }
+
+    /// Return a description of these features that does not depend on `HashMap` iteration order,
+    /// for use in cache keys.
+    ///
+    /// For `TargetCpu::Native`, this includes the features detected on the host.
+    pub(crate) fn fingerprint(&self) -> String {
+        let mut specific_features = self
+            .specific_features
+            .iter()
+            .map(|(feature, enabled)| format!("{}{:?}", if *enabled { '+' } else { '-' }, feature))
+            .collect::<Vec<_>>();
+        specific_features.sort();
+        format!(
+            "{:?} [{}] {:?}",
+            self.cpu,
+            specific_features.join(","),
+            ModuleFeatures::from(self)
+        )
+    }
+
     /// Return a `cranelift_codegen::isa::Builder` configured with these CPU features.
 
     pub fn isa_builder(&self, target: Triple) -> Result<isa::Builder, Error> {
         use SpecificFeature::*;
         use TargetCpu::*;
 
+        if target.architecture != Architecture::X86_64 {
+            // The CPU profiles and specific features are all x86 features, so the only
+            // configuration that makes sense for other targets is the default one.
+            if !self.cpu.features().is_empty() || !self.specific_features.is_empty() {
+                return Err(Error::Unsupported(format!(
+                    "CPU features are only supported for x86-64 targets, not {}",
+                    target
+                )));
+            }
+            return isa::lookup(target).map_err(Error::UnsupportedIsa);
+        }
+
         let mut isa_builder = if let Native = self.cpu {
             cranelift_native::builder().map_err(|_| {
                 Error::Unsupported("host machine is not a supported target".to_string())
@@ -0,0 +1,778 @@
+//! Native debug information, translated from the DWARF sections of a WebAssembly module.
+//!
+//! Toolchains that target WebAssembly put DWARF in custom sections, with addresses that are offsets
+//! into the code section. Cranelift tags each instruction it generates with the offset in the
+//! module of the operator it came from, which lets us map those addresses to native code: address
+//! ranges become ranges of the function symbols, and line tables are regenerated from the native
+//! instructions.
+//!
+//! Variables at fixed addresses in linear memory are located relative to the heap base, which is
+//! also the `vmctx` argument of every guest function. When debug info is enabled, each function
+//! spills `vmctx` to its first stack slot, and the frame base of each subprogram reads it back from
+//! there. Other locations refer to wasm locals or the operand stack, which Cranelift does not track,
+//! so they are dropped.
+
+use crate::error::Error;
+use cranelift_codegen::entity::{EntityRef, SecondaryMap};
+use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_frontend::FunctionBuilder;
+use cranelift_module::FuncId;
+use gimli::constants;
+use gimli::read::{self, EndianSlice};
+use gimli::write::{self, Address, EndianVec, Writer};
+use gimli::{Encoding, Format, LineEncoding, LittleEndian, SectionId, UnitSectionOffset};
+use object::write::{Object, Relocation, SymbolId};
+use object::{RelocationEncoding, RelocationKind, SectionKind};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+use wasmparser::{ModuleReader, SectionCode};
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// The encoding of the generated DWARF, which only supports x86-64 targets.
+const ENCODING: Encoding = Encoding {
+    format: Format::Dwarf32,
+    version: 4,
+    address_size: 8,
+};
+
+/// The distance from the frame pointer to the stack pointer on entry to a function, which stack
+/// slot offsets are relative to: the return address and the caller's frame pointer are in between.
+const FRAME_POINTER_TO_SLOTS: i64 = 16;
+
+/// Spill `vmctx` at the start of a function, where the function's frame base can find it.
+///
+/// This must create the first stack slot of the function.
+pub(crate) fn spill_vmctx(builder: &mut FunctionBuilder<'_>) {
+    let vmctx = builder
+        .func
+        .special_param(ir::ArgumentPurpose::VMContext)
+        .expect("guest functions have a vmctx parameter");
+    let slot =
+        builder.create_stack_slot(ir::StackSlotData::new(ir::StackSlotKind::ExplicitSlot, 8));
+    debug_assert_eq!(slot.index(), 0);
+    builder.ins().stack_store(vmctx, slot, 0);
+}
+
+/// How the native code of a compiled function maps back to the module.
+pub(crate) struct FunctionMap {
+    func_id: FuncId,
+    /// The offset in the module of the end of the function's body.
+    body_end: u32,
+    /// The size of the native code.
+    len: u32,
+    /// The offset of each native instruction with a source location, in order, and the offset in
+    /// the module of the operator it came from.
+    instructions: Vec<(u32, u32)>,
+    /// Where `vmctx` is spilled, relative to the frame pointer.
+    vmctx_offset: i64,
+}
+
+impl FunctionMap {
+    /// Describe `func`, which has been compiled to `len` bytes of native code, and whose body ends
+    /// at `body_end` in the module.
+    pub(crate) fn new(
+        func_id: FuncId,
+        body_end: u32,
+        func: &ir::Function,
+        isa: &dyn TargetIsa,
+        len: u32,
+    ) -> Self {
+        let encinfo = isa.encoding_info();
+        let mut blocks = func.layout.blocks().collect::<Vec<_>>();
+        blocks.sort_by_key(|block| func.offsets[*block]);
+        let mut instructions = vec![];
+        for block in blocks {
+            for (offset, inst, _size) in func.inst_offsets(block, &encinfo) {
+                let srcloc = func.srclocs[inst];
+                if !srcloc.is_default() {
+                    instructions.push((offset, srcloc.bits()));
+                }
+            }
+        }
+
+        let vmctx_slot = &func.stack_slots[ir::StackSlot::new(0)];
+        debug_assert_eq!(vmctx_slot.kind, ir::StackSlotKind::ExplicitSlot);
+        let vmctx_offset = FRAME_POINTER_TO_SLOTS
+            + i64::from(
+                vmctx_slot
+                    .offset
+                    .expect("stack slots are laid out by compilation"),
+            );
+
+        Self {
+            func_id,
+            body_end,
+            len,
+            instructions,
+            vmctx_offset,
+        }
+    }
+}
+
+/// Add native debug sections to `object`, if the module `wasm` has DWARF sections.
+///
+/// `functions` describes every function defined by the module, in order, and `symbols` maps them
+/// to the symbols of their code.
+pub(crate) fn write_debug_sections(
+    object: &mut Object,
+    symbols: &SecondaryMap<FuncId, Option<SymbolId>>,
+    wasm: &[u8],
+    functions: &[FunctionMap],
+) -> Result<(), Error> {
+    let (wasm_sections, code_section) = read_sections(wasm)?;
+    if !wasm_sections.contains_key(SectionId::DebugInfo.name()) {
+        return Ok(());
+    }
+    let section = |id: SectionId| -> Result<Reader<'_>, gimli::Error> {
+        let data = wasm_sections.get(id.name()).cloned().unwrap_or(&[]);
+        Ok(EndianSlice::new(data, LittleEndian))
+    };
+    let dwarf = read::Dwarf::load(section, |_| Ok(EndianSlice::new(&[], LittleEndian)))?;
+
+    let translator = Translator::new(functions, code_section);
+    let mut sections = write::Sections::new(RelocWriter::new());
+    translator.translate(&dwarf)?.write(&mut sections)?;
+
+    sections.for_each(|id, section| -> Result<(), Error> {
+        if section.data.len() == 0 {
+            return Ok(());
+        }
+        let section_id =
+            object.add_section(vec![], id.name().as_bytes().to_vec(), SectionKind::Debug);
+        object.append_section_data(section_id, section.data.slice(), 1);
+        for reloc in section.relocs.iter() {
+            let symbol = symbols[functions[reloc.function].func_id]
+                .expect("functions with debug info are defined");
+            object
+                .add_relocation(
+                    section_id,
+                    Relocation {
+                        offset: reloc.offset,
+                        size: 64,
+                        kind: RelocationKind::Absolute,
+                        encoding: RelocationEncoding::Generic,
+                        symbol,
+                        addend: reloc.addend,
+                    },
+                )
+                .map_err(|source| {
+                    Error::ObjectArtifact(source, format!("Relocating {}", id.name()))
+                })?;
+        }
+        Ok(())
+    })
+}
+
+/// Find the DWARF sections of a module, and the offset of the contents of the code section, which
+/// DWARF addresses are relative to.
+fn read_sections(wasm: &[u8]) -> Result<(HashMap<&str, &[u8]>, u32), Error> {
+    let mut reader = ModuleReader::new(wasm)?;
+    let mut sections = HashMap::new();
+    let mut code_section = 0;
+    while !reader.eof() {
+        let section = reader.read()?;
+        match section.code {
+            SectionCode::Code => code_section = section.range().start as u32,
+            SectionCode::Custom { name, .. } if name.starts_with(".debug_") => {
+                let range = section.range();
+                sections.insert(name, &wasm[range.start..range.end]);
+            }
+            _ => (),
+        }
+    }
+    Ok((sections, code_section))
+}
+
+/// A native address range within the code of a function, as an index into the function maps and
+/// a range of offsets from the start of the function.
+type NativeRange = (usize, Range<u32>);
+
+struct Translator<'a> {
+    functions: &'a [FunctionMap],
+    /// The code addresses of each function, from the start of its size to the end of its body,
+    /// which is what a `DW_TAG_subprogram` covers.
+    ranges: Vec<Range<u64>>,
+    code_section: u32,
+}
+
+impl<'a> Translator<'a> {
+    fn new(functions: &'a [FunctionMap], code_section: u32) -> Self {
+        // the first function starts after the count of functions; no address can be below it,
+        // which is why linkers use 0 as the address of dead code
+        let mut start = 1;
+        let ranges = functions
+            .iter()
+            .map(|func| {
+                let end = u64::from(func.body_end - code_section);
+                let range = start..end;
+                start = end;
+                range
+            })
+            .collect();
+        Self {
+            functions,
+            ranges,
+            code_section,
+        }
+    }
+
+    /// The code address of an instruction's source location.
+    fn code_address(&self, srcloc: u32) -> u64 {
+        u64::from(srcloc.saturating_sub(self.code_section))
+    }
+
+    /// The native code generated for the code addresses in `wasm`, in order.
+    fn native_ranges(&self, wasm: &Range<u64>) -> Vec<NativeRange> {
+        let mut native = vec![];
+        if wasm.start == 0 || wasm.start >= wasm.end {
+            return native;
+        }
+        // the comparison never returns `Equal`, so this finds the first function ending after the
+        // start of the range
+        let first = match self.ranges.binary_search_by(|range| {
+            if range.end <= wasm.start {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(i) | Err(i) => i,
+        };
+        for (index, range) in self.ranges.iter().enumerate().skip(first) {
+            if range.start >= wasm.end {
+                break;
+            }
+            let func = &self.functions[index];
+            if wasm.start <= range.start && range.end <= wasm.end {
+                native.push((index, 0..func.len));
+                continue;
+            }
+            let mut covered: Option<Range<u32>> = None;
+            for (i, &(offset, srcloc)) in func.instructions.iter().enumerate() {
+                let addr = self.code_address(srcloc);
+                if addr < wasm.start || addr >= wasm.end {
+                    continue;
+                }
+                let end = func
+                    .instructions
+                    .get(i + 1)
+                    .map(|(next, _)| *next)
+                    .unwrap_or(func.len);
+                covered = Some(match covered {
+                    None => offset..end,
+                    Some(c) => c.start.min(offset)..c.end.max(end),
+                });
+            }
+            if let Some(covered) = covered {
+                native.push((index, covered));
+            }
+        }
+        native
+    }
+
+    fn translate(&self, dwarf: &read::Dwarf<Reader<'_>>) -> Result<write::Dwarf, Error> {
+        let mut out = write::Dwarf::new();
+        let mut entries = HashMap::new();
+        let mut units = vec![];
+
+        // Create every entry first, since attributes can refer to entries of other units.
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            let unit = dwarf.unit(header)?;
+            let functions = {
+                let mut tree = unit.entries_tree(None)?;
+                let root = tree.root()?;
+                self.wasm_ranges(dwarf, &unit, root.entry())?
+                    .iter()
+                    .flat_map(|range| self.native_ranges(range))
+                    .map(|(index, _)| index)
+                    .collect::<Vec<_>>()
+            };
+            let (line_program, files) = self.line_program(dwarf, &unit, &functions)?;
+            let unit_id = out.units.add(write::Unit::new(ENCODING, line_program));
+            let out_unit = out.units.get_mut(unit_id);
+
+            let mut offsets = vec![];
+            let mut tree = unit.entries_tree(None)?;
+            let root = out_unit.root();
+            add_entries(tree.root()?, out_unit, root, &mut offsets)?;
+            for (offset, id) in offsets.iter() {
+                entries.insert(offset.to_unit_section_offset(&unit), (unit_id, *id));
+            }
+            units.push((unit, unit_id, offsets, files));
+        }
+
+        for (unit, unit_id, offsets, files) in units.iter() {
+            let out_unit = out.units.get_mut(*unit_id);
+            for (offset, id) in offsets.iter() {
+                let entry = unit.entry(*offset)?;
+                let context = EntryContext {
+                    dwarf,
+                    unit,
+                    files,
+                    entries: &entries,
+                };
+                self.translate_entry(&context, &entry, out_unit, *id, &mut out.strings)?;
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// The code addresses an entry covers, in any of the forms DWARF allows.
+    fn wasm_ranges(
+        &self,
+        dwarf: &read::Dwarf<Reader<'_>>,
+        unit: &read::Unit<Reader<'_>>,
+        entry: &read::DebuggingInformationEntry<'_, '_, Reader<'_>>,
+    ) -> Result<Vec<Range<u64>>, Error> {
+        if let Some(ranges) = entry.attr_value(constants::DW_AT_ranges)? {
+            let mut ranges = match dwarf.attr_ranges_offset(unit, ranges)? {
+                Some(offset) => dwarf.ranges(unit, offset)?,
+                None => return Ok(vec![]),
+            };
+            let mut wasm = vec![];
+            while let Some(range) = ranges.next()? {
+                wasm.push(range.begin..range.end);
+            }
+            return Ok(wasm);
+        }
+        let low = match entry.attr_value(constants::DW_AT_low_pc)? {
+            Some(read::AttributeValue::Addr(low)) => low,
+            _ => return Ok(vec![]),
+        };
+        let high = match entry.attr_value(constants::DW_AT_high_pc)? {
+            Some(read::AttributeValue::Addr(high)) => high,
+            Some(len) => match len.udata_value() {
+                Some(len) => low + len,
+                None => return Ok(vec![]),
+            },
+            None => return Ok(vec![]),
+        };
+        Ok(vec![low..high])
+    }
+
+    /// Create the line program of a unit, with the directories and files of the original, and
+    /// rows for the code of `functions`.
+    ///
+    /// Also returns the mapping from the original file indexes to the new files.
+    fn line_program(
+        &self,
+        dwarf: &read::Dwarf<Reader<'_>>,
+        unit: &read::Unit<Reader<'_>>,
+        functions: &[usize],
+    ) -> Result<(write::LineProgram, Vec<Option<write::FileId>>), Error> {
+        let program = match unit.line_program {
+            Some(ref program) => program.clone(),
+            None => return Ok((write::LineProgram::none(), vec![])),
+        };
+        let string = |value: Option<read::AttributeValue<Reader<'_>>>| -> Result<_, Error> {
+            Ok(match value {
+                Some(value) => dwarf.attr_string(unit, value)?.slice().to_vec(),
+                None => vec![],
+            })
+        };
+
+        let (comp_dir, comp_file, dirs, file_names) = {
+            let header = program.header();
+            let dirs = header
+                .include_directories()
+                .iter()
+                .map(|dir| string(Some(*dir)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let file_names = header
+                .file_names()
+                .iter()
+                .map(|file| Ok((string(Some(file.path_name()))?, file.directory_index())))
+                .collect::<Result<Vec<_>, Error>>()?;
+            (
+                string(header.directory(0))?,
+                string(header.file(0).map(|file| file.path_name()))?,
+                dirs,
+                file_names,
+            )
+        };
+        let or = |s: Vec<u8>, default: &str| {
+            if s.is_empty() {
+                default.as_bytes().to_vec()
+            } else {
+                s
+            }
+        };
+        let mut out = write::LineProgram::new(
+            ENCODING,
+            LineEncoding::default(),
+            write::LineString::String(or(comp_dir, ".")),
+            write::LineString::String(or(comp_file, "<unknown>")),
+            None,
+        );
+
+        // Before DWARF 5, index 0 of directories and files is the compilation unit's, and the
+        // header lists the rest.
+        let first_index = if unit.header.version() < 5 { 1 } else { 0 };
+        let mut dir_ids = vec![out.default_directory(); first_index];
+        for dir in dirs {
+            let id = if dir.is_empty() {
+                out.default_directory()
+            } else {
+                out.add_directory(write::LineString::String(dir))
+            };
+            dir_ids.push(id);
+        }
+        let mut files = vec![None; first_index];
+        for (name, dir) in file_names {
+            files.push(if name.is_empty() {
+                None
+            } else {
+                let dir = dir_ids
+                    .get(dir as usize)
+                    .cloned()
+                    .unwrap_or_else(|| out.default_directory());
+                Some(out.add_file(write::LineString::String(name), dir, None))
+            });
+        }
+
+        let mut rows = vec![];
+        let mut program_rows = program.rows();
+        while let Some((_, row)) = program_rows.next_row()? {
+            rows.push(LineRow {
+                address: row.address(),
+                file: row.file_index(),
+                line: row.line().unwrap_or(0),
+                column: match row.column() {
+                    read::ColumnType::LeftEdge => 0,
+                    read::ColumnType::Column(column) => column,
+                },
+                is_statement: row.is_stmt(),
+                end_sequence: row.end_sequence(),
+            });
+        }
+        // at equal addresses, the end of one sequence comes before the start of the next
+        rows.sort_by_key(|row| (row.address, !row.end_sequence));
+
+        for &index in functions {
+            let func = &self.functions[index];
+            out.begin_sequence(Some(Address::Symbol {
+                symbol: index,
+                addend: 0,
+            }));
+            let mut previous = None;
+            for &(offset, srcloc) in func.instructions.iter() {
+                let row = match find_row(&rows, self.code_address(srcloc)) {
+                    Some(row) => row,
+                    None => continue,
+                };
+                let file = match files.get(row.file as usize).cloned().flatten() {
+                    Some(file) => file,
+                    None => continue,
+                };
+                let location = (file, row.line, row.column);
+                if previous == Some(location) {
+                    continue;
+                }
+                previous = Some(location);
+                let out_row = out.row();
+                out_row.address_offset = u64::from(offset);
+                out_row.file = file;
+                out_row.line = row.line;
+                out_row.column = row.column;
+                out_row.is_statement = row.is_statement;
+                out.generate_row();
+            }
+            out.end_sequence(u64::from(func.len));
+        }
+
+        Ok((out, files))
+    }
+
+    /// Copy the attributes of `entry` to `id`, translating addresses and locations.
+    fn translate_entry<'input>(
+        &self,
+        context: &EntryContext<'_, 'input>,
+        entry: &read::DebuggingInformationEntry<'_, '_, Reader<'input>>,
+        unit: &mut write::Unit,
+        id: write::UnitEntryId,
+        strings: &mut write::StringTable,
+    ) -> Result<(), Error> {
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next()? {
+            let value = match attr.name() {
+                constants::DW_AT_low_pc
+                | constants::DW_AT_high_pc
+                | constants::DW_AT_ranges
+                | constants::DW_AT_frame_base
+                | constants::DW_AT_sibling => None,
+                constants::DW_AT_location => translate_location(context.unit, attr.value()),
+                _ => context.translate_value(attr.value(), strings)?,
+            };
+            if let Some(value) = value {
+                unit.get_mut(id).set(attr.name(), value);
+            }
+        }
+
+        let wasm = self.wasm_ranges(context.dwarf, context.unit, entry)?;
+        let native = wasm
+            .iter()
+            .flat_map(|range| self.native_ranges(range))
+            .collect::<Vec<_>>();
+        if native.is_empty() {
+            return Ok(());
+        }
+        let is_unit = id == unit.root();
+        let out = unit.get_mut(id);
+        if entry.tag() == constants::DW_TAG_subprogram {
+            let mut frame_base = vec![constants::DW_OP_breg6.0];
+            gimli::leb128::write::signed(
+                &mut frame_base,
+                self.functions[native[0].0].vmctx_offset,
+            )?;
+            frame_base.push(constants::DW_OP_deref.0);
+            out.set(
+                constants::DW_AT_frame_base,
+                write::AttributeValue::Exprloc(write::Expression::raw(frame_base)),
+            );
+        }
+        // Range lists are relative to the base address of the unit, so giving the unit a base of
+        // zero lets every range list use symbol addresses.
+        if native.len() == 1 && !is_unit {
+            let (index, ref range) = native[0];
+            out.set(
+                constants::DW_AT_low_pc,
+                write::AttributeValue::Address(Address::Symbol {
+                    symbol: index,
+                    addend: i64::from(range.start),
+                }),
+            );
+            out.set(
+                constants::DW_AT_high_pc,
+                write::AttributeValue::Udata(u64::from(range.end - range.start)),
+            );
+        } else {
+            let ranges = native
+                .into_iter()
+                .map(|(index, range)| write::Range::StartLength {
+                    begin: Address::Symbol {
+                        symbol: index,
+                        addend: i64::from(range.start),
+                    },
+                    length: u64::from(range.end - range.start),
+                })
+                .collect();
+            let ranges = unit.ranges.add(write::RangeList(ranges));
+            let out = unit.get_mut(id);
+            if is_unit {
+                out.set(
+                    constants::DW_AT_low_pc,
+                    write::AttributeValue::Address(Address::Constant(0)),
+                );
+            }
+            out.set(
+                constants::DW_AT_ranges,
+                write::AttributeValue::RangeListRef(ranges),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The parts of the input a translated attribute can refer to.
+struct EntryContext<'a, 'input> {
+    dwarf: &'a read::Dwarf<Reader<'input>>,
+    unit: &'a read::Unit<Reader<'input>>,
+    files: &'a [Option<write::FileId>],
+    entries: &'a HashMap<UnitSectionOffset, (write::UnitId, write::UnitEntryId)>,
+}
+
+impl<'a, 'input> EntryContext<'a, 'input> {
+    /// Translate an attribute value that does not depend on code addresses, or return `None` if it
+    /// can't be represented in the output.
+    fn translate_value(
+        &self,
+        value: read::AttributeValue<Reader<'input>>,
+        strings: &mut write::StringTable,
+    ) -> Result<Option<write::AttributeValue>, Error> {
+        use gimli::read::AttributeValue as In;
+        use gimli::write::AttributeValue as Out;
+        Ok(Some(match value {
+            In::Block(data) => Out::Block(data.slice().to_vec()),
+            In::Data1(data) => Out::Data1(data),
+            In::Data2(data) => Out::Data2(data),
+            In::Data4(data) => Out::Data4(data),
+            In::Data8(data) => Out::Data8(data),
+            In::Sdata(data) => Out::Sdata(data),
+            In::Udata(data) => Out::Udata(data),
+            In::Exprloc(expr) => Out::Exprloc(write::Expression::raw(expr.0.slice().to_vec())),
+            In::Flag(flag) => Out::Flag(flag),
+            In::UnitRef(offset) => {
+                match self.entries.get(&offset.to_unit_section_offset(self.unit)) {
+                    Some((_, id)) => Out::UnitRef(*id),
+                    None => return Ok(None),
+                }
+            }
+            In::DebugInfoRef(offset) => {
+                match self
+                    .entries
+                    .get(&UnitSectionOffset::DebugInfoOffset(offset))
+                {
+                    Some((unit, id)) => Out::DebugInfoRef(write::Reference::Entry(*unit, *id)),
+                    None => return Ok(None),
+                }
+            }
+            In::DebugLineRef(_) => Out::LineProgramRef,
+            In::String(_) | In::DebugStrRef(_) | In::DebugLineStrRef(_) => {
+                let string = self.dwarf.attr_string(self.unit, value)?;
+                Out::StringRef(strings.add(string.slice()))
+            }
+            In::Encoding(value) => Out::Encoding(value),
+            In::DecimalSign(value) => Out::DecimalSign(value),
+            In::Endianity(value) => Out::Endianity(value),
+            In::Accessibility(value) => Out::Accessibility(value),
+            In::Visibility(value) => Out::Visibility(value),
+            In::Virtuality(value) => Out::Virtuality(value),
+            In::Language(value) => Out::Language(value),
+            In::AddressClass(value) => Out::AddressClass(value),
+            In::IdentifierCase(value) => Out::IdentifierCase(value),
+            In::CallingConvention(value) => Out::CallingConvention(value),
+            In::Inline(value) => Out::Inline(value),
+            In::Ordering(value) => Out::Ordering(value),
+            In::FileIndex(index) => match self.files.get(index as usize).cloned().flatten() {
+                Some(file) => Out::FileIndex(Some(file)),
+                None => return Ok(None),
+            },
+            // addresses other than the code ranges, location lists, and the other sections we
+            // don't translate
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Translate the location of a variable at a fixed address in linear memory to an offset from the
+/// frame base, which is the heap base. Other locations are dropped.
+fn translate_location(
+    unit: &read::Unit<Reader<'_>>,
+    value: read::AttributeValue<Reader<'_>>,
+) -> Option<write::AttributeValue> {
+    let expr = match value {
+        read::AttributeValue::Exprloc(expr) => expr.0.slice(),
+        _ => return None,
+    };
+    let address_size = usize::from(unit.encoding().address_size);
+    if expr.len() != 1 + address_size || expr[0] != constants::DW_OP_addr.0 {
+        return None;
+    }
+    let address = expr[1..]
+        .iter()
+        .rev()
+        .fold(0i64, |address, byte| (address << 8) | i64::from(*byte));
+    let mut location = vec![constants::DW_OP_fbreg.0];
+    gimli::leb128::write::signed(&mut location, address).ok()?;
+    Some(write::AttributeValue::Exprloc(write::Expression::raw(
+        location,
+    )))
+}
+
+/// Add the children of `node` under `id`, recording the offset of each entry in the input.
+fn add_entries(
+    node: read::EntriesTreeNode<'_, '_, '_, Reader<'_>>,
+    unit: &mut write::Unit,
+    id: write::UnitEntryId,
+    offsets: &mut Vec<(read::UnitOffset, write::UnitEntryId)>,
+) -> Result<(), Error> {
+    offsets.push((node.entry().offset(), id));
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let child_id = unit.add(id, child.entry().tag());
+        add_entries(child, unit, child_id, offsets)?;
+    }
+    Ok(())
+}
+
+/// A row of a wasm line program.
+struct LineRow {
+    address: u64,
+    file: u64,
+    line: u64,
+    column: u64,
+    is_statement: bool,
+    end_sequence: bool,
+}
+
+/// Find the row covering `address` in `rows`, which are sorted by address.
+fn find_row(rows: &[LineRow], address: u64) -> Option<&LineRow> {
+    // the comparison never returns `Equal`, so this finds the first row after `address`
+    let after = match rows.binary_search_by(|row| {
+        if row.address <= address {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }
+    }) {
+        Ok(i) | Err(i) => i,
+    };
+    match after.checked_sub(1).map(|i| &rows[i]) {
+        Some(row) if !row.end_sequence => Some(row),
+        _ => None,
+    }
+}
+
+/// A relocation of a function address in a debug section.
+#[derive(Clone)]
+struct DebugReloc {
+    offset: u64,
+    /// The index of the function in the function maps.
+    function: usize,
+    addend: i64,
+}
+
+/// A debug section being written, which records the relocations of function addresses.
+#[derive(Clone)]
+struct RelocWriter {
+    data: EndianVec<LittleEndian>,
+    relocs: Vec<DebugReloc>,
+}
+
+impl RelocWriter {
+    fn new() -> Self {
+        Self {
+            data: EndianVec::new(LittleEndian),
+            relocs: vec![],
+        }
+    }
+}
+
+impl Writer for RelocWriter {
+    type Endian = LittleEndian;
+
+    fn endian(&self) -> Self::Endian {
+        LittleEndian
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> write::Result<()> {
+        self.data.write(bytes)
+    }
+
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) -> write::Result<()> {
+        self.data.write_at(offset, bytes)
+    }
+
+    fn write_address(&mut self, address: Address, size: u8) -> write::Result<()> {
+        match address {
+            Address::Constant(value) => self.write_udata(value, size),
+            Address::Symbol { symbol, addend } => {
+                self.relocs.push(DebugReloc {
+                    offset: self.data.len() as u64,
+                    function: symbol,
+                    addend,
+                });
+                self.write_udata(0, size)
+            }
+        }
+    }
+}
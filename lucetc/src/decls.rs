@@ -11,15 +11,16 @@ use cranelift_codegen::ir;
 use cranelift_codegen::isa::TargetFrontendConfig;
 use cranelift_module::{Backend as ClifBackend, Linkage, Module as ClifModule};
 use cranelift_wasm::{
-    Global, GlobalIndex, GlobalInit, MemoryIndex, SignatureIndex, Table, TableIndex,
-    TargetEnvironment,
+    DataIndex, ElemIndex, Global, GlobalIndex, GlobalInit, MemoryIndex, SignatureIndex, Table,
+    TableElementType, TableIndex, TargetEnvironment,
 };
 use lucet_module::bindings::Bindings;
 use lucet_module::ModuleFeatures;
 use lucet_module::{
     owned::OwnedLinearMemorySpec, ExportFunction, FunctionIndex as LucetFunctionIndex,
     FunctionMetadata, Global as GlobalVariant, GlobalDef, GlobalSpec, HeapSpec, ImportFunction,
-    ModuleData, Signature as LucetSignature, UniqueSignatureIndex,
+    ModuleData, Signature as LucetSignature, TableElementType as LucetTableElementType, TableSpec,
+    UniqueSignatureIndex,
 };
 use std::collections::HashMap;
 use wasmparser::FuncType;
@@ -274,7 +275,11 @@ impl<'a> ModuleDecls<'a> {
         clif_module: &mut ClifModule<B>,
         runtime: Runtime,
     ) -> Result<(), Error> {
-        for (func, functype) in runtime.functions.iter() {
+        // declare the runtime functions in a stable order, since declaration order determines
+        // their place in the function manifest
+        let mut functions = runtime.functions.iter().collect::<Vec<_>>();
+        functions.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+        for (func, functype) in functions {
             let func_id = decls.declare_new_function(
                 clif_module,
                 functype.name.clone(),
@@ -344,9 +349,12 @@ impl<'a> ModuleDecls<'a> {
                         Err(Error::GlobalDeclarationError(ix.as_u32()))
                     }
                 }
-                GlobalInit::V128Const(_) | GlobalInit::RefNullConst | GlobalInit::RefFunc(_) => {
-                    Err(Error::GlobalUnsupported(ix.as_u32()))
-                }
+                // Null references are represented as 0, whether they are `funcref`s or `externref`s.
+                GlobalInit::RefNullConst => Ok(GlobalVariant::Def(GlobalDef::I64(0))),
+                GlobalInit::V128Const(v) => Ok(GlobalVariant::Def(GlobalDef::V128(
+                    u128::from_le_bytes(v.0),
+                ))),
+                GlobalInit::RefFunc(_) => Err(Error::GlobalUnsupported(ix.as_u32())),
             }?;
 
             globals.push(GlobalSpec::new(global, g_decl.export_names.clone()));
@@ -398,13 +406,14 @@ impl<'a> ModuleDecls<'a> {
         self.info.target_config()
     }
 
+    /// Iterate over the functions defined by the module and their bodies, in index order.
     pub fn function_bodies(&self) -> impl Iterator<Item = (FunctionDecl<'_>, &(&'a [u8], usize))> {
-        Box::new(
-            self.info
-                .function_bodies
-                .iter()
-                .map(move |(fidx, code)| (self.get_func(*fidx).unwrap(), code)),
-        )
+        let mut bodies = self.info.function_bodies.iter().collect::<Vec<_>>();
+        // the order functions are defined in determines their layout in the object file
+        bodies.sort_by_key(|(fidx, _)| **fidx);
+        bodies
+            .into_iter()
+            .map(move |(fidx, code)| (self.get_func(*fidx).unwrap(), code))
     }
 
     pub fn get_func(&self, func_index: UniqueFuncIndex) -> Option<FunctionDecl<'_>> {
@@ -540,9 +549,65 @@ impl<'a> ModuleDecls<'a> {
             })
             .collect::<Result<Vec<LucetSignature>, Error>>()?;
 
+        let tables_spec = self
+            .info
+            .tables
+            .values()
+            .map(|table| TableSpec {
+                element_type: match table.entity.ty {
+                    TableElementType::Func => LucetTableElementType::FuncRef,
+                    TableElementType::Val(_) => LucetTableElementType::ExternRef,
+                },
+                minimum: table.entity.minimum,
+                maximum: table.entity.maximum,
+            })
+            .collect();
+
+        // Segments are indexed densely, with active segments left empty.
+        let passive_data_count = self
+            .info
+            .passive_data
+            .keys()
+            .map(|idx| idx.index() + 1)
+            .max()
+            .unwrap_or(0);
+        let passive_data = (0..passive_data_count)
+            .map(|idx| {
+                self.info
+                    .passive_data
+                    .get(&DataIndex::new(idx))
+                    .copied()
+                    .unwrap_or(&[])
+            })
+            .collect();
+        let passive_elements_count = self
+            .info
+            .passive_elements
+            .keys()
+            .map(|idx| idx.index() + 1)
+            .max()
+            .unwrap_or(0);
+        let passive_elements = (0..passive_elements_count)
+            .map(|idx| {
+                self.info
+                    .passive_elements
+                    .get(&ElemIndex::new(idx))
+                    .map(|elements| {
+                        elements
+                            .iter()
+                            .map(|fn_idx| LucetFunctionIndex::from_u32(fn_idx.as_u32()))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            })
+            .collect();
+
         Ok(ModuleData::new(
             linear_memory,
             self.globals_spec.clone(),
+            tables_spec,
+            passive_data,
+            passive_elements,
             functions,
             self.imports.clone(),
             self.exports.clone(),
@@ -20,6 +20,10 @@ pub enum Error {
     IOError(#[from] std::io::Error),
     #[error("Converting to Wasm signature: {0}")]
     SignatureConversion(#[from] SignatureError),
+    #[error("Reading DWARF: {0}")]
+    DwarfRead(#[from] gimli::read::Error),
+    #[error("Writing DWARF: {0}")]
+    DwarfWrite(#[from] gimli::write::Error),
     #[error("Wasm validation: {0}")]
     WasmValidation(#[from] wasmparser::BinaryReaderError),
     #[error("Wat input: {0}")]
@@ -55,14 +59,22 @@ pub enum Error {
     Input(String),
     #[error("Ld error: {0}")]
     LdError(String),
+    #[error("Link error: {0}")]
+    Link(String),
     #[error("Memory specs: {0}")]
     MemorySpecs(String),
+    #[error("Output is not reproducible: {0}")]
+    NotReproducible(String),
     #[error("Metadata serializer; start index points to a non-function: {0}")]
     MetadataSerializer(#[source] ClifModuleError),
+    #[error("Output: {0}")]
+    Output(String),
     #[error("Output function: error writing function {1}")]
     OutputFunction(#[source] std::fmt::Error, String),
     #[error("Signature error: {0}")]
     Signature(String),
+    #[error("Building compilation thread pool: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
     #[error("Table index is out of bounds: {0}")]
     TableIndexError(String),
     #[error("Initializer {0:?} out of range for {1:?}")]
@@ -1,4 +1,5 @@
 use super::runtime::RuntimeFunc;
+use crate::debug;
 use crate::decls::ModuleDecls;
 use crate::pointer::{NATIVE_POINTER, NATIVE_POINTER_SIZE};
 use crate::table::TABLE_REF_SIZE;
@@ -11,7 +12,7 @@ use cranelift_wasm::{
     FuncEnvironment, FuncIndex, FuncTranslationState, GlobalIndex, GlobalVariable, MemoryIndex,
     SignatureIndex, TableIndex, TargetEnvironment, WasmError, WasmResult,
 };
-use lucet_module::InstanceRuntimeData;
+use lucet_module::{GlobalValue, InstanceRuntimeData};
 use memoffset::offset_of;
 use std::collections::HashMap;
 use wasmparser::Operator;
@@ -19,20 +20,34 @@ use wasmparser::Operator;
 pub struct FuncInfo<'a> {
     module_decls: &'a ModuleDecls<'a>,
     count_instructions: bool,
+    fuel_checks: bool,
+    epoch_interruption: bool,
+    debug_info: bool,
     scope_costs: Vec<u32>,
     vmctx_value: Option<ir::GlobalValue>,
     global_base_value: Option<ir::GlobalValue>,
+    tables_ptr_value: Option<ir::GlobalValue>,
     runtime_funcs: HashMap<RuntimeFunc, ir::FuncRef>,
 }
 
 impl<'a> FuncInfo<'a> {
-    pub fn new(module_decls: &'a ModuleDecls<'a>, count_instructions: bool) -> Self {
+    pub fn new(
+        module_decls: &'a ModuleDecls<'a>,
+        count_instructions: bool,
+        fuel_checks: bool,
+        epoch_interruption: bool,
+        debug_info: bool,
+    ) -> Self {
         Self {
             module_decls,
             count_instructions,
+            fuel_checks,
+            epoch_interruption,
+            debug_info,
             scope_costs: vec![0],
             vmctx_value: None,
             global_base_value: None,
+            tables_ptr_value: None,
             runtime_funcs: HashMap::new(),
         }
     }
@@ -61,6 +76,22 @@ impl<'a> FuncInfo<'a> {
         })
     }
 
+    pub fn get_tables_ptr(&mut self, func: &mut ir::Function) -> ir::GlobalValue {
+        self.tables_ptr_value.unwrap_or_else(|| {
+            let vmctx = self.get_vmctx(func);
+            let tables_ptr_value = func.create_global_value(ir::GlobalValueData::Load {
+                base: vmctx,
+                offset: (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
+                    + (offset_of!(InstanceRuntimeData, tables_ptr) as i32))
+                    .into(),
+                global_type: ir::types::I64,
+                readonly: false,
+            });
+            self.tables_ptr_value = Some(tables_ptr_value);
+            tables_ptr_value
+        })
+    }
+
     pub fn get_runtime_func(
         &mut self,
         runtime_func: RuntimeFunc,
@@ -81,6 +112,98 @@ impl<'a> FuncInfo<'a> {
         })
     }
 
+    /// Trap with `TrapCode::TableOutOfBounds` unless `end` is within the bounds of the table.
+    ///
+    /// `end` is an `i64` index one past the last element accessed, so the check passes if it is
+    /// equal to the length of the table.
+    fn insert_table_bounds_check(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        table_index: TableIndex,
+        end: ir::Value,
+    ) -> WasmResult<()> {
+        let table_bound_offset = (TABLE_REF_SIZE as u32)
+            .checked_mul(table_index.as_u32())
+            .and_then(|entry| entry.checked_add(NATIVE_POINTER_SIZE as u32))
+            .filter(|offset| *offset <= std::i32::MAX as u32)
+            .ok_or(WasmError::ImplLimitExceeded)?;
+        let tables_ptr_gv = self.get_tables_ptr(pos.func);
+        let tables_ptr = pos.ins().global_value(NATIVE_POINTER, tables_ptr_gv);
+        let bound = pos.ins().load(
+            ir::types::I64,
+            ir::MemFlags::trusted(),
+            tables_ptr,
+            table_bound_offset as i32,
+        );
+        let out_of_bounds = pos
+            .ins()
+            .icmp(ir::condcodes::IntCC::UnsignedGreaterThan, end, bound);
+        pos.ins()
+            .trapnz(out_of_bounds, ir::TrapCode::TableOutOfBounds);
+        Ok(())
+    }
+
+    /// Call a runtime function with the given arguments, passing the vmctx first, and return the
+    /// call's results.
+    fn call_runtime_func(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        runtime_func: RuntimeFunc,
+        args: &[ir::Value],
+    ) -> Vec<ir::Value> {
+        let func = self.get_runtime_func(runtime_func, &mut pos.func);
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("vmctx available");
+        let mut call_args = Vec::with_capacity(args.len() + 1);
+        call_args.push(vmctx);
+        call_args.extend_from_slice(args);
+        let inst = pos.ins().call(func, &call_args);
+        pos.func.dfg.inst_results(inst).to_vec()
+    }
+
+    /// Insert a check of the instance's epoch deadline at the cursor position.
+    ///
+    /// If the current epoch has reached the deadline, the guest traps with
+    /// `TrapCode::Interrupt`. The check is a handful of loads and a compare, so it is cheap
+    /// enough to run at every function entry and loop header, which bounds the time a guest can
+    /// run between checks.
+    fn insert_epoch_check(&mut self, pos: &mut FuncCursor<'_>) {
+        let epoch_ptr_offset: ir::immediates::Offset32 =
+            (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
+                + offset_of!(InstanceRuntimeData, epoch_ptr) as i32)
+                .into();
+        let epoch_deadline_offset: ir::immediates::Offset32 =
+            (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
+                + offset_of!(InstanceRuntimeData, epoch_deadline) as i32)
+                .into();
+        let vmctx_gv = self.get_vmctx(pos.func);
+        let addr = pos.ins().global_value(self.pointer_type(), vmctx_gv);
+        let trusted_mem = ir::MemFlags::trusted();
+
+        //    Insert a sequence of clif that is, functionally:
+        //
+        //    let epoch: u64 = *vmctx.epoch_ptr;
+        //    if epoch >= vmctx.epoch_deadline {
+        //        trap(Interrupt);
+        //    }
+
+        let epoch_ptr = pos
+            .ins()
+            .load(self.pointer_type(), trusted_mem, addr, epoch_ptr_offset);
+        let epoch = pos.ins().load(ir::types::I64, trusted_mem, epoch_ptr, 0);
+        let epoch_deadline =
+            pos.ins()
+                .load(ir::types::I64, trusted_mem, addr, epoch_deadline_offset);
+        let deadline_reached = pos.ins().icmp(
+            ir::condcodes::IntCC::UnsignedGreaterThanOrEqual,
+            epoch,
+            epoch_deadline,
+        );
+        pos.ins().trapnz(deadline_reached, ir::TrapCode::Interrupt);
+    }
+
     fn update_instruction_count_instrumentation(
         &mut self,
         op: &Operator<'_>,
@@ -138,6 +261,51 @@ impl<'a> FuncInfo<'a> {
                 .ins()
                 .store(trusted_mem, new_instr_count, addr, instr_count_offset);
 
+            if environ.fuel_checks {
+                //    Then, for modules compiled with fuel checks, check the count against the
+                //    instance's fuel limit:
+                //
+                //    if instruction_count > vmctx.instruction_count_bound {
+                //        lucet_vmctx_out_of_fuel(vmctx);
+                //    }
+                //
+                //    The runtime function either terminates the instance, or yields until the host
+                //    has raised the bound, so execution only continues past this point with fuel
+                //    remaining.
+
+                let bound_offset: ir::immediates::Offset32 =
+                    (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
+                        + offset_of!(InstanceRuntimeData, instruction_count_bound) as i32)
+                        .into();
+                let bound = builder
+                    .ins()
+                    .load(ir::types::I64, trusted_mem, addr, bound_offset);
+                let out_of_fuel = builder.ins().icmp(
+                    ir::condcodes::IntCC::UnsignedGreaterThan,
+                    new_instr_count,
+                    bound,
+                );
+
+                let out_of_fuel_block = builder.create_block();
+                let continuation_block = builder.create_block();
+                builder.ins().brnz(out_of_fuel, out_of_fuel_block, &[]);
+                builder.ins().jump(continuation_block, &[]);
+                builder.seal_block(out_of_fuel_block);
+
+                builder.switch_to_block(out_of_fuel_block);
+                let out_of_fuel_func =
+                    environ.get_runtime_func(RuntimeFunc::OutOfFuel, builder.func);
+                let vmctx = builder
+                    .func
+                    .special_param(ir::ArgumentPurpose::VMContext)
+                    .unwrap();
+                builder.ins().call(out_of_fuel_func, &[vmctx]);
+                builder.ins().jump(continuation_block, &[]);
+                builder.seal_block(continuation_block);
+
+                builder.switch_to_block(continuation_block);
+            }
+
             *environ.scope_costs.last_mut().unwrap() = 0;
         };
 
@@ -280,7 +448,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         let global_base = self.get_global_base(func);
         let global = self.module_decls.get_global(index).expect("valid global");
         let index = index.as_u32() as i32;
-        let offset = (index * NATIVE_POINTER_SIZE as i32).into();
+        let offset = (index * std::mem::size_of::<GlobalValue>() as i32).into();
         Ok(GlobalVariable::Memory {
             gv: global_base,
             offset,
@@ -314,33 +482,32 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
     ) -> Result<ir::Table, WasmError> {
         let index_type = ir::types::I64;
         let table_decl = self.module_decls.get_table(index).expect("valid table");
-        let base_gv = func.create_global_value(ir::GlobalValueData::Symbol {
-            name: table_decl.contents_name.into(),
-            offset: 0.into(),
-            colocated: true,
-            tls: false,
-        });
-        let tables_list_gv = func.create_global_value(ir::GlobalValueData::Symbol {
-            name: self.module_decls.get_tables_list_name().as_externalname(),
-            offset: 0.into(),
-            colocated: true,
-            tls: false,
-        });
+        // Tables live in the instance, where `table.grow` and friends may replace them, so both the
+        // base and the bound are reloaded through `InstanceRuntimeData::tables_ptr`.
+        let tables_ptr_gv = self.get_tables_ptr(func);
 
-        let table_bound_offset = (TABLE_REF_SIZE as u32)
+        let table_base_offset = (TABLE_REF_SIZE as u32)
             .checked_mul(index.as_u32())
-            .and_then(|entry| entry.checked_add(NATIVE_POINTER_SIZE as u32))
+            .ok_or(WasmError::ImplLimitExceeded)?;
+        let table_bound_offset = table_base_offset
+            .checked_add(NATIVE_POINTER_SIZE as u32)
             .ok_or(WasmError::ImplLimitExceeded)?;
 
         if table_bound_offset > std::i32::MAX as u32 {
             return Err(WasmError::ImplLimitExceeded);
         }
 
+        let base_gv = func.create_global_value(ir::GlobalValueData::Load {
+            base: tables_ptr_gv,
+            global_type: NATIVE_POINTER,
+            offset: (table_base_offset as i32).into(),
+            readonly: false,
+        });
         let bound_gv = func.create_global_value(ir::GlobalValueData::Load {
-            base: tables_list_gv,
+            base: tables_ptr_gv,
             global_type: index_type,
             offset: (table_bound_offset as i32).into(),
-            readonly: true,
+            readonly: false,
         });
         let element_size = ((NATIVE_POINTER_SIZE * 2) as u64).into();
         let min_size = (table_decl.table.minimum as u64).into();
@@ -494,156 +661,220 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
 
     fn translate_memory_copy(
         &mut self,
-        _pos: FuncCursor,
+        mut pos: FuncCursor,
         _index: MemoryIndex,
         _heap: ir::Heap,
-        _dst: ir::Value,
-        _src: ir::Value,
-        _len: ir::Value,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        let status = self.call_runtime_func(&mut pos, RuntimeFunc::MemoryCopy, &[dst, src, len])[0];
+        pos.ins().trapnz(status, ir::TrapCode::HeapOutOfBounds);
+        Ok(())
     }
 
     fn translate_memory_fill(
         &mut self,
-        _pos: FuncCursor,
+        mut pos: FuncCursor,
         _index: MemoryIndex,
         _heap: ir::Heap,
-        _dst: ir::Value,
-        _val: ir::Value,
-        _len: ir::Value,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        let status = self.call_runtime_func(&mut pos, RuntimeFunc::MemoryFill, &[dst, val, len])[0];
+        pos.ins().trapnz(status, ir::TrapCode::HeapOutOfBounds);
+        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
     fn translate_memory_init(
         &mut self,
-        _pos: FuncCursor,
+        mut pos: FuncCursor,
         _index: MemoryIndex,
         _heap: ir::Heap,
-        _seg_index: u32,
-        _dst: ir::Value,
-        _src: ir::Value,
-        _len: ir::Value,
+        seg_index: u32,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        let seg_index = pos.ins().iconst(ir::types::I32, seg_index as i64);
+        let status = self.call_runtime_func(
+            &mut pos,
+            RuntimeFunc::MemoryInit,
+            &[seg_index, dst, src, len],
+        )[0];
+        pos.ins().trapnz(status, ir::TrapCode::HeapOutOfBounds);
+        Ok(())
     }
 
-    fn translate_data_drop(&mut self, _pos: FuncCursor, _seg_index: u32) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+    fn translate_data_drop(&mut self, mut pos: FuncCursor, seg_index: u32) -> WasmResult<()> {
+        let seg_index = pos.ins().iconst(ir::types::I32, seg_index as i64);
+        self.call_runtime_func(&mut pos, RuntimeFunc::DataDrop, &[seg_index]);
+        Ok(())
     }
 
     fn translate_table_size(
         &mut self,
-        _pos: FuncCursor,
-        _index: TableIndex,
+        mut pos: FuncCursor,
+        index: TableIndex,
         _table: ir::Table,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        let table_index = pos.ins().iconst(ir::types::I32, index.as_u32() as i64);
+        Ok(self.call_runtime_func(&mut pos, RuntimeFunc::TableSize, &[table_index])[0])
     }
 
     fn translate_table_grow(
         &mut self,
-        _pos: FuncCursor,
-        _table_index: TableIndex,
-        _delta: ir::Value,
-        _init_value: ir::Value,
+        mut pos: FuncCursor,
+        table_index: TableIndex,
+        delta: ir::Value,
+        init_value: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        let table_index = pos
+            .ins()
+            .iconst(ir::types::I32, table_index.as_u32() as i64);
+        Ok(self.call_runtime_func(
+            &mut pos,
+            RuntimeFunc::TableGrow,
+            &[table_index, delta, init_value],
+        )[0])
     }
 
     fn translate_table_get(
         &mut self,
-        _pos: FuncCursor,
-        _table_index: TableIndex,
-        _index: ir::Value,
+        mut pos: FuncCursor,
+        table_index: TableIndex,
+        index: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        let index_u64 = pos.ins().uextend(ir::types::I64, index);
+        let end = pos.ins().iadd_imm(index_u64, 1);
+        self.insert_table_bounds_check(&mut pos, table_index, end)?;
+        let table_index = pos
+            .ins()
+            .iconst(ir::types::I32, table_index.as_u32() as i64);
+        Ok(self.call_runtime_func(&mut pos, RuntimeFunc::TableGet, &[table_index, index])[0])
     }
 
     fn translate_table_set(
         &mut self,
-        _pos: FuncCursor,
-        _table_index: TableIndex,
-        _value: ir::Value,
-        _index: ir::Value,
+        mut pos: FuncCursor,
+        table_index: TableIndex,
+        value: ir::Value,
+        index: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        let index_u64 = pos.ins().uextend(ir::types::I64, index);
+        let end = pos.ins().iadd_imm(index_u64, 1);
+        self.insert_table_bounds_check(&mut pos, table_index, end)?;
+        let table_index = pos
+            .ins()
+            .iconst(ir::types::I32, table_index.as_u32() as i64);
+        self.call_runtime_func(
+            &mut pos,
+            RuntimeFunc::TableSet,
+            &[table_index, index, value],
+        );
+        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
     fn translate_table_copy(
         &mut self,
-        _pos: FuncCursor,
-        _dst_table_index: TableIndex,
+        mut pos: FuncCursor,
+        dst_table_index: TableIndex,
         _dst_table: ir::Table,
-        _src_table_index: TableIndex,
+        src_table_index: TableIndex,
         _src_table: ir::Table,
-        _dst: ir::Value,
-        _src: ir::Value,
-        _len: ir::Value,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        let dst_table_index = pos
+            .ins()
+            .iconst(ir::types::I32, dst_table_index.as_u32() as i64);
+        let src_table_index = pos
+            .ins()
+            .iconst(ir::types::I32, src_table_index.as_u32() as i64);
+        let status = self.call_runtime_func(
+            &mut pos,
+            RuntimeFunc::TableCopy,
+            &[dst_table_index, src_table_index, dst, src, len],
+        )[0];
+        pos.ins().trapnz(status, ir::TrapCode::TableOutOfBounds);
+        Ok(())
     }
 
     fn translate_table_fill(
         &mut self,
-        _pos: FuncCursor,
-        _table_index: TableIndex,
-        _dst: ir::Value,
-        _val: ir::Value,
-        _len: ir::Value,
+        mut pos: FuncCursor,
+        table_index: TableIndex,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        // Both operands are 32 bits, so their sum can't overflow 64 bits.
+        let dst_u64 = pos.ins().uextend(ir::types::I64, dst);
+        let len_u64 = pos.ins().uextend(ir::types::I64, len);
+        let end = pos.ins().iadd(dst_u64, len_u64);
+        self.insert_table_bounds_check(&mut pos, table_index, end)?;
+        let table_index = pos
+            .ins()
+            .iconst(ir::types::I32, table_index.as_u32() as i64);
+        self.call_runtime_func(
+            &mut pos,
+            RuntimeFunc::TableFill,
+            &[table_index, dst, val, len],
+        );
+        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
     fn translate_table_init(
         &mut self,
-        _pos: FuncCursor,
-        _seg_index: u32,
-        _table_index: TableIndex,
+        mut pos: FuncCursor,
+        seg_index: u32,
+        table_index: TableIndex,
         _table: ir::Table,
-        _dst: ir::Value,
-        _src: ir::Value,
-        _len: ir::Value,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        let table_index = pos
+            .ins()
+            .iconst(ir::types::I32, table_index.as_u32() as i64);
+        let seg_index = pos.ins().iconst(ir::types::I32, seg_index as i64);
+        let status = self.call_runtime_func(
+            &mut pos,
+            RuntimeFunc::TableInit,
+            &[table_index, seg_index, dst, src, len],
+        )[0];
+        pos.ins().trapnz(status, ir::TrapCode::TableOutOfBounds);
+        Ok(())
     }
 
-    fn translate_elem_drop(&mut self, _pos: FuncCursor, _seg_index: u32) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+    fn translate_elem_drop(&mut self, mut pos: FuncCursor, seg_index: u32) -> WasmResult<()> {
+        let seg_index = pos.ins().iconst(ir::types::I32, seg_index as i64);
+        self.call_runtime_func(&mut pos, RuntimeFunc::ElemDrop, &[seg_index]);
+        Ok(())
     }
 
-    fn translate_ref_func(&mut self, _pos: FuncCursor, _func_index: u32) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+    fn translate_ref_func(
+        &mut self,
+        mut pos: FuncCursor,
+        func_index: u32,
+    ) -> WasmResult<ir::Value> {
+        // The runtime looks functions up in the function manifest, which is in unique function
+        // index order.
+        let unique_index = *self
+            .module_decls
+            .info
+            .function_mapping
+            .get(FuncIndex::from_u32(func_index))
+            .expect("function indices are valid");
+        let func_index = pos
+            .ins()
+            .iconst(ir::types::I32, unique_index.as_u32() as i64);
+        Ok(self.call_runtime_func(&mut pos, RuntimeFunc::RefFunc, &[func_index])[0])
     }
 
     fn translate_custom_global_get(
@@ -667,6 +898,27 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         ))
     }
 
+    fn before_translate_function(
+        &mut self,
+        builder: &mut FunctionBuilder<'_>,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if self.debug_info {
+            debug::spill_vmctx(builder);
+        }
+        if self.epoch_interruption {
+            self.insert_epoch_check(&mut builder.cursor());
+        }
+        Ok(())
+    }
+
+    fn translate_loop_header(&mut self, mut pos: FuncCursor<'_>) -> WasmResult<()> {
+        if self.epoch_interruption {
+            self.insert_epoch_check(&mut pos);
+        }
+        Ok(())
+    }
+
     fn before_translate_operator(
         &mut self,
         op: &Operator<'_>,
@@ -1,10 +1,13 @@
 #![deny(bare_trait_objects)]
 
+mod cache;
 mod compiler;
+mod debug;
 mod decls;
 mod error;
 mod function;
 mod heap;
+mod link;
 mod load;
 mod module;
 mod name;
@@ -18,17 +21,20 @@ mod table;
 mod traps;
 mod types;
 
+use crate::cache::{ArtifactCache, ArtifactKind, CacheKey};
 use crate::load::read_bytes;
 pub use crate::{
     compiler::{Compiler, CompilerBuilder, CpuFeatures, OptLevel, SpecificFeature, TargetCpu},
     error::Error,
     heap::HeapSettings,
+    link::Linker,
     load::read_module,
 };
 pub use lucet_module::bindings::Bindings;
 pub use lucet_validate::Validator;
 use signature::{PublicKey, SecretKey};
 use std::env;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use target_lexicon::Triple;
@@ -46,6 +52,8 @@ pub struct Lucetc {
     pk: Option<PublicKey>,
     sign: bool,
     verify: bool,
+    cache: Option<ArtifactCache>,
+    linker: Option<Linker>,
 }
 
 pub trait AsLucetc {
@@ -102,8 +110,41 @@ pub trait LucetcOpts {
     fn with_sign(self) -> Self;
     fn count_instructions(&mut self, enable_count: bool);
     fn with_count_instructions(self, enable_count: bool) -> Self;
+    fn fuel_checks(&mut self, enable_fuel_checks: bool);
+    fn with_fuel_checks(self, enable_fuel_checks: bool) -> Self;
+    fn epoch_interruption(&mut self, enable_epoch_interruption: bool);
+    fn with_epoch_interruption(self, enable_epoch_interruption: bool) -> Self;
     fn canonicalize_nans(&mut self, enable_canonicalize_nans: bool);
     fn with_canonicalize_nans(self, enable_canonicalize_nans: bool) -> Self;
+    fn threads(&mut self, threads: usize);
+    fn with_threads(self, threads: usize) -> Self;
+    fn debug_info(&mut self, debug_info: bool);
+    fn with_debug_info(self, debug_info: bool) -> Self;
+    fn call_site_offsets(&mut self, call_site_offsets: bool);
+    fn with_call_site_offsets(self, call_site_offsets: bool) -> Self;
+
+    /// Reuse artifacts compiled earlier with the same input and settings, keeping them in
+    /// `cache_dir`.
+    ///
+    /// The directory is created if it does not exist. Artifacts are keyed by a hash of the input,
+    /// the bindings, the compiler settings, and the `lucetc` version, and shared objects are also
+    /// keyed by the `LD` and `LDFLAGS` environment variables.
+    fn cache_dir(&mut self, cache_dir: PathBuf);
+    /// Reuse artifacts compiled earlier with the same input and settings, keeping them in
+    /// `cache_dir`.
+    ///
+    /// See [`cache_dir`](#tymethod.cache_dir).
+    fn with_cache_dir(self, cache_dir: PathBuf) -> Self;
+
+    /// Choose how shared objects are linked.
+    ///
+    /// By default, the built-in linker is used for the targets it supports, and the external linker
+    /// for every other target. See [`Linker::default_for`](enum.Linker.html#method.default_for).
+    fn linker(&mut self, linker: Linker);
+    /// Choose how shared objects are linked.
+    ///
+    /// See [`linker`](#tymethod.linker).
+    fn with_linker(self, linker: Linker) -> Self;
 }
 
 impl<T: AsLucetc> LucetcOpts for T {
@@ -248,6 +289,26 @@ impl<T: AsLucetc> LucetcOpts for T {
         self
     }
 
+    fn fuel_checks(&mut self, fuel_checks: bool) {
+        self.as_lucetc().builder.fuel_checks(fuel_checks);
+    }
+
+    fn with_fuel_checks(mut self, fuel_checks: bool) -> Self {
+        self.fuel_checks(fuel_checks);
+        self
+    }
+
+    fn epoch_interruption(&mut self, epoch_interruption: bool) {
+        self.as_lucetc()
+            .builder
+            .epoch_interruption(epoch_interruption);
+    }
+
+    fn with_epoch_interruption(mut self, epoch_interruption: bool) -> Self {
+        self.epoch_interruption(epoch_interruption);
+        self
+    }
+
     fn canonicalize_nans(&mut self, enable_nans_canonicalization: bool) {
         self.as_lucetc()
             .builder
@@ -258,6 +319,53 @@ impl<T: AsLucetc> LucetcOpts for T {
         self.canonicalize_nans(enable_nans_canonicalization);
         self
     }
+
+    fn threads(&mut self, threads: usize) {
+        self.as_lucetc().builder.threads(threads);
+    }
+
+    fn with_threads(mut self, threads: usize) -> Self {
+        self.threads(threads);
+        self
+    }
+
+    fn debug_info(&mut self, debug_info: bool) {
+        self.as_lucetc().builder.debug_info(debug_info);
+    }
+
+    fn with_debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info(debug_info);
+        self
+    }
+
+    fn call_site_offsets(&mut self, call_site_offsets: bool) {
+        self.as_lucetc()
+            .builder
+            .call_site_offsets(call_site_offsets);
+    }
+
+    fn with_call_site_offsets(mut self, call_site_offsets: bool) -> Self {
+        self.call_site_offsets(call_site_offsets);
+        self
+    }
+
+    fn cache_dir(&mut self, cache_dir: PathBuf) {
+        self.as_lucetc().cache = Some(ArtifactCache::new(cache_dir));
+    }
+
+    fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir(cache_dir);
+        self
+    }
+
+    fn linker(&mut self, linker: Linker) {
+        self.as_lucetc().linker = Some(linker);
+    }
+
+    fn with_linker(mut self, linker: Linker) -> Self {
+        self.linker(linker);
+        self
+    }
 }
 
 impl Lucetc {
@@ -271,6 +379,8 @@ impl Lucetc {
             sk: None,
             sign: false,
             verify: false,
+            cache: None,
+            linker: None,
         }
     }
 
@@ -284,6 +394,8 @@ impl Lucetc {
             sk: None,
             sign: false,
             verify: false,
+            cache: None,
+            linker: None,
         })
     }
 
@@ -302,38 +414,189 @@ impl Lucetc {
         Ok((module_binary, bindings))
     }
 
-    pub fn object_file<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
-        let (module_contents, bindings) = self.build()?;
-        let compiler = self.builder.create(&module_contents, &bindings)?;
-        let obj = compiler.object_file()?;
-        obj.write(output.as_ref())?;
+    /// Produce an artifact at `output` with `compile`, or copy it from the cache if one is
+    /// configured and has an artifact for the same input and settings.
+    fn cached<F>(
+        &self,
+        kind: ArtifactKind,
+        module_contents: &[u8],
+        bindings: &Bindings,
+        output: &Path,
+        compile: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&Path) -> Result<(), Error>,
+    {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return compile(output),
+        };
+
+        let key = CacheKey::new(kind, module_contents, bindings, &self.builder);
+        // the validator is not part of the key, since it does not affect the generated code, but
+        // it still gets to reject the input on a cache hit
+        if let Some(validator) = self.builder.validator_ref() {
+            validator
+                .validate(module_contents)
+                .map_err(Error::LucetValidation)?;
+        }
+        if cache.get(&key, output) {
+            return Ok(());
+        }
 
+        compile(output)?;
+        cache.insert(&key, output);
         Ok(())
     }
 
-    pub fn clif_ir<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
-        let (module_contents, bindings) = self.build()?;
-
-        let compiler = self.builder.create(&module_contents, &bindings)?;
+    /// Produce an artifact with `compile` twice, bypassing the cache, and fail with
+    /// `Error::NotReproducible` unless both compilations produce the same bytes. On success, the
+    /// artifact is written to `output`.
+    fn check_reproducible<F>(&self, output: &Path, compile: F) -> Result<(), Error>
+    where
+        F: Fn(&Path) -> Result<(), Error>,
+    {
+        let dir = tempfile::Builder::new().prefix("lucetc").tempdir()?;
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        compile(&first)?;
+        compile(&second)?;
+
+        let first_bytes = fs::read(&first)?;
+        let second_bytes = fs::read(&second)?;
+        if first_bytes != second_bytes {
+            let offset = first_bytes
+                .iter()
+                .zip(second_bytes.iter())
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| first_bytes.len().min(second_bytes.len()));
+            return Err(Error::NotReproducible(format!(
+                "artifacts differ starting at byte {:#x} (lengths {} and {})",
+                offset,
+                first_bytes.len(),
+                second_bytes.len()
+            )));
+        }
+        fs::copy(&first, output)?;
+        Ok(())
+    }
 
-        compiler.cranelift_funcs()?.write(&output)?;
+    fn compile_object_file(
+        &self,
+        module_contents: &[u8],
+        bindings: &Bindings,
+        output: &Path,
+    ) -> Result<(), Error> {
+        let compiler = self.builder.create(module_contents, bindings)?;
+        let obj = compiler.object_file()?;
+        obj.write(output)?;
 
         Ok(())
     }
 
-    pub fn shared_object_file<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+    fn linker_ref(&self) -> Linker {
+        self.linker
+            .unwrap_or_else(|| Linker::default_for(self.builder.target_ref()))
+    }
+
+    fn compile_shared_object_file(
+        &self,
+        module_contents: &[u8],
+        bindings: &Bindings,
+        output: &Path,
+    ) -> Result<(), Error> {
+        if self.linker_ref() == Linker::Builtin {
+            let target = self.builder.target_ref();
+            if !Linker::builtin_supports(target) {
+                return Err(Error::Unsupported(format!(
+                    "the built-in linker cannot link for {}",
+                    target
+                )));
+            }
+            let compiler = self.builder.create(module_contents, bindings)?;
+            let obj = compiler.object_file()?;
+            fs::write(output, link::link_shared_object(&obj.bytes()?)?)?;
+            return Ok(());
+        }
+
         let dir = tempfile::Builder::new().prefix("lucetc").tempdir()?;
+        // the object file always has the same name, so the temporary directory cannot leak into
+        // the shared object
         let objpath = dir.path().join("tmp.o");
-        self.object_file(objpath.clone())?;
-        link_so(objpath, self.builder.target_ref(), &output)?;
+        self.compile_object_file(module_contents, bindings, &objpath)?;
+        link_so(objpath, self.builder.target_ref(), output)
+    }
+
+    fn sign_if_requested(&self, output: &Path) -> Result<(), Error> {
         if self.sign {
             let sk = self.sk.as_ref().ok_or(Error::Signature(
                 "signing requires a secret key".to_string(),
             ))?;
-            signature::sign_module(&output, sk)?;
+            signature::sign_module(output, sk)?;
         }
         Ok(())
     }
+
+    pub fn object_file<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+        let (module_contents, bindings) = self.build()?;
+        self.cached(
+            ArtifactKind::Object,
+            &module_contents,
+            &bindings,
+            output.as_ref(),
+            |output| self.compile_object_file(&module_contents, &bindings, output),
+        )
+    }
+
+    /// Like [`object_file`](#method.object_file), but compile the input twice and fail with
+    /// `Error::NotReproducible` if the two object files differ.
+    ///
+    /// The artifact cache is not used.
+    pub fn check_reproducible_object_file<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+        let (module_contents, bindings) = self.build()?;
+        self.check_reproducible(output.as_ref(), |output| {
+            self.compile_object_file(&module_contents, &bindings, output)
+        })
+    }
+
+    pub fn clif_ir<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+        let (module_contents, bindings) = self.build()?;
+
+        let compiler = self.builder.create(&module_contents, &bindings)?;
+
+        compiler.cranelift_funcs()?.write(&output)?;
+
+        Ok(())
+    }
+
+    pub fn shared_object_file<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+        let (module_contents, bindings) = self.build()?;
+        // artifacts are cached before signing, so the cache does not depend on the secret key
+        self.cached(
+            ArtifactKind::SharedObject(self.linker_ref()),
+            &module_contents,
+            &bindings,
+            output.as_ref(),
+            |output| self.compile_shared_object_file(&module_contents, &bindings, output),
+        )?;
+        self.sign_if_requested(output.as_ref())
+    }
+
+    /// Like [`shared_object_file`](#method.shared_object_file), but compile and link the input
+    /// twice and fail with `Error::NotReproducible` if the two shared objects differ.
+    ///
+    /// The artifact cache is not used. The shared object is signed only after the check, if
+    /// signing is requested.
+    pub fn check_reproducible_shared_object_file<P: AsRef<Path>>(
+        &self,
+        output: P,
+    ) -> Result<(), Error> {
+        let (module_contents, bindings) = self.build()?;
+        self.check_reproducible(output.as_ref(), |output| {
+            self.compile_shared_object_file(&module_contents, &bindings, output)
+        })?;
+        self.sign_if_requested(output.as_ref())
+    }
 }
 
 const LD_DEFAULT: &str = "ld";
@@ -395,7 +658,9 @@ fn ldflags_default(target: &Triple) -> String {
     use target_lexicon::OperatingSystem;
 
     match target.operating_system {
-        OperatingSystem::Linux => "-shared",
+        // Fix the options that GNU ld's defaults vary on between installations, and leave out the
+        // build ID, so that the shared object only depends on the object file.
+        OperatingSystem::Linux => "-shared --build-id=none --hash-style=gnu",
         OperatingSystem::Darwin | OperatingSystem::MacOSX { .. } => {
             "-dylib -dead_strip -export_dynamic -undefined dynamic_lookup"
         }
@@ -0,0 +1,1171 @@
+//! A minimal ELF linker, which turns the relocatable object `lucetc` generates into a shared
+//! object without running an external linker.
+//!
+//! Only what `lucetc` generates is supported: a single x86-64 relocatable object with
+//! position-independent code. Every defined global symbol is exported and bound locally, and
+//! references to undefined symbols are left to the dynamic linker. Calls to undefined functions go
+//! through stubs that jump via the global offset table, which is filled in when the object is
+//! loaded rather than lazily.
+//!
+//! Debug information is kept, with its relocations applied. The relocations are also kept, as
+//! relative relocations in sections that are not loaded, so that a copy of the shared object can be
+//! adjusted to the address it is loaded at before handing it to a debugger.
+
+use crate::error::Error;
+use byteorder::{ByteOrder, LittleEndian};
+use lucet_module::{LeBytes, LeBytesError};
+use std::collections::HashMap;
+use target_lexicon::{Architecture, BinaryFormat, Triple};
+
+/// How `Lucetc` links shared objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Linker {
+    /// Write the shared object directly, without any host tools. Only x86-64 ELF targets are
+    /// supported.
+    Builtin,
+    /// Run the linker named by the `LD` environment variable, or `ld`, with the flags in the
+    /// `LDFLAGS` environment variable, or defaults for the target. This is the default for targets
+    /// the built-in linker does not support, and may be chosen for any other.
+    External,
+}
+
+impl Linker {
+    /// The linker used when none is chosen: the built-in linker for the targets it supports, and
+    /// the external linker for every other target.
+    pub fn default_for(target: &Triple) -> Self {
+        if Self::builtin_supports(target) {
+            Linker::Builtin
+        } else {
+            Linker::External
+        }
+    }
+
+    pub(crate) fn builtin_supports(target: &Triple) -> bool {
+        target.architecture == Architecture::X86_64 && target.binary_format == BinaryFormat::Elf
+    }
+}
+
+const PAGE_SIZE: u64 = 0x1000;
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_REL: u16 = 1;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_GNU_STACK: u32 = 0x6474_e551;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_HASH: u32 = 5;
+const SHT_DYNAMIC: u32 = 6;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHT_DYNSYM: u32 = 11;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+const SHN_COMMON: u16 = 0xfff2;
+const STB_LOCAL: u8 = 0;
+const STV_DEFAULT: u8 = 0;
+const STV_PROTECTED: u8 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_STRSZ: u64 = 10;
+const DT_SYMENT: u64 = 11;
+const DT_RELACOUNT: u64 = 0x6fff_fff9;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_GLOB_DAT: u32 = 6;
+const R_X86_64_RELATIVE: u32 = 8;
+const R_X86_64_GOTPCREL: u32 = 9;
+const R_X86_64_GOTPCRELX: u32 = 41;
+const R_X86_64_REX_GOTPCRELX: u32 = 42;
+
+const EHDR_SIZE: u64 = 64;
+const PHDR_SIZE: u64 = 56;
+const SHDR_SIZE: u64 = 64;
+const SYM_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+const DYN_SIZE: u64 = 16;
+const GOT_ENTRY_SIZE: u64 = 8;
+/// `jmp *got(%rip)` followed by a two-byte nop.
+const STUB_SIZE: u64 = 8;
+
+const PHNUM: u64 = 6;
+
+// Indices of the sections in the output, in the order their headers are written. Debug sections
+// and their relocations follow these.
+const SHNDX_HASH: u16 = 1;
+const SHNDX_DYNSYM: u16 = 2;
+const SHNDX_DYNSTR: u16 = 3;
+const SHNDX_RELA_DYN: u16 = 4;
+const SHNDX_TEXT: u16 = 5;
+const SHNDX_STUBS: u16 = 6;
+const SHNDX_RELRO: u16 = 7;
+const SHNDX_DYNAMIC: u16 = 8;
+const SHNDX_GOT: u16 = 9;
+const SHNDX_DATA: u16 = 10;
+const SHNDX_BSS: u16 = 11;
+const SHNDX_SYMTAB: u16 = 12;
+const SHNDX_STRTAB: u16 = 13;
+const SHNDX_SHSTRTAB: u16 = 14;
+const SHNUM: u16 = 15;
+
+macro_rules! link_error {
+    ($($arg:tt)*) => {
+        Error::Link(format!($($arg)*))
+    }
+}
+
+impl From<LeBytesError> for Error {
+    fn from(e: LeBytesError) -> Self {
+        link_error!("{}", e)
+    }
+}
+
+/// The output sections that input sections are merged into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum OutputSection {
+    Text,
+    /// Read-only data, which is made read-only after relocation.
+    RelRo,
+    Data,
+    Bss,
+}
+
+impl OutputSection {
+    fn shndx(self) -> u16 {
+        match self {
+            OutputSection::Text => SHNDX_TEXT,
+            OutputSection::RelRo => SHNDX_RELRO,
+            OutputSection::Data => SHNDX_DATA,
+            OutputSection::Bss => SHNDX_BSS,
+        }
+    }
+}
+
+struct Rela {
+    offset: u64,
+    sym: usize,
+    kind: u32,
+    addend: i64,
+}
+
+struct InputSection<'a> {
+    name: &'a str,
+    /// Whether this is a `.debug_*` section, which is not loaded but is kept in the output.
+    is_debug: bool,
+    align: u64,
+    size: u64,
+    data: &'a [u8],
+    relocs: Vec<Rela>,
+    /// Where the section goes in the output, and its offset there.
+    placement: Option<(OutputSection, u64)>,
+}
+
+struct InputSymbol<'a> {
+    name: &'a str,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+impl<'a> InputSymbol<'a> {
+    fn is_undefined(&self) -> bool {
+        self.shndx == SHN_UNDEF
+    }
+
+    fn is_exported(&self) -> bool {
+        let visibility = self.other & 0x3;
+        self.info >> 4 != STB_LOCAL
+            && !self.name.is_empty()
+            && (self.is_undefined() || visibility == STV_DEFAULT || visibility == STV_PROTECTED)
+    }
+}
+
+struct Input<'a> {
+    sections: Vec<InputSection<'a>>,
+    symbols: Vec<InputSymbol<'a>>,
+    /// The index of the first non-local symbol.
+    first_global: u32,
+}
+
+impl<'a> Input<'a> {
+    fn parse(obj: &'a [u8]) -> Result<Self, Error> {
+        let file = LeBytes(obj);
+        let ident = file.slice(0, EHDR_SIZE)?;
+        if &ident[0..4] != b"\x7fELF" {
+            return Err(link_error!("input is not an ELF file"));
+        }
+        if ident[4] != ELFCLASS64 || ident[5] != ELFDATA2LSB {
+            return Err(link_error!(
+                "only 64-bit little-endian objects can be linked"
+            ));
+        }
+        if file.u16(16)? != ET_REL {
+            return Err(link_error!("input is not a relocatable object"));
+        }
+        if file.u16(18)? != EM_X86_64 {
+            return Err(link_error!("only x86-64 objects can be linked"));
+        }
+
+        let shoff = file.u64(0x28)?;
+        let shentsize = file.u16(0x3a)? as u64;
+        let shnum = file.u16(0x3c)? as u64;
+        let shstrndx = file.u16(0x3e)? as u64;
+        if shnum > 0 && shentsize < SHDR_SIZE {
+            return Err(link_error!("section header entries are too small"));
+        }
+        let header = |i: u64| shoff + i * shentsize;
+        let shstrtab_offset = file.u64(header(shstrndx) + 24)?;
+
+        let mut sections = vec![];
+        let mut symtab = None;
+        let mut relas = vec![];
+        for i in 0..shnum {
+            let sh = header(i);
+            let name = file.str(shstrtab_offset + file.u32(sh)? as u64)?;
+            let sh_type = file.u32(sh + 4)?;
+            let flags = file.u64(sh + 8)?;
+            let offset = file.u64(sh + 24)?;
+            let size = file.u64(sh + 32)?;
+            let link = file.u32(sh + 40)?;
+            let info = file.u32(sh + 44)?;
+            let align = file.u64(sh + 48)?.max(1);
+            if !align.is_power_of_two() {
+                return Err(link_error!("section {} has alignment {}", name, align));
+            }
+
+            let is_debug = flags & SHF_ALLOC == 0 && name.starts_with(".debug_");
+            let data = match sh_type {
+                SHT_NOBITS => &[][..],
+                _ if flags & SHF_ALLOC != 0 || is_debug => file.slice(offset, size)?,
+                _ => &[][..],
+            };
+            let placement = if flags & SHF_ALLOC == 0 {
+                None
+            } else if sh_type == SHT_NOBITS {
+                Some((OutputSection::Bss, 0))
+            } else if flags & SHF_EXECINSTR != 0 {
+                Some((OutputSection::Text, 0))
+            } else if flags & SHF_WRITE != 0 {
+                Some((OutputSection::Data, 0))
+            } else {
+                Some((OutputSection::RelRo, 0))
+            };
+            sections.push(InputSection {
+                name,
+                is_debug,
+                align,
+                size,
+                data,
+                relocs: vec![],
+                placement,
+            });
+
+            match sh_type {
+                SHT_SYMTAB => symtab = Some((offset, size, link, info)),
+                SHT_RELA => relas.push((offset, size, info)),
+                SHT_REL => return Err(link_error!("REL relocations are not supported")),
+                _ => (),
+            }
+        }
+
+        let (symtab_offset, symtab_size, strtab_index, first_global) =
+            symtab.ok_or_else(|| link_error!("input has no symbol table"))?;
+        let strtab_offset = file.u64(header(strtab_index as u64) + 24)?;
+        let symbols = (0..symtab_size / SYM_SIZE)
+            .map(|i| {
+                let sym = symtab_offset + i * SYM_SIZE;
+                Ok(InputSymbol {
+                    name: file.str(strtab_offset + file.u32(sym)? as u64)?,
+                    info: file.u8(sym + 4)?,
+                    other: file.u8(sym + 5)?,
+                    shndx: file.u16(sym + 6)?,
+                    value: file.u64(sym + 8)?,
+                    size: file.u64(sym + 16)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        for (offset, size, target) in relas {
+            let target = sections
+                .get_mut(target as usize)
+                .ok_or_else(|| link_error!("relocations for missing section {}", target))?;
+            for i in 0..size / RELA_SIZE {
+                let rela = offset + i * RELA_SIZE;
+                let info = file.u64(rela + 8)?;
+                let sym = (info >> 32) as usize;
+                if sym >= symbols.len() {
+                    return Err(link_error!("relocation symbol {} is out of bounds", sym));
+                }
+                target.relocs.push(Rela {
+                    offset: file.u64(rela)?,
+                    sym,
+                    kind: (info & 0xffff_ffff) as u32,
+                    addend: file.u64(rela + 16)? as i64,
+                });
+            }
+        }
+
+        Ok(Input {
+            sections,
+            symbols,
+            first_global,
+        })
+    }
+}
+
+/// An ELF string table under construction.
+struct StringTable {
+    bytes: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            bytes: vec![0],
+            offsets: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, s: &str) -> u32 {
+        if s.is_empty() {
+            return 0;
+        }
+        if let Some(offset) = self.offsets.get(s) {
+            return *offset;
+        }
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+        self.offsets.insert(s.to_owned(), offset);
+        offset
+    }
+}
+
+/// The hash function for the `DT_HASH` table, from the System V ABI.
+fn elf_hash(name: &str) -> u32 {
+    let mut h: u32 = 0;
+    for &c in name.as_bytes() {
+        h = (h << 4).wrapping_add(c as u32);
+        let g = h & 0xf000_0000;
+        if g != 0 {
+            h ^= g >> 24;
+        }
+        h &= !g;
+    }
+    h
+}
+
+fn align_to(n: u64, align: u64) -> u64 {
+    (n + align - 1) & !(align - 1)
+}
+
+struct DynamicReloc {
+    offset: u64,
+    kind: u32,
+    /// Index into the dynamic symbol table.
+    sym: u32,
+    addend: i64,
+}
+
+/// Where everything goes in the output.
+struct Layout {
+    hash: u64,
+    dynsym: u64,
+    dynstr: u64,
+    rela_dyn: u64,
+    ro_end: u64,
+    text: u64,
+    stubs: u64,
+    rx_end: u64,
+    relro: u64,
+    dynamic: u64,
+    got: u64,
+    relro_end: u64,
+    data: u64,
+    bss: u64,
+    bss_end: u64,
+}
+
+impl Layout {
+    fn base(&self, section: OutputSection) -> u64 {
+        match section {
+            OutputSection::Text => self.text,
+            OutputSection::RelRo => self.relro,
+            OutputSection::Data => self.data,
+            OutputSection::Bss => self.bss,
+        }
+    }
+}
+
+/// Link the relocatable object `obj` into a shared object.
+pub(crate) fn link_shared_object(obj: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut input = Input::parse(obj)?;
+
+    // Place each input section in its output section.
+    let mut sizes: HashMap<OutputSection, u64> = HashMap::new();
+    let mut aligns: HashMap<OutputSection, u64> = HashMap::new();
+    for section in input.sections.iter_mut() {
+        if let Some((output, ref mut offset)) = section.placement {
+            let size = sizes.entry(output).or_insert(0);
+            *offset = align_to(*size, section.align);
+            *size = *offset + section.size;
+            let align = aligns.entry(output).or_insert(1);
+            *align = (*align).max(section.align);
+        }
+    }
+    let size_of = |output| sizes.get(&output).cloned().unwrap_or(0);
+    let align_of = |output| aligns.get(&output).cloned().unwrap_or(1);
+    if align_of(OutputSection::Text) > PAGE_SIZE
+        || align_of(OutputSection::RelRo) > PAGE_SIZE
+        || align_of(OutputSection::Data) > PAGE_SIZE
+    {
+        return Err(link_error!(
+            "sections may not be aligned to more than a page"
+        ));
+    }
+
+    // Export every global symbol, and import every undefined one.
+    let dynsyms = input
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, sym)| sym.is_exported())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let dynsym_index = dynsyms
+        .iter()
+        .enumerate()
+        .map(|(dynsym, sym)| (*sym, dynsym as u32 + 1))
+        .collect::<HashMap<_, _>>();
+    let mut dynstr = StringTable::new();
+    let dynsym_names = dynsyms
+        .iter()
+        .map(|sym| dynstr.add(input.symbols[*sym].name))
+        .collect::<Vec<_>>();
+
+    // Find out which symbols need global offset table entries and stubs, and how many dynamic
+    // relocations there will be.
+    let mut got: Vec<usize> = vec![];
+    let mut got_index: HashMap<usize, u64> = HashMap::new();
+    let mut stubs: Vec<usize> = vec![];
+    let mut stub_index: HashMap<usize, u64> = HashMap::new();
+    let mut abs_relocs = 0;
+    for section in input.sections.iter() {
+        let output = match section.placement {
+            Some((output, _)) => output,
+            None => continue,
+        };
+        for rela in section.relocs.iter() {
+            let sym = &input.symbols[rela.sym];
+            let mut needs_got = false;
+            match rela.kind {
+                R_X86_64_NONE => (),
+                R_X86_64_64 => {
+                    if output == OutputSection::Text {
+                        return Err(link_error!(
+                            "absolute relocation against `{}` in code is not position-independent",
+                            sym.name
+                        ));
+                    }
+                    abs_relocs += 1;
+                }
+                R_X86_64_PC32 | R_X86_64_PLT32 => {
+                    if sym.is_undefined() {
+                        if output != OutputSection::Text {
+                            return Err(link_error!(
+                                "relative relocation against undefined symbol `{}` in data",
+                                sym.name
+                            ));
+                        }
+                        if !stub_index.contains_key(&rela.sym) {
+                            stub_index.insert(rela.sym, stubs.len() as u64);
+                            stubs.push(rela.sym);
+                        }
+                        needs_got = true;
+                    }
+                }
+                R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => {
+                    needs_got = true;
+                }
+                other => {
+                    return Err(link_error!(
+                        "unsupported relocation type {} against `{}` in {}",
+                        other,
+                        sym.name,
+                        section.name
+                    ))
+                }
+            }
+            if needs_got && !got_index.contains_key(&rela.sym) {
+                got_index.insert(rela.sym, got.len() as u64);
+                got.push(rela.sym);
+            }
+        }
+    }
+    let dynamic_relocs = abs_relocs + got.len() as u64;
+
+    let dynamic_entries = 10;
+    let nbucket = (dynsyms.len() as u64 + 1).max(1);
+    let nchain = dynsyms.len() as u64 + 1;
+
+    // Lay the sections out. File offsets are the same as addresses, which keeps every segment's
+    // offset congruent to its address modulo the page size.
+    let hash = align_to(EHDR_SIZE + PHNUM * PHDR_SIZE, 8);
+    let dynsym = align_to(hash + 4 * (2 + nbucket + nchain), 8);
+    let dynstr_addr = dynsym + SYM_SIZE * (dynsyms.len() as u64 + 1);
+    let rela_dyn = align_to(dynstr_addr + dynstr.bytes.len() as u64, 8);
+    let ro_end = rela_dyn + RELA_SIZE * dynamic_relocs;
+    let text = align_to(ro_end, PAGE_SIZE);
+    let stubs_addr = align_to(text + size_of(OutputSection::Text), STUB_SIZE);
+    let rx_end = stubs_addr + STUB_SIZE * stubs.len() as u64;
+    let relro = align_to(rx_end, PAGE_SIZE);
+    let dynamic = align_to(relro + size_of(OutputSection::RelRo), 8);
+    let got_addr = dynamic + DYN_SIZE * dynamic_entries;
+    let relro_end = got_addr + GOT_ENTRY_SIZE * got.len() as u64;
+    // start the writable data on a new page, so that the whole RELRO range can be protected
+    let data = align_to(relro_end, PAGE_SIZE);
+    let bss = align_to(
+        data + size_of(OutputSection::Data),
+        align_of(OutputSection::Bss),
+    );
+    let layout = Layout {
+        hash,
+        dynsym,
+        dynstr: dynstr_addr,
+        rela_dyn,
+        ro_end,
+        text,
+        stubs: stubs_addr,
+        rx_end,
+        relro,
+        dynamic,
+        got: got_addr,
+        relro_end,
+        data,
+        bss,
+        bss_end: bss + size_of(OutputSection::Bss),
+    };
+
+    let symbol_addr = |sym: &InputSymbol<'_>| -> Result<Option<u64>, Error> {
+        match sym.shndx {
+            SHN_UNDEF => Ok(None),
+            SHN_ABS => Ok(Some(sym.value)),
+            SHN_COMMON => Err(link_error!("common symbol `{}` is not supported", sym.name)),
+            shndx => match input.sections.get(shndx as usize) {
+                Some(InputSection {
+                    placement: Some((output, offset)),
+                    ..
+                }) => Ok(Some(layout.base(*output) + offset + sym.value)),
+                _ => Err(link_error!(
+                    "symbol `{}` is not in an allocated section",
+                    sym.name
+                )),
+            },
+        }
+    };
+
+    let mut out = vec![0u8; layout.bss as usize];
+    let mut relocs: Vec<DynamicReloc> = vec![];
+
+    // Copy the section contents and apply their relocations.
+    for section in input.sections.iter() {
+        let (output, offset) = match section.placement {
+            Some((OutputSection::Bss, _)) | None => continue,
+            Some(placement) => placement,
+        };
+        let base = layout.base(output) + offset;
+        out[base as usize..(base + section.size) as usize].copy_from_slice(section.data);
+
+        for rela in section.relocs.iter() {
+            if rela.offset + 4 > section.size {
+                return Err(link_error!(
+                    "relocation in {} is out of bounds",
+                    section.name
+                ));
+            }
+            let sym = &input.symbols[rela.sym];
+            let place = base + rela.offset;
+            let pc_relative = |target: u64| -> Result<u32, Error> {
+                let value = (target as i64)
+                    .wrapping_add(rela.addend)
+                    .wrapping_sub(place as i64);
+                if value < i32::min_value() as i64 || value > i32::max_value() as i64 {
+                    return Err(link_error!("relocation against `{}` overflows", sym.name));
+                }
+                Ok(value as i32 as u32)
+            };
+            match rela.kind {
+                R_X86_64_NONE => (),
+                R_X86_64_64 => {
+                    if rela.offset + 8 > section.size {
+                        return Err(link_error!(
+                            "relocation in {} is out of bounds",
+                            section.name
+                        ));
+                    }
+                    let reloc = match symbol_addr(sym)? {
+                        Some(addr) => {
+                            let value = (addr as i64).wrapping_add(rela.addend);
+                            // also fill in the place, for tools that read the file directly
+                            LittleEndian::write_u64(&mut out[place as usize..], value as u64);
+                            DynamicReloc {
+                                offset: place,
+                                kind: R_X86_64_RELATIVE,
+                                sym: 0,
+                                addend: value,
+                            }
+                        }
+                        None => DynamicReloc {
+                            offset: place,
+                            kind: R_X86_64_64,
+                            sym: dynsym_index[&rela.sym],
+                            addend: rela.addend,
+                        },
+                    };
+                    relocs.push(reloc);
+                }
+                R_X86_64_PC32 | R_X86_64_PLT32 => {
+                    let target = match symbol_addr(sym)? {
+                        Some(addr) => addr,
+                        None => layout.stubs + STUB_SIZE * stub_index[&rela.sym],
+                    };
+                    LittleEndian::write_u32(&mut out[place as usize..], pc_relative(target)?);
+                }
+                R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => {
+                    let target = layout.got + GOT_ENTRY_SIZE * got_index[&rela.sym];
+                    LittleEndian::write_u32(&mut out[place as usize..], pc_relative(target)?);
+                }
+                _ => unreachable!("relocation types were checked while scanning"),
+            }
+        }
+    }
+
+    // Fill in the global offset table, which the dynamic linker relocates at load time.
+    for (i, sym_index) in got.iter().enumerate() {
+        let slot = layout.got + GOT_ENTRY_SIZE * i as u64;
+        let sym = &input.symbols[*sym_index];
+        let reloc = match symbol_addr(sym)? {
+            Some(addr) => {
+                LittleEndian::write_u64(&mut out[slot as usize..], addr);
+                DynamicReloc {
+                    offset: slot,
+                    kind: R_X86_64_RELATIVE,
+                    sym: 0,
+                    addend: addr as i64,
+                }
+            }
+            None => DynamicReloc {
+                offset: slot,
+                kind: R_X86_64_GLOB_DAT,
+                sym: dynsym_index[sym_index],
+                addend: 0,
+            },
+        };
+        relocs.push(reloc);
+    }
+
+    // Each stub jumps through the global offset table entry for its symbol.
+    for (i, sym_index) in stubs.iter().enumerate() {
+        let stub = layout.stubs + STUB_SIZE * i as u64;
+        let slot = layout.got + GOT_ENTRY_SIZE * got_index[sym_index];
+        let rel = (slot as i64 - (stub as i64 + 6)) as i32 as u32;
+        let code = &mut out[stub as usize..(stub + STUB_SIZE) as usize];
+        code[0..2].copy_from_slice(&[0xff, 0x25]);
+        LittleEndian::write_u32(&mut code[2..6], rel);
+        code[6..8].copy_from_slice(&[0x66, 0x90]);
+    }
+
+    // Relative relocations go first, so `DT_RELACOUNT` can tell the dynamic linker about them.
+    relocs.sort_by_key(|reloc| (reloc.kind != R_X86_64_RELATIVE, reloc.offset));
+    let relative_relocs = relocs
+        .iter()
+        .filter(|reloc| reloc.kind == R_X86_64_RELATIVE)
+        .count() as u64;
+    for (i, reloc) in relocs.iter().enumerate() {
+        let entry = &mut out[(layout.rela_dyn + RELA_SIZE * i as u64) as usize..];
+        LittleEndian::write_u64(&mut entry[0..8], reloc.offset);
+        LittleEndian::write_u64(
+            &mut entry[8..16],
+            ((reloc.sym as u64) << 32) | reloc.kind as u64,
+        );
+        LittleEndian::write_i64(&mut entry[16..24], reloc.addend);
+    }
+
+    // The dynamic symbol table, and its hash table.
+    let mut buckets = vec![0u32; nbucket as usize];
+    let mut chains = vec![0u32; nchain as usize];
+    for (i, sym_index) in dynsyms.iter().enumerate() {
+        let sym = &input.symbols[*sym_index];
+        let dynsym_entry = i as u32 + 1;
+        let (shndx, value) = match symbol_addr(sym)? {
+            None => (SHN_UNDEF, 0),
+            Some(addr) => (output_shndx(&input, sym), addr),
+        };
+        write_symbol(
+            &mut out[(layout.dynsym + SYM_SIZE * dynsym_entry as u64) as usize..],
+            dynsym_names[i],
+            sym.info,
+            sym.other,
+            shndx,
+            value,
+            sym.size,
+        );
+
+        let bucket = (elf_hash(sym.name) as u64 % nbucket) as usize;
+        chains[dynsym_entry as usize] = buckets[bucket];
+        buckets[bucket] = dynsym_entry;
+    }
+    {
+        let table = &mut out[layout.hash as usize..];
+        LittleEndian::write_u32(&mut table[0..4], nbucket as u32);
+        LittleEndian::write_u32(&mut table[4..8], nchain as u32);
+        for (i, entry) in buckets.iter().chain(chains.iter()).enumerate() {
+            LittleEndian::write_u32(&mut table[8 + 4 * i..], *entry);
+        }
+    }
+    out[layout.dynstr as usize..layout.dynstr as usize + dynstr.bytes.len()]
+        .copy_from_slice(&dynstr.bytes);
+
+    let dynamic_tags = [
+        (DT_HASH, layout.hash),
+        (DT_STRTAB, layout.dynstr),
+        (DT_SYMTAB, layout.dynsym),
+        (DT_STRSZ, dynstr.bytes.len() as u64),
+        (DT_SYMENT, SYM_SIZE),
+        (DT_RELA, layout.rela_dyn),
+        (DT_RELASZ, RELA_SIZE * relocs.len() as u64),
+        (DT_RELAENT, RELA_SIZE),
+        (DT_RELACOUNT, relative_relocs),
+        (DT_NULL, 0),
+    ];
+    assert_eq!(dynamic_tags.len() as u64, dynamic_entries);
+    for (i, (tag, value)) in dynamic_tags.iter().enumerate() {
+        let entry = &mut out[(layout.dynamic + DYN_SIZE * i as u64) as usize..];
+        LittleEndian::write_u64(&mut entry[0..8], *tag);
+        LittleEndian::write_u64(&mut entry[8..16], *value);
+    }
+
+    // The static symbol table keeps the input's order, so locals stay ahead of globals.
+    let mut strtab = StringTable::new();
+    let symtab_offset = align_to(out.len() as u64, 8);
+    out.resize(symtab_offset as usize, 0);
+    for sym in input.symbols.iter() {
+        let (shndx, value) = match sym.shndx {
+            SHN_UNDEF => (SHN_UNDEF, 0),
+            SHN_ABS => (SHN_ABS, sym.value),
+            _ => match symbol_addr(sym) {
+                Ok(Some(addr)) => (output_shndx(&input, sym), addr),
+                // symbols in sections that are not loaded, such as debug information
+                _ => (SHN_ABS, 0),
+            },
+        };
+        let mut entry = [0u8; SYM_SIZE as usize];
+        write_symbol(
+            &mut entry,
+            strtab.add(sym.name),
+            sym.info,
+            sym.other,
+            shndx,
+            value,
+            sym.size,
+        );
+        out.extend_from_slice(&entry);
+    }
+    let strtab_offset = out.len() as u64;
+    out.extend_from_slice(&strtab.bytes);
+
+    // Debug sections can only refer to addresses in the loaded sections, so their relocations
+    // become relative relocations.
+    let mut debug_sections: Vec<(String, SectionHeader)> = vec![];
+    for section in input.sections.iter().filter(|section| section.is_debug) {
+        let offset = out.len() as u64;
+        out.extend_from_slice(section.data);
+        let mut relative = vec![];
+        for rela in section.relocs.iter() {
+            let sym = &input.symbols[rela.sym];
+            if rela.offset + 8 > section.size {
+                return Err(link_error!(
+                    "relocation in {} is out of bounds",
+                    section.name
+                ));
+            }
+            match (rela.kind, symbol_addr(sym)?) {
+                (R_X86_64_64, Some(addr)) => {
+                    let value = (addr as i64).wrapping_add(rela.addend);
+                    let place = offset + rela.offset;
+                    LittleEndian::write_u64(&mut out[place as usize..], value as u64);
+                    relative.push((rela.offset, value));
+                }
+                (kind, _) => {
+                    return Err(link_error!(
+                        "unsupported relocation type {} against `{}` in {}",
+                        kind,
+                        sym.name,
+                        section.name
+                    ))
+                }
+            }
+        }
+        let target = SHNUM as u32 + debug_sections.len() as u32;
+        debug_sections.push((
+            section.name.to_owned(),
+            SectionHeader {
+                sh_type: SHT_PROGBITS,
+                offset: Some(offset),
+                size: section.size,
+                align: 1,
+                ..SectionHeader::default()
+            },
+        ));
+        if relative.is_empty() {
+            continue;
+        }
+
+        let rela_offset = align_to(out.len() as u64, 8);
+        out.resize(rela_offset as usize, 0);
+        for (place, value) in relative.iter() {
+            let mut entry = [0u8; RELA_SIZE as usize];
+            LittleEndian::write_u64(&mut entry[0..8], *place);
+            LittleEndian::write_u64(&mut entry[8..16], R_X86_64_RELATIVE as u64);
+            LittleEndian::write_i64(&mut entry[16..24], *value);
+            out.extend_from_slice(&entry);
+        }
+        debug_sections.push((
+            format!(".rela{}", section.name),
+            SectionHeader {
+                sh_type: SHT_RELA,
+                offset: Some(rela_offset),
+                size: RELA_SIZE * relative.len() as u64,
+                link: SHNDX_SYMTAB as u32,
+                info: target,
+                align: 8,
+                entsize: RELA_SIZE,
+                ..SectionHeader::default()
+            },
+        ));
+    }
+
+    let mut shstrtab = StringTable::new();
+    let section_names = [
+        "",
+        ".hash",
+        ".dynsym",
+        ".dynstr",
+        ".rela.dyn",
+        ".text",
+        ".plt.got",
+        ".data.rel.ro",
+        ".dynamic",
+        ".got",
+        ".data",
+        ".bss",
+        ".symtab",
+        ".strtab",
+        ".shstrtab",
+    ]
+    .iter()
+    .cloned()
+    .chain(debug_sections.iter().map(|(name, _)| name.as_str()))
+    .map(|name| shstrtab.add(name))
+    .collect::<Vec<_>>();
+    let shstrtab_offset = out.len() as u64;
+    out.extend_from_slice(&shstrtab.bytes);
+
+    let shoff = align_to(out.len() as u64, 8);
+    out.resize(shoff as usize, 0);
+    let alloc = SHF_ALLOC;
+    let alloc_write = SHF_ALLOC | SHF_WRITE;
+    let alloc_exec = SHF_ALLOC | SHF_EXECINSTR;
+    let section_headers: [SectionHeader; SHNUM as usize] = [
+        SectionHeader::default(),
+        SectionHeader {
+            sh_type: SHT_HASH,
+            flags: alloc,
+            addr: layout.hash,
+            size: layout.dynsym - layout.hash,
+            link: SHNDX_DYNSYM as u32,
+            align: 8,
+            entsize: 4,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_DYNSYM,
+            flags: alloc,
+            addr: layout.dynsym,
+            size: layout.dynstr - layout.dynsym,
+            link: SHNDX_DYNSTR as u32,
+            // every dynamic symbol is global
+            info: 1,
+            align: 8,
+            entsize: SYM_SIZE,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_STRTAB,
+            flags: alloc,
+            addr: layout.dynstr,
+            size: dynstr.bytes.len() as u64,
+            align: 1,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_RELA,
+            flags: alloc,
+            addr: layout.rela_dyn,
+            size: layout.ro_end - layout.rela_dyn,
+            link: SHNDX_DYNSYM as u32,
+            align: 8,
+            entsize: RELA_SIZE,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_PROGBITS,
+            flags: alloc_exec,
+            addr: layout.text,
+            size: size_of(OutputSection::Text),
+            align: align_of(OutputSection::Text),
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_PROGBITS,
+            flags: alloc_exec,
+            addr: layout.stubs,
+            size: layout.rx_end - layout.stubs,
+            align: STUB_SIZE,
+            entsize: STUB_SIZE,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_PROGBITS,
+            flags: alloc_write,
+            addr: layout.relro,
+            size: size_of(OutputSection::RelRo),
+            align: align_of(OutputSection::RelRo),
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_DYNAMIC,
+            flags: alloc_write,
+            addr: layout.dynamic,
+            size: DYN_SIZE * dynamic_entries,
+            link: SHNDX_DYNSTR as u32,
+            align: 8,
+            entsize: DYN_SIZE,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_PROGBITS,
+            flags: alloc_write,
+            addr: layout.got,
+            size: layout.relro_end - layout.got,
+            align: 8,
+            entsize: GOT_ENTRY_SIZE,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_PROGBITS,
+            flags: alloc_write,
+            addr: layout.data,
+            size: size_of(OutputSection::Data),
+            align: align_of(OutputSection::Data),
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_NOBITS,
+            flags: alloc_write,
+            addr: layout.bss,
+            size: layout.bss_end - layout.bss,
+            align: align_of(OutputSection::Bss),
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_SYMTAB,
+            offset: Some(symtab_offset),
+            size: strtab_offset - symtab_offset,
+            link: SHNDX_STRTAB as u32,
+            info: input.first_global,
+            align: 8,
+            entsize: SYM_SIZE,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_STRTAB,
+            offset: Some(strtab_offset),
+            size: strtab.bytes.len() as u64,
+            align: 1,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_type: SHT_STRTAB,
+            offset: Some(shstrtab_offset),
+            size: shstrtab.bytes.len() as u64,
+            align: 1,
+            ..SectionHeader::default()
+        },
+    ];
+    let headers = section_headers
+        .iter()
+        .chain(debug_sections.iter().map(|(_, header)| header));
+    for (header, name) in headers.zip(section_names) {
+        let mut entry = [0u8; SHDR_SIZE as usize];
+        header.write(&mut entry, name);
+        out.extend_from_slice(&entry);
+    }
+
+    // Finally, the ELF header and program headers at the start of the file.
+    {
+        let ehdr = &mut out[0..EHDR_SIZE as usize];
+        ehdr[0..4].copy_from_slice(b"\x7fELF");
+        ehdr[4] = ELFCLASS64;
+        ehdr[5] = ELFDATA2LSB;
+        ehdr[6] = EV_CURRENT;
+        LittleEndian::write_u16(&mut ehdr[16..18], ET_DYN);
+        LittleEndian::write_u16(&mut ehdr[18..20], EM_X86_64);
+        LittleEndian::write_u32(&mut ehdr[20..24], EV_CURRENT as u32);
+        LittleEndian::write_u64(&mut ehdr[32..40], EHDR_SIZE);
+        LittleEndian::write_u64(&mut ehdr[40..48], shoff);
+        LittleEndian::write_u16(&mut ehdr[52..54], EHDR_SIZE as u16);
+        LittleEndian::write_u16(&mut ehdr[54..56], PHDR_SIZE as u16);
+        LittleEndian::write_u16(&mut ehdr[56..58], PHNUM as u16);
+        LittleEndian::write_u16(&mut ehdr[58..60], SHDR_SIZE as u16);
+        LittleEndian::write_u16(&mut ehdr[60..62], SHNUM + debug_sections.len() as u16);
+        LittleEndian::write_u16(&mut ehdr[62..64], SHNDX_SHSTRTAB);
+    }
+    let program_headers = [
+        // (type, flags, address, file size, memory size, alignment)
+        (PT_LOAD, PF_R, 0, layout.ro_end, layout.ro_end, PAGE_SIZE),
+        (
+            PT_LOAD,
+            PF_R | PF_X,
+            layout.text,
+            layout.rx_end - layout.text,
+            layout.rx_end - layout.text,
+            PAGE_SIZE,
+        ),
+        (
+            PT_LOAD,
+            PF_R | PF_W,
+            layout.relro,
+            layout.bss - layout.relro,
+            layout.bss_end - layout.relro,
+            PAGE_SIZE,
+        ),
+        (
+            PT_DYNAMIC,
+            PF_R | PF_W,
+            layout.dynamic,
+            DYN_SIZE * dynamic_entries,
+            DYN_SIZE * dynamic_entries,
+            8,
+        ),
+        (
+            PT_GNU_RELRO,
+            PF_R,
+            layout.relro,
+            layout.relro_end - layout.relro,
+            layout.relro_end - layout.relro,
+            1,
+        ),
+        (PT_GNU_STACK, PF_R | PF_W, 0, 0, 0, 16),
+    ];
+    assert_eq!(program_headers.len() as u64, PHNUM);
+    for (i, (p_type, flags, addr, filesz, memsz, align)) in program_headers.iter().enumerate() {
+        let phdr = &mut out[(EHDR_SIZE + PHDR_SIZE * i as u64) as usize..];
+        LittleEndian::write_u32(&mut phdr[0..4], *p_type);
+        LittleEndian::write_u32(&mut phdr[4..8], *flags);
+        // file offsets are the same as addresses
+        LittleEndian::write_u64(&mut phdr[8..16], *addr);
+        LittleEndian::write_u64(&mut phdr[16..24], *addr);
+        LittleEndian::write_u64(&mut phdr[24..32], *addr);
+        LittleEndian::write_u64(&mut phdr[32..40], *filesz);
+        LittleEndian::write_u64(&mut phdr[40..48], *memsz);
+        LittleEndian::write_u64(&mut phdr[48..56], *align);
+    }
+
+    Ok(out)
+}
+
+/// The output section index for a symbol defined in the input.
+fn output_shndx(input: &Input<'_>, sym: &InputSymbol<'_>) -> u16 {
+    match sym.shndx {
+        SHN_ABS => SHN_ABS,
+        shndx => match input.sections[shndx as usize].placement {
+            Some((output, _)) => output.shndx(),
+            None => SHN_ABS,
+        },
+    }
+}
+
+fn write_symbol(
+    entry: &mut [u8],
+    name: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+) {
+    LittleEndian::write_u32(&mut entry[0..4], name);
+    entry[4] = info;
+    entry[5] = other;
+    LittleEndian::write_u16(&mut entry[6..8], shndx);
+    LittleEndian::write_u64(&mut entry[8..16], value);
+    LittleEndian::write_u64(&mut entry[16..24], size);
+}
+
+#[derive(Default)]
+struct SectionHeader {
+    sh_type: u32,
+    flags: u64,
+    addr: u64,
+    /// The file offset, if it is not the same as the address.
+    offset: Option<u64>,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, entry: &mut [u8], name: u32) {
+        LittleEndian::write_u32(&mut entry[0..4], name);
+        LittleEndian::write_u32(&mut entry[4..8], self.sh_type);
+        LittleEndian::write_u64(&mut entry[8..16], self.flags);
+        LittleEndian::write_u64(&mut entry[16..24], self.addr);
+        LittleEndian::write_u64(&mut entry[24..32], self.offset.unwrap_or(self.addr));
+        LittleEndian::write_u64(&mut entry[32..40], self.size);
+        LittleEndian::write_u32(&mut entry[40..44], self.link);
+        LittleEndian::write_u32(&mut entry[44..48], self.info);
+        LittleEndian::write_u64(&mut entry[48..56], self.align);
+        LittleEndian::write_u64(&mut entry[56..64], self.entsize);
+    }
+}
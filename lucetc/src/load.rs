@@ -3,7 +3,7 @@ use crate::signature::{self, PublicKey};
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
-use wabt::{wat2wasm, ErrorKind};
+use wabt::{wat2wasm_with_features, ErrorKind, Features};
 
 pub fn read_module<P: AsRef<Path>>(
     path: P,
@@ -31,7 +31,7 @@ pub fn read_bytes(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
     let converted = if wasm_preamble(&bytes) {
         bytes
     } else {
-        wat2wasm(bytes).map_err(|err| {
+        wat2wasm_with_features(bytes, wat_features()).map_err(|err| {
             let mut result = String::from("wat2wasm error: ");
             result.push_str(&format!("{}", err));
             match unsafe { std::mem::transmute::<wabt::Error, wabt::ErrorKind>(err) } {
@@ -52,6 +52,18 @@ pub fn read_bytes(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
     Ok(converted)
 }
 
+/// The WebAssembly proposals that may be used in text format modules.
+///
+/// These should match the proposals `lucetc` accepts when validating binary modules.
+fn wat_features() -> Features {
+    let mut features = Features::new();
+    features.enable_multi_value();
+    features.enable_reference_types();
+    features.enable_bulk_memory();
+    features.enable_simd();
+    features
+}
+
 pub fn read_to_u8s<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Error> {
     let mut buf: Vec<u8> = Vec::new();
     let mut file = File::open(path)?;
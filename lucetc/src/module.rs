@@ -92,6 +92,15 @@ pub struct ModuleInfo<'a> {
 
     /// Data initializers: local only
     pub data_initializers: HashMap<MemoryIndex, Vec<DataInitializer<'a>>>,
+
+    /// Provided by `declare_passive_element`
+    pub passive_elements: HashMap<ElemIndex, Box<[UniqueFuncIndex]>>,
+
+    /// Provided by `declare_passive_data`
+    pub passive_data: HashMap<DataIndex, &'a [u8]>,
+
+    /// Whether any signature, global, or function body uses the SIMD proposal
+    pub uses_simd: bool,
 }
 
 impl<'a> ModuleInfo<'a> {
@@ -114,6 +123,9 @@ impl<'a> ModuleInfo<'a> {
             function_bodies: HashMap::new(),
             table_elems: HashMap::new(),
             data_initializers: HashMap::new(),
+            passive_elements: HashMap::new(),
+            passive_data: HashMap::new(),
+            uses_simd: false,
         }
     }
 
@@ -157,6 +169,15 @@ impl<'a> ModuleEnvironment<'a> for ModuleInfo<'a> {
         wasm_func_type: &FuncType,
         mut sig: ir::Signature,
     ) -> WasmResult<()> {
+        if wasm_func_type
+            .params
+            .iter()
+            .chain(wasm_func_type.returns.iter())
+            .any(|ty| *ty == wasmparser::Type::V128)
+        {
+            self.uses_simd = true;
+        }
+
         sig.params.insert(
             0,
             ir::AbiParam::special(NATIVE_POINTER, ir::ArgumentPurpose::VMContext),
@@ -215,6 +236,7 @@ impl<'a> ModuleEnvironment<'a> for ModuleInfo<'a> {
             self.imported_globals.len(),
             "import globals are declared first"
         );
+        self.uses_simd |= global.ty.is_vector();
         self.globals.push(Exportable::new(global));
         self.imported_globals.push((module, field));
         Ok(())
@@ -276,6 +298,7 @@ impl<'a> ModuleEnvironment<'a> for ModuleInfo<'a> {
     }
 
     fn declare_global(&mut self, global: Global) -> WasmResult<()> {
+        self.uses_simd |= global.ty.is_vector();
         self.globals.push(Exportable::new(global));
         Ok(())
     }
@@ -345,6 +368,9 @@ impl<'a> ModuleEnvironment<'a> for ModuleInfo<'a> {
     ) -> WasmResult<()> {
         let func_index =
             UniqueFuncIndex::new(self.imported_funcs.len() + self.function_bodies.len());
+        if !self.uses_simd {
+            self.uses_simd = body_uses_simd(body_bytes)?;
+        }
         self.function_bodies
             .insert(func_index, (body_bytes, body_offset));
         Ok(())
@@ -423,13 +449,51 @@ impl<'a> ModuleEnvironment<'a> for ModuleInfo<'a> {
 
     fn declare_passive_element(
         &mut self,
-        _index: ElemIndex,
-        _elements: Box<[FuncIndex]>,
+        index: ElemIndex,
+        elements: Box<[FuncIndex]>,
     ) -> WasmResult<()> {
-        unimplemented!();
+        let elements_vec: Vec<FuncIndex> = elements.into();
+        let uniquified_elements = elements_vec
+            .into_iter()
+            .map(|fn_idx| {
+                *self
+                    .function_mapping
+                    .get(fn_idx)
+                    .expect("function indices are valid")
+            })
+            .collect();
+        self.passive_elements.insert(index, uniquified_elements);
+        Ok(())
+    }
+
+    fn declare_passive_data(&mut self, data_index: DataIndex, data: &'a [u8]) -> WasmResult<()> {
+        self.passive_data.insert(data_index, data);
+        Ok(())
     }
+}
+
+/// The prefix byte shared by the opcodes of all SIMD instructions.
+const SIMD_PREFIX: u8 = 0xfd;
 
-    fn declare_passive_data(&mut self, _data_index: DataIndex, _data: &'a [u8]) -> WasmResult<()> {
-        unimplemented!();
+/// Check whether a function body declares `v128` locals or contains SIMD instructions.
+fn body_uses_simd(body_bytes: &[u8]) -> WasmResult<bool> {
+    let body = wasmparser::FunctionBody::new(0, body_bytes);
+
+    let mut locals = body.get_locals_reader()?;
+    for _ in 0..locals.get_count() {
+        let (_, ty) = locals.read()?;
+        if ty == wasmparser::Type::V128 {
+            return Ok(true);
+        }
+    }
+
+    // positions are relative to the start of the body, since that's the offset it was created with
+    let mut operators = body.get_operators_reader()?;
+    while !operators.eof() {
+        if body_bytes[operators.original_position()] == SIMD_PREFIX {
+            return Ok(true);
+        }
+        operators.read()?;
     }
+    Ok(false)
 }
@@ -3,7 +3,6 @@ use crate::name::Name;
 use cranelift_codegen::{ir, isa};
 use cranelift_object::ObjectProduct;
 use object::write::Object;
-use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -11,12 +10,13 @@ use std::path::Path;
 pub(crate) const FUNCTION_MANIFEST_SYM: &str = "lucet_function_manifest";
 
 pub struct CraneliftFuncs {
-    funcs: HashMap<Name, ir::Function>,
+    /// Functions in the order they are written out.
+    funcs: Vec<(Name, ir::Function)>,
     isa: Box<dyn isa::TargetIsa>,
 }
 
 impl CraneliftFuncs {
-    pub fn new(funcs: HashMap<Name, ir::Function>, isa: Box<dyn isa::TargetIsa>) -> Self {
+    pub fn new(funcs: Vec<(Name, ir::Function)>, isa: Box<dyn isa::TargetIsa>) -> Self {
         Self { funcs, isa }
     }
     /// This outputs a .clif file
@@ -54,11 +54,14 @@ impl ObjectFile {
             Error::Input(message);
         });
         let mut file = File::create(path)?;
-        let bytes = self
-            .object
-            .write()
-            .map_err(|source| Error::ObjectArtifact(source, "Write error".to_owned()))?;
-        file.write_all(&bytes)?;
+        file.write_all(&self.bytes()?)?;
         Ok(())
     }
+
+    /// The contents of the object file.
+    pub fn bytes(&self) -> Result<Vec<u8>, Error> {
+        self.object
+            .write()
+            .map_err(|source| Error::ObjectArtifact(source, "Write error".to_owned()))
+    }
 }
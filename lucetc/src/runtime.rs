@@ -1,5 +1,5 @@
-use cranelift_codegen::ir::{types, AbiParam, Signature};
-use cranelift_codegen::isa::TargetFrontendConfig;
+use cranelift_codegen::ir::{types, AbiParam, Signature, Type};
+use cranelift_codegen::isa::{CallConv, TargetFrontendConfig};
 use std::collections::HashMap;
 use wasmparser::FuncType;
 
@@ -7,6 +7,20 @@ use wasmparser::FuncType;
 pub enum RuntimeFunc {
     MemSize,
     MemGrow,
+    OutOfFuel,
+    TableGet,
+    TableSet,
+    TableSize,
+    TableGrow,
+    TableFill,
+    RefFunc,
+    MemoryCopy,
+    MemoryFill,
+    MemoryInit,
+    DataDrop,
+    TableCopy,
+    TableInit,
+    ElemDrop,
 }
 
 pub struct RuntimeFuncType {
@@ -54,6 +68,171 @@ impl Runtime {
                 },
             },
         );
+        functions.insert(
+            RuntimeFunc::OutOfFuel,
+            RuntimeFuncType {
+                name: "lucet_vmctx_out_of_fuel".to_owned(),
+                signature: Signature {
+                    params: vec![],
+                    returns: vec![],
+                    call_conv: target.default_call_conv,
+                },
+                wasm_func_type: FuncType {
+                    params: vec![].into_boxed_slice(),
+                    returns: vec![].into_boxed_slice(),
+                },
+            },
+        );
+
+        // Reference values are passed as opaque `r64`s: a function pointer for `funcref`s, or a
+        // host value for `externref`s. Null references are 0.
+        let i32_ty = (types::I32, wasmparser::Type::I32);
+        let ref_ty = (types::R64, wasmparser::Type::ExternRef);
+        let call_conv = target.default_call_conv;
+        functions.insert(
+            RuntimeFunc::TableGet,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_get",
+                &[i32_ty, i32_ty], // table index, element index
+                &[ref_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::TableSet,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_set",
+                &[i32_ty, i32_ty, ref_ty], // table index, element index, value
+                &[],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::TableSize,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_size",
+                &[i32_ty], // table index
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::TableGrow,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_grow",
+                &[i32_ty, i32_ty, ref_ty], // table index, elements to grow, initial value
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::TableFill,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_fill",
+                &[i32_ty, i32_ty, ref_ty, i32_ty], // table index, start, value, length
+                &[],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::RefFunc,
+            RuntimeFuncType::new(
+                "lucet_vmctx_ref_func",
+                &[i32_ty], // function index
+                &[ref_ty],
+                call_conv,
+            ),
+        );
+
+        // Bulk memory operations check their bounds in the runtime, and return a nonzero status
+        // if they are out of bounds so that the guest can trap.
+        functions.insert(
+            RuntimeFunc::MemoryCopy,
+            RuntimeFuncType::new(
+                "lucet_vmctx_memory_copy",
+                &[i32_ty, i32_ty, i32_ty], // destination, source, length
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::MemoryFill,
+            RuntimeFuncType::new(
+                "lucet_vmctx_memory_fill",
+                &[i32_ty, i32_ty, i32_ty], // destination, value, length
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::MemoryInit,
+            RuntimeFuncType::new(
+                "lucet_vmctx_memory_init",
+                &[i32_ty, i32_ty, i32_ty, i32_ty], // segment, destination, source, length
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::DataDrop,
+            RuntimeFuncType::new(
+                "lucet_vmctx_data_drop",
+                &[i32_ty], // segment
+                &[],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::TableCopy,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_copy",
+                // destination table, source table, destination, source, length
+                &[i32_ty, i32_ty, i32_ty, i32_ty, i32_ty],
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::TableInit,
+            RuntimeFuncType::new(
+                "lucet_vmctx_table_init",
+                // table, segment, destination, source, length
+                &[i32_ty, i32_ty, i32_ty, i32_ty, i32_ty],
+                &[i32_ty],
+                call_conv,
+            ),
+        );
+        functions.insert(
+            RuntimeFunc::ElemDrop,
+            RuntimeFuncType::new(
+                "lucet_vmctx_elem_drop",
+                &[i32_ty], // segment
+                &[],
+                call_conv,
+            ),
+        );
         Self { functions }
     }
 }
+
+impl RuntimeFuncType {
+    fn new(
+        name: &str,
+        params: &[(Type, wasmparser::Type)],
+        returns: &[(Type, wasmparser::Type)],
+        call_conv: CallConv,
+    ) -> Self {
+        RuntimeFuncType {
+            name: name.to_owned(),
+            signature: Signature {
+                params: params.iter().map(|(ty, _)| AbiParam::new(*ty)).collect(),
+                returns: returns.iter().map(|(ty, _)| AbiParam::new(*ty)).collect(),
+                call_conv,
+            },
+            wasm_func_type: FuncType {
+                params: params.iter().map(|(_, ty)| *ty).collect(),
+                returns: returns.iter().map(|(_, ty)| *ty).collect(),
+            },
+        }
+    }
+}
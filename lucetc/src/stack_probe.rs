@@ -16,13 +16,14 @@ use cranelift_codegen::{
     isa::CallConv,
 };
 use cranelift_module::{Backend as ClifBackend, Linkage, Module as ClifModule, TrapSite};
+use target_lexicon::{Architecture, Triple};
 use wasmparser::FuncType;
 
 /// Stack probe symbol name
 pub const STACK_PROBE_SYM: &str = "lucet_probestack";
 
-/// The binary of the stack probe.
-pub(crate) const STACK_PROBE_BINARY: &[u8] = &[
+/// The binary of the x86-64 stack probe, which takes the frame size in `%rax`.
+const STACK_PROBE_BINARY_X86_64: &[u8] = &[
     // 49 89 c3                     mov    %rax,%r11
     // 48 81 ec 00 10 00 00         sub    $0x1000,%rsp
     // 48 85 64 24 08               test   %rsp,0x8(%rsp)
@@ -38,19 +39,49 @@ pub(crate) const STACK_PROBE_BINARY: &[u8] = &[
     0x29, 0xdc, 0x48, 0x85, 0x64, 0x24, 0x08, 0x48, 0x01, 0xc4, 0xc3,
 ];
 
-pub fn trap_sites() -> Vec<TrapSite> {
-    vec![
-        TrapSite {
-            offset: 10, /* test %rsp,0x8(%rsp) */
-            srcloc: ir::SourceLoc::default(),
-            code: ir::TrapCode::StackOverflow,
-        },
-        TrapSite {
-            offset: 34, /* test %rsp,0x8(%rsp) */
+/// The binary of the AArch64 stack probe, which takes the frame size in `x16`.
+///
+/// Unlike the x86-64 probe, this one does not move the stack pointer; it only touches each page
+/// of the new frame, lowest address last.
+const STACK_PROBE_BINARY_AARCH64: &[u8] = &[
+    // f1 03 00 91                  mov    x17, sp
+    // 31 06 40 d1                  sub    x17, x17, #1, lsl #12
+    // 3f 02 40 f9                  ldr    xzr, [x17]
+    // 10 06 40 d1                  sub    x16, x16, #1, lsl #12
+    // 1f 06 40 f1                  cmp    x16, #1, lsl #12
+    // 88 ff ff 54                  b.hi   <lucet_probestack+0x4>
+    // 31 02 10 cb                  sub    x17, x17, x16
+    // 3f 02 40 f9                  ldr    xzr, [x17]
+    // c0 03 5f d6                  ret
+    0xf1, 0x03, 0x00, 0x91, 0x31, 0x06, 0x40, 0xd1, 0x3f, 0x02, 0x40, 0xf9, 0x10, 0x06, 0x40, 0xd1,
+    0x1f, 0x06, 0x40, 0xf1, 0x88, 0xff, 0xff, 0x54, 0x31, 0x02, 0x10, 0xcb, 0x3f, 0x02, 0x40, 0xf9,
+    0xc0, 0x03, 0x5f, 0xd6,
+];
+
+/// The binary of the stack probe for the given target.
+pub(crate) fn binary(target: &Triple) -> &'static [u8] {
+    match target.architecture {
+        Architecture::Aarch64(_) => STACK_PROBE_BINARY_AARCH64,
+        _ => STACK_PROBE_BINARY_X86_64,
+    }
+}
+
+pub fn trap_sites(target: &Triple) -> Vec<TrapSite> {
+    let offsets: &[u32] = match target.architecture {
+        Architecture::Aarch64(_) => &[8 /* ldr xzr, [x17] */, 28 /* ldr xzr, [x17] */],
+        _ => &[
+            10, /* test %rsp,0x8(%rsp) */
+            34, /* test %rsp,0x8(%rsp) */
+        ],
+    };
+    offsets
+        .iter()
+        .map(|&offset| TrapSite {
+            offset,
             srcloc: ir::SourceLoc::default(),
             code: ir::TrapCode::StackOverflow,
-        },
-    ]
+        })
+        .collect()
 }
 
 pub fn declare<'a, B: ClifBackend>(
@@ -69,8 +100,109 @@ pub fn declare<'a, B: ClifBackend>(
             Signature {
                 params: vec![],
                 returns: vec![AbiParam::new(types::I32)],
-                call_conv: CallConv::SystemV, // the stack probe function is very specific to x86_64, and possibly to SystemV ABI platforms?
+                call_conv: CallConv::SystemV, // the stack probe function is very specific to its architecture, and possibly to SystemV ABI platforms?
             },
         )
         .unwrap())
 }
+
+#[cfg(all(test, target_arch = "aarch64"))]
+mod tests {
+    use super::{trap_sites, STACK_PROBE_BINARY_AARCH64};
+    use libc::{c_char, c_int, c_void, siginfo_t};
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use target_lexicon::Triple;
+
+    extern "C" {
+        fn __clear_cache(start: *mut c_char, end: *mut c_char);
+    }
+
+    /// `mov x16, x0`, so that the probe can be called with the frame size as its first argument.
+    const MOV_X16_X0: [u8; 4] = [0xf0, 0x03, 0x00, 0xaa];
+
+    /// Map the probe as executable code, behind an instruction that moves its argument into `x16`,
+    /// and return the address of the probe itself along with the function to call it through.
+    ///
+    /// The mapping is never freed.
+    fn map_probe() -> (usize, extern "C" fn(u64)) {
+        let code: Vec<u8> = MOV_X16_X0
+            .iter()
+            .chain(STACK_PROBE_BINARY_AARCH64)
+            .cloned()
+            .collect();
+        unsafe {
+            let mem = libc::mmap(
+                ptr::null_mut(),
+                code.len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(mem, libc::MAP_FAILED);
+            ptr::copy_nonoverlapping(code.as_ptr(), mem as *mut u8, code.len());
+            __clear_cache(mem as *mut c_char, (mem as *mut c_char).add(code.len()));
+            assert_eq!(
+                libc::mprotect(mem, code.len(), libc::PROT_READ | libc::PROT_EXEC),
+                0
+            );
+            (
+                mem as usize + MOV_X16_X0.len(),
+                std::mem::transmute::<*mut c_void, extern "C" fn(u64)>(mem),
+            )
+        }
+    }
+
+    #[test]
+    fn probe_takes_frame_size_in_x16() {
+        let (_, probe) = map_probe();
+        // the probe leaves the stack pointer alone, so returning at all means it did not clobber
+        // the caller's frame
+        for &frame_size in &[0x1000, 0x2010, 0x10000] {
+            probe(frame_size);
+        }
+    }
+
+    static PROBE_START: AtomicUsize = AtomicUsize::new(0);
+
+    /// Exit with the index of the instruction in the probe that faulted.
+    extern "C" fn exit_with_fault_offset(_: c_int, _: *mut siginfo_t, ucontext: *mut c_void) {
+        let pc = unsafe { (*(ucontext as *const libc::ucontext_t)).uc_mcontext.pc } as usize;
+        let offset = pc.wrapping_sub(PROBE_START.load(Ordering::SeqCst));
+        unsafe { libc::_exit((offset / 4).min(254) as c_int) };
+    }
+
+    #[test]
+    fn probe_faults_at_trap_sites() {
+        let (probe_start, probe) = map_probe();
+        PROBE_START.store(probe_start, Ordering::SeqCst);
+
+        // probing a frame much larger than the test thread's stack runs into its guard page, in
+        // a child process so that the fault does not take the test harness down with it
+        let child = unsafe { libc::fork() };
+        if child == 0 {
+            unsafe {
+                let mut sa: libc::sigaction = std::mem::zeroed();
+                sa.sa_sigaction = exit_with_fault_offset as usize;
+                sa.sa_flags = libc::SA_SIGINFO;
+                libc::sigaction(libc::SIGSEGV, &sa, ptr::null_mut());
+                probe(64 << 20);
+                libc::_exit(255);
+            }
+        }
+
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+        assert!(libc::WIFEXITED(status), "probe child did not exit");
+        assert_ne!(libc::WEXITSTATUS(status), 255, "probe did not fault");
+        let offset = 4 * libc::WEXITSTATUS(status) as u32;
+        assert!(
+            trap_sites(&Triple::host())
+                .iter()
+                .any(|site| site.offset == offset),
+            "probe faulted at offset {}, which is not a trap site",
+            offset
+        );
+    }
+}
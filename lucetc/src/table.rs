@@ -5,11 +5,13 @@ use crate::pointer::NATIVE_POINTER_SIZE;
 use byteorder::{LittleEndian, WriteBytesExt};
 use cranelift_codegen::entity::EntityRef;
 use cranelift_module::{Backend as ClifBackend, DataContext, DataId, Module as ClifModule};
-use cranelift_wasm::{TableElementType, TableIndex};
+use cranelift_wasm::TableIndex;
 use std::io::Cursor;
 
 /// This symbol will be used to reference the `tables` field in `Module` - a sequence of tables.
-/// At the moment it will either be one or no tables, but in the future may grow.
+///
+/// These are the initial contents of the tables; the runtime copies them into each instance, where
+/// guest code may modify them.
 pub const TABLE_SYM: &str = "lucet_tables";
 /// This is functionally the size of `&[TableEntry]`, but defined here because it may not
 /// necessarily have the same field ordering.
@@ -22,14 +24,6 @@ enum Elem {
 }
 
 fn table_elements(decl: &TableDecl<'_>) -> Result<Vec<Elem>, Error> {
-    match decl.table.ty {
-        TableElementType::Func => Ok(()),
-        _ => {
-            let message = format!("table with non-function elements: {:?}", decl);
-            Err(Error::Unsupported(message))
-        }
-    }?;
-
     let mut elems = vec![Elem::Empty; decl.table.minimum as usize];
 
     for initializer in decl.elems.iter() {
@@ -68,7 +62,8 @@ pub fn write_table_data<B: ClifBackend>(
     let mut table_ctx = DataContext::new();
     let mut tables_count = 0;
 
-    if let Ok(table_decl) = decls.get_table(TableIndex::new(0)) {
+    for table_index in (0..decls.info.tables.len()).map(TableIndex::new) {
+        let table_decl = decls.get_table(table_index)?;
         // Indirect calls are performed by looking up the callee function and type in a table that
         // is present in the same object file.
         // The table is an array of pairs of (type index, function pointer). Both elements in the
@@ -107,7 +102,7 @@ pub fn write_table_data<B: ClifBackend>(
                         return Err(Error::FunctionIndexError(message));
                     }
                 }
-                // EMPTY:
+                // EMPTY, and all elements of `externref` tables, which start out null:
                 Elem::Empty => {
                     // First element is the signature index. These will always be 32 bits in wasm, so
                     // u64::max will always be out of bounds.
@@ -16,6 +16,9 @@ fn to_lucet_valuetype(ty: &wasmparser::Type) -> Result<ValueType, ValueError> {
         wasmparser::Type::I64 => Ok(ValueType::I64),
         wasmparser::Type::F32 => Ok(ValueType::F32),
         wasmparser::Type::F64 => Ok(ValueType::F64),
+        wasmparser::Type::ExternRef => Ok(ValueType::ExternRef),
+        wasmparser::Type::FuncRef => Ok(ValueType::FuncRef),
+        wasmparser::Type::V128 => Ok(ValueType::V128),
         _ => Err(ValueError::Unrepresentable),
     }
 }
@@ -23,7 +26,6 @@ fn to_lucet_valuetype(ty: &wasmparser::Type) -> Result<ValueType, ValueError> {
 #[derive(Debug, Error)]
 pub enum SignatureError {
     Type(wasmparser::Type, ValueError),
-    Multivalue,
 }
 
 impl Display for SignatureError {
@@ -41,18 +43,13 @@ pub fn to_lucet_signature(func_type: &FuncType) -> Result<Signature, SignatureEr
         })
         .collect::<Result<Vec<ValueType>, SignatureError>>()?;
 
-    let ret_ty: Option<ValueType> = match &*func_type.returns {
-        &[] => None,
-        &[ref ret_ty] => {
-            let value_ty =
-                to_lucet_valuetype(ret_ty).map_err(|e| SignatureError::Type(ret_ty.clone(), e))?;
-
-            Some(value_ty)
-        }
-        _ => {
-            return Err(SignatureError::Multivalue);
-        }
-    };
+    let results = func_type
+        .returns
+        .iter()
+        .map(|ret_ty| {
+            to_lucet_valuetype(ret_ty).map_err(|e| SignatureError::Type(ret_ty.clone(), e))
+        })
+        .collect::<Result<Vec<ValueType>, SignatureError>>()?;
 
-    Ok(Signature { params, ret_ty })
+    Ok(Signature { params, results })
 }
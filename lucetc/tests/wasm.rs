@@ -56,6 +56,7 @@ mod module_data {
             &b,
             h,
             false,
+            false,
             &None,
             false,
         )
@@ -571,6 +572,7 @@ mod validate {
             &b,
             h,
             false,
+            false,
             &Some(v),
             false,
         )
@@ -613,3 +615,505 @@ mod validate {
         let _obj = c.object_file().expect("codegen");
     }
 }
+
+mod cache {
+    use lucetc::{Lucetc, LucetcOpts, OptLevel};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn cached_artifacts(cache_dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(cache_dir)
+            .expect("cache directory exists")
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn reuses_artifacts_with_same_settings() {
+        let workdir = TempDir::new().expect("create working directory");
+        let cache_dir = workdir.path().join("cache");
+        let output = workdir.path().join("out.o");
+
+        let c = Lucetc::new("tests/wasm/fibonacci.wat").with_cache_dir(cache_dir.clone());
+        c.object_file(&output).expect("compile");
+        let artifacts = cached_artifacts(&cache_dir);
+        assert_eq!(artifacts.len(), 1);
+        assert!(artifacts[0].ends_with(".o"));
+        assert_eq!(
+            fs::read(&output).unwrap(),
+            fs::read(cache_dir.join(&artifacts[0])).unwrap()
+        );
+
+        // overwrite the cached artifact, so a cache hit is observable in the output
+        fs::write(cache_dir.join(&artifacts[0]), b"cached").unwrap();
+        c.object_file(&output).expect("compile");
+        assert_eq!(fs::read(&output).unwrap(), b"cached");
+    }
+
+    #[test]
+    fn settings_are_part_of_the_key() {
+        let workdir = TempDir::new().expect("create working directory");
+        let cache_dir = workdir.path().join("cache");
+        let output = workdir.path().join("out.o");
+
+        Lucetc::new("tests/wasm/fibonacci.wat")
+            .with_cache_dir(cache_dir.clone())
+            .with_opt_level(OptLevel::None)
+            .object_file(&output)
+            .expect("compile");
+        Lucetc::new("tests/wasm/fibonacci.wat")
+            .with_cache_dir(cache_dir.clone())
+            .with_opt_level(OptLevel::Speed)
+            .object_file(&output)
+            .expect("compile");
+        Lucetc::new("tests/wasm/arith.wat")
+            .with_cache_dir(cache_dir.clone())
+            .with_opt_level(OptLevel::Speed)
+            .object_file(&output)
+            .expect("compile");
+
+        assert_eq!(cached_artifacts(&cache_dir).len(), 3);
+    }
+}
+
+mod parallel {
+    use super::load_wat_module;
+    use lucetc::Compiler;
+    use tempfile::TempDir;
+
+    fn compile_with_threads(file: &str, threads: usize) -> Vec<u8> {
+        let m = load_wat_module(file);
+        let b = super::test_bindings();
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.o");
+        Compiler::builder()
+            .with_threads(threads)
+            .create(&m, &b)
+            .unwrap_or_else(|_| panic!("compile {}", file))
+            .object_file()
+            .unwrap_or_else(|_| panic!("codegen {}", file))
+            .write(&output)
+            .expect("write object file");
+        std::fs::read(&output).expect("read object file")
+    }
+
+    #[test]
+    fn output_does_not_depend_on_threads() {
+        for file in &["call", "import_many", "icall_sparse", "locals_csr"] {
+            let serial = compile_with_threads(file, 1);
+            for threads in &[2, 8] {
+                assert!(
+                    compile_with_threads(file, *threads) == serial,
+                    "{} compiled with {} threads differs from a serial compilation",
+                    file,
+                    threads
+                );
+            }
+        }
+    }
+}
+
+mod reproducible {
+    use lucetc::{Lucetc, LucetcOpts};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn object_files_are_identical() {
+        let workdir = TempDir::new().expect("create working directory");
+        let first = workdir.path().join("first.o");
+        let second = workdir.path().join("second.o");
+
+        Lucetc::new("tests/wasm/icall_sparse.wat")
+            .with_bindings(super::test_bindings())
+            .object_file(&first)
+            .expect("compile");
+        Lucetc::new("tests/wasm/icall_sparse.wat")
+            .with_bindings(super::test_bindings())
+            .with_threads(3)
+            .object_file(&second)
+            .expect("compile");
+        assert!(fs::read(&first).unwrap() == fs::read(&second).unwrap());
+    }
+
+    #[test]
+    fn clif_is_identical() {
+        let workdir = TempDir::new().expect("create working directory");
+        let first = workdir.path().join("first.clif");
+        let second = workdir.path().join("second.clif");
+
+        let c = Lucetc::new("tests/wasm/import_many.wat").with_bindings(super::test_bindings());
+        c.clif_ir(&first).expect("compile");
+        c.clif_ir(&second).expect("compile");
+        assert_eq!(
+            fs::read_to_string(&first).unwrap(),
+            fs::read_to_string(&second).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_reproducible() {
+        let workdir = TempDir::new().expect("create working directory");
+        let obj = workdir.path().join("out.o");
+        let so = workdir.path().join("out.so");
+
+        let c = Lucetc::new("tests/wasm/data_segment.wat");
+        c.check_reproducible_object_file(&obj)
+            .expect("object file is reproducible");
+        c.check_reproducible_shared_object_file(&so)
+            .expect("shared object is reproducible");
+        assert!(obj.is_file());
+        assert!(so.is_file());
+    }
+}
+
+mod link {
+    use lucetc::{Error, Linker, Lucetc, LucetcOpts};
+    use std::fs;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+    use tempfile::TempDir;
+
+    #[test]
+    fn builtin_linker_writes_shared_objects() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.so");
+        for file in &["call", "import_many", "icall_sparse", "data_segment"] {
+            Lucetc::new(format!("tests/wasm/{}.wat", file))
+                .with_bindings(super::test_bindings())
+                .with_linker(Linker::Builtin)
+                .shared_object_file(&output)
+                .unwrap_or_else(|e| panic!("link {}: {}", file, e));
+            let so = fs::read(&output).unwrap();
+            assert_eq!(&so[0..4], b"\x7fELF");
+            // e_type is ET_DYN
+            assert_eq!(&so[16..18], &[3, 0]);
+        }
+    }
+
+    #[test]
+    fn builtin_linker_is_the_default_where_supported() {
+        let linux = Triple::from_str("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(Linker::default_for(&linux), Linker::Builtin);
+        let aarch64 = Triple::from_str("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(Linker::default_for(&aarch64), Linker::External);
+    }
+
+    #[test]
+    fn builtin_linker_rejects_other_formats() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.so");
+        let target = Triple::from_str("x86_64-apple-darwin").unwrap();
+        assert_eq!(Linker::default_for(&target), Linker::External);
+        match Lucetc::new("tests/wasm/call.wat")
+            .with_target(target)
+            .with_linker(Linker::Builtin)
+            .shared_object_file(&output)
+        {
+            Err(Error::Unsupported(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(()) => panic!("built-in linker produced a shared object for macOS"),
+        }
+    }
+}
+
+mod aarch64 {
+    use lucetc::{CpuFeatures, Error, Lucetc, LucetcOpts, SpecificFeature};
+    use std::fs;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+    use tempfile::TempDir;
+
+    fn target() -> Triple {
+        Triple::from_str("aarch64-unknown-linux-gnu").unwrap()
+    }
+
+    #[test]
+    fn compiles_object_files() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.o");
+        for file in &["call", "icall_sparse", "data_segment", "globals_import"] {
+            Lucetc::new(format!("tests/wasm/{}.wat", file))
+                .with_bindings(super::test_bindings())
+                .with_target(target())
+                .object_file(&output)
+                .unwrap_or_else(|e| panic!("compile {}: {}", file, e));
+            let obj = fs::read(&output).unwrap();
+            assert_eq!(&obj[0..4], b"\x7fELF");
+            // e_machine is EM_AARCH64
+            assert_eq!(&obj[18..20], &[183, 0]);
+        }
+    }
+
+    #[test]
+    fn rejects_x86_features() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.o");
+        let mut cpu_features = CpuFeatures::baseline();
+        cpu_features.set(SpecificFeature::SSE41, true);
+        match Lucetc::new("tests/wasm/call.wat")
+            .with_target(target())
+            .with_cpu_features(cpu_features)
+            .object_file(&output)
+        {
+            Err(Error::Unsupported(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(()) => panic!("compiled an aarch64 module with x86 features"),
+        }
+    }
+}
+
+mod debug_info {
+    use super::load_wat_module;
+    use byteorder::{ByteOrder, LittleEndian};
+    use gimli::{constants, write, Encoding, Format, LineEncoding};
+    use lucetc::{Error, Linker, Lucetc, LucetcOpts};
+    use std::fs;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+    use tempfile::TempDir;
+    use wasmparser::{ModuleReader, SectionCode};
+
+    /// Append DWARF sections to `wasm`, describing its only function as `main` in `main.c`, which
+    /// uses a variable `counter` at address 0x400 of linear memory.
+    fn with_dwarf(mut wasm: Vec<u8>) -> Vec<u8> {
+        let mut reader = ModuleReader::new(&wasm).unwrap();
+        let mut code_len = 0;
+        while !reader.eof() {
+            let section = reader.read().unwrap();
+            if let SectionCode::Code = section.code {
+                code_len = section.range().end - section.range().start;
+            }
+        }
+        // the count of functions, and then the function
+        let (low_pc, high_pc) = (1, code_len as u64);
+
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut program = write::LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            write::LineString::String(b"/src".to_vec()),
+            write::LineString::String(b"main.c".to_vec()),
+            None,
+        );
+        let dir = program.default_directory();
+        let file = program.add_file(write::LineString::String(b"main.c".to_vec()), dir, None);
+        program.begin_sequence(Some(write::Address::Constant(low_pc)));
+        program.row().file = file;
+        program.row().line = 3;
+        program.generate_row();
+        program.end_sequence(high_pc - low_pc);
+
+        let mut dwarf = write::Dwarf::new();
+        let unit_id = dwarf.units.add(write::Unit::new(encoding, program));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        let attrs = [
+            (
+                constants::DW_AT_name,
+                write::AttributeValue::String(b"main.c".to_vec()),
+            ),
+            (
+                constants::DW_AT_stmt_list,
+                write::AttributeValue::LineProgramRef,
+            ),
+            (
+                constants::DW_AT_low_pc,
+                write::AttributeValue::Address(write::Address::Constant(low_pc)),
+            ),
+            (
+                constants::DW_AT_high_pc,
+                write::AttributeValue::Udata(high_pc - low_pc),
+            ),
+        ];
+        for (name, value) in attrs.iter() {
+            unit.get_mut(root).set(*name, value.clone());
+        }
+        let main = unit.add(root, constants::DW_TAG_subprogram);
+        for (name, value) in attrs
+            .iter()
+            .filter(|(name, _)| *name != constants::DW_AT_stmt_list)
+        {
+            unit.get_mut(main).set(*name, value.clone());
+        }
+        unit.get_mut(main).set(
+            constants::DW_AT_name,
+            write::AttributeValue::String(b"main".to_vec()),
+        );
+        let counter = unit.add(main, constants::DW_TAG_variable);
+        unit.get_mut(counter).set(
+            constants::DW_AT_name,
+            write::AttributeValue::String(b"counter".to_vec()),
+        );
+        unit.get_mut(counter).set(
+            constants::DW_AT_location,
+            write::AttributeValue::Exprloc(write::Expression::raw(vec![
+                constants::DW_OP_addr.0,
+                0x00,
+                0x04,
+                0x00,
+                0x00,
+            ])),
+        );
+
+        let mut sections = write::Sections::new(write::EndianVec::new(gimli::LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        sections
+            .for_each(|id, data| -> write::Result<()> {
+                if data.slice().is_empty() {
+                    return Ok(());
+                }
+                let mut payload = vec![];
+                gimli::leb128::write::unsigned(&mut payload, id.name().len() as u64).unwrap();
+                payload.extend_from_slice(id.name().as_bytes());
+                payload.extend_from_slice(data.slice());
+                wasm.push(0);
+                gimli::leb128::write::unsigned(&mut wasm, payload.len() as u64).unwrap();
+                wasm.extend_from_slice(&payload);
+                Ok(())
+            })
+            .unwrap();
+        wasm
+    }
+
+    /// The contents of the section `name` of an ELF file.
+    fn section<'a>(elf: &'a [u8], name: &str) -> Option<&'a [u8]> {
+        let shoff = LittleEndian::read_u64(&elf[0x28..]) as usize;
+        let shnum = LittleEndian::read_u16(&elf[0x3c..]) as usize;
+        let shstrndx = LittleEndian::read_u16(&elf[0x3e..]) as usize;
+        let header = |index: usize| &elf[shoff + index * 64..shoff + (index + 1) * 64];
+        let contents = |header: &[u8]| {
+            let offset = LittleEndian::read_u64(&header[24..]) as usize;
+            let size = LittleEndian::read_u64(&header[32..]) as usize;
+            &elf[offset..offset + size]
+        };
+        let names = contents(header(shstrndx));
+        (0..shnum).map(header).find_map(|header| {
+            let name_offset = LittleEndian::read_u32(header) as usize;
+            let section_name = names[name_offset..].split(|b| *b == 0).next().unwrap();
+            if section_name == name.as_bytes() {
+                Some(contents(header))
+            } else {
+                None
+            }
+        })
+    }
+
+    #[test]
+    fn translates_dwarf() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.so");
+        Lucetc::try_from_bytes(with_dwarf(load_wat_module("arith")))
+            .unwrap()
+            .with_debug_info(true)
+            .with_linker(Linker::Builtin)
+            .shared_object_file(&output)
+            .expect("compile");
+        let so = fs::read(&output).unwrap();
+        assert!(section(&so, ".rela.debug_info").is_some());
+
+        let dwarf = gimli::read::Dwarf::load(
+            |id| -> gimli::read::Result<_> {
+                Ok(gimli::read::EndianSlice::new(
+                    section(&so, id.name()).unwrap_or(&[]),
+                    gimli::LittleEndian,
+                ))
+            },
+            |_| Ok(gimli::read::EndianSlice::new(&[], gimli::LittleEndian)),
+        )
+        .unwrap();
+        let header = dwarf.units().next().unwrap().expect("a unit");
+        let unit = dwarf.unit(header).unwrap();
+        let mut entries = unit.entries();
+        let (mut main, mut counter) = (false, false);
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            let name = match entry.attr_value(constants::DW_AT_name).unwrap() {
+                Some(name) => dwarf
+                    .attr_string(&unit, name)
+                    .unwrap()
+                    .to_string()
+                    .unwrap()
+                    .to_owned(),
+                None => continue,
+            };
+            let expr = |attr| match entry.attr_value(attr).unwrap() {
+                Some(gimli::read::AttributeValue::Exprloc(expr)) => expr.0.to_vec(),
+                other => panic!("{:?} of {} is {:?}", attr, name, other),
+            };
+            if name == "main" {
+                main = true;
+                match entry.attr_value(constants::DW_AT_low_pc).unwrap() {
+                    Some(gimli::read::AttributeValue::Addr(addr)) => assert_ne!(addr, 0),
+                    other => panic!("low pc of main is {:?}", other),
+                }
+                assert_eq!(
+                    expr(constants::DW_AT_frame_base)[0],
+                    constants::DW_OP_breg6.0
+                );
+            } else if name == "counter" {
+                counter = true;
+                // relative to the heap base
+                assert_eq!(
+                    expr(constants::DW_AT_location),
+                    vec![constants::DW_OP_fbreg.0, 0x80, 0x08]
+                );
+            }
+        }
+        assert!(main && counter);
+    }
+
+    #[test]
+    fn modules_without_dwarf_have_none() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.o");
+        Lucetc::new("tests/wasm/arith.wat")
+            .with_debug_info(true)
+            .object_file(&output)
+            .expect("compile");
+        assert!(section(&fs::read(&output).unwrap(), ".debug_info").is_none());
+    }
+
+    #[test]
+    fn rejects_other_targets() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.o");
+        match Lucetc::new("tests/wasm/arith.wat")
+            .with_target(Triple::from_str("aarch64-unknown-linux-gnu").unwrap())
+            .with_debug_info(true)
+            .object_file(&output)
+        {
+            Err(Error::Unsupported(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(()) => panic!("translated debug info for aarch64"),
+        }
+    }
+}
+
+mod call_site_offsets {
+    use lucetc::{Error, Lucetc, LucetcOpts};
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+    use tempfile::TempDir;
+
+    #[test]
+    fn rejects_other_targets() {
+        let workdir = TempDir::new().expect("create working directory");
+        let output = workdir.path().join("out.o");
+        match Lucetc::new("tests/wasm/call.wat")
+            .with_target(Triple::from_str("aarch64-unknown-linux-gnu").unwrap())
+            .with_call_site_offsets(true)
+            .object_file(&output)
+        {
+            Err(Error::Unsupported(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(()) => panic!("recorded call site offsets for aarch64"),
+        }
+    }
+}